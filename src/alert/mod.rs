@@ -0,0 +1,591 @@
+use clock::{self, Timestamp};
+use device::{DeviceCollection, DeviceCollectionState, HealthPolicy};
+use reading::Quantity;
+use rocket::config::Config;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+use zone::{ZoneCollection, ZoneCollectionState};
+
+mod notifier;
+
+pub use self::notifier::{LogNotifier, Notifier, SmtpNotifier, WebhookNotifier};
+
+pub type AlertEngineState = Arc<Mutex<AlertEngine>>;
+
+const SETPOINT_TOLERANCE: f64 = 0.5;
+const RESOLVED_RETENTION: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    DeviceOffline,
+    LowBattery,
+    SetpointNotReached,
+    DeviceFault,
+    ReadingOutOfRange,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Active,
+    Acknowledged,
+    Resolved,
+}
+
+impl AlertState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertState::Active => "active",
+            AlertState::Acknowledged => "acknowledged",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertRule {
+    DeviceOffline,
+    LowBattery,
+    SetpointNotReached { hours: f64 },
+    DeviceFault,
+    ReadingOutOfRange {
+        quantity: Quantity,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        above: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        below: Option<f64>,
+    },
+}
+
+impl AlertRule {
+    pub fn kind(&self) -> AlertKind {
+        match self {
+            AlertRule::DeviceOffline => AlertKind::DeviceOffline,
+            AlertRule::LowBattery => AlertKind::LowBattery,
+            AlertRule::SetpointNotReached { .. } => AlertKind::SetpointNotReached,
+            AlertRule::DeviceFault => AlertKind::DeviceFault,
+            AlertRule::ReadingOutOfRange { .. } => AlertKind::ReadingOutOfRange,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            AlertRule::SetpointNotReached { hours } if *hours <= 0.0 => {
+                Err("setpoint_not_reached rules need a positive number of hours".to_string())
+            }
+            AlertRule::ReadingOutOfRange {
+                above: None,
+                below: None,
+                ..
+            } => Err("reading_out_of_range rules need above, below or both".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct Alert {
+    kind: AlertKind,
+    subject_uuid: Uuid,
+    message: String,
+    state: AlertState,
+    raised_at: Timestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acknowledged_at: Option<Timestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_at: Option<Timestamp>,
+}
+
+impl Alert {
+    pub fn new(kind: AlertKind, subject_uuid: Uuid, message: String, raised_at: Timestamp) -> Alert {
+        Alert {
+            kind,
+            subject_uuid,
+            message,
+            state: AlertState::Active,
+            raised_at,
+            acknowledged_at: None,
+            resolved_at: None,
+        }
+    }
+
+    pub fn kind(&self) -> AlertKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn state(&self) -> AlertState {
+        self.state
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state != AlertState::Resolved
+    }
+
+    fn acknowledge(&mut self, now: Timestamp) {
+        if self.state == AlertState::Active {
+            self.state = AlertState::Acknowledged;
+            self.acknowledged_at = Some(now);
+        }
+    }
+
+    fn resolve(&mut self, now: Timestamp) {
+        if self.is_open() {
+            self.state = AlertState::Resolved;
+            self.resolved_at = Some(now);
+        }
+    }
+}
+
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    alerts: HashMap<Uuid, Alert>,
+    below_setpoint_since: HashMap<Uuid, Timestamp>,
+    interval: Duration,
+    last_evaluated: Option<Timestamp>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> AlertEngine {
+        AlertEngine {
+            rules,
+            alerts: HashMap::new(),
+            below_setpoint_since: HashMap::new(),
+            interval: Duration::from_secs(60),
+            last_evaluated: None,
+        }
+    }
+
+    pub fn last_evaluated(&self) -> Option<Timestamp> {
+        self.last_evaluated
+    }
+
+    pub fn is_evaluating(&self, now: Timestamp) -> bool {
+        clock::ran_recently(self.last_evaluated, self.interval.as_secs(), now)
+    }
+
+    pub fn default_rules() -> Vec<AlertRule> {
+        vec![
+            AlertRule::DeviceOffline,
+            AlertRule::LowBattery,
+            AlertRule::SetpointNotReached { hours: 3.0 },
+            AlertRule::DeviceFault,
+        ]
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    pub fn set_rules(&mut self, rules: Vec<AlertRule>) -> Result<(), String> {
+        for rule in &rules {
+            rule.validate()?;
+        }
+        self.set_validated_rules(rules);
+        Ok(())
+    }
+
+    pub fn set_validated_rules(&mut self, rules: Vec<AlertRule>) {
+        self.rules = rules;
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> Option<&Alert> {
+        self.alerts.get(uuid)
+    }
+
+    pub fn get_all_with_state(&self, state: Option<AlertState>) -> HashMap<Uuid, Alert> {
+        let mut alerts = self.alerts.clone();
+        if let Some(state) = state {
+            alerts.retain(|_, alert| alert.state == state);
+        }
+        alerts
+    }
+
+    pub fn acknowledge(&mut self, uuid: &Uuid, now: Timestamp) -> Option<&Alert> {
+        let alert = self.alerts.get_mut(uuid)?;
+        alert.acknowledge(now);
+        Some(alert)
+    }
+
+    pub fn resolve(&mut self, uuid: &Uuid, now: Timestamp) -> Option<&Alert> {
+        let alert = self.alerts.get_mut(uuid)?;
+        alert.resolve(now);
+        Some(alert)
+    }
+
+    pub fn forget_subject(&mut self, subject_uuid: &Uuid) {
+        self.alerts.retain(|_, alert| alert.subject_uuid != *subject_uuid);
+        self.below_setpoint_since.remove(subject_uuid);
+    }
+
+    pub fn evaluate(
+        &mut self,
+        now: Timestamp,
+        zones: &ZoneCollection,
+        devices: &DeviceCollection,
+        policy: &HealthPolicy,
+    ) -> Vec<(Uuid, Alert)> {
+        self.last_evaluated = Some(now);
+        let conditions = self.conditions(now, zones, devices, policy);
+        let holds = |alert: &Alert| {
+            conditions
+                .iter()
+                .any(|&(kind, subject_uuid, _)| kind == alert.kind && subject_uuid == alert.subject_uuid)
+        };
+
+        let mut changed = Vec::new();
+        for (uuid, alert) in &mut self.alerts {
+            if alert.is_open() && !holds(alert) {
+                alert.resolve(now);
+                changed.push((*uuid, alert.clone()));
+            }
+        }
+
+        for (kind, subject_uuid, message) in conditions.iter().cloned() {
+            let already_open = self
+                .alerts
+                .values()
+                .any(|alert| alert.is_open() && alert.kind == kind && alert.subject_uuid == subject_uuid);
+            if !already_open {
+                let uuid = Uuid::new_v4();
+                let alert = Alert::new(kind, subject_uuid, message, now);
+                self.alerts.insert(uuid, alert.clone());
+                changed.push((uuid, alert));
+            }
+        }
+
+        self.alerts.retain(|_, alert| {
+            alert
+                .resolved_at
+                .map_or(true, |resolved_at| now.saturating_sub(resolved_at) < RESOLVED_RETENTION)
+        });
+
+        changed
+    }
+
+    fn conditions(
+        &mut self,
+        now: Timestamp,
+        zones: &ZoneCollection,
+        devices: &DeviceCollection,
+        policy: &HealthPolicy,
+    ) -> Vec<(AlertKind, Uuid, String)> {
+        let mut conditions = Vec::new();
+
+        for rule in self.rules.clone() {
+            match rule {
+                AlertRule::DeviceOffline => {
+                    for (uuid, device) in devices.iter() {
+                        if device.is_online(now, policy) == Some(false) {
+                            let message = format!("{} is offline", device.name());
+                            conditions.push((rule.kind(), *uuid, message));
+                        }
+                    }
+                }
+                AlertRule::LowBattery => {
+                    for (uuid, device) in devices.iter() {
+                        if device.has_low_battery(policy) {
+                            let battery = device.health().battery.unwrap_or(0);
+                            let message = format!("{} battery is at {}%", device.name(), battery);
+                            conditions.push((rule.kind(), *uuid, message));
+                        }
+                    }
+                }
+                AlertRule::DeviceFault => {
+                    for (uuid, device) in devices.iter() {
+                        if let Some(ref fault) = device.health().fault {
+                            let message = format!("{} reported a fault: {}", device.name(), fault);
+                            conditions.push((rule.kind(), *uuid, message));
+                        }
+                    }
+                }
+                AlertRule::SetpointNotReached { hours } => {
+                    for (uuid, zone) in zones.iter() {
+                        match devices.zone_temperature(uuid) {
+                            Some(temperature) if temperature < zone.setpoint().celsius() - SETPOINT_TOLERANCE => {
+                                let since = *self.below_setpoint_since.entry(*uuid).or_insert(now);
+                                if now.saturating_sub(since) as f64 >= hours * 60.0 * 60.0 {
+                                    let message = format!(
+                                        "{} is at {:.1}, below its setpoint of {:.1} for over {} hours",
+                                        zone.name(),
+                                        temperature,
+                                        zone.setpoint().celsius(),
+                                        hours
+                                    );
+                                    conditions.push((rule.kind(), *uuid, message));
+                                }
+                            }
+                            _ => {
+                                self.below_setpoint_since.remove(uuid);
+                            }
+                        }
+                    }
+                }
+                AlertRule::ReadingOutOfRange { quantity, above, below } => {
+                    for (uuid, zone) in zones.iter() {
+                        let reading = match devices.zone_reading(uuid, quantity) {
+                            Some(reading) => reading,
+                            None => continue,
+                        };
+                        let limit = match (above, below) {
+                            (Some(above), _) if reading > above => ("above", above),
+                            (_, Some(below)) if reading < below => ("below", below),
+                            _ => continue,
+                        };
+                        let message = format!(
+                            "{} {} is at {:.1} {}, {} {:.1}",
+                            zone.name(),
+                            quantity.as_str(),
+                            reading,
+                            quantity.unit(),
+                            limit.0,
+                            limit.1
+                        );
+                        conditions.push((rule.kind(), *uuid, message));
+                    }
+                }
+            }
+        }
+
+        conditions
+    }
+}
+
+pub struct AlertConfig {
+    pub interval: Duration,
+    pub notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl AlertConfig {
+    pub fn from_config(config: &Config) -> AlertConfig {
+        let mut alert_config = AlertConfig {
+            interval: Duration::from_secs(60),
+            notifiers: vec![Box::new(LogNotifier)],
+        };
+
+        let table = match config.get_table("alerts") {
+            Ok(table) => table,
+            Err(_) => return alert_config,
+        };
+        let get_str = |key: &str| table.get(key).and_then(|value| value.as_str());
+
+        if let Some(interval) = table.get("interval").and_then(|value| value.as_integer()) {
+            if interval > 0 {
+                alert_config.interval = Duration::from_secs(interval as u64);
+            } else {
+                warn!("Ignoring invalid alert interval: {}", interval);
+            }
+        }
+        if let Some(url) = get_str("webhook_url") {
+            match WebhookNotifier::new(url) {
+                Some(webhook) => alert_config.notifiers.push(Box::new(webhook)),
+                None => warn!("Ignoring invalid alert webhook URL: {}", url),
+            }
+        }
+        if let (Some(relay), Some(from), Some(to)) = (get_str("smtp_relay"), get_str("smtp_from"), get_str("smtp_to")) {
+            alert_config
+                .notifiers
+                .push(Box::new(SmtpNotifier::new(relay, from, to)));
+        }
+
+        alert_config
+    }
+}
+
+pub fn spawn_evaluator(
+    engine: AlertEngineState,
+    zones: ZoneCollectionState,
+    devices: DeviceCollectionState,
+    policy: HealthPolicy,
+    config: AlertConfig,
+) -> thread::JoinHandle<()> {
+    engine.lock().unwrap().interval = config.interval;
+
+    thread::spawn(move || loop {
+        let changed = {
+            let zones = zones.lock().unwrap();
+            let devices = devices.lock().unwrap();
+            engine.lock().unwrap().evaluate(clock::now(), &zones, &devices, &policy)
+        };
+
+        for (uuid, alert) in &changed {
+            for notifier in &config.notifiers {
+                if let Err(error) = notifier.notify(uuid, alert) {
+                    error!("Failed to send notification for alert {}: {}", uuid, error);
+                }
+            }
+        }
+
+        thread::sleep(config.interval);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device::Device;
+    use fixtures::uuid;
+    use temperature::Temperature;
+    use zone::Zone;
+
+    const HOUR: u64 = 60 * 60;
+
+    fn zone_with_cold_device(zone_uuid: Uuid, device_uuid: Uuid) -> (ZoneCollection, DeviceCollection) {
+        let mut zone = Zone::new("Living Room".to_string());
+        zone.set_setpoint(Temperature::from_celsius(21.0));
+        let mut zones = ZoneCollection::new();
+        zones.add(zone_uuid, zone);
+
+        let mut device = Device::new("STRV".to_string(), Some(zone_uuid));
+        device.set_reading(Quantity::Temperature, 17.0);
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, device);
+
+        (zones, devices)
+    }
+
+    #[test]
+    fn raises_alert_once_for_low_battery() {
+        let device_uuid = uuid(2);
+        let mut device = Device::new("STRV".to_string(), None);
+        device.report(Some(5), None, None, 1000);
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, device);
+        let mut engine = AlertEngine::new(vec![AlertRule::LowBattery]);
+        let policy = HealthPolicy::default();
+
+        let changed = engine.evaluate(1000, &ZoneCollection::new(), &devices, &policy);
+        assert_eq!(1, changed.len());
+        assert_eq!(AlertKind::LowBattery, changed[0].1.kind());
+        assert_eq!(AlertState::Active, changed[0].1.state());
+
+        let changed = engine.evaluate(1060, &ZoneCollection::new(), &devices, &policy);
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn resolves_alert_when_condition_clears() {
+        let device_uuid = uuid(2);
+        let mut device = Device::new("Boiler".to_string(), None);
+        device.set_fault(Some("E119".to_string()));
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, device);
+        let mut engine = AlertEngine::new(vec![AlertRule::DeviceFault]);
+        let policy = HealthPolicy::default();
+
+        let (alert_uuid, _) = engine.evaluate(1000, &ZoneCollection::new(), &devices, &policy)[0].clone();
+        devices.get_mut(&device_uuid).unwrap().set_fault(None);
+        let changed = engine.evaluate(1060, &ZoneCollection::new(), &devices, &policy);
+
+        assert_eq!(1, changed.len());
+        assert_eq!(alert_uuid, changed[0].0);
+        assert_eq!(AlertState::Resolved, changed[0].1.state());
+    }
+
+    #[test]
+    fn acknowledged_alert_is_not_raised_again() {
+        let device_uuid = uuid(2);
+        let mut device = Device::new("STRV".to_string(), None);
+        device.report(Some(90), None, None, 0);
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, device);
+        let mut engine = AlertEngine::new(vec![AlertRule::DeviceOffline]);
+        let policy = HealthPolicy::default();
+
+        let (alert_uuid, _) = engine.evaluate(2 * HOUR, &ZoneCollection::new(), &devices, &policy)[0].clone();
+        engine.acknowledge(&alert_uuid, 2 * HOUR);
+        let changed = engine.evaluate(3 * HOUR, &ZoneCollection::new(), &devices, &policy);
+
+        assert!(changed.is_empty());
+        assert_eq!(AlertState::Acknowledged, engine.get(&alert_uuid).unwrap().state());
+    }
+
+    #[test]
+    fn setpoint_not_reached_waits_for_configured_hours() {
+        let zone_uuid = uuid(1);
+        let device_uuid = uuid(2);
+        let (zones, devices) = zone_with_cold_device(zone_uuid, device_uuid);
+        let mut engine = AlertEngine::new(vec![AlertRule::SetpointNotReached { hours: 2.0 }]);
+        let policy = HealthPolicy::default();
+
+        assert!(engine.evaluate(0, &zones, &devices, &policy).is_empty());
+        assert!(engine.evaluate(HOUR, &zones, &devices, &policy).is_empty());
+
+        let changed = engine.evaluate(2 * HOUR, &zones, &devices, &policy);
+        assert_eq!(1, changed.len());
+        assert_eq!(AlertKind::SetpointNotReached, changed[0].1.kind());
+    }
+
+    #[test]
+    fn zone_reaching_setpoint_restarts_the_wait() {
+        let zone_uuid = uuid(1);
+        let device_uuid = uuid(2);
+        let (zones, mut devices) = zone_with_cold_device(zone_uuid, device_uuid);
+        let mut engine = AlertEngine::new(vec![AlertRule::SetpointNotReached { hours: 2.0 }]);
+        let policy = HealthPolicy::default();
+
+        engine.evaluate(0, &zones, &devices, &policy);
+        devices.get_mut(&device_uuid).unwrap().set_reading(Quantity::Temperature, 21.0);
+        engine.evaluate(HOUR, &zones, &devices, &policy);
+        devices.get_mut(&device_uuid).unwrap().set_reading(Quantity::Temperature, 17.0);
+        engine.evaluate(2 * HOUR, &zones, &devices, &policy);
+
+        assert!(engine.evaluate(3 * HOUR, &zones, &devices, &policy).is_empty());
+    }
+
+    #[test]
+    fn raises_alert_for_zone_reading_out_of_range() {
+        let zone_uuid = uuid(1);
+        let device_uuid = uuid(2);
+        let (zones, mut devices) = zone_with_cold_device(zone_uuid, device_uuid);
+        let rule = AlertRule::ReadingOutOfRange {
+            quantity: Quantity::Co2,
+            above: Some(1000.0),
+            below: None,
+        };
+        let mut engine = AlertEngine::new(vec![rule]);
+        let policy = HealthPolicy::default();
+
+        devices.get_mut(&device_uuid).unwrap().set_reading(Quantity::Co2, 800.0);
+        assert!(engine.evaluate(0, &zones, &devices, &policy).is_empty());
+
+        devices.get_mut(&device_uuid).unwrap().set_reading(Quantity::Co2, 1400.0);
+        let changed = engine.evaluate(60, &zones, &devices, &policy);
+        assert_eq!(1, changed.len());
+        assert_eq!(AlertKind::ReadingOutOfRange, changed[0].1.kind());
+        assert_eq!("Living Room co2 is at 1400.0 ppm, above 1000.0", changed[0].1.message());
+    }
+
+    #[test]
+    fn rule_with_non_positive_hours_is_rejected() {
+        let mut engine = AlertEngine::new(AlertEngine::default_rules());
+
+        let result = engine.set_rules(vec![AlertRule::SetpointNotReached { hours: 0.0 }]);
+
+        assert!(result.is_err());
+        assert_eq!(AlertEngine::default_rules().as_slice(), engine.rules());
+    }
+
+    #[test]
+    fn forget_subject_removes_its_alerts() {
+        let device_uuid = uuid(2);
+        let mut device = Device::new("STRV".to_string(), None);
+        device.report(Some(5), None, None, 1000);
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, device);
+        let mut engine = AlertEngine::new(vec![AlertRule::LowBattery]);
+        engine.evaluate(1000, &ZoneCollection::new(), &devices, &HealthPolicy::default());
+
+        engine.forget_subject(&device_uuid);
+
+        assert!(engine.get_all_with_state(None).is_empty());
+    }
+}
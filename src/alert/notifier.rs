@@ -0,0 +1,229 @@
+use super::Alert;
+use http::{connect, HttpUrl};
+use std::io::{BufRead, BufReader, Write};
+use uuid::Uuid;
+
+pub trait Notifier: Send {
+    fn notify(&self, uuid: &Uuid, alert: &Alert) -> Result<(), String>;
+}
+
+fn subject(alert: &Alert) -> String {
+    format!("[{}] {}", alert.state().as_str(), alert.message())
+}
+
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify(&self, uuid: &Uuid, alert: &Alert) -> Result<(), String> {
+        info!("Alert {}: {}", uuid, subject(alert));
+        Ok(())
+    }
+}
+
+pub struct WebhookNotifier {
+    url: HttpUrl,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Option<WebhookNotifier> {
+        HttpUrl::parse(url).map(|url| WebhookNotifier { url })
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, uuid: &Uuid, alert: &Alert) -> Result<(), String> {
+        let mut body = serde_json::to_value(alert).map_err(|e| e.to_string())?;
+        body["uuid"] = serde_json::Value::String(uuid.to_string());
+        let body = body.to_string();
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            self.url.path,
+            self.url.host,
+            body.len(),
+            body
+        );
+
+        let mut stream = connect(&self.url.address)?;
+        stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream)
+            .read_line(&mut status_line)
+            .map_err(|e| e.to_string())?;
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => Err(format!("webhook responded with {}", status_line.trim())),
+        }
+    }
+}
+
+pub struct SmtpNotifier {
+    relay: String,
+    from: String,
+    to: String,
+}
+
+impl SmtpNotifier {
+    pub fn new(relay: &str, from: &str, to: &str) -> SmtpNotifier {
+        SmtpNotifier {
+            relay: relay.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+}
+
+impl Notifier for SmtpNotifier {
+    fn notify(&self, uuid: &Uuid, alert: &Alert) -> Result<(), String> {
+        let stream = connect(&self.relay)?;
+        let mut writer = stream.try_clone().map_err(|e| e.to_string())?;
+        let mut reader = BufReader::new(stream);
+
+        expect_reply(&mut reader, "220")?;
+        let mut command = |line: String, expected: &str| {
+            writer
+                .write_all(format!("{}\r\n", line).as_bytes())
+                .map_err(|e| e.to_string())?;
+            expect_reply(&mut reader, expected)
+        };
+        command("HELO smart-home".to_string(), "250")?;
+        command(format!("MAIL FROM:<{}>", self.from), "250")?;
+        command(format!("RCPT TO:<{}>", self.to), "250")?;
+        command("DATA".to_string(), "354")?;
+        let body = format!(
+            "{}\nAlert {} is {} since it was raised at {}.",
+            alert.message(),
+            uuid,
+            alert.state().as_str(),
+            alert.raised_at
+        );
+        command(
+            format!(
+                "From: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}.",
+                self.from,
+                self.to,
+                header_value(&subject(alert)),
+                data_lines(&body)
+            ),
+            "250",
+        )?;
+        command("QUIT".to_string(), "221")
+    }
+}
+
+fn header_value(text: &str) -> String {
+    let text: String = text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    if text.is_ascii() {
+        return text;
+    }
+
+    let mut words = vec![String::new()];
+    for c in text.chars() {
+        let mut encoded = String::new();
+        let mut buffer = [0; 4];
+        for byte in c.encode_utf8(&mut buffer).bytes() {
+            match byte {
+                b' ' => encoded.push('_'),
+                b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' => encoded.push(byte as char),
+                _ => encoded.push_str(&format!("={:02X}", byte)),
+            }
+        }
+        // Encoded words are at most 75 characters long, 12 of which are taken by `=?UTF-8?Q?` and `?=`.
+        if words.last().map_or(0, String::len) + encoded.len() > 63 {
+            words.push(String::new());
+        }
+        if let Some(word) = words.last_mut() {
+            word.push_str(&encoded);
+        }
+    }
+    words
+        .iter()
+        .map(|word| format!("=?UTF-8?Q?{}?=", word))
+        .collect::<Vec<_>>()
+        .join("\r\n ")
+}
+
+fn data_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.starts_with('.') {
+                format!(".{}\r\n", line)
+            } else {
+                format!("{}\r\n", line)
+            }
+        })
+        .collect()
+}
+
+fn expect_reply<R: BufRead>(reader: &mut R, expected: &str) -> Result<(), String> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("connection closed by SMTP relay".to_string());
+        }
+        if !line.starts_with(expected) {
+            return Err(format!("expected {} from SMTP relay, got {}", expected, line.trim()));
+        }
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AlertKind;
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn alert() -> Alert {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        Alert::new(AlertKind::LowBattery, device_uuid, "STRV battery is at 5%".to_string(), 1000)
+    }
+
+    #[test]
+    fn email_headers_cannot_be_injected() {
+        assert_eq!("Boiler Bcc: someone", header_value("Boiler\r\nBcc: someone"));
+        assert_eq!("=?UTF-8?Q?K=C3=BCche_is_cold?=", header_value("Küche is cold"));
+    }
+
+    #[test]
+    fn email_body_lines_starting_with_a_dot_are_stuffed() {
+        assert_eq!("Hall\r\n..\r\n..hidden\r\n", data_lines("Hall\n.\r\n.hidden"));
+    }
+
+    #[test]
+    fn webhook_rejects_non_http_urls() {
+        assert!(WebhookNotifier::new("https://hub.local/alerts").is_none());
+        assert!(WebhookNotifier::new("http:///alerts").is_none());
+    }
+
+    #[test]
+    fn webhook_posts_alert_as_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with('}') {
+                let length = stream.read(&mut buffer).unwrap();
+                request.push_str(&String::from_utf8_lossy(&buffer[..length]));
+            }
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            request
+        });
+
+        let alert_uuid = Uuid::parse_str("88f573e2-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let result = WebhookNotifier::new(&url).unwrap().notify(&alert_uuid, &alert());
+        let request = server.join().unwrap();
+
+        assert!(result.is_ok());
+        assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(request.contains("\"kind\":\"low_battery\""));
+        assert!(request.contains(&alert_uuid.to_string()));
+    }
+}
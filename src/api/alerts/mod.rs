@@ -0,0 +1,97 @@
+use alert::{Alert, AlertEngineState, AlertRule, AlertState};
+use api::preferences::UnitJson;
+use clock;
+use rocket::http::{RawStr, Status};
+use rocket::request::FromFormValue;
+use rocket::response::status;
+use rocket::{Rocket, State};
+use rocket_contrib::json::{Json, JsonValue};
+use rocket_contrib::uuid::Uuid as RocketUuid;
+
+pub fn mount(rocket: Rocket, engine: AlertEngineState) -> Rocket {
+    rocket
+        .mount(
+            "/alerts",
+            routes![
+                get_alerts,
+                get_alert_from_uuid,
+                acknowledge_alert,
+                resolve_alert,
+                get_alert_rules,
+                put_alert_rules
+            ],
+        )
+        .manage(engine)
+}
+
+impl<'v> FromFormValue<'v> for AlertState {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<AlertState, &'v RawStr> {
+        match form_value.as_str() {
+            "active" => Ok(AlertState::Active),
+            "acknowledged" => Ok(AlertState::Acknowledged),
+            "resolved" => Ok(AlertState::Resolved),
+            _ => Err(form_value),
+        }
+    }
+}
+
+#[get("/?<state>", format = "application/json")]
+fn get_alerts(state: Option<AlertState>, engine: State<AlertEngineState>) -> JsonValue {
+    let alerts = engine.lock().unwrap().get_all_with_state(state);
+    json!({ "alerts": alerts })
+}
+
+#[get("/<uuid>", format = "application/json")]
+fn get_alert_from_uuid(uuid: RocketUuid, engine: State<AlertEngineState>) -> Option<Json<Alert>> {
+    engine.lock().unwrap().get(&uuid.into_inner()).cloned().map(Json)
+}
+
+#[post("/<uuid>/acknowledge", format = "application/json")]
+fn acknowledge_alert(uuid: RocketUuid, engine: State<AlertEngineState>) -> Option<Json<Alert>> {
+    engine
+        .lock()
+        .unwrap()
+        .acknowledge(&uuid.into_inner(), clock::now())
+        .cloned()
+        .map(Json)
+}
+
+#[post("/<uuid>/resolve", format = "application/json")]
+fn resolve_alert(uuid: RocketUuid, engine: State<AlertEngineState>) -> Option<Json<Alert>> {
+    engine
+        .lock()
+        .unwrap()
+        .resolve(&uuid.into_inner(), clock::now())
+        .cloned()
+        .map(Json)
+}
+
+#[derive(Serialize, Deserialize)]
+struct AlertRules {
+    rules: Vec<AlertRule>,
+}
+
+#[get("/rules", format = "application/json")]
+fn get_alert_rules(engine: State<AlertEngineState>) -> Json<AlertRules> {
+    let rules = engine.lock().unwrap().rules().to_vec();
+    Json(AlertRules { rules })
+}
+
+#[put("/rules", format = "application/json", data = "<rules>")]
+fn put_alert_rules(
+    rules: UnitJson<AlertRules>,
+    engine: State<AlertEngineState>,
+) -> Result<Json<AlertRules>, status::Custom<String>> {
+    let rules = rules.into_inner().rules;
+    engine
+        .lock()
+        .unwrap()
+        .set_rules(rules.clone())
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    Ok(Json(AlertRules { rules }))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,161 @@
+use super::*;
+use alert::AlertEngine;
+use device::{Device, DeviceCollection, HealthPolicy};
+use fixtures::response_body_json;
+use rocket::http::{ContentType, Status};
+use rocket::local::Client;
+use std::sync::Mutex;
+use uuid::Uuid;
+use zone::ZoneCollection;
+
+fn create_client_with_mounts(engine: AlertEngine) -> Client {
+    let rocket = rocket::ignite();
+    let rocket = mount(rocket, AlertEngineState::new(Mutex::new(engine)));
+    Client::new(rocket).unwrap()
+}
+
+fn engine_with_low_battery_alert() -> (AlertEngine, Uuid) {
+    let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+    let mut device = Device::new("STRV".to_string(), None);
+    device.report(Some(5), None, None, clock::now());
+    let mut devices = DeviceCollection::new();
+    devices.add(device_uuid, device);
+
+    let mut engine = AlertEngine::new(AlertEngine::default_rules());
+    let changed = engine.evaluate(clock::now(), &ZoneCollection::new(), &devices, &HealthPolicy::default());
+    let alert_uuid = changed[0].0;
+    (engine, alert_uuid)
+}
+
+mod get_alerts {
+    use super::*;
+
+    #[test]
+    fn with_no_alerts_returns_empty_json_object_with_alerts_key() {
+        let client = create_client_with_mounts(AlertEngine::new(AlertEngine::default_rules()));
+
+        let mut response = client.get("/alerts").header(ContentType::JSON).dispatch();
+
+        assert_eq!(json!({ "alerts": {} }), response_body_json(&mut response));
+    }
+
+    #[test]
+    fn with_alert_returns_uuid_mapped_to_alert() {
+        let (engine, alert_uuid) = engine_with_low_battery_alert();
+        let client = create_client_with_mounts(engine);
+
+        let mut response = client.get("/alerts").header(ContentType::JSON).dispatch();
+        let body = response_body_json(&mut response);
+        let alert = &body["alerts"][alert_uuid.to_string()];
+
+        assert_eq!("low_battery", alert["kind"]);
+        assert_eq!("active", alert["state"]);
+        assert_eq!("STRV battery is at 5%", alert["message"]);
+    }
+
+    #[test]
+    fn query_by_state_only_returns_alerts_in_that_state() {
+        let (engine, _) = engine_with_low_battery_alert();
+        let client = create_client_with_mounts(engine);
+
+        let mut response = client
+            .get("/alerts?state=resolved")
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(json!({ "alerts": {} }), response_body_json(&mut response));
+    }
+}
+
+mod alert_lifecycle {
+    use super::*;
+
+    #[test]
+    fn acknowledge_moves_alert_to_acknowledged() {
+        let (engine, alert_uuid) = engine_with_low_battery_alert();
+        let client = create_client_with_mounts(engine);
+
+        let mut response = client
+            .post(format!("/alerts/{}/acknowledge", alert_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+        let body = response_body_json(&mut response);
+
+        assert_eq!("acknowledged", body["state"]);
+        assert!(body["acknowledged_at"].is_u64());
+    }
+
+    #[test]
+    fn resolve_moves_alert_to_resolved() {
+        let (engine, alert_uuid) = engine_with_low_battery_alert();
+        let client = create_client_with_mounts(engine);
+
+        client
+            .post(format!("/alerts/{}/resolve", alert_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+        let mut response = client
+            .get(format!("/alerts/{}", alert_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+        let body = response_body_json(&mut response);
+
+        assert_eq!("resolved", body["state"]);
+        assert!(body["resolved_at"].is_u64());
+    }
+
+    #[test]
+    fn acknowledge_unknown_alert_returns_404() {
+        let client = create_client_with_mounts(AlertEngine::new(AlertEngine::default_rules()));
+
+        let response = client
+            .post("/alerts/84fa1356-d5de-11e8-9f8b-f2801f1b9fd1/acknowledge")
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::NotFound, response.status());
+    }
+}
+
+mod alert_rules {
+    use super::*;
+
+    #[test]
+    fn get_returns_current_rules() {
+        let client = create_client_with_mounts(AlertEngine::new(vec![AlertRule::SetpointNotReached { hours: 4.0 }]));
+
+        let mut response = client.get("/alerts/rules").header(ContentType::JSON).dispatch();
+
+        let expected = json!({ "rules": [{ "kind": "setpoint_not_reached", "hours": 4.0 }] });
+        assert_eq!(expected, response_body_json(&mut response));
+    }
+
+    #[test]
+    fn put_replaces_rules() {
+        let client = create_client_with_mounts(AlertEngine::new(AlertEngine::default_rules()));
+        let rules = json!({ "rules": [{ "kind": "device_offline" }] });
+
+        client
+            .put("/alerts/rules")
+            .body(rules.to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+        let mut response = client.get("/alerts/rules").header(ContentType::JSON).dispatch();
+
+        assert_eq!(rules, response_body_json(&mut response));
+    }
+
+    #[test]
+    fn put_invalid_rule_returns_422() {
+        let client = create_client_with_mounts(AlertEngine::new(AlertEngine::default_rules()));
+        let rules = json!({ "rules": [{ "kind": "setpoint_not_reached", "hours": -1.0 }] });
+
+        let response = client
+            .put("/alerts/rules")
+            .body(rules.to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+}
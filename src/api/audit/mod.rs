@@ -0,0 +1,62 @@
+use audit::{AuditEntry, AuditLogState};
+use clock::Timestamp;
+use rocket::request::{self, Form, FromRequest, Request};
+use rocket::{Outcome, Rocket, State};
+use rocket_contrib::json::JsonValue;
+use rocket_contrib::uuid::Uuid as RocketUuid;
+use temperature::TemperatureUnit;
+
+pub fn mount(rocket: Rocket, audit: AuditLogState) -> Rocket {
+    rocket.mount("/audit", routes![get_audit]).manage(audit)
+}
+
+pub struct Actor(String);
+
+impl Actor {
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Actor {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Actor, ()> {
+        let actor = match request.headers().get_one("X-Actor") {
+            Some(actor) if !actor.trim().is_empty() => actor.trim().to_string(),
+            _ => request
+                .client_ip()
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+        };
+        Outcome::Success(Actor(actor))
+    }
+}
+
+#[derive(FromForm)]
+struct AuditQuery {
+    resource_uuid: Option<RocketUuid>,
+    actor: Option<String>,
+    since: Option<Timestamp>,
+    #[form(field = "unit")]
+    _unit: Option<TemperatureUnit>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.resource_uuid
+            .map_or(true, |uuid| uuid.into_inner() == entry.resource_uuid())
+            && self.actor.as_ref().map_or(true, |actor| actor == entry.actor())
+            && self.since.map_or(true, |since| entry.timestamp() >= since)
+    }
+}
+
+#[get("/?<query..>", format = "application/json")]
+fn get_audit(query: Form<AuditQuery>, audit: State<AuditLogState>) -> JsonValue {
+    let audit = audit.lock().unwrap();
+    let entries: Vec<&AuditEntry> = audit.entries().filter(|entry| query.matches(entry)).collect();
+    json!({ "entries": entries })
+}
+
+#[cfg(test)]
+mod tests;
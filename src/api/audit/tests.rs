@@ -0,0 +1,106 @@
+use super::*;
+use alert::{AlertEngine, AlertEngineState};
+use api::zones;
+use audit::{AuditLog, AuditRetention};
+use fixtures::response_body_json;
+use rocket::http::{ContentType, Header};
+use rocket::local::Client;
+use rule::{RuleEngine, RuleEngineState};
+use scene::{SceneCollection, SceneCollectionState};
+use std::sync::Mutex;
+use zone::{ZoneCollection, ZoneCollectionState};
+
+fn create_client_with_mounts() -> Client {
+    let rocket = rocket::ignite()
+        .manage(SceneCollectionState::new(Mutex::new(SceneCollection::new())))
+        .manage(RuleEngineState::new(Mutex::new(RuleEngine::new())))
+        .manage(AlertEngineState::new(Mutex::new(AlertEngine::new(AlertEngine::default_rules()))));
+    let rocket = mount(rocket, AuditLogState::new(Mutex::new(AuditLog::new(AuditRetention::default()))));
+    let rocket = zones::mount(rocket, ZoneCollectionState::new(Mutex::new(ZoneCollection::new())));
+    Client::new(rocket).unwrap()
+}
+
+fn post_zone(client: &Client, actor: &str) -> String {
+    let response = client
+        .post("/zones")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Actor", actor.to_string()))
+        .body(r#"{"name":"Kitchen","setpoint":18.0}"#)
+        .dispatch();
+    response.headers().get_one("Location").unwrap()["/zones/".len()..].to_string()
+}
+
+#[test]
+fn with_no_changes_returns_empty_entries() {
+    let client = create_client_with_mounts();
+
+    let mut response = client.get("/audit").header(ContentType::JSON).dispatch();
+
+    assert_eq!(json!({ "entries": [] }), response_body_json(&mut response));
+}
+
+#[test]
+fn records_zone_lifecycle_with_actor_and_diff() {
+    let client = create_client_with_mounts();
+    let zone_uuid = post_zone(&client, "phone");
+    client
+        .patch(format!("/zones/{}", zone_uuid))
+        .header(ContentType::JSON)
+        .header(Header::new("X-Actor", "thermostat"))
+        .body(r#"{"setpoint":21.5}"#)
+        .dispatch();
+    client
+        .delete(format!("/zones/{}", zone_uuid))
+        .header(ContentType::JSON)
+        .dispatch();
+
+    let mut response = client.get("/audit").header(ContentType::JSON).dispatch();
+    let body = response_body_json(&mut response);
+    let entries = body["entries"].as_array().unwrap();
+
+    assert_eq!(3, entries.len());
+    assert_eq!("create", entries[0]["action"]);
+    assert_eq!("phone", entries[0]["actor"]);
+    assert_eq!(zone_uuid, entries[0]["resource_uuid"]);
+    assert_eq!("update", entries[1]["action"]);
+    assert_eq!("thermostat", entries[1]["actor"]);
+    assert_eq!(1, entries[1]["changes"].as_object().unwrap().len());
+    assert_eq!(18.0, entries[1]["changes"]["setpoint"]["before"]);
+    assert_eq!(21.5, entries[1]["changes"]["setpoint"]["after"]);
+    assert_eq!("delete", entries[2]["action"]);
+    assert!(entries[2]["changes"]["name"]["after"].is_null());
+}
+
+#[test]
+fn query_filters_by_actor_and_resource() {
+    let client = create_client_with_mounts();
+    let kitchen_uuid = post_zone(&client, "phone");
+    post_zone(&client, "laptop");
+
+    let mut response = client.get("/audit?actor=laptop").header(ContentType::JSON).dispatch();
+    let body = response_body_json(&mut response);
+    assert_eq!(1, body["entries"].as_array().unwrap().len());
+    assert_eq!("laptop", body["entries"][0]["actor"]);
+
+    let mut response = client
+        .get(format!("/audit?resource_uuid={}", kitchen_uuid))
+        .header(ContentType::JSON)
+        .dispatch();
+    let body = response_body_json(&mut response);
+    assert_eq!(1, body["entries"].as_array().unwrap().len());
+    assert_eq!(kitchen_uuid, body["entries"][0]["resource_uuid"]);
+}
+
+#[test]
+fn actor_defaults_to_unknown_without_header_or_address() {
+    let client = create_client_with_mounts();
+    client
+        .post("/zones")
+        .header(ContentType::JSON)
+        .body(r#"{"name":"Kitchen","setpoint":18.0}"#)
+        .dispatch();
+
+    let mut response = client.get("/audit").header(ContentType::JSON).dispatch();
+
+    assert_eq!("unknown", response_body_json(&mut response)["entries"][0]["actor"]);
+}
@@ -0,0 +1,133 @@
+use alert::AlertEngineState;
+use api::audit::Actor;
+use api::devices::forget_device;
+use api::preferences::UnitJson;
+use audit::AuditLogState;
+use backup::{Backup, Home, RestoreMode};
+use calendar::CalendarState;
+use clock;
+use device::DeviceCollectionState;
+use energy::EnergyLedgerState;
+use hal::HalState;
+use presence::PresenceState;
+use rocket::http::{RawStr, Status};
+use rocket::request::FromFormValue;
+use rocket::response::status;
+use rocket::{Rocket, State};
+use rocket_contrib::json::Json;
+use rule::RuleEngineState;
+use scene::SceneCollectionState;
+use temperature::UnitPreferencesState;
+use weather::WeatherCompensationState;
+use zone::ZoneCollectionState;
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/", routes![get_backup, post_restore])
+}
+
+impl<'v> FromFormValue<'v> for RestoreMode {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<RestoreMode, &'v RawStr> {
+        match form_value.as_str() {
+            "replace" => Ok(RestoreMode::Replace),
+            "merge" => Ok(RestoreMode::Merge),
+            _ => Err(form_value),
+        }
+    }
+}
+
+#[get("/backup", format = "application/json")]
+fn get_backup(
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    scenes: State<SceneCollectionState>,
+    rules: State<RuleEngineState>,
+    presence: State<PresenceState>,
+    weather: State<WeatherCompensationState>,
+    calendar: State<CalendarState>,
+    energy: State<EnergyLedgerState>,
+    preferences: State<UnitPreferencesState>,
+    alerts: State<AlertEngineState>,
+) -> Json<Backup> {
+    let home = Home {
+        zones: &mut zones.lock().unwrap(),
+        devices: &mut devices.lock().unwrap(),
+        scenes: &mut scenes.lock().unwrap(),
+        rules: &mut rules.lock().unwrap(),
+        presence: &mut presence.lock().unwrap(),
+        weather: &mut weather.lock().unwrap(),
+        calendar: &mut calendar.lock().unwrap(),
+        energy: &mut energy.lock().unwrap(),
+        preferences: &mut preferences.lock().unwrap(),
+        alerts: &mut alerts.lock().unwrap(),
+    };
+    Json(Backup::new(clock::now(), &home))
+}
+
+#[post("/restore?<mode>", format = "application/json", data = "<backup>")]
+fn post_restore(
+    backup: UnitJson<Backup>,
+    mode: Option<RestoreMode>,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    scenes: State<SceneCollectionState>,
+    rules: State<RuleEngineState>,
+    presence: State<PresenceState>,
+    weather: State<WeatherCompensationState>,
+    calendar: State<CalendarState>,
+    energy: State<EnergyLedgerState>,
+    preferences: State<UnitPreferencesState>,
+    alerts: State<AlertEngineState>,
+    hal: State<HalState>,
+    audit: State<AuditLogState>,
+) -> Result<Status, status::Custom<String>> {
+    let now = clock::now();
+    let mut zones = zones.lock().unwrap();
+    let mut devices = devices.lock().unwrap();
+    let mut scenes = scenes.lock().unwrap();
+    let mut rules = rules.lock().unwrap();
+    let mut presence = presence.lock().unwrap();
+    let mut weather = weather.lock().unwrap();
+    let mut calendar = calendar.lock().unwrap();
+    let mut energy = energy.lock().unwrap();
+    let mut preferences = preferences.lock().unwrap();
+    let mut alerts = alerts.lock().unwrap();
+    let (zones_before, devices_before) = (zones.clone(), devices.clone());
+
+    let mut home = Home {
+        zones: &mut zones,
+        devices: &mut devices,
+        scenes: &mut scenes,
+        rules: &mut rules,
+        presence: &mut presence,
+        weather: &mut weather,
+        calendar: &mut calendar,
+        energy: &mut energy,
+        preferences: &mut preferences,
+        alerts: &mut alerts,
+    };
+    backup
+        .into_inner()
+        .restore(now, mode.unwrap_or(RestoreMode::Replace), &mut home)
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+
+    let (removed_zones, removed_devices) = audit.lock().unwrap().record_changes(
+        now,
+        actor.name(),
+        (&zones_before, &devices_before),
+        (&*zones, &*devices),
+    );
+    for uuid in removed_zones {
+        alerts.forget_subject(&uuid);
+    }
+    for uuid in removed_devices {
+        forget_device(&uuid, &hal, &mut alerts);
+    }
+
+    Ok(Status::NoContent)
+}
+
+#[cfg(test)]
+mod tests;
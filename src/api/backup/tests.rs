@@ -0,0 +1,160 @@
+use super::*;
+use alert::AlertEngine;
+use api::audit::mount as mount_audit;
+use audit::{AuditLog, AuditRetention};
+use calendar::{Calendar, CalendarConfig};
+use device::{Device, DeviceCollection};
+use energy::{EnergyLedger, Tariff};
+use fixtures::response_body_json;
+use hal::Hal;
+use presence::{GracePeriods, Presence};
+use rocket::http::ContentType;
+use rocket::local::Client;
+use rocket_contrib::json::JsonValue;
+use rule::RuleEngine;
+use scene::SceneCollection;
+use std::sync::Mutex;
+use temperature::UnitPreferences;
+use uuid::Uuid;
+use weather::{CompensationCurve, WeatherCompensation};
+use zone::{Zone, ZoneCollection};
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+const DEVICE_UUID: &str = "84fa1356-d5de-11e8-9f8b-f2801f1b9fd1";
+
+fn create_client_with_mounts(zones: ZoneCollection, devices: DeviceCollection) -> Client {
+    let rocket = rocket::ignite()
+        .manage(ZoneCollectionState::new(Mutex::new(zones)))
+        .manage(DeviceCollectionState::new(Mutex::new(devices)))
+        .manage(SceneCollectionState::new(Mutex::new(SceneCollection::new())))
+        .manage(RuleEngineState::new(Mutex::new(RuleEngine::new())))
+        .manage(PresenceState::new(Mutex::new(Presence::new(GracePeriods::default()))))
+        .manage(WeatherCompensationState::new(Mutex::new(WeatherCompensation::new(
+            CompensationCurve::default(),
+        ))))
+        .manage(CalendarState::new(Mutex::new(Calendar::new(CalendarConfig::default()))))
+        .manage(EnergyLedgerState::new(Mutex::new(EnergyLedger::new(Tariff::default()))))
+        .manage(UnitPreferencesState::new(Mutex::new(UnitPreferences::new())))
+        .manage(HalState::new(Hal::new()))
+        .manage(AlertEngineState::new(Mutex::new(AlertEngine::new(AlertEngine::default_rules()))));
+    let rocket = mount_audit(rocket, AuditLogState::new(Mutex::new(AuditLog::new(AuditRetention::default()))));
+    let rocket = mount(rocket);
+    Client::new(rocket).unwrap()
+}
+
+fn create_client_with_home() -> Client {
+    let zone_uuid = Uuid::parse_str(ZONE_UUID).unwrap();
+    let mut zones = ZoneCollection::new();
+    zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+    let mut devices = DeviceCollection::new();
+    devices.add(
+        Uuid::parse_str(DEVICE_UUID).unwrap(),
+        Device::new("STRV".to_string(), Some(zone_uuid)),
+    );
+    create_client_with_mounts(zones, devices)
+}
+
+fn create_empty_client() -> Client {
+    create_client_with_mounts(ZoneCollection::new(), DeviceCollection::new())
+}
+
+fn get_backup_string(client: &Client) -> String {
+    let mut response = client.get("/backup").header(ContentType::JSON).dispatch();
+    response.body_string().unwrap()
+}
+
+#[test]
+fn backup_contains_the_whole_configuration() {
+    let client = create_client_with_home();
+
+    let backup: JsonValue = serde_json::from_str(&get_backup_string(&client)).unwrap();
+
+    assert_eq!(2, backup["format_version"]);
+    assert_eq!("Living Room", backup["zones"][ZONE_UUID]["name"]);
+    assert_eq!(ZONE_UUID, backup["devices"][DEVICE_UUID]["zone_uuid"]);
+    assert_eq!(4, backup["alert_rules"].as_array().unwrap().len());
+    for key in &["scenes", "rules", "presence_zones", "unit_preferences"] {
+        assert_eq!(json!({}), JsonValue(backup[*key].clone()), "{}", key);
+    }
+    assert_eq!(70.0, backup["compensation_curve"]["max_flow"]);
+    assert!(backup["tariff"]["price_per_kwh"].is_number());
+}
+
+#[test]
+fn restore_on_another_hub_recreates_everything_with_the_same_uuids() {
+    let backup = get_backup_string(&create_client_with_home());
+    let client = create_empty_client();
+
+    let response = client
+        .post("/restore")
+        .header(ContentType::JSON)
+        .body(&backup)
+        .dispatch();
+
+    assert_eq!(Status::NoContent, response.status());
+    let restored: JsonValue = serde_json::from_str(&get_backup_string(&client)).unwrap();
+    let original: JsonValue = serde_json::from_str(&backup).unwrap();
+    assert_eq!(original["zones"], restored["zones"]);
+    assert_eq!(original["devices"], restored["devices"]);
+}
+
+#[test]
+fn restore_is_recorded_in_the_audit_log() {
+    let backup = get_backup_string(&create_client_with_home());
+    let client = create_empty_client();
+
+    client
+        .post("/restore")
+        .header(ContentType::JSON)
+        .body(&backup)
+        .dispatch();
+
+    let mut response = client.get("/audit").header(ContentType::JSON).dispatch();
+    let body = response_body_json(&mut response);
+    let entries = body["entries"].as_array().unwrap();
+    assert_eq!(2, entries.len());
+    assert!(entries.iter().all(|entry| entry["action"] == "create"));
+}
+
+#[test]
+fn merge_keeps_existing_zones_and_replace_removes_them() {
+    let client = create_client_with_home();
+    let empty_backup = get_backup_string(&create_empty_client());
+
+    client
+        .post("/restore?mode=merge")
+        .header(ContentType::JSON)
+        .body(&empty_backup)
+        .dispatch();
+    let backup: JsonValue = serde_json::from_str(&get_backup_string(&client)).unwrap();
+    assert!(backup["zones"].get(ZONE_UUID).is_some());
+
+    client
+        .post("/restore?mode=replace")
+        .header(ContentType::JSON)
+        .body(&empty_backup)
+        .dispatch();
+    let backup: JsonValue = serde_json::from_str(&get_backup_string(&client)).unwrap();
+    assert_eq!(json!({}), JsonValue(backup["zones"].clone()));
+    assert_eq!(json!({}), JsonValue(backup["devices"].clone()));
+}
+
+#[test]
+fn invalid_backup_returns_422_and_changes_nothing() {
+    let client = create_client_with_home();
+    let before = get_backup_string(&client);
+    let mut backup: JsonValue = serde_json::from_str(&before).unwrap();
+    backup["zones"] = json!({}).into_inner();
+
+    let response = client
+        .post("/restore")
+        .header(ContentType::JSON)
+        .body(backup.to_string())
+        .dispatch();
+
+    assert_eq!(Status::UnprocessableEntity, response.status());
+    let after: JsonValue = serde_json::from_str(&get_backup_string(&client)).unwrap();
+    let before: JsonValue = serde_json::from_str(&before).unwrap();
+    assert_eq!(before["zones"], after["zones"]);
+    assert_eq!(before["devices"], after["devices"]);
+}
@@ -0,0 +1,254 @@
+use alert::AlertEngineState;
+use api::audit::Actor;
+use api::devices::{check_unused, forget_device, patch_device_with_json};
+use api::preferences::UnitJson;
+use api::zones::{check_unused as check_zone_unused, patch_zone_with_json};
+use audit::{Action, AuditLogState, ResourceType};
+use clock;
+use device::{Device, DeviceCollection, DeviceCollectionState};
+use hal::HalState;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Rocket, State};
+use rocket_contrib::json::{Json, JsonValue};
+use rule::{RuleEngine, RuleEngineState};
+use scene::{SceneCollection, SceneCollectionState};
+use serde::Serialize;
+use serde_json::Value;
+use std::mem;
+use uuid::Uuid;
+use zone::{Zone, ZoneCollection, ZoneCollectionState};
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/batch", routes![post_batch])
+}
+
+#[derive(Deserialize)]
+struct Operation {
+    action: Action,
+    resource_type: ResourceType,
+    #[serde(default)]
+    uuid: Option<Uuid>,
+    #[serde(default)]
+    body: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Batch {
+    operations: Vec<Operation>,
+}
+
+#[derive(Serialize)]
+struct OperationResult {
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl OperationResult {
+    fn new<T: Serialize>(status: Status, uuid: Uuid, body: Option<&T>) -> OperationResult {
+        OperationResult {
+            status: status.code,
+            uuid: Some(uuid),
+            body: body.and_then(|body| serde_json::to_value(body).ok()),
+            error: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BatchResults {
+    results: Vec<OperationResult>,
+}
+
+type OperationError = (Status, String);
+
+fn required_uuid(operation: &Operation) -> Result<Uuid, OperationError> {
+    operation
+        .uuid
+        .ok_or_else(|| (Status::BadRequest, "updates and deletes need a uuid".to_string()))
+}
+
+fn object_body(operation: &Operation) -> Result<&Value, OperationError> {
+    match operation.body {
+        Some(ref body) if body.is_object() => Ok(body),
+        _ => Err((Status::UnprocessableEntity, "body must be a JSON object".to_string())),
+    }
+}
+
+fn new_uuid(operation: &Operation, exists: impl Fn(&Uuid) -> bool) -> Result<Uuid, OperationError> {
+    let uuid = operation.uuid.unwrap_or_else(Uuid::new_v4);
+    if exists(&uuid) {
+        return Err((Status::Conflict, format!("{} already exists", uuid)));
+    }
+    Ok(uuid)
+}
+
+fn check_zone_exists(zone_uuid: Uuid, zones: &ZoneCollection) -> Result<(), OperationError> {
+    match zones.get(&zone_uuid) {
+        Some(_) => Ok(()),
+        None => Err((Status::UnprocessableEntity, format!("zone {} does not exist", zone_uuid))),
+    }
+}
+
+fn apply_to_zone(
+    operation: &Operation,
+    zones: &mut ZoneCollection,
+    scenes: &SceneCollection,
+    rules: &RuleEngine,
+) -> Result<OperationResult, OperationError> {
+    match operation.action {
+        Action::Create => {
+            let zone: Zone = serde_json::from_value(object_body(operation)?.clone())
+                .map_err(|error| (Status::UnprocessableEntity, error.to_string()))?;
+            let uuid = new_uuid(operation, |uuid| zones.get(uuid).is_some())?;
+            zones
+                .check_parent(&uuid, &zone)
+                .map_err(|error| (Status::UnprocessableEntity, error))?;
+            zones.add(uuid, zone);
+            Ok(OperationResult::new(Status::Created, uuid, zones.get(&uuid)))
+        }
+        Action::Update => {
+            let uuid = required_uuid(operation)?;
+            let patch = JsonValue::from(object_body(operation)?.clone());
+            let mut zone = zones
+                .get(&uuid)
+                .cloned()
+                .ok_or_else(|| (Status::NotFound, format!("zone {} does not exist", uuid)))?;
+            patch_zone_with_json(&mut zone, &patch)
+                .and_then(|_| zones.check_parent(&uuid, &zone))
+                .map_err(|error| (Status::UnprocessableEntity, error))?;
+            zones.add(uuid, zone);
+            Ok(OperationResult::new(Status::Ok, uuid, zones.get(&uuid)))
+        }
+        Action::Delete => {
+            let uuid = required_uuid(operation)?;
+            if zones.get(&uuid).is_some() {
+                check_zone_unused(&uuid, scenes, rules).map_err(|error| (Status::Conflict, error))?;
+            }
+            zones.remove(&uuid);
+            Ok(OperationResult::new::<Zone>(Status::NoContent, uuid, None))
+        }
+    }
+}
+
+fn apply_to_device(
+    operation: &Operation,
+    zones: &ZoneCollection,
+    devices: &mut DeviceCollection,
+    scenes: &SceneCollection,
+    rules: &RuleEngine,
+) -> Result<OperationResult, OperationError> {
+    match operation.action {
+        Action::Create => {
+            let mut device: Device = serde_json::from_value(object_body(operation)?.clone())
+                .map_err(|error| (Status::UnprocessableEntity, error.to_string()))?;
+            device
+                .validate()
+                .map_err(|error| (Status::UnprocessableEntity, error))?;
+            if let Some(zone_uuid) = device.zone_uuid() {
+                check_zone_exists(zone_uuid, zones)?;
+            }
+            let uuid = new_uuid(operation, |uuid| devices.get(uuid).is_some())?;
+            devices.restore_from_tombstone(&mut device);
+            devices.add(uuid, device.clone());
+            Ok(OperationResult::new(Status::Created, uuid, Some(&device)))
+        }
+        Action::Update => {
+            let uuid = required_uuid(operation)?;
+            let body = object_body(operation)?;
+            if body.get("demand").is_some() {
+                let error = "demand cannot be set in a batch, as switching a relay cannot be undone";
+                return Err((Status::UnprocessableEntity, error.to_string()));
+            }
+            match body.get("zone_uuid") {
+                Some(Value::Null) | None => {}
+                Some(zone_uuid) => {
+                    let zone_uuid = zone_uuid
+                        .as_str()
+                        .and_then(|zone_uuid| Uuid::parse_str(zone_uuid).ok())
+                        .ok_or_else(|| (Status::UnprocessableEntity, "zone_uuid must be a UUID or null".to_string()))?;
+                    check_zone_exists(zone_uuid, zones)?;
+                }
+            }
+
+            let device = devices
+                .get_mut(&uuid)
+                .ok_or_else(|| (Status::NotFound, format!("device {} does not exist", uuid)))?;
+            patch_device_with_json(device, &JsonValue::from(body.clone()))
+                .map_err(|error| (Status::UnprocessableEntity, error))?;
+            Ok(OperationResult::new(Status::Ok, uuid, Some(&*device)))
+        }
+        Action::Delete => {
+            let uuid = required_uuid(operation)?;
+            if devices.get(&uuid).is_some() {
+                check_unused(&uuid, scenes, rules).map_err(|error| (Status::Conflict, error))?;
+            }
+            devices.remove(&uuid, false);
+            Ok(OperationResult::new::<Device>(Status::NoContent, uuid, None))
+        }
+    }
+}
+
+#[post("/", format = "application/json", data = "<batch>")]
+fn post_batch(
+    batch: UnitJson<Batch>,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    scenes: State<SceneCollectionState>,
+    rules: State<RuleEngineState>,
+    hal: State<HalState>,
+    alerts: State<AlertEngineState>,
+    audit: State<AuditLogState>,
+) -> status::Custom<Json<BatchResults>> {
+    let mut zones = zones.lock().unwrap();
+    let mut devices = devices.lock().unwrap();
+    let (mut new_zones, mut new_devices) = (zones.clone(), devices.clone());
+    let (scenes, rules) = (scenes.lock().unwrap(), rules.lock().unwrap());
+
+    let mut results = Vec::new();
+    for operation in &batch.operations {
+        let result = match operation.resource_type {
+            ResourceType::Zone => apply_to_zone(operation, &mut new_zones, &scenes, &rules),
+            ResourceType::Device => apply_to_device(operation, &new_zones, &mut new_devices, &scenes, &rules),
+        };
+        match result {
+            Ok(result) => results.push(result),
+            Err((status, error)) => {
+                results.push(OperationResult {
+                    status: status.code,
+                    uuid: operation.uuid,
+                    body: None,
+                    error: Some(error),
+                });
+                return status::Custom(status, Json(BatchResults { results }));
+            }
+        }
+    }
+
+    let zones_before = mem::replace(&mut *zones, new_zones);
+    let devices_before = mem::replace(&mut *devices, new_devices);
+    let mut alerts = alerts.lock().unwrap();
+    let (removed_zones, removed_devices) = audit.lock().unwrap().record_changes(
+        clock::now(),
+        actor.name(),
+        (&zones_before, &devices_before),
+        (&*zones, &*devices),
+    );
+    for uuid in removed_zones {
+        alerts.forget_subject(&uuid);
+    }
+    for uuid in removed_devices {
+        forget_device(&uuid, &hal, &mut alerts);
+    }
+
+    status::Custom(Status::Ok, Json(BatchResults { results }))
+}
+
+#[cfg(test)]
+mod tests;
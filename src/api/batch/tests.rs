@@ -0,0 +1,213 @@
+use super::*;
+use alert::AlertEngine;
+use api::audit::mount as mount_audit;
+use audit::{AuditLog, AuditRetention};
+use fixtures::response_body_json;
+use hal::Hal;
+use rocket::http::ContentType;
+use rocket::local::{Client, LocalResponse};
+use std::sync::{Arc, Mutex};
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+const NEW_ZONE_UUID: &str = "56fd8e82-d260-46df-809f-662f070e9e1e";
+const VALVE_UUID: &str = "84fa1356-d5de-11e8-9f8b-f2801f1b9fd1";
+const SENSOR_UUID: &str = "88f573e2-d5de-11e8-9f8b-f2801f1b9fd1";
+
+fn create_client_with_home() -> (Client, ZoneCollectionState, DeviceCollectionState) {
+    let zone_uuid = Uuid::parse_str(ZONE_UUID).unwrap();
+    let mut zones = ZoneCollection::new();
+    zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+    let mut devices = DeviceCollection::new();
+    devices.add(
+        Uuid::parse_str(VALVE_UUID).unwrap(),
+        Device::new("STRV".to_string(), Some(zone_uuid)),
+    );
+    devices.add(
+        Uuid::parse_str(SENSOR_UUID).unwrap(),
+        Device::new("Sensor".to_string(), Some(zone_uuid)),
+    );
+    let mut rules = RuleEngine::new();
+    let boost = json!({
+        "name": "Shower boost",
+        "trigger": { "type": "event", "name": "shower" },
+        "actions": [
+            { "type": "set_setpoint", "subject_uuid": VALVE_UUID, "setpoint": 24.0 },
+            { "type": "set_setpoint", "subject_uuid": ZONE_UUID, "setpoint": 22.0 }
+        ]
+    });
+    rules.add(Uuid::new_v4(), serde_json::from_value(boost.into_inner()).unwrap()).unwrap();
+    let zones = Arc::new(Mutex::new(zones));
+    let devices = Arc::new(Mutex::new(devices));
+
+    let rocket = rocket::ignite()
+        .manage(zones.clone())
+        .manage(devices.clone())
+        .manage(SceneCollectionState::new(Mutex::new(SceneCollection::new())))
+        .manage(RuleEngineState::new(Mutex::new(rules)))
+        .manage(HalState::new(Hal::new()))
+        .manage(AlertEngineState::new(Mutex::new(AlertEngine::new(AlertEngine::default_rules()))));
+    let rocket = mount_audit(rocket, AuditLogState::new(Mutex::new(AuditLog::new(AuditRetention::default()))));
+    let rocket = mount(rocket);
+    (Client::new(rocket).unwrap(), zones, devices)
+}
+
+fn dispatch_batch<'c>(client: &'c Client, operations: JsonValue) -> LocalResponse<'c> {
+    client
+        .post("/batch")
+        .header(ContentType::JSON)
+        .body(json!({ "operations": operations }).to_string())
+        .dispatch()
+}
+
+#[test]
+fn creates_zone_and_moves_devices_into_it() {
+    let (client, zones, devices) = create_client_with_home();
+
+    let mut response = dispatch_batch(
+        &client,
+        json!([
+            { "action": "create", "resource_type": "zone", "uuid": NEW_ZONE_UUID,
+              "body": { "name": "Bedroom", "setpoint": 18.0 } },
+            { "action": "update", "resource_type": "device", "uuid": VALVE_UUID,
+              "body": { "zone_uuid": NEW_ZONE_UUID } },
+            { "action": "update", "resource_type": "device", "uuid": SENSOR_UUID,
+              "body": { "zone_uuid": NEW_ZONE_UUID } }
+        ]),
+    );
+
+    assert_eq!(Status::Ok, response.status());
+    let body = response_body_json(&mut response);
+    assert_eq!(201, body["results"][0]["status"]);
+    assert_eq!(200, body["results"][1]["status"]);
+    assert_eq!(NEW_ZONE_UUID, body["results"][2]["body"]["zone_uuid"]);
+
+    let new_zone_uuid = Uuid::parse_str(NEW_ZONE_UUID).unwrap();
+    assert_eq!("Bedroom", zones.lock().unwrap().get(&new_zone_uuid).unwrap().name());
+    assert_eq!(2, devices.lock().unwrap().get_all_with_zone(Some(new_zone_uuid)).iter().count());
+}
+
+#[test]
+fn failing_operation_changes_nothing() {
+    let (client, zones, devices) = create_client_with_home();
+
+    let mut response = dispatch_batch(
+        &client,
+        json!([
+            { "action": "delete", "resource_type": "device", "uuid": SENSOR_UUID },
+            { "action": "update", "resource_type": "zone", "uuid": NEW_ZONE_UUID, "body": { "setpoint": 21.0 } }
+        ]),
+    );
+
+    assert_eq!(Status::NotFound, response.status());
+    let body = response_body_json(&mut response);
+    assert_eq!(2, body["results"].as_array().unwrap().len());
+    assert_eq!(404, body["results"][1]["status"]);
+    assert!(body["results"][1]["error"].is_string());
+
+    let sensor_uuid = Uuid::parse_str(SENSOR_UUID).unwrap();
+    assert!(devices.lock().unwrap().get(&sensor_uuid).is_some());
+    assert_eq!(None, zones.lock().unwrap().get(&Uuid::parse_str(NEW_ZONE_UUID).unwrap()));
+}
+
+#[test]
+fn moving_device_to_missing_zone_is_rejected() {
+    let (client, _, devices) = create_client_with_home();
+
+    let response = dispatch_batch(
+        &client,
+        json!([{ "action": "update", "resource_type": "device", "uuid": VALVE_UUID,
+                 "body": { "zone_uuid": NEW_ZONE_UUID } }]),
+    );
+
+    assert_eq!(Status::UnprocessableEntity, response.status());
+    let valve = devices.lock().unwrap().get(&Uuid::parse_str(VALVE_UUID).unwrap()).cloned().unwrap();
+    assert_eq!(Some(Uuid::parse_str(ZONE_UUID).unwrap()), valve.zone_uuid());
+}
+
+#[test]
+fn moving_device_to_invalid_zone_uuid_is_rejected() {
+    let (client, _, devices) = create_client_with_home();
+
+    let response = dispatch_batch(
+        &client,
+        json!([{ "action": "update", "resource_type": "device", "uuid": VALVE_UUID,
+                 "body": { "zone_uuid": "not-a-uuid" } }]),
+    );
+
+    assert_eq!(Status::UnprocessableEntity, response.status());
+    let valve = devices.lock().unwrap().get(&Uuid::parse_str(VALVE_UUID).unwrap()).cloned().unwrap();
+    assert_eq!(Some(Uuid::parse_str(ZONE_UUID).unwrap()), valve.zone_uuid());
+}
+
+#[test]
+fn deleting_device_used_by_rule_is_a_conflict() {
+    let (client, _, devices) = create_client_with_home();
+
+    let response = dispatch_batch(
+        &client,
+        json!([{ "action": "delete", "resource_type": "device", "uuid": VALVE_UUID }]),
+    );
+
+    assert_eq!(Status::Conflict, response.status());
+    assert!(devices.lock().unwrap().get(&Uuid::parse_str(VALVE_UUID).unwrap()).is_some());
+}
+
+#[test]
+fn deleting_zone_used_by_rule_is_a_conflict() {
+    let (client, zones, _) = create_client_with_home();
+
+    let response = dispatch_batch(
+        &client,
+        json!([{ "action": "delete", "resource_type": "zone", "uuid": ZONE_UUID }]),
+    );
+
+    assert_eq!(Status::Conflict, response.status());
+    assert!(zones.lock().unwrap().get(&Uuid::parse_str(ZONE_UUID).unwrap()).is_some());
+}
+
+#[test]
+fn create_with_existing_uuid_is_a_conflict() {
+    let (client, _, _) = create_client_with_home();
+
+    let response = dispatch_batch(
+        &client,
+        json!([{ "action": "create", "resource_type": "zone", "uuid": ZONE_UUID, "body": { "name": "Attic" } }]),
+    );
+
+    assert_eq!(Status::Conflict, response.status());
+}
+
+#[test]
+fn demand_cannot_be_set_in_a_batch() {
+    let (client, _, _) = create_client_with_home();
+
+    let response = dispatch_batch(
+        &client,
+        json!([{ "action": "update", "resource_type": "device", "uuid": VALVE_UUID, "body": { "demand": true } }]),
+    );
+
+    assert_eq!(Status::UnprocessableEntity, response.status());
+}
+
+#[test]
+fn every_change_is_recorded_in_the_audit_log() {
+    let (client, _, _) = create_client_with_home();
+
+    dispatch_batch(
+        &client,
+        json!([
+            { "action": "update", "resource_type": "device", "uuid": VALVE_UUID, "body": { "name": "Radiator" } },
+            { "action": "delete", "resource_type": "device", "uuid": SENSOR_UUID }
+        ]),
+    );
+
+    let mut response = client.get("/audit").header(ContentType::JSON).dispatch();
+    let body = response_body_json(&mut response);
+    let actions: Vec<&str> = body["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(vec!["update", "delete"], actions);
+}
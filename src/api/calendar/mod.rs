@@ -0,0 +1,111 @@
+use api::audit::Actor;
+use audit::AuditLogState;
+use calendar::{Calendar, CalendarState, CalendarStatus, SIZE_LIMIT};
+use clock::{self, Timestamp};
+use device::{DeviceCollection, DeviceCollectionState};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Data, Rocket, State};
+use rocket_contrib::json::Json;
+use std::io::Read;
+use zone::{ZoneCollection, ZoneCollectionState};
+
+pub fn mount(rocket: Rocket, calendar: CalendarState) -> Rocket {
+    rocket
+        .mount("/calendar", routes![get_calendar, put_calendar, put_calendar_file, delete_calendar])
+        .manage(calendar)
+}
+
+#[derive(Deserialize)]
+struct CalendarFile {
+    path: String,
+}
+
+fn update(
+    now: Timestamp,
+    actor: &Actor,
+    zones: &mut ZoneCollection,
+    devices: &DeviceCollection,
+    calendar: &mut Calendar,
+    audit: &AuditLogState,
+) {
+    let zones_before = zones.clone();
+    if calendar.update(now, zones) {
+        audit
+            .lock()
+            .unwrap()
+            .record_changes(now, actor.name(), (&zones_before, devices), (&*zones, devices));
+    }
+}
+
+#[get("/", format = "application/json")]
+fn get_calendar(calendar: State<CalendarState>) -> Json<CalendarStatus> {
+    Json(calendar.lock().unwrap().status(clock::now()))
+}
+
+#[put("/", format = "text/calendar", data = "<ics>")]
+fn put_calendar(
+    ics: Data,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    calendar: State<CalendarState>,
+    audit: State<AuditLogState>,
+) -> Result<Json<CalendarStatus>, status::Custom<String>> {
+    let mut body = String::new();
+    ics.open()
+        .take(SIZE_LIMIT)
+        .read_to_string(&mut body)
+        .map_err(|error| status::Custom(Status::BadRequest, error.to_string()))?;
+
+    let now = clock::now();
+    let mut zones = zones.lock().unwrap();
+    let devices = devices.lock().unwrap();
+    let mut calendar = calendar.lock().unwrap();
+    calendar
+        .upload(now, &body)
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    update(now, &actor, &mut zones, &devices, &mut calendar, &audit);
+    Ok(Json(calendar.status(now)))
+}
+
+#[put("/file", format = "application/json", data = "<file>")]
+fn put_calendar_file(
+    file: Json<CalendarFile>,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    calendar: State<CalendarState>,
+    audit: State<AuditLogState>,
+) -> Result<Json<CalendarStatus>, status::Custom<String>> {
+    let now = clock::now();
+    let mut zones = zones.lock().unwrap();
+    let devices = devices.lock().unwrap();
+    let mut calendar = calendar.lock().unwrap();
+    calendar
+        .file_in_directory(&file.path)
+        .and_then(|path| calendar.watch_file(now, &path))
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    update(now, &actor, &mut zones, &devices, &mut calendar, &audit);
+    Ok(Json(calendar.status(now)))
+}
+
+#[delete("/", format = "application/json")]
+fn delete_calendar(
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    calendar: State<CalendarState>,
+    audit: State<AuditLogState>,
+) -> Status {
+    let now = clock::now();
+    let mut zones = zones.lock().unwrap();
+    let devices = devices.lock().unwrap();
+    let mut calendar = calendar.lock().unwrap();
+    calendar.clear();
+    update(now, &actor, &mut zones, &devices, &mut calendar, &audit);
+    Status::NoContent
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,140 @@
+use super::*;
+use calendar::CalendarConfig;
+use fixtures::{self, response_body_json, Home};
+use rocket::http::ContentType;
+use rocket::local::{Client, LocalResponse};
+use rocket_contrib::json::JsonValue;
+use std::env;
+use std::fs;
+use std::sync::Mutex;
+use uuid::Uuid;
+use zone::Zone;
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+const AWAY: &str = "BEGIN:VCALENDAR\r\n\
+    BEGIN:VEVENT\r\n\
+    UID:away@family\r\n\
+    SUMMARY:Away\r\n\
+    DTSTART;VALUE=DATE:20000101\r\n\
+    DTEND;VALUE=DATE:21000101\r\n\
+    END:VEVENT\r\n\
+    END:VCALENDAR\r\n";
+
+fn create_home() -> Home {
+    let mut zones = ZoneCollection::new();
+    zones.add(Uuid::parse_str(ZONE_UUID).unwrap(), Zone::new("Living Room".to_string()));
+    let config = CalendarConfig {
+        directory: env::temp_dir().to_str().map(str::to_string),
+        ..CalendarConfig::default()
+    };
+
+    fixtures::create_home(zones, DeviceCollection::new(), |rocket, zones, devices| {
+        let rocket = rocket.manage(zones).manage(devices);
+        mount(rocket, CalendarState::new(Mutex::new(Calendar::new(config))))
+    })
+}
+
+fn living_room_setpoint(home: &Home) -> f64 {
+    let zones = home.zones.lock().unwrap();
+    zones.get(&Uuid::parse_str(ZONE_UUID).unwrap()).unwrap().setpoint().celsius()
+}
+
+fn upload<'c>(client: &'c Client, ics: &str) -> LocalResponse<'c> {
+    client
+        .put("/calendar")
+        .header(ContentType::new("text", "calendar"))
+        .body(ics)
+        .dispatch()
+}
+
+mod upload_calendar {
+    use super::*;
+
+    #[test]
+    fn applies_events_that_are_on_and_records_them() {
+        let home = create_home();
+        let client = &home.client;
+
+        let mut response = upload(client, AWAY);
+
+        assert_eq!(Status::Ok, response.status());
+        let calendar = response_body_json(&mut response);
+        assert_eq!("away", calendar["events"][0]["effect"]["type"]);
+        assert_eq!("away@family", calendar["active"][0]);
+        assert_eq!(Some(12.0), Some(living_room_setpoint(&home)));
+        let mut response = client.get("/audit").header(ContentType::JSON).dispatch();
+        assert_eq!(1, response_body_json(&mut response)["entries"].as_array().unwrap().len());
+    }
+
+    #[test]
+    fn file_that_is_not_a_calendar_returns_422() {
+        let home = create_home();
+
+        let response = upload(&home.client, "SUMMARY:Away");
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn delete_puts_back_setpoints() {
+        let home = create_home();
+        let client = &home.client;
+        upload(client, AWAY);
+
+        let response = client.delete("/calendar").header(ContentType::JSON).dispatch();
+
+        assert_eq!(Status::NoContent, response.status());
+        assert_eq!(Some(16.0), Some(living_room_setpoint(&home)));
+        let mut response = client.get("/calendar").header(ContentType::JSON).dispatch();
+        assert!(response_body_json(&mut response)["events"].as_array().unwrap().is_empty());
+    }
+}
+
+mod calendar_file {
+    use super::*;
+
+    fn put_file<'c>(client: &'c Client, path: &str) -> LocalResponse<'c> {
+        client
+            .put("/calendar/file")
+            .header(ContentType::JSON)
+            .body(json!({ "path": path }).to_string())
+            .dispatch()
+    }
+
+    #[test]
+    fn reads_events_from_file() {
+        let home = create_home();
+        let path = env::temp_dir().join(format!("family-{}.ics", std::process::id()));
+        fs::write(&path, AWAY).unwrap();
+
+        let mut response = put_file(&home.client, path.to_str().unwrap());
+
+        let path = fs::canonicalize(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(path.to_str().unwrap(), response_body_json(&mut response)["file"]);
+        assert_eq!(Some(12.0), Some(living_room_setpoint(&home)));
+    }
+
+    #[test]
+    fn missing_file_returns_422() {
+        let home = create_home();
+
+        let response = put_file(&home.client, "/nonexistent/family.ics");
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn file_outside_the_directory_returns_422_like_a_missing_file() {
+        let home = create_home();
+
+        let mut response = put_file(&home.client, "/etc/hostname");
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+        assert_eq!(
+            Some("/etc/hostname is not a file in the calendar directory".to_string()),
+            response.body_string()
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, status, Responder, Response};
+use rocket::Outcome;
+
+fn etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+fn matches(tags: &str, version: u64, weak: bool) -> bool {
+    let etag = etag(version);
+    tags.split(',').map(str::trim).any(|tag| {
+        tag == "*" || tag == etag || (weak && tag.starts_with("W/") && tag[2..] == etag[..])
+    })
+}
+
+pub enum Versioned<R> {
+    Body(R, u64),
+    NotModified(u64),
+}
+
+impl<'r, R: Responder<'r>> Responder<'r> for Versioned<R> {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        match self {
+            Versioned::Body(body, version) => Response::build_from(body.respond_to(request)?)
+                .raw_header("ETag", etag(version))
+                .ok(),
+            Versioned::NotModified(version) => Response::build()
+                .status(Status::NotModified)
+                .raw_header("ETag", etag(version))
+                .ok(),
+        }
+    }
+}
+
+pub struct Preconditions {
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Preconditions {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Preconditions, ()> {
+        let headers = request.headers();
+        Outcome::Success(Preconditions {
+            if_match: headers.get_one("If-Match").map(String::from),
+            if_none_match: headers.get_one("If-None-Match").map(String::from),
+        })
+    }
+}
+
+impl Preconditions {
+    pub fn check_if_match(&self, version: u64) -> Result<(), status::Custom<String>> {
+        match self.if_match {
+            Some(ref tags) if !matches(tags, version, false) => Err(status::Custom(
+                Status::PreconditionFailed,
+                format!("the resource has changed, its ETag is now {}", etag(version)),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_put(&self, version: Option<u64>) -> Result<(), status::Custom<String>> {
+        match version {
+            Some(version) => {
+                self.check_if_match(version)?;
+                match self.if_none_match {
+                    Some(ref tags) if matches(tags, version, true) => Err(status::Custom(
+                        Status::PreconditionFailed,
+                        format!("the resource already exists, its ETag is {}", etag(version)),
+                    )),
+                    _ => Ok(()),
+                }
+            }
+            None if self.if_match.is_some() => Err(status::Custom(
+                Status::PreconditionFailed,
+                "the resource does not exist".to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn respond<R>(&self, body: R, version: u64) -> Versioned<R> {
+        match self.if_none_match {
+            Some(ref tags) if matches(tags, version, true) => Versioned::NotModified(version),
+            _ => Versioned::Body(body, version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strong_comparison_ignores_weak_tags() {
+        assert!(matches("\"3\"", 3, false));
+        assert!(matches("\"2\", \"3\"", 3, false));
+        assert!(!matches("W/\"3\"", 3, false));
+        assert!(matches("W/\"3\"", 3, true));
+    }
+
+    #[test]
+    fn wildcard_matches_any_version() {
+        assert!(matches("*", 7, false));
+    }
+}
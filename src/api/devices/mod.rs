@@ -1,67 +1,264 @@
-use device::{Device, DeviceCollection};
+use alert::{AlertEngine, AlertEngineState};
+use api::audit::Actor;
+use api::conditional::{Preconditions, Versioned};
+use api::idempotency::{IdempotencyKey, Replays, ReplaysState};
+use api::preferences::UnitJson;
+use audit::{AuditLogState, ResourceType};
+use clock;
+use device::{Device, DeviceCollection, DeviceCollectionState, DeviceType, HealthPolicy};
+use hal::HalState;
+use page::{self, Page, Sort};
+use reading::Quantity;
+use rocket::http::{RawStr, Status};
+use rocket::request::{Form, FromFormValue};
 use rocket::response::status;
 use rocket::{Rocket, State};
 use rocket_contrib::json::{Json, JsonValue};
 use rocket_contrib::uuid::Uuid as RocketUuid;
+use rule::{RuleEngine, RuleEngineState};
+use scene::{SceneCollection, SceneCollectionState};
+use std::collections::BTreeMap;
 use std::sync::Mutex;
+use temperature::{Temperature, TemperatureUnit};
 use uuid::Uuid;
 
-type DeviceCollectionState = Mutex<DeviceCollection>;
+pub fn mount(rocket: Rocket, devices: DeviceCollectionState) -> Rocket {
+    let health_policy = HealthPolicy::from_config(rocket.config());
 
-pub fn mount(rocket: Rocket, devices: DeviceCollection) -> Rocket {
     rocket
         .mount(
             "/devices",
             routes![
                 get_devices,
-                get_devices_with_query,
+                get_device_types,
+                get_devices_health,
                 get_device_from_uuid,
+                put_device_from_uuid,
                 patch_device_from_uuid,
-                post_device
+                delete_device_from_uuid,
+                post_device,
+                post_device_report
             ],
         )
-        .manage(DeviceCollectionState::new(devices))
+        .manage(devices)
+        .manage(health_policy)
+        .manage(ReplaysState::<Device>::new(Mutex::new(Replays::new())))
 }
 
-#[get("/", format = "application/json")]
-fn get_devices(devices: State<DeviceCollectionState>) -> JsonValue {
-    json!(devices.inner())
+impl<'v> FromFormValue<'v> for DeviceType {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<DeviceType, &'v RawStr> {
+        form_value.as_str().parse().map_err(|_| form_value)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ZoneFilter(Option<Uuid>);
+
+impl<'v> FromFormValue<'v> for ZoneFilter {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<ZoneFilter, &'v RawStr> {
+        if form_value.as_str() == "none" {
+            return Ok(ZoneFilter(None));
+        }
+        Uuid::parse_str(form_value.as_str())
+            .map(|uuid| ZoneFilter(Some(uuid)))
+            .map_err(|_| form_value)
+    }
+}
+
+#[derive(FromForm)]
+pub struct DeviceQuery {
+    zone_uuid: Option<ZoneFilter>,
+    #[form(field = "type")]
+    device_type: Option<DeviceType>,
+    name: Option<String>,
+    setpoint_min: Option<f64>,
+    setpoint_max: Option<f64>,
+    online: Option<bool>,
+    unassigned: Option<bool>,
+    sort: Option<Sort>,
+    cursor: Option<String>,
+    limit: Option<usize>,
+    #[form(field = "unit")]
+    _unit: Option<TemperatureUnit>,
+}
+
+pub fn list_devices(
+    devices: &DeviceCollection,
+    query: &DeviceQuery,
+    unit: TemperatureUnit,
+    policy: &HealthPolicy,
+) -> Result<Page<Device>, status::Custom<String>> {
+    let devices = match query.device_type {
+        Some(device_type) => devices.get_all_with_type(device_type),
+        None => devices.clone(),
+    };
+
+    let now = clock::now();
+    let sort = query.sort.unwrap_or_default();
+    let name = query.name.as_ref().map(String::as_str);
+    let items = devices
+        .iter()
+        .filter(|(_, device)| page::name_matches(device.name(), name))
+        .filter(|(_, device)| {
+            let setpoint = device.setpoint().map(|setpoint| setpoint.in_unit(unit));
+            page::in_range(setpoint, query.setpoint_min, query.setpoint_max)
+        })
+        .filter(|(_, device)| {
+            query
+                .online
+                .map_or(true, |online| device.is_online(now, policy) == Some(online))
+        })
+        .filter(|(_, device)| {
+            query
+                .unassigned
+                .map_or(true, |unassigned| device.zone_uuid().is_none() == unassigned)
+        })
+        .map(|(uuid, device)| {
+            let key = sort.key(device.name(), device.setpoint().map(Temperature::celsius), devices.created_at(uuid));
+            (key, *uuid, device.clone())
+        })
+        .collect();
+
+    Page::new("devices", items, sort, query.cursor.as_ref().map(String::as_str), query.limit)
+        .map_err(|error| status::Custom(Status::BadRequest, error))
+}
+
+#[get("/?<query..>", format = "application/json")]
+fn get_devices(
+    query: Form<DeviceQuery>,
+    unit: TemperatureUnit,
+    devices: State<DeviceCollectionState>,
+    policy: State<HealthPolicy>,
+) -> Result<Json<Page<Device>>, status::Custom<String>> {
+    let devices = devices.lock().unwrap();
+    let page = match query.zone_uuid {
+        Some(ZoneFilter(zone_uuid)) => list_devices(&devices.get_all_with_zone(zone_uuid), &query, unit, &policy),
+        None => list_devices(&devices, &query, unit, &policy),
+    };
+    page.map(Json)
+}
+
+#[get("/types", format = "application/json")]
+fn get_device_types() -> JsonValue {
+    let types: serde_json::Map<String, serde_json::Value> = DeviceType::ALL
+        .iter()
+        .map(|device_type| {
+            let capabilities = serde_json::to_value(device_type.capabilities()).unwrap();
+            (device_type.as_str().to_string(), capabilities)
+        })
+        .collect();
+
+    json!({ "types": types })
+}
+
+#[get("/health", format = "application/json")]
+fn get_devices_health(devices: State<DeviceCollectionState>, policy: State<HealthPolicy>) -> JsonValue {
+    let devices = devices.lock().unwrap();
+    let now = clock::now();
+
+    json!({
+        "low_battery": devices.filter(|device| device.has_low_battery(&policy)),
+        "offline": devices.filter(|device| device.is_online(now, &policy) == Some(false)),
+    })
 }
 
 #[post("/", data = "<device>", format = "application/json")]
-fn post_device(device: Json<Device>, devices: State<DeviceCollectionState>) -> status::Created<Json<Device>> {
+fn post_device(
+    device: UnitJson<Device>,
+    idempotency_key: IdempotencyKey,
+    actor: Actor,
+    devices: State<DeviceCollectionState>,
+    replays: State<ReplaysState<Device>>,
+    audit: State<AuditLogState>,
+) -> Result<status::Created<Json<Device>>, status::Custom<String>> {
+    let now = clock::now();
+    let request = device.into_inner();
     let mut devices = devices.lock().unwrap();
+    let mut replays = replays.lock().unwrap();
+    if let Some((uuid, device)) = replays.replay(&idempotency_key, &request, now)? {
+        return Ok(status::Created(format!("/devices/{}", uuid), Some(Json(device))));
+    }
+
+    let mut device = request.clone();
+    device
+        .validate()
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    devices.restore_from_tombstone(&mut device);
     let uuid = Uuid::new_v4();
     devices.add(uuid, device.clone());
+    replays.record(&idempotency_key, &request, uuid, &device, now);
+    audit
+        .lock()
+        .unwrap()
+        .record(now, actor.name(), ResourceType::Device, uuid, None, Some(&device));
 
-    status::Created(format!("/devices/{}", uuid), Some(device))
+    Ok(status::Created(format!("/devices/{}", uuid), Some(Json(device))))
 }
 
-#[get("/?<zone_uuid>", format = "application/json")]
-fn get_devices_with_query(
-    zone_uuid: RocketUuid,
+#[put("/<uuid>", data = "<device>", format = "application/json")]
+fn put_device_from_uuid(
+    uuid: RocketUuid,
+    preconditions: Preconditions,
+    actor: Actor,
+    device: UnitJson<Device>,
     devices: State<DeviceCollectionState>,
-) -> Option<Json<DeviceCollection>> {
-    let devices = devices.lock().unwrap();
-    let devices = devices.get_all_with_zone(zone_uuid.into_inner());
-    if let Some(devices) = devices {
-        Some(Json(devices))
-    } else {
-        None
+    audit: State<AuditLogState>,
+) -> Result<status::Custom<Versioned<Json<Device>>>, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let mut device = device.into_inner();
+    device
+        .validate()
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+
+    let mut devices = devices.lock().unwrap();
+    preconditions.check_put(devices.version(&uuid))?;
+    let before = devices.get(&uuid).cloned();
+    if let Some(ref before) = before {
+        device.keep_reports_from(before);
     }
+    devices.add(uuid, device.clone());
+    audit
+        .lock()
+        .unwrap()
+        .record(clock::now(), actor.name(), ResourceType::Device, uuid, before.as_ref(), Some(&device));
+
+    let status = if before.is_some() { Status::Ok } else { Status::Created };
+    let version = devices.version(&uuid).unwrap_or_default();
+    Ok(status::Custom(status, Versioned::Body(Json(device), version)))
 }
 
 #[get("/<uuid>", format = "application/json")]
-fn get_device_from_uuid(uuid: RocketUuid, devices: State<DeviceCollectionState>) -> Option<Json<Device>> {
-    if let Some(device) = devices.lock().unwrap().get(&uuid.into_inner()) {
-        Some(Json(device.clone()))
-    } else {
-        None
-    }
+fn get_device_from_uuid(
+    uuid: RocketUuid,
+    preconditions: Preconditions,
+    devices: State<DeviceCollectionState>,
+) -> Option<Versioned<Json<Device>>> {
+    let uuid = uuid.into_inner();
+    let devices = devices.lock().unwrap();
+    let device = devices.get(&uuid)?;
+    Some(preconditions.respond(Json(device.clone()), devices.version(&uuid)?))
 }
 
-fn patch_device_with_json(device: &mut Device, patch_json: &JsonValue) {
-    let patch_json = patch_json.as_object().unwrap();
+pub fn patch_device_with_json(device: &mut Device, patch_json: &JsonValue) -> Result<(), String> {
+    let patch_json = patch_json.as_object().ok_or_else(|| "patch must be a JSON object".to_string())?;
+    let capabilities = device.capabilities();
+
+    if patch_json.contains_key("setpoint") && !capabilities.has_setpoint {
+        return Err(format!("devices of type {} have no setpoint", device.device_type().as_str()));
+    }
+
+    if patch_json.contains_key("demand") && !capabilities.is_actuator {
+        return Err(format!("devices of type {} are not actuators", device.device_type().as_str()));
+    }
+
+    let zone_uuid = match patch_json.get("zone_uuid").and_then(|zone_uuid| zone_uuid.as_str()) {
+        Some(zone_uuid) => Some(Uuid::parse_str(zone_uuid).map_err(|_| format!("invalid zone_uuid {}", zone_uuid))?),
+        None => None,
+    };
 
     if patch_json.contains_key("name") {
         if let Some(patch_name) = patch_json["name"].as_str() {
@@ -70,32 +267,182 @@ fn patch_device_with_json(device: &mut Device, patch_json: &JsonValue) {
     }
 
     if patch_json.contains_key("zone_uuid") {
-        if let Some(patch_zone_uuid) = patch_json["zone_uuid"].as_str() {
-            let uuid = Uuid::parse_str(patch_zone_uuid).unwrap();
-            device.set_zone_uuid(Some(uuid));
-        } else {
-            device.set_zone_uuid(None);
-        }
+        device.set_zone_uuid(zone_uuid);
     }
 
     if patch_json.contains_key("setpoint") {
         if let Some(patch_setpoint) = patch_json["setpoint"].as_f64() {
-            device.set_setpoint(patch_setpoint);
+            device.set_setpoint(Temperature::from_celsius(patch_setpoint));
         }
     }
+
+    Ok(())
 }
 
 #[patch("/<uuid>", format = "application/json", data = "<patch_json>")]
 fn patch_device_from_uuid(
     uuid: RocketUuid,
-    patch_json: Json<JsonValue>,
+    preconditions: Preconditions,
+    actor: Actor,
+    patch_json: UnitJson<JsonValue>,
     devices: State<DeviceCollectionState>,
-) -> Option<Json<Device>> {
-    if let Some(device) = devices.lock().unwrap().get_mut(&uuid.into_inner()) {
-        patch_device_with_json(device, &patch_json);
-        Some(Json(device.clone()))
+    hal: State<HalState>,
+    audit: State<AuditLogState>,
+) -> Result<Option<Versioned<Json<Device>>>, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let mut devices = devices.lock().unwrap();
+    match devices.version(&uuid) {
+        Some(version) => preconditions.check_if_match(version)?,
+        None => return Ok(None),
+    }
+
+    // Patched on a copy, so that a patch that is rejected does not give the device a new version.
+    let before = match devices.get(&uuid) {
+        Some(device) => device.clone(),
+        None => return Ok(None),
+    };
+    let mut device = before.clone();
+    patch_device_with_json(&mut device, &patch_json)
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+
+    // The relay is switched last, so that when it fails nothing of the patch is kept.
+    if let Some(demand) = patch_json.get("demand").and_then(|demand| demand.as_bool()) {
+        hal.lock()
+            .unwrap()
+            .set_demand(&uuid, demand)
+            .map_err(|error| status::Custom(Status::InternalServerError, error.to_string()))?;
+        device.set_demand(demand);
+        device.set_fault(None);
+    }
+
+    devices.add(uuid, device.clone());
+    audit
+        .lock()
+        .unwrap()
+        .record(clock::now(), actor.name(), ResourceType::Device, uuid, Some(&before), Some(&device));
+
+    Ok(devices.version(&uuid).map(|version| Versioned::Body(Json(device), version)))
+}
+
+pub fn check_unused(uuid: &Uuid, scenes: &SceneCollection, rules: &RuleEngine) -> Result<(), String> {
+    if let Some((scene_uuid, _)) = scenes.iter().find(|(_, scene)| scene.targets_device(uuid)) {
+        return Err(format!("device {} is a target of scene {}", uuid, scene_uuid));
+    }
+    if let Some((rule_uuid, _)) = rules.iter().find(|(_, rule)| rule.refers_to(uuid)) {
+        return Err(format!("device {} is used by rule {}", uuid, rule_uuid));
+    }
+    Ok(())
+}
+
+pub fn forget_device(uuid: &Uuid, hal: &HalState, alerts: &mut AlertEngine) {
+    if let Err(error) = hal.lock().unwrap().unpair(uuid) {
+        error!("Failed to switch off the relay of removed device {}: {}", uuid, error);
+    }
+    alerts.forget_subject(uuid);
+}
+
+#[delete("/<uuid>?<tombstone>", format = "application/json")]
+fn delete_device_from_uuid(
+    uuid: RocketUuid,
+    tombstone: Option<bool>,
+    preconditions: Preconditions,
+    actor: Actor,
+    devices: State<DeviceCollectionState>,
+    scenes: State<SceneCollectionState>,
+    rules: State<RuleEngineState>,
+    hal: State<HalState>,
+    alerts: State<AlertEngineState>,
+    audit: State<AuditLogState>,
+) -> Result<Status, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let mut devices = devices.lock().unwrap();
+    if let Some(version) = devices.version(&uuid) {
+        preconditions.check_if_match(version)?;
+        check_unused(&uuid, &scenes.lock().unwrap(), &rules.lock().unwrap())
+            .map_err(|error| status::Custom(Status::Conflict, error))?;
+    }
+    let device = match devices.remove(&uuid, tombstone.unwrap_or(false)) {
+        Some(device) => device,
+        None => return Ok(Status::NoContent),
+    };
+
+    forget_device(&uuid, &hal, &mut alerts.lock().unwrap());
+    audit
+        .lock()
+        .unwrap()
+        .record(clock::now(), actor.name(), ResourceType::Device, uuid, Some(&device), None);
+    Ok(Status::NoContent)
+}
+
+#[derive(Deserialize)]
+struct DeviceReport {
+    battery: Option<u8>,
+    rssi: Option<i16>,
+    link_quality: Option<u8>,
+    temperature: Option<f64>,
+    #[serde(default)]
+    readings: BTreeMap<Quantity, f64>,
+    valve_position: Option<u8>,
+    fault: Option<String>,
+}
+
+#[post("/<uuid>/report", format = "application/json", data = "<report>")]
+fn post_device_report(
+    uuid: RocketUuid,
+    report: UnitJson<DeviceReport>,
+    devices: State<DeviceCollectionState>,
+) -> Result<Option<Json<Device>>, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let mut readings: Vec<(Quantity, f64)> = report
+        .temperature
+        .map(|temperature| (Quantity::Temperature, temperature))
+        .into_iter()
+        .collect();
+    readings.extend(report.readings.iter().map(|(quantity, value)| (*quantity, *value)));
+
+    let mut devices = devices.lock().unwrap();
+    // Checked before the device is borrowed to change it, so that a rejected report does not give it a new version.
+    if let Some(device) = devices.get(&uuid) {
+        if report.battery.is_some() && !device.capabilities().reports_battery {
+            let error = format!("devices of type {} do not report battery", device.device_type().as_str());
+            return Err(status::Custom(Status::UnprocessableEntity, error));
+        }
+        if report.battery.map_or(false, |battery| battery > 100) {
+            let error = "battery is a percentage, so at most 100".to_string();
+            return Err(status::Custom(Status::UnprocessableEntity, error));
+        }
+        for &(quantity, value) in &readings {
+            if !device.capabilities().readings.contains(&quantity) {
+                let device_type = device.device_type().as_str();
+                let error = format!("devices of type {} do not report {}", device_type, quantity.as_str());
+                return Err(status::Custom(Status::UnprocessableEntity, error));
+            }
+            quantity
+                .validate(value)
+                .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+        }
+        if report.valve_position.is_some() && !device.capabilities().reports_valve_position {
+            let error = format!("devices of type {} do not report valve position", device.device_type().as_str());
+            return Err(status::Custom(Status::UnprocessableEntity, error));
+        }
+        if report.valve_position.map_or(false, |valve_position| valve_position > 100) {
+            let error = "valve_position is a percentage, so at most 100".to_string();
+            return Err(status::Custom(Status::UnprocessableEntity, error));
+        }
+    }
+
+    if let Some(device) = devices.get_mut(&uuid) {
+        device.report(report.battery, report.rssi, report.link_quality, clock::now());
+        for (quantity, value) in readings {
+            device.set_reading(quantity, value);
+        }
+        if let Some(valve_position) = report.valve_position {
+            device.set_valve_position(valve_position);
+        }
+        device.set_fault(report.fault.clone());
+        Ok(Some(Json(device.clone())))
     } else {
-        None
+        Ok(None)
     }
 }
 
@@ -1,14 +1,40 @@
 use super::*;
+use alert::AlertEngine;
+use api::audit::mount as mount_audit;
+use audit::{AuditLog, AuditRetention};
+use hal::{FakeOutput, Hal, HalError, OutputDriver, Relay};
 use rocket::http::{ContentType, Status};
 use rocket::local::{Client, LocalResponse};
 use rocket_contrib::json::{Json, JsonValue};
 use serde_json::map::Values;
 use serde_json::Value;
+use std::sync::Mutex;
 use uuid::Uuid;
 
+struct BrokenOutput;
+
+impl OutputDriver for BrokenOutput {
+    fn write(&mut self, _: bool) -> Result<(), HalError> {
+        Err(HalError::Gpio("line busy".to_string()))
+    }
+}
+
 fn create_client_with_mounts(devices: DeviceCollection) -> Client {
-    let rocket = rocket::ignite();
-    let rocket = mount(rocket, devices);
+    create_client_with_mounts_and_hal(devices, Hal::new())
+}
+
+fn create_client_with_mounts_and_hal(devices: DeviceCollection, hal: Hal) -> Client {
+    create_client_with_mounts_and_scenes(devices, hal, SceneCollection::new())
+}
+
+fn create_client_with_mounts_and_scenes(devices: DeviceCollection, hal: Hal, scenes: SceneCollection) -> Client {
+    let rocket = rocket::ignite()
+        .manage(HalState::new(hal))
+        .manage(SceneCollectionState::new(Mutex::new(scenes)))
+        .manage(RuleEngineState::new(Mutex::new(RuleEngine::new())))
+        .manage(AlertEngineState::new(Mutex::new(AlertEngine::new(AlertEngine::default_rules()))));
+    let rocket = mount_audit(rocket, AuditLogState::new(Mutex::new(AuditLog::new(AuditRetention::default()))));
+    let rocket = mount(rocket, DeviceCollectionState::new(Mutex::new(devices)));
     Client::new(rocket).unwrap()
 }
 
@@ -66,11 +92,13 @@ mod get_devices {
             "devices": {
                 device1_uuid.to_string(): {
                     "name": device1_name,
+                    "type": "radiator_valve",
                     "setpoint": 16.0,
                     "zone_uuid": null
                 },
                 device2_uuid.to_string(): {
                     "name": device2_name,
+                    "type": "radiator_valve",
                     "setpoint": 16.0,
                     "zone_uuid": null
                 }
@@ -100,6 +128,7 @@ mod get_devices {
             "devices": {
                 device2_uuid.to_string(): {
                     "name": device2_name,
+                    "type": "radiator_valve",
                     "setpoint": 16.0,
                     "zone_uuid": device2_zone
                 }
@@ -109,7 +138,7 @@ mod get_devices {
     }
 
     #[test]
-    fn with_devices_but_invalid_query_returns_404_error() {
+    fn with_devices_but_none_in_queried_zone_returns_empty_devices() {
         let device1_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
         let device1_name = "Device Name".to_string();
         let device1_zone = Uuid::parse_str("c00727d8-eee8-4a0e-850e-b81a74440e78").unwrap();
@@ -122,20 +151,50 @@ mod get_devices {
         let client = create_client_with_mounts(devices);
 
         let zone_uuid = "690ad0c5-a04f-479f-9d1f-d076df3a2c7b";
-        let response = get_devices_with_query_return_response(&client, zone_uuid);
+        let mut response = get_devices_with_query_return_response(&client, zone_uuid);
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
 
-        assert_eq!(Status::NotFound, response.status());
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(json!({ "devices": {} }), body);
     }
 
     #[test]
-    fn query_with_no_devices_returns_404_error() {
+    fn query_with_no_devices_returns_empty_devices() {
         let devices = DeviceCollection::new();
         let client = create_client_with_mounts(devices);
 
         let zone_uuid = "690ad0c5-a04f-479f-9d1f-d076df3a2c7b";
-        let response = get_devices_with_query_return_response(&client, zone_uuid);
+        let mut response = get_devices_with_query_return_response(&client, zone_uuid);
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
 
-        assert_eq!(Status::NotFound, response.status());
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(json!({ "devices": {} }), body);
+    }
+
+    #[test]
+    fn query_with_zone_none_returns_devices_without_a_zone() {
+        let assigned_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let unassigned_uuid = Uuid::parse_str("88f573e2-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let zone_uuid = Uuid::parse_str("c00727d8-eee8-4a0e-850e-b81a74440e78").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(assigned_uuid, Device::new("Assigned".to_string(), Some(zone_uuid)));
+        devices.add(unassigned_uuid, Device::new("Unassigned".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let mut response = get_devices_with_query_return_response(&client, "none");
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        let expected = json!({
+            "devices": {
+                unassigned_uuid.to_string(): {
+                    "name": "Unassigned",
+                    "type": "radiator_valve",
+                    "setpoint": 16.0,
+                    "zone_uuid": null
+                }
+            }
+        });
+        assert_eq!(expected, body);
     }
 
     #[test]
@@ -160,11 +219,13 @@ mod get_devices {
             "devices": {
                 device1_uuid.to_string(): {
                     "name": device1_name,
+                    "type": "radiator_valve",
                     "setpoint": 16.0,
                     "zone_uuid": device1_zone
                 },
                 device2_uuid.to_string(): {
                     "name": device2_name,
+                    "type": "radiator_valve",
                     "setpoint": 16.0,
                     "zone_uuid": device2_zone
                 }
@@ -172,6 +233,59 @@ mod get_devices {
         });
         assert_eq!(expected, body);
     }
+
+    #[test]
+    fn query_by_type_returns_only_devices_of_that_type() {
+        let valve_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let sensor_uuid = Uuid::parse_str("88f573e2-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(valve_uuid, Device::new("Valve".to_string(), None));
+        devices.add(
+            sensor_uuid,
+            Device::with_type("Sensor".to_string(), None, DeviceType::TemperatureSensor),
+        );
+        let client = create_client_with_mounts(devices);
+
+        let mut response = client
+            .get("/devices?type=temperature_sensor")
+            .header(ContentType::JSON)
+            .dispatch();
+        let body = response.body_string().unwrap();
+        let body = serde_json::from_str(&body).unwrap();
+
+        let expected = json!({
+            "devices": {
+                sensor_uuid.to_string(): {
+                    "name": "Sensor",
+                    "type": "temperature_sensor",
+                    "zone_uuid": null
+                }
+            }
+        });
+        assert_eq!(expected, body);
+    }
+
+    #[test]
+    fn types_lists_capabilities_of_every_device_type() {
+        let client = create_client_with_mounts(DeviceCollection::new());
+
+        let mut response = client.get("/devices/types").header(ContentType::JSON).dispatch();
+        let body = response.body_string().unwrap();
+        let body: Value = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(DeviceType::ALL.len(), body["types"].as_object().unwrap().len());
+        assert_eq!(
+            json!({
+                "has_setpoint": false,
+                "reports_temperature": true,
+                "reports_battery": true,
+                "reports_valve_position": false,
+                "is_actuator": false,
+                "readings": ["temperature", "humidity", "co2", "pressure", "illuminance"]
+            }),
+            body["types"]["temperature_sensor"]
+        );
+    }
 }
 
 mod get_device {
@@ -198,6 +312,7 @@ mod get_device {
 
         let expected = json!({
             "name": device_name,
+            "type": "radiator_valve",
             "setpoint": 16.0,
             "zone_uuid": null
         });
@@ -221,6 +336,7 @@ mod get_device {
 
         let expected = json!({
         "name": device1_name,
+        "type": "radiator_valve",
         "setpoint": 16.0,
         "zone_uuid": null
         });
@@ -231,6 +347,7 @@ mod get_device {
 
         let expected = json!({
         "name": device2_name,
+        "type": "radiator_valve",
         "setpoint": 16.0,
         "zone_uuid": null
         });
@@ -262,6 +379,7 @@ mod get_device {
 
         let expected = json!({
             "name": device_name,
+            "type": "radiator_valve",
             "setpoint": 16.0,
             "zone_uuid": null
         });
@@ -272,6 +390,7 @@ mod get_device {
 
         let expected = json!({
             "name": device_name,
+            "type": "radiator_valve",
             "setpoint": 16.0,
             "zone_uuid": null
         });
@@ -339,11 +458,13 @@ mod patch_device {
             "devices": {
                 device1_uuid.to_string(): {
                     "name": patched_name,
+                    "type": "radiator_valve",
                     "setpoint": 16.0,
                     "zone_uuid": null
                 },
                 device2_uuid.to_string(): {
                     "name": device2_name,
+                    "type": "radiator_valve",
                     "setpoint": 16.0,
                     "zone_uuid": null
                 }
@@ -377,11 +498,13 @@ mod patch_device {
             "devices": {
                 device1_uuid.to_string(): {
                     "name": device1_name,
+                    "type": "radiator_valve",
                     "setpoint": 16.0,
                     "zone_uuid": null
                 },
                 device2_uuid.to_string(): {
                     "name": device2_name,
+                    "type": "radiator_valve",
                     "setpoint": 16.0,
                     "zone_uuid": "b098d5ca-1311-4145-80b2-0e9b2944efd3"
                 }
@@ -435,11 +558,119 @@ mod patch_device {
 
         let expected = json!({
             "name": device1_name,
+            "type": "radiator_valve",
             "setpoint": patch_setpoint,
             "zone_uuid": zone_uuid
         });
         assert_eq!(expected, body);
     }
+
+    #[test]
+    fn setpoint_on_device_without_setpoint_returns_422() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(
+            device_uuid,
+            Device::with_type("Sensor".to_string(), None, DeviceType::TemperatureSensor),
+        );
+        let client = create_client_with_mounts(devices);
+
+        let response = patch_device_return_response(&client, device_uuid, json!({ "setpoint": 21.0 }));
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn invalid_zone_uuid_returns_422_and_keeps_device() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("Valve".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let patch_json = json!({ "name": "Renamed", "zone_uuid": "not-a-uuid" });
+        let response = patch_device_return_response(&client, device_uuid, patch_json);
+        assert_eq!(Status::UnprocessableEntity, response.status());
+
+        let body = get_device_return_response_body_string(&client, &device_uuid.to_string());
+        let body: JsonValue = serde_json::from_str(&body).unwrap();
+        assert_eq!("Valve", body["name"]);
+    }
+
+    #[test]
+    fn patch_that_is_not_an_object_returns_422() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("Valve".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        for patch_json in vec![json!([]), json!(5)] {
+            let response = patch_device_return_response(&client, device_uuid, patch_json);
+            assert_eq!(Status::UnprocessableEntity, response.status());
+        }
+
+        let body = get_device_return_response_body_string(&client, &device_uuid.to_string());
+        let body: JsonValue = serde_json::from_str(&body).unwrap();
+        assert_eq!("Valve", body["name"]);
+    }
+
+    #[test]
+    fn demand_switches_attached_relay() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(
+            device_uuid,
+            Device::with_type("Boiler".to_string(), None, DeviceType::HeatSource),
+        );
+        let output = FakeOutput::new();
+        let mut hal = Hal::new();
+        hal.attach_relay(device_uuid, Relay::new(Box::new(output.clone()), false));
+
+        let client = create_client_with_mounts_and_hal(devices, hal);
+
+        patch_device_return_response(&client, device_uuid, json!({ "demand": true }));
+        assert_eq!(Some(true), output.level());
+
+        patch_device_return_response(&client, device_uuid, json!({ "demand": false }));
+        assert_eq!(Some(false), output.level());
+    }
+
+    #[test]
+    fn failing_relay_returns_500_and_changes_nothing() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(
+            device_uuid,
+            Device::with_type("Boiler".to_string(), None, DeviceType::HeatSource),
+        );
+        let mut hal = Hal::new();
+        hal.attach_relay(device_uuid, Relay::new(Box::new(BrokenOutput), false));
+        let client = create_client_with_mounts_and_hal(devices, hal);
+
+        let response = patch_device_return_response(&client, device_uuid, json!({ "name": "Heater", "demand": true }));
+        assert_eq!(Status::InternalServerError, response.status());
+
+        let body = get_device_return_response_body_string(&client, &device_uuid.to_string());
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!("Boiler", body["name"]);
+        assert!(body.get("demand").is_none());
+        assert!(body["health"].get("fault").is_none());
+    }
+
+    #[test]
+    fn demand_is_remembered() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(
+            device_uuid,
+            Device::with_type("Boiler".to_string(), None, DeviceType::HeatSource),
+        );
+        let client = create_client_with_mounts(devices);
+
+        let mut response = patch_device_return_response(&client, device_uuid, json!({ "demand": true }));
+
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(Some(true), body["demand"].as_bool());
+    }
 }
 
 mod post_device {
@@ -496,6 +727,7 @@ mod post_device {
 
         let expected = json!({
             "name": name,
+            "type": "radiator_valve",
             "setpoint": 16.0,
             "zone_uuid": null
         });
@@ -574,4 +806,655 @@ mod post_device {
 
         assert!(devices.is_empty());
     }
+
+    #[test]
+    fn device_without_setpoint_capability_is_added_without_setpoint() {
+        let devices = DeviceCollection::new();
+        let client = create_client_with_mounts(devices);
+        let device = Device::with_type("Hall Sensor".to_string(), None, DeviceType::TemperatureSensor);
+
+        let mut response = post_device_return_response(&client, &device);
+        let body = response.body_string().unwrap();
+        let body = serde_json::from_str(&body).unwrap();
+
+        let expected = json!({
+            "name": "Hall Sensor",
+            "type": "temperature_sensor",
+            "zone_uuid": null
+        });
+        assert_eq!(expected, body);
+    }
+
+    #[test]
+    fn device_with_setpoint_it_cannot_have_returns_422() {
+        let devices = DeviceCollection::new();
+        let client = create_client_with_mounts(devices);
+
+        let response = client
+            .post("/devices")
+            .body(json!({ "name": "Boiler", "type": "heat_source", "setpoint": 60.0 }).to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn device_without_setpoint_gets_default_setpoint() {
+        let devices = DeviceCollection::new();
+        let client = create_client_with_mounts(devices);
+
+        let mut response = client
+            .post("/devices")
+            .body(json!({ "name": "STRV", "type": "radiator_valve" }).to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        assert_eq!(16.0, body["setpoint"]);
+    }
+
+    #[test]
+    fn reports_in_body_are_ignored() {
+        let client = create_client_with_mounts(DeviceCollection::new());
+
+        let device = json!({
+            "name": "STRV",
+            "temperature": 30.0,
+            "readings": { "temperature": { "value": 30.0, "unit": "°C" } },
+            "valve_position": 100,
+            "health": { "battery": 100, "last_seen": 4102444800u64, "fault": "E119" }
+        });
+        let mut response = client
+            .post("/devices")
+            .body(device.to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        assert_eq!(Status::Created, response.status());
+        for key in &["temperature", "readings", "valve_position", "health"] {
+            assert!(body.get(key).is_none(), "{} was set from the body", key);
+        }
+    }
+}
+
+mod put_device {
+    use super::*;
+
+    const DEVICE_UUID: &str = "84fa1356-d5de-11e8-9f8b-f2801f1b9fd1";
+
+    fn put_device_return_response<'c>(client: &'c Client, device: &JsonValue) -> LocalResponse<'c> {
+        client
+            .put(format!("/devices/{}", DEVICE_UUID))
+            .body(device.to_string())
+            .header(ContentType::JSON)
+            .dispatch()
+    }
+
+    #[test]
+    fn with_new_uuid_creates_device_with_that_uuid() {
+        let client = create_client_with_mounts(DeviceCollection::new());
+
+        let response = put_device_return_response(&client, &json!({ "name": "STRV", "zone_uuid": null }));
+
+        assert_eq!(Status::Created, response.status());
+        let body: Value = serde_json::from_str(&get_device_return_response_body_string(&client, DEVICE_UUID)).unwrap();
+        assert_eq!("STRV", body["name"]);
+    }
+
+    #[test]
+    fn with_existing_uuid_replaces_device_but_keeps_its_reports() {
+        let mut device = Device::new("STRV".to_string(), None);
+        device.set_reading(Quantity::Temperature, 19.5);
+        let mut devices = DeviceCollection::new();
+        devices.add(Uuid::parse_str(DEVICE_UUID).unwrap(), device);
+        let client = create_client_with_mounts(devices);
+
+        let response = put_device_return_response(&client, &json!({ "name": "Radiator", "zone_uuid": null }));
+
+        assert_eq!(Status::Ok, response.status());
+        let body: Value = serde_json::from_str(&get_device_return_response_body_string(&client, DEVICE_UUID)).unwrap();
+        assert_eq!("Radiator", body["name"]);
+        assert_eq!(19.5, body["temperature"]);
+    }
+
+    #[test]
+    fn reports_in_body_do_not_replace_those_of_the_device() {
+        let mut device = Device::new("STRV".to_string(), None);
+        device.set_reading(Quantity::Temperature, 19.5);
+        let mut devices = DeviceCollection::new();
+        devices.add(Uuid::parse_str(DEVICE_UUID).unwrap(), device);
+        let client = create_client_with_mounts(devices);
+
+        let device = json!({ "name": "STRV", "zone_uuid": null, "temperature": 30.0, "health": { "battery": 5 } });
+        put_device_return_response(&client, &device);
+
+        let body: Value = serde_json::from_str(&get_device_return_response_body_string(&client, DEVICE_UUID)).unwrap();
+        assert_eq!(19.5, body["temperature"]);
+        assert_eq!(19.5, body["readings"]["temperature"]["value"]);
+        assert!(body.get("health").is_none());
+    }
+
+    #[test]
+    fn with_invalid_device_returns_422() {
+        let client = create_client_with_mounts(DeviceCollection::new());
+
+        let device = json!({ "name": "Hall", "type": "temperature_sensor", "setpoint": 20.0, "zone_uuid": null });
+        let response = put_device_return_response(&client, &device);
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+}
+
+mod post_device_with_idempotency_key {
+    use super::*;
+    use rocket::http::Header;
+
+    fn post_device_with_key<'c>(client: &'c Client, device: &Device, key: &str) -> LocalResponse<'c> {
+        client
+            .post("/devices")
+            .body(json!(device).to_string())
+            .header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", key.to_string()))
+            .dispatch()
+    }
+
+    #[test]
+    fn retry_returns_the_original_device_without_creating_another() {
+        let client = create_client_with_mounts(DeviceCollection::new());
+        let device = Device::new("STRV".to_string(), None);
+
+        let first = post_device_with_key(&client, &device, "3f1c");
+        let retry = post_device_with_key(&client, &device, "3f1c");
+
+        assert_eq!(Status::Created, retry.status());
+        assert_eq!(first.headers().get_one("Location"), retry.headers().get_one("Location"));
+        let mut response = client.get("/devices").header(ContentType::JSON).dispatch();
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(1, body["devices"].as_object().unwrap().len());
+    }
+
+    #[test]
+    fn key_reused_for_another_device_returns_422() {
+        let client = create_client_with_mounts(DeviceCollection::new());
+
+        post_device_with_key(&client, &Device::new("STRV".to_string(), None), "3f1c");
+        let response = post_device_with_key(&client, &Device::new("Radiator".to_string(), None), "3f1c");
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+}
+
+mod delete_device {
+    use super::*;
+
+    fn delete_device_return_response<'c>(client: &'c Client, uuid: Uuid, query: &str) -> LocalResponse<'c> {
+        client
+            .delete(format!("/devices/{}{}", uuid, query))
+            .header(ContentType::JSON)
+            .dispatch()
+    }
+
+    fn post_device_return_body(client: &Client, device: JsonValue) -> Value {
+        let mut response = client
+            .post("/devices")
+            .body(device.to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+        serde_json::from_str(&response.body_string().unwrap()).unwrap()
+    }
+
+    fn paired_device(zone_uuid: Uuid) -> Device {
+        serde_json::from_value(json!({
+            "name": "Kitchen STRV",
+            "zone_uuid": zone_uuid,
+            "hardware_id": "00:15:8d:00:01:02"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn removes_device() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let response = delete_device_return_response(&client, device_uuid, "");
+        let get_response = client
+            .get(format!("/devices/{}", device_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::NoContent, response.status());
+        assert_eq!(Status::NotFound, get_response.status());
+    }
+
+    #[test]
+    fn switches_off_relay() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let output = FakeOutput::new();
+        let mut hal = Hal::new();
+        hal.attach_relay(device_uuid, Relay::new(Box::new(output.clone()), false));
+        hal.set_demand(&device_uuid, true).unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(
+            device_uuid,
+            Device::with_type("Boiler".to_string(), None, DeviceType::Relay),
+        );
+        let client = create_client_with_mounts_and_hal(devices, hal);
+
+        delete_device_return_response(&client, device_uuid, "");
+
+        assert_eq!(Some(false), output.level());
+    }
+
+    #[test]
+    fn re_pairing_after_tombstone_restores_name_and_zone() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let zone_uuid = Uuid::parse_str("c00727d8-eee8-4a0e-850e-b81a74440e78").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, paired_device(zone_uuid));
+        let client = create_client_with_mounts(devices);
+
+        delete_device_return_response(&client, device_uuid, "?tombstone=true");
+        let body = post_device_return_body(
+            &client,
+            json!({ "name": "New Valve", "zone_uuid": null, "hardware_id": "00:15:8d:00:01:02" }),
+        );
+
+        assert_eq!("Kitchen STRV", body["name"]);
+        assert_eq!(zone_uuid.to_string(), body["zone_uuid"]);
+    }
+
+    #[test]
+    fn re_pairing_without_tombstone_keeps_new_name() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let zone_uuid = Uuid::parse_str("c00727d8-eee8-4a0e-850e-b81a74440e78").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, paired_device(zone_uuid));
+        let client = create_client_with_mounts(devices);
+
+        delete_device_return_response(&client, device_uuid, "");
+        let body = post_device_return_body(
+            &client,
+            json!({ "name": "New Valve", "zone_uuid": null, "hardware_id": "00:15:8d:00:01:02" }),
+        );
+
+        assert_eq!("New Valve", body["name"]);
+        assert!(body["zone_uuid"].is_null());
+    }
+
+    #[test]
+    fn target_of_scene_returns_409_and_keeps_device() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), None));
+        let mut scenes = SceneCollection::new();
+        let scene = format!(r#"{{"name":"Cosy","devices":{{"{}":{{"setpoint":22.0}}}}}}"#, device_uuid);
+        scenes.add(Uuid::new_v4(), serde_json::from_str(&scene).unwrap());
+        let client = create_client_with_mounts_and_scenes(devices, Hal::new(), scenes);
+
+        let response = delete_device_return_response(&client, device_uuid, "");
+        let get_response = client
+            .get(format!("/devices/{}", device_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::Conflict, response.status());
+        assert_eq!(Status::Ok, get_response.status());
+    }
+}
+
+mod conditional_requests {
+    use super::*;
+    use rocket::http::Header;
+
+    #[test]
+    fn report_changes_etag_so_stale_patch_returns_412() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let response = client
+            .get(format!("/devices/{}", device_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+        let etag = response.headers().get_one("ETag").unwrap().to_string();
+        client
+            .post(format!("/devices/{}/report", device_uuid))
+            .body(json!({ "battery": 80 }).to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+        let response = client
+            .patch(format!("/devices/{}", device_uuid))
+            .body(json!({ "name": "Renamed" }).to_string())
+            .header(ContentType::JSON)
+            .header(Header::new("If-Match", etag))
+            .dispatch();
+
+        assert_eq!(Status::PreconditionFailed, response.status());
+    }
+
+    #[test]
+    fn rejected_patch_and_report_keep_etag() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(
+            device_uuid,
+            Device::with_type("Sensor".to_string(), None, DeviceType::TemperatureSensor),
+        );
+        let client = create_client_with_mounts(devices);
+        let get_etag = || {
+            let response = client
+                .get(format!("/devices/{}", device_uuid))
+                .header(ContentType::JSON)
+                .dispatch();
+            response.headers().get_one("ETag").unwrap().to_string()
+        };
+        let etag = get_etag();
+
+        let patch_response = client
+            .patch(format!("/devices/{}", device_uuid))
+            .body(json!({ "setpoint": 21.0 }).to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+        let report_response = client
+            .post(format!("/devices/{}/report", device_uuid))
+            .body(json!({ "valve_position": 50 }).to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::UnprocessableEntity, patch_response.status());
+        assert_eq!(Status::UnprocessableEntity, report_response.status());
+        assert_eq!(etag, get_etag());
+    }
+}
+
+mod get_devices_with_query {
+    use super::*;
+
+    fn get_devices_return_body(client: &Client, query: &str) -> Value {
+        let mut response = client
+            .get(format!("/devices?{}", query))
+            .header(ContentType::JSON)
+            .dispatch();
+        serde_json::from_str(&response.body_string().unwrap()).unwrap()
+    }
+
+    fn device_names(body: &Value) -> Vec<&str> {
+        let mut names: Vec<&str> = body["devices"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|device| device["name"].as_str().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn create_client_with_valves_and_sensor() -> Client {
+        let zone_uuid = Uuid::parse_str("c00727d8-eee8-4a0e-850e-b81a74440e78").unwrap();
+        let mut devices = DeviceCollection::new();
+        let mut hall = Device::new("Hall STRV".to_string(), Some(zone_uuid));
+        hall.set_setpoint(Temperature::from_celsius(21.0));
+        devices.add(Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap(), hall);
+        let mut landing = Device::new("Landing STRV".to_string(), None);
+        landing.set_setpoint(Temperature::from_celsius(17.0));
+        devices.add(Uuid::parse_str("88f573e2-d5de-11e8-9f8b-f2801f1b9fd1").unwrap(), landing);
+        let sensor = Device::with_type("Hall Sensor".to_string(), None, DeviceType::TemperatureSensor);
+        devices.add(Uuid::parse_str("8a70d6f5-a877-4331-8f15-0f20e4d34c0f").unwrap(), sensor);
+        create_client_with_mounts(devices)
+    }
+
+    #[test]
+    fn unassigned_returns_only_devices_without_a_zone() {
+        let client = create_client_with_valves_and_sensor();
+        let body = get_devices_return_body(&client, "unassigned=true");
+
+        assert_eq!(vec!["Hall Sensor", "Landing STRV"], device_names(&body));
+    }
+
+    #[test]
+    fn name_and_setpoint_filters_combine() {
+        let client = create_client_with_valves_and_sensor();
+        let body = get_devices_return_body(&client, "name=hall&setpoint_min=20.0");
+
+        assert_eq!(vec!["Hall STRV"], device_names(&body));
+    }
+
+    #[test]
+    fn online_returns_only_devices_that_reported_recently() {
+        let client = create_client_with_valves_and_sensor();
+        client
+            .post("/devices/8a70d6f5-a877-4331-8f15-0f20e4d34c0f/report")
+            .body(json!({ "battery": 90 }).to_string())
+            .header(ContentType::JSON)
+            .dispatch();
+
+        let body = get_devices_return_body(&client, "online=true");
+
+        assert_eq!(vec!["Hall Sensor"], device_names(&body));
+    }
+
+    #[test]
+    fn limit_and_cursor_page_through_sorted_devices() {
+        let client = create_client_with_valves_and_sensor();
+        let first = get_devices_return_body(&client, "sort=name&limit=2");
+        let cursor = first["next_cursor"].as_str().unwrap();
+        let second = get_devices_return_body(&client, &format!("sort=name&limit=2&cursor={}", cursor));
+
+        assert_eq!(vec!["Hall STRV", "Hall Sensor"], device_names(&first));
+        assert_eq!(vec!["Landing STRV"], device_names(&second));
+        assert!(second.get("next_cursor").is_none());
+    }
+}
+
+mod device_health {
+    use super::*;
+
+    fn post_report_return_response<'c>(client: &'c Client, uuid: Uuid, report: JsonValue) -> LocalResponse<'c> {
+        client
+            .post(format!("/devices/{}/report", uuid))
+            .body(report.to_string())
+            .header(ContentType::JSON)
+            .dispatch()
+    }
+
+    fn get_health_return_body(client: &Client) -> Value {
+        let mut response = client.get("/devices/health").header(ContentType::JSON).dispatch();
+        serde_json::from_str(&response.body_string().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn report_updates_device_health() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let report = json!({ "battery": 85, "rssi": -67, "link_quality": 180 });
+        let mut response = post_report_return_response(&client, device_uuid, report);
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        assert_eq!(85, body["health"]["battery"]);
+        assert_eq!(-67, body["health"]["rssi"]);
+        assert_eq!(180, body["health"]["link_quality"]);
+        assert!(body["health"]["last_seen"].is_u64());
+    }
+
+    #[test]
+    fn report_keeps_values_not_included() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        post_report_return_response(&client, device_uuid, json!({ "battery": 85 }));
+        let mut response = post_report_return_response(&client, device_uuid, json!({ "rssi": -70 }));
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        assert_eq!(85, body["health"]["battery"]);
+        assert_eq!(-70, body["health"]["rssi"]);
+    }
+
+    #[test]
+    fn report_records_temperature_and_fault() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let report = json!({ "temperature": 19.5, "fault": "Valve stuck" });
+        let mut response = post_report_return_response(&client, device_uuid, report);
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(19.5, body["temperature"]);
+        assert_eq!("Valve stuck", body["health"]["fault"]);
+
+        let mut response = post_report_return_response(&client, device_uuid, json!({}));
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(19.5, body["temperature"]);
+        assert!(body["health"].get("fault").is_none());
+    }
+
+    #[test]
+    fn report_records_readings_with_their_units() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(
+            device_uuid,
+            Device::with_type("Thermostat".to_string(), None, DeviceType::WallThermostat),
+        );
+        let client = create_client_with_mounts(devices);
+
+        let report = json!({ "temperature": 20.5, "readings": { "humidity": 48.0 } });
+        let mut response = post_report_return_response(&client, device_uuid, report);
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        assert_eq!(20.5, body["temperature"]);
+        assert_eq!(20.5, body["readings"]["temperature"]["value"]);
+        assert_eq!(48.0, body["readings"]["humidity"]["value"]);
+        assert_eq!("%", body["readings"]["humidity"]["unit"]);
+    }
+
+    #[test]
+    fn report_of_quantity_device_cannot_measure_returns_422() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let response = post_report_return_response(&client, device_uuid, json!({ "readings": { "co2": 800 } }));
+        assert_eq!(Status::UnprocessableEntity, response.status());
+
+        let report = json!({ "temperature": 19.0, "readings": { "humidity": 40.0 } });
+        let response = post_report_return_response(&client, device_uuid, report);
+        assert_eq!(Status::UnprocessableEntity, response.status());
+        let body = get_device_return_response_body_string(&client, &device_uuid.to_string());
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert!(body.get("temperature").is_none());
+    }
+
+    #[test]
+    fn report_records_valve_position() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let mut response = post_report_return_response(&client, device_uuid, json!({ "valve_position": 40 }));
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(40, body["valve_position"]);
+
+        let response = post_report_return_response(&client, device_uuid, json!({ "valve_position": 101 }));
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn valve_position_report_for_thermostat_returns_422() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(
+            device_uuid,
+            Device::with_type("Thermostat".to_string(), None, DeviceType::WallThermostat),
+        );
+        let client = create_client_with_mounts(devices);
+
+        let response = post_report_return_response(&client, device_uuid, json!({ "valve_position": 40 }));
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn battery_report_for_mains_powered_device_returns_422() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(
+            device_uuid,
+            Device::with_type("Boiler".to_string(), None, DeviceType::HeatSource),
+        );
+        let client = create_client_with_mounts(devices);
+
+        let response = post_report_return_response(&client, device_uuid, json!({ "battery": 50 }));
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn battery_report_over_100_returns_422_and_keeps_health() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let response = post_report_return_response(&client, device_uuid, json!({ "battery": 101 }));
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+        let body = get_device_return_response_body_string(&client, &device_uuid.to_string());
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert!(body.get("health").is_none());
+    }
+
+    #[test]
+    fn report_for_unknown_device_returns_404() {
+        let client = create_client_with_mounts(DeviceCollection::new());
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+
+        let response = post_report_return_response(&client, device_uuid, json!({ "battery": 50 }));
+
+        assert_eq!(Status::NotFound, response.status());
+    }
+
+    #[test]
+    fn summary_lists_low_battery_and_offline_devices() {
+        let low_battery_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let offline_uuid = Uuid::parse_str("88f573e2-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let healthy_uuid = Uuid::parse_str("c00727d8-eee8-4a0e-850e-b81a74440e78").unwrap();
+        let never_seen_uuid = Uuid::parse_str("92024abf-6f13-4e6f-b519-0176a16e4ee0").unwrap();
+
+        let mut low_battery = Device::new("Low".to_string(), None);
+        low_battery.report(Some(5), None, None, clock::now());
+        let mut offline = Device::new("Offline".to_string(), None);
+        offline.report(Some(90), None, None, 0);
+        let mut healthy = Device::new("Healthy".to_string(), None);
+        healthy.report(Some(90), None, None, clock::now());
+
+        let mut devices = DeviceCollection::new();
+        devices.add(low_battery_uuid, low_battery);
+        devices.add(offline_uuid, offline);
+        devices.add(healthy_uuid, healthy);
+        devices.add(never_seen_uuid, Device::new("New".to_string(), None));
+        let client = create_client_with_mounts(devices);
+
+        let body = get_health_return_body(&client);
+        let low_battery = body["low_battery"]["devices"].as_object().unwrap();
+        let offline = body["offline"]["devices"].as_object().unwrap();
+
+        assert_eq!(1, low_battery.len());
+        assert!(low_battery.contains_key(&low_battery_uuid.to_string()));
+        assert_eq!(1, offline.len());
+        assert!(offline.contains_key(&offline_uuid.to_string()));
+    }
 }
@@ -0,0 +1,31 @@
+use rocket::http::ContentType;
+use rocket::response::content::{Content, Html};
+use rocket::Rocket;
+use serde_yaml::Value as Yaml;
+
+mod page;
+#[cfg(test)]
+pub mod spec;
+
+pub const OPENAPI_SPEC: &str = include_str!("../../../openapi.yaml");
+
+fn field<'a>(value: &'a Yaml, name: &str) -> Option<&'a Yaml> {
+    value.as_mapping()?.get(&Yaml::String(name.to_string()))
+}
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/", routes![get_openapi_spec, get_docs])
+}
+
+#[get("/openapi.yaml")]
+fn get_openapi_spec() -> Content<&'static str> {
+    Content(ContentType::new("application", "x-yaml"), OPENAPI_SPEC)
+}
+
+#[get("/docs")]
+fn get_docs() -> Html<String> {
+    Html(page::render(OPENAPI_SPEC))
+}
+
+#[cfg(test)]
+mod tests;
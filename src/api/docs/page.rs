@@ -0,0 +1,129 @@
+use super::field;
+use serde_yaml::Value as Yaml;
+
+const LAYOUT: &str = include_str!("docs.html");
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn text<'a>(value: &'a Yaml, name: &str) -> &'a str {
+    field(value, name).and_then(Yaml::as_str).unwrap_or("")
+}
+
+fn resolve<'a>(root: &'a Yaml, value: &'a Yaml) -> &'a Yaml {
+    let reference = match field(value, "$ref").and_then(Yaml::as_str) {
+        Some(reference) if reference.starts_with("#/") => reference,
+        _ => return value,
+    };
+    reference[2..].split('/').try_fold(root, |target, name| field(target, name)).unwrap_or(value)
+}
+
+fn schema_name(schema: &Yaml) -> String {
+    if let Some(reference) = field(schema, "$ref").and_then(Yaml::as_str) {
+        return reference.rsplit('/').next().unwrap_or(reference).to_string();
+    }
+    match text(schema, "type") {
+        "array" => format!("array of {}", field(schema, "items").map_or(String::new(), schema_name)),
+        schema_type => schema_type.to_string(),
+    }
+}
+
+fn render_parameters(root: &Yaml, parameters: &[&Yaml], html: &mut String) {
+    if parameters.is_empty() {
+        return;
+    }
+    html.push_str("<table><tr><th>Parameter</th><th>In</th><th>Description</th></tr>");
+    for parameter in parameters {
+        let parameter = resolve(root, parameter);
+        let required = field(parameter, "required").and_then(Yaml::as_bool) == Some(true);
+        html.push_str(&format!(
+            "<tr><td><code>{}</code>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(text(parameter, "name")),
+            if required { " (required)" } else { "" },
+            escape(text(parameter, "in")),
+            escape(text(parameter, "description")),
+        ));
+    }
+    html.push_str("</table>");
+}
+
+fn render_body(content: Option<&Yaml>) -> String {
+    let schema = content
+        .and_then(Yaml::as_mapping)
+        .and_then(|content| content.iter().next())
+        .and_then(|(media_type, media)| Some((media_type.as_str()?, field(media, "schema")?)));
+    match schema {
+        Some((media_type, schema)) => format!(" <code>{}</code> {}", escape(media_type), escape(&schema_name(schema))),
+        None => String::new(),
+    }
+}
+
+fn render_operation(root: &Yaml, path: &str, method: &str, item: &Yaml, operation: &Yaml, html: &mut String) {
+    html.push_str(&format!(
+        "<section class=\"operation\"><h3><span class=\"method {}\">{}</span> <code>{}</code> {}</h3>",
+        method,
+        method.to_uppercase(),
+        escape(path),
+        escape(text(operation, "summary")),
+    ));
+    let description = text(operation, "description");
+    if !description.is_empty() {
+        html.push_str(&format!("<p>{}</p>", escape(description)));
+    }
+
+    let mut parameters = Vec::new();
+    for source in [item, operation].iter().cloned() {
+        if let Some(listed) = field(source, "parameters").and_then(Yaml::as_sequence) {
+            parameters.extend(listed);
+        }
+    }
+    render_parameters(root, &parameters, html);
+
+    if let Some(body) = field(operation, "requestBody") {
+        let body = resolve(root, body);
+        html.push_str(&format!("<p>Request body:{}</p>", render_body(field(body, "content"))));
+    }
+
+    html.push_str("<ul class=\"responses\">");
+    if let Some(responses) = field(operation, "responses").and_then(Yaml::as_mapping) {
+        for (status, response) in responses {
+            let status = match *status {
+                Yaml::Number(ref status) => status.to_string(),
+                ref status => status.as_str().unwrap_or("").to_string(),
+            };
+            let response = resolve(root, response);
+            html.push_str(&format!(
+                "<li><strong>{}</strong> {}{}</li>",
+                escape(&status),
+                escape(text(response, "description")),
+                render_body(field(response, "content")),
+            ));
+        }
+    }
+    html.push_str("</ul></section>");
+}
+
+pub fn render(spec: &str) -> String {
+    let root: Yaml = serde_yaml::from_str(spec).expect("openapi.yaml is not valid YAML");
+
+    let (title, description) = match field(&root, "info") {
+        Some(info) => (text(info, "title"), text(info, "description")),
+        None => ("", ""),
+    };
+
+    let mut html = format!("<h1>{}</h1><p>{}</p>", escape(title), escape(description));
+    if let Some(paths) = field(&root, "paths").and_then(Yaml::as_mapping) {
+        for (path, item) in paths {
+            let path = path.as_str().unwrap_or("");
+            for method in METHODS.iter() {
+                if let Some(operation) = field(item, method) {
+                    render_operation(&root, path, method, item, operation, &mut html);
+                }
+            }
+        }
+    }
+    LAYOUT.replace("{title}", &escape(title)).replace("{operations}", &html)
+}
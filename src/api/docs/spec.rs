@@ -0,0 +1,169 @@
+use super::{field, OPENAPI_SPEC};
+use serde_json::Value as Json;
+use serde_yaml::Value as Yaml;
+use uuid::Uuid;
+
+pub struct Spec {
+    root: Yaml,
+}
+
+fn match_template(template: &str, path: &str) -> Option<usize> {
+    let template: Vec<&str> = template.trim_end_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    if template.len() != path.len() {
+        return None;
+    }
+
+    let mut parameters = 0;
+    for (expected, actual) in template.iter().zip(path.iter()) {
+        if expected.starts_with('{') && expected.ends_with('}') {
+            parameters += 1;
+        } else if expected != actual {
+            return None;
+        }
+    }
+    Some(parameters)
+}
+
+impl Spec {
+    pub fn load() -> Spec {
+        Spec {
+            root: serde_yaml::from_str(OPENAPI_SPEC).expect("openapi.yaml is not valid YAML"),
+        }
+    }
+
+    fn operation(&self, method: &str, path: &str) -> Result<&Yaml, String> {
+        let path = path.split('?').next().unwrap_or(path);
+        let paths = field(&self.root, "paths")
+            .and_then(Yaml::as_mapping)
+            .ok_or("openapi.yaml has no paths")?;
+
+        paths
+            .iter()
+            .filter_map(|(template, item)| {
+                let parameters = match_template(template.as_str()?, path)?;
+                Some((parameters, field(item, &method.to_lowercase())?))
+            })
+            .min_by_key(|&(parameters, _)| parameters)
+            .map(|(_, operation)| operation)
+            .ok_or_else(|| format!("{} {} is not documented", method, path))
+    }
+
+    pub fn documents(&self, method: &str, path: &str) -> bool {
+        self.operation(method, path).is_ok()
+    }
+
+    pub fn check_response(&self, method: &str, path: &str, status: u16, body: &str) -> Result<(), String> {
+        let operation = self.operation(method, path)?;
+        let response = field(operation, "responses")
+            .and_then(Yaml::as_mapping)
+            .and_then(|responses| {
+                responses
+                    .iter()
+                    .find(|&(code, _)| code.as_u64() == Some(u64::from(status)))
+            })
+            .map(|(_, response)| response)
+            .ok_or_else(|| format!("{} {} does not document status {}", method, path, status))?;
+
+        match field(response, "content") {
+            Some(content) => match field(content, "application/json").and_then(|json| field(json, "schema")) {
+                Some(schema) => {
+                    let body: Json = serde_json::from_str(body)
+                        .map_err(|e| format!("{} {} returned invalid JSON: {}", method, path, e))?;
+                    self.check(schema, &body, "$")
+                        .map_err(|e| format!("{} {} {}: {}", method, path, status, e))
+                }
+                None => Ok(()),
+            },
+            None if body.is_empty() => Ok(()),
+            None => Err(format!("{} {} {} documents no body but returned {}", method, path, status, body)),
+        }
+    }
+
+    fn resolve<'a>(&'a self, schema: &'a Yaml) -> Result<&'a Yaml, String> {
+        let reference = match field(schema, "$ref").and_then(Yaml::as_str) {
+            Some(reference) => reference,
+            None => return Ok(schema),
+        };
+        if !reference.starts_with("#/") {
+            return Err(format!("unsupported $ref {}", reference));
+        }
+
+        let mut target = &self.root;
+        for name in reference[2..].split('/') {
+            target = field(target, name).ok_or_else(|| format!("$ref {} does not exist", reference))?;
+        }
+        self.resolve(target)
+    }
+
+    fn check(&self, schema: &Yaml, value: &Json, at: &str) -> Result<(), String> {
+        let schema = self.resolve(schema)?;
+        let is = |name: &str| field(schema, name).and_then(Yaml::as_bool) == Some(true);
+
+        if is("writeOnly") {
+            return Err(format!("{} is write-only but was returned", at));
+        }
+        if value.is_null() {
+            return if is("nullable") {
+                Ok(())
+            } else {
+                Err(format!("{} is null but not nullable", at))
+            };
+        }
+
+        let schema_type = field(schema, "type").and_then(Yaml::as_str);
+        let matches_type = match schema_type {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("string") => value.is_string(),
+            Some("number") => value.is_number(),
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("boolean") => value.is_boolean(),
+            Some(other) => return Err(format!("{} has unknown type {} in the spec", at, other)),
+            None => true,
+        };
+        if !matches_type {
+            return Err(format!("{} should be {} but is {}", at, schema_type.unwrap_or_default(), value));
+        }
+
+        if let Some(allowed) = field(schema, "enum").and_then(Yaml::as_sequence) {
+            if !allowed.iter().any(|allowed| allowed.as_str() == value.as_str()) {
+                return Err(format!("{} is {} which is not in the enum", at, value));
+            }
+        }
+        if field(schema, "format").and_then(Yaml::as_str) == Some("uuid") {
+            Uuid::parse_str(value.as_str().unwrap_or_default()).map_err(|_| format!("{} is not a UUID", at))?;
+        }
+
+        if let Some(object) = value.as_object() {
+            if let Some(required) = field(schema, "required").and_then(Yaml::as_sequence) {
+                for name in required.iter().filter_map(Yaml::as_str) {
+                    if !object.contains_key(name) {
+                        return Err(format!("{} is missing required property {}", at, name));
+                    }
+                }
+            }
+
+            for (name, property) in object {
+                let property_at = format!("{}.{}", at, name);
+                let property_schema = field(schema, "properties").and_then(|properties| field(properties, name));
+                match (property_schema, field(schema, "additionalProperties")) {
+                    (Some(property_schema), _) => self.check(property_schema, property, &property_at)?,
+                    (None, Some(Yaml::Bool(true))) => {}
+                    (None, Some(additional)) if additional.is_mapping() => {
+                        self.check(additional, property, &property_at)?
+                    }
+                    _ => return Err(format!("{} is not in the spec", property_at)),
+                }
+            }
+        }
+
+        if let (Some(array), Some(items)) = (value.as_array(), field(schema, "items")) {
+            for (index, item) in array.iter().enumerate() {
+                self.check(items, item, &format!("{}[{}]", at, index))?;
+            }
+        }
+
+        Ok(())
+    }
+}
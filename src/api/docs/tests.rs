@@ -0,0 +1,453 @@
+use super::spec::Spec;
+use super::*;
+use alert::{AlertEngine, AlertEngineState};
+use api::{
+    audit, backup, batch, calendar, devices, energy, heat_source, history, preferences, presence, rules, scenes, zones,
+};
+use audit::{AuditLog, AuditLogState, AuditRetention};
+use calendar::{Calendar, CalendarConfig, CalendarState};
+use clock;
+use device::{Device, DeviceCollection, DeviceCollectionState, DeviceType};
+use energy::{EnergyLedger, EnergyLedgerState, Tariff};
+use hal::{FakeOutput, Hal, HalState, Relay};
+use history::{HistoryRetention, ReadingHistory, ReadingHistoryState};
+use presence::{GracePeriods, Presence, PresenceState};
+use reading::Quantity;
+use rocket::http::{ContentType, Method, Status};
+use rocket::local::Client;
+use rule::{RuleEngine, RuleEngineState};
+use scene::{SceneCollection, SceneCollectionState};
+use std::sync::Mutex;
+use temperature::{UnitPreferences, UnitPreferencesState};
+use uuid::Uuid;
+use weather::{CompensationCurve, WeatherCompensation, WeatherCompensationState};
+use zone::{Zone, ZoneCollection, ZoneCollectionState};
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+const VALVE_UUID: &str = "84fa1356-d5de-11e8-9f8b-f2801f1b9fd1";
+const SENSOR_UUID: &str = "88f573e2-d5de-11e8-9f8b-f2801f1b9fd1";
+const RELAY_UUID: &str = "56fd8e82-d260-46df-809f-662f070e9e1e";
+
+fn create_client_with_mounts() -> Client {
+    let rocket = rocket::ignite();
+    let rocket = mount(rocket);
+    Client::new(rocket).unwrap()
+}
+
+fn create_zones_and_devices_rocket() -> Rocket {
+    let zone_uuid = Uuid::parse_str(ZONE_UUID).unwrap();
+    let mut zones = ZoneCollection::new();
+    zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+
+    let mut devices = DeviceCollection::new();
+    let mut valve = Device::new("STRV".to_string(), Some(zone_uuid));
+    valve.validate().unwrap();
+    valve.set_valve_position(40);
+    valve.set_reading(Quantity::Temperature, 19.5);
+    devices.add(Uuid::parse_str(VALVE_UUID).unwrap(), valve);
+    let mut sensor = Device::with_type("Hall Sensor".to_string(), None, DeviceType::TemperatureSensor);
+    sensor.set_reading(Quantity::Temperature, 20.5);
+    sensor.set_reading(Quantity::Co2, 650.0);
+    devices.add(Uuid::parse_str(SENSOR_UUID).unwrap(), sensor);
+    let relay_uuid = Uuid::parse_str(RELAY_UUID).unwrap();
+    devices.add(relay_uuid, Device::with_type("Boiler".to_string(), None, DeviceType::Relay));
+
+    let mut hal = Hal::new();
+    hal.attach_relay(relay_uuid, Relay::new(Box::new(FakeOutput::new()), false));
+
+    let mut ledger = EnergyLedger::new(Tariff::default());
+    ledger.sample(clock::now() - 60, &zones, &devices);
+    ledger.sample(clock::now(), &zones, &devices);
+    let mut readings = ReadingHistory::new(HistoryRetention::default());
+    readings.sample(clock::now(), &zones, &devices);
+
+    let rocket = rocket::ignite()
+        .manage(HalState::new(hal))
+        .manage(AlertEngineState::new(Mutex::new(AlertEngine::new(AlertEngine::default_rules()))));
+    let rocket = backup::mount(rocket);
+    let rocket = batch::mount(rocket);
+    let rocket = audit::mount(rocket, AuditLogState::new(Mutex::new(AuditLog::new(AuditRetention::default()))));
+    let rocket = zones::mount(rocket, ZoneCollectionState::new(Mutex::new(zones)));
+    let rocket = devices::mount(rocket, DeviceCollectionState::new(Mutex::new(devices)));
+    let rocket = scenes::mount(rocket, SceneCollectionState::new(Mutex::new(SceneCollection::new())));
+    let rocket = rules::mount(rocket, RuleEngineState::new(Mutex::new(RuleEngine::new())));
+    let rocket = presence::mount(rocket, PresenceState::new(Mutex::new(Presence::new(GracePeriods::default()))));
+    let mut weather = WeatherCompensation::new(CompensationCurve::default());
+    weather.record(0, Ok(4.0), &mut ZoneCollection::new());
+    let rocket = heat_source::mount(rocket, WeatherCompensationState::new(Mutex::new(weather)));
+    let rocket = calendar::mount(rocket, CalendarState::new(Mutex::new(Calendar::new(CalendarConfig::default()))));
+    let rocket = energy::mount(rocket, EnergyLedgerState::new(Mutex::new(ledger)));
+    let rocket = history::mount(rocket, ReadingHistoryState::new(Mutex::new(readings)));
+    preferences::mount(rocket, UnitPreferencesState::new(Mutex::new(UnitPreferences::new())))
+}
+
+fn dispatch_and_check(client: &Client, spec: &Spec, method: Method, path: &str, body: Option<&str>) -> Status {
+    let mut request = client.req(method, path).header(ContentType::JSON);
+    if let Some(body) = body {
+        request.set_body(body);
+    }
+    let mut response = request.dispatch();
+    let response_body = response.body_string().unwrap_or_default();
+
+    if let Err(error) = spec.check_response(method.as_str(), path, response.status().code, &response_body) {
+        panic!("{}", error);
+    }
+    response.status()
+}
+
+#[test]
+fn get_openapi_yaml_returns_the_spec() {
+    let client = create_client_with_mounts();
+    let mut response = client.get("/openapi.yaml").dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(Some(ContentType::new("application", "x-yaml")), response.content_type());
+    assert_eq!(Some(OPENAPI_SPEC.to_string()), response.body_string());
+}
+
+#[test]
+fn get_docs_returns_page_rendering_the_spec() {
+    let client = create_client_with_mounts();
+    let mut response = client.get("/docs").dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(Some(ContentType::HTML), response.content_type());
+    let body = response.body_string().unwrap();
+    assert!(body.contains("<title>Home Automation in Rust - API</title>"));
+    assert!(body.contains("<span class=\"method patch\">PATCH</span> <code>/zones/{zone_uuid}</code>"));
+    assert!(!body.contains("<script"));
+}
+
+#[test]
+fn every_zone_and_device_route_is_documented() {
+    let spec = Spec::load();
+    let rocket = create_zones_and_devices_rocket();
+
+    for route in rocket.routes() {
+        let path: Vec<String> = route
+            .uri
+            .path()
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with('<') {
+                    Uuid::nil().to_string()
+                } else {
+                    segment.to_string()
+                }
+            })
+            .collect();
+        let path = path.join("/");
+
+        assert!(
+            spec.documents(route.method.as_str(), &path),
+            "{} {} is not in openapi.yaml",
+            route.method,
+            route.uri.path()
+        );
+    }
+}
+
+#[test]
+fn zone_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let zone_path = format!("/zones/{}", ZONE_UUID);
+
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/zones", None));
+    assert_eq!(
+        Status::Created,
+        dispatch_and_check(&client, &spec, Method::Post, "/zones", Some(r#"{"name":"Bedroom","setpoint":18.0}"#))
+    );
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, &zone_path, None));
+    let zone = r#"{"name":"Lounge","setpoint":20.0}"#;
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Put, &zone_path, Some(zone)));
+    let room = format!(r#"{{"name":"Snug","setpoint":20.0,"parent_uuid":"{}"}}"#, ZONE_UUID);
+    assert_eq!(Status::Created, dispatch_and_check(&client, &spec, Method::Post, "/zones", Some(&room)));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/zones?tree=true", None));
+    let new_zone_path = format!("/zones/{}", Uuid::nil());
+    assert_eq!(Status::Created, dispatch_and_check(&client, &spec, Method::Put, &new_zone_path, Some(zone)));
+    let zone_devices_path = format!("{}/devices", zone_path);
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, &zone_devices_path, None));
+    assert_eq!(
+        Status::Ok,
+        dispatch_and_check(&client, &spec, Method::Patch, &zone_path, Some(r#"{"setpoint":21.5}"#))
+    );
+    assert_eq!(Status::NoContent, dispatch_and_check(&client, &spec, Method::Delete, &zone_path, None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/audit", None));
+}
+
+#[test]
+fn device_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let valve_path = format!("/devices/{}", VALVE_UUID);
+    let sensor_report_path = format!("/devices/{}/report", SENSOR_UUID);
+    let relay_path = format!("/devices/{}", RELAY_UUID);
+
+    let report = r#"{"battery":85,"rssi":-67,"temperature":19.5,"readings":{"humidity":48.0},"fault":"E119"}"#;
+    assert_eq!(
+        Status::Ok,
+        dispatch_and_check(&client, &spec, Method::Post, &sensor_report_path, Some(report))
+    );
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/devices", None));
+    assert_eq!(
+        Status::Ok,
+        dispatch_and_check(&client, &spec, Method::Get, &format!("/devices?zone_uuid={}", ZONE_UUID), None)
+    );
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/devices?type=relay", None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/devices?zone_uuid=none", None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/devices/types", None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/devices/health", None));
+    assert_eq!(
+        Status::Created,
+        dispatch_and_check(&client, &spec, Method::Post, "/devices", Some(r#"{"name":"STRV Two","zone_uuid":null}"#))
+    );
+    let sensor_with_setpoint = r#"{"name":"Sensor","type":"temperature_sensor","setpoint":20.0,"zone_uuid":null}"#;
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Post, "/devices", Some(sensor_with_setpoint))
+    );
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, &valve_path, None));
+    let valve = r#"{"name":"STRV One","zone_uuid":null}"#;
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Put, &valve_path, Some(valve)));
+    assert_eq!(
+        Status::Ok,
+        dispatch_and_check(&client, &spec, Method::Patch, &valve_path, Some(r#"{"name":"STRV One","setpoint":20.0}"#))
+    );
+    assert_eq!(
+        Status::Ok,
+        dispatch_and_check(&client, &spec, Method::Patch, &relay_path, Some(r#"{"demand":true}"#))
+    );
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Patch, &relay_path, Some(r#"{"setpoint":20.0}"#))
+    );
+    assert_eq!(
+        Status::NoContent,
+        dispatch_and_check(&client, &spec, Method::Delete, &format!("{}?tombstone=true", valve_path), None)
+    );
+    let relay_report_path = format!("/devices/{}/report", RELAY_UUID);
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Post, &relay_report_path, Some(r#"{"temperature":19.5}"#))
+    );
+}
+
+#[test]
+fn backup_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let backup = client.get("/backup").header(ContentType::JSON).dispatch().body_string().unwrap();
+
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/backup", None));
+    assert_eq!(
+        Status::NoContent,
+        dispatch_and_check(&client, &spec, Method::Post, "/restore?mode=merge", Some(&backup))
+    );
+    let future_backup = r#"{"format_version":3,"created_at":0,"zones":{},"devices":{},"alert_rules":[]}"#;
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Post, "/restore", Some(future_backup))
+    );
+}
+
+#[test]
+fn batch_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let batch = format!(
+        r#"{{"operations":[
+            {{"action":"create","resource_type":"zone","body":{{"name":"Bedroom","setpoint":18.0}}}},
+            {{"action":"update","resource_type":"device","uuid":"{}","body":{{"zone_uuid":null}}}},
+            {{"action":"delete","resource_type":"device","uuid":"{}"}}
+        ]}}"#,
+        VALVE_UUID, SENSOR_UUID
+    );
+    let missing_zone = format!(
+        r#"{{"operations":[{{"action":"update","resource_type":"zone","uuid":"{}","body":{{}}}}]}}"#,
+        Uuid::nil()
+    );
+
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Post, "/batch", Some(&batch)));
+    assert_eq!(
+        Status::NotFound,
+        dispatch_and_check(&client, &spec, Method::Post, "/batch", Some(&missing_zone))
+    );
+}
+
+#[test]
+fn scene_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let scene = format!(
+        r#"{{"name":"Evening","zones":{{"{}":{{"setpoint":19.0}}}},"devices":{{"{}":{{"setpoint":20.0}}}}}}"#,
+        ZONE_UUID, VALVE_UUID
+    );
+    let scene_path = format!("/scenes/{}", Uuid::nil());
+
+    assert_eq!(Status::Created, dispatch_and_check(&client, &spec, Method::Put, &scene_path, Some(&scene)));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Put, &scene_path, Some(&scene)));
+    assert_eq!(Status::Created, dispatch_and_check(&client, &spec, Method::Post, "/scenes", Some(&scene)));
+    let unknown_zone = format!(r#"{{"name":"Evening","zones":{{"{}":{{"setpoint":19.0}}}}}}"#, Uuid::nil());
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Post, "/scenes", Some(&unknown_zone))
+    );
+    let activate_path = format!("{}/activate", scene_path);
+    assert_eq!(Status::NoContent, dispatch_and_check(&client, &spec, Method::Post, &activate_path, None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/scenes", None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, &scene_path, None));
+
+    let valve_path = format!("/devices/{}", VALVE_UUID);
+    client.delete(valve_path).header(ContentType::JSON).dispatch();
+    assert_eq!(Status::Conflict, dispatch_and_check(&client, &spec, Method::Post, &activate_path, None));
+    assert_eq!(Status::NoContent, dispatch_and_check(&client, &spec, Method::Delete, &scene_path, None));
+}
+
+#[test]
+fn rule_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let rule = format!(
+        r#"{{"name":"Cold hall","trigger":{{"type":"reading","subject_uuid":"{}","below":16.0}},
+            "conditions":[{{"type":"time_between","from":"06:00","to":"23:00"}}],
+            "actions":[{{"type":"set_setpoint","subject_uuid":"{}","setpoint":21.0,"minutes":60}}]}}"#,
+        SENSOR_UUID, ZONE_UUID
+    );
+    let rule_path = format!("/rules/{}", Uuid::nil());
+
+    assert_eq!(Status::Created, dispatch_and_check(&client, &spec, Method::Put, &rule_path, Some(&rule)));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Put, &rule_path, Some(&rule)));
+    assert_eq!(Status::Created, dispatch_and_check(&client, &spec, Method::Post, "/rules", Some(&rule)));
+    let no_actions = r#"{"name":"Doorbell","trigger":{"type":"event","name":"doorbell"},"actions":[]}"#;
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Post, "/rules", Some(no_actions))
+    );
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/rules", None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, &rule_path, None));
+
+    let boost = r#"{"name":"Doorbell","trigger":{"type":"event","name":"doorbell"},
+        "actions":[{"type":"adjust_setpoint","delta":1.5,"minutes":30}]}"#;
+    assert_eq!(
+        Status::Ok,
+        dispatch_and_check(&client, &spec, Method::Post, "/rules/dry_run?event=doorbell", Some(boost))
+    );
+    assert_eq!(Status::Created, dispatch_and_check(&client, &spec, Method::Post, "/rules", Some(boost)));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Post, "/rules/events/doorbell", None));
+    assert_eq!(Status::NoContent, dispatch_and_check(&client, &spec, Method::Delete, &rule_path, None));
+}
+
+#[test]
+fn presence_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let zone_path = format!("/presence/zones/{}", ZONE_UUID);
+    let setpoints = r#"{"comfort_setpoint":21.0,"eco_setpoint":17.0}"#;
+
+    assert_eq!(Status::Created, dispatch_and_check(&client, &spec, Method::Put, &zone_path, Some(setpoints)));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Put, &zone_path, Some(setpoints)));
+    let unknown_zone_path = format!("/presence/zones/{}", Uuid::nil());
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Put, &unknown_zone_path, Some(setpoints))
+    );
+    let arrival = format!(r#"{{"movement":"arrive","zone_uuid":"{}"}}"#, ZONE_UUID);
+    assert_eq!(
+        Status::NoContent,
+        dispatch_and_check(&client, &spec, Method::Post, "/presence/agents/phone", Some(&arrival))
+    );
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/presence", None));
+    assert_eq!(
+        Status::NoContent,
+        dispatch_and_check(&client, &spec, Method::Delete, "/presence/agents/phone", None)
+    );
+    assert_eq!(Status::NoContent, dispatch_and_check(&client, &spec, Method::Delete, &zone_path, None));
+}
+
+#[test]
+fn heat_source_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let curve = r#"{"mild_outdoor":16.0,"design_outdoor":-8.0,"min_flow":28.0,"max_flow":60.0,
+        "max_setpoint_offset":1.0}"#;
+
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/heat_source", None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Put, "/heat_source/curve", Some(curve)));
+    let inverted = r#"{"mild_outdoor":-8.0,"design_outdoor":16.0,"min_flow":28.0,"max_flow":60.0}"#;
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Put, "/heat_source/curve", Some(inverted))
+    );
+}
+
+#[test]
+fn calendar_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:Living Room: 21\r\nDTSTART:20000101T000000Z\r\n\
+        DTEND:21000101T000000Z\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+
+    let mut response = client
+        .put("/calendar")
+        .header(ContentType::new("text", "calendar"))
+        .body(ics)
+        .dispatch();
+    assert_eq!(Status::Ok, response.status());
+    spec.check_response("PUT", "/calendar", 200, &response.body_string().unwrap()).unwrap();
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/calendar", None));
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Put, "/calendar/file", Some(r#"{"path":"/nonexistent.ics"}"#))
+    );
+    assert_eq!(Status::NoContent, dispatch_and_check(&client, &spec, Method::Delete, "/calendar", None));
+}
+
+#[test]
+fn energy_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let tariff = r#"{"heat_source_kw":18.0,"price_per_kwh":0.12,
+        "periods":[{"from":"00:30","to":"04:30","price_per_kwh":0.07}]}"#;
+
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/energy", None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/energy?period=month&count=2", None));
+    assert_eq!(
+        Status::UnprocessableEntity,
+        dispatch_and_check(&client, &spec, Method::Get, "/energy?count=1000", None)
+    );
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Put, "/energy/tariff", Some(tariff)));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/energy/tariff", None));
+}
+
+#[test]
+fn readings_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let zone_readings_path = format!("/zones/{}/readings", ZONE_UUID);
+    let history_path = format!("/history/{}", ZONE_UUID);
+
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, &zone_readings_path, None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, &history_path, None));
+    let device_history_path = format!("/history/{}?quantity=co2&since=0", SENSOR_UUID);
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, &device_history_path, None));
+    let unknown_path = format!("/history/{}", Uuid::nil());
+    assert_eq!(Status::NotFound, dispatch_and_check(&client, &spec, Method::Get, &unknown_path, None));
+}
+
+#[test]
+fn preferences_responses_match_the_spec() {
+    let spec = Spec::load();
+    let client = Client::new(create_zones_and_devices_rocket()).unwrap();
+    let preferences = r#"{"temperature_unit":"fahrenheit"}"#;
+
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, "/preferences", None));
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Put, "/preferences", Some(preferences)));
+    assert_eq!(Status::NoContent, dispatch_and_check(&client, &spec, Method::Delete, "/preferences", None));
+    let zone_path = format!("/zones/{}?unit=fahrenheit", ZONE_UUID);
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, &zone_path, None));
+    let devices_path = "/devices?unit=F";
+    assert_eq!(Status::Ok, dispatch_and_check(&client, &spec, Method::Get, devices_path, None));
+    let unknown_unit_path = format!("/zones/{}?unit=kelvin", ZONE_UUID);
+    assert_eq!(Status::BadRequest, dispatch_and_check(&client, &spec, Method::Get, &unknown_unit_path, None));
+}
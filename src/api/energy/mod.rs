@@ -0,0 +1,67 @@
+use clock;
+use energy::{EnergyLedgerState, Period, Tariff};
+use rocket::http::{RawStr, Status};
+use rocket::request::FromFormValue;
+use rocket::response::status;
+use rocket::{Rocket, State};
+use rocket_contrib::json::{Json, JsonValue};
+
+const MAX_COUNT: usize = 366;
+
+pub fn mount(rocket: Rocket, energy: EnergyLedgerState) -> Rocket {
+    rocket
+        .mount("/energy", routes![get_energy, get_tariff, put_tariff])
+        .manage(energy)
+}
+
+impl<'v> FromFormValue<'v> for Period {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<Period, &'v RawStr> {
+        match form_value.as_str() {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            "month" => Ok(Period::Month),
+            _ => Err(form_value),
+        }
+    }
+}
+
+#[get("/?<period>&<count>", format = "application/json")]
+fn get_energy(
+    period: Option<Period>,
+    count: Option<usize>,
+    energy: State<EnergyLedgerState>,
+) -> Result<JsonValue, status::Custom<String>> {
+    let period = period.unwrap_or(Period::Day);
+    let count = count.unwrap_or_else(|| period.default_count());
+    if count == 0 || count > MAX_COUNT {
+        let error = format!("count must be from 1 to {}", MAX_COUNT);
+        return Err(status::Custom(Status::UnprocessableEntity, error));
+    }
+
+    let reports = energy.lock().unwrap().report(period, clock::now(), count);
+    Ok(json!({ "period": period, "reports": reports }))
+}
+
+#[get("/tariff", format = "application/json")]
+fn get_tariff(energy: State<EnergyLedgerState>) -> Json<Tariff> {
+    Json(energy.lock().unwrap().tariff().clone())
+}
+
+#[put("/tariff", format = "application/json", data = "<tariff>")]
+fn put_tariff(
+    tariff: Json<Tariff>,
+    energy: State<EnergyLedgerState>,
+) -> Result<Json<Tariff>, status::Custom<String>> {
+    let tariff = tariff.into_inner();
+    energy
+        .lock()
+        .unwrap()
+        .set_tariff(tariff.clone())
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    Ok(Json(tariff))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,118 @@
+use super::*;
+use device::{Device, DeviceCollection, DeviceType};
+use energy::EnergyLedger;
+use fixtures::response_body_json;
+use rocket::http::ContentType;
+use rocket::local::{Client, LocalResponse};
+use std::sync::Mutex;
+use uuid::Uuid;
+use zone::{Zone, ZoneCollection};
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+
+fn create_client(energy: EnergyLedgerState) -> Client {
+    Client::new(mount(rocket::ignite(), energy)).unwrap()
+}
+
+fn new_energy() -> EnergyLedgerState {
+    EnergyLedgerState::new(Mutex::new(EnergyLedger::new(Tariff::default())))
+}
+
+fn energy_with_boiler_run() -> EnergyLedgerState {
+    let zone_uuid = Uuid::parse_str(ZONE_UUID).unwrap();
+    let mut zones = ZoneCollection::new();
+    zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+    let mut devices = DeviceCollection::new();
+    let mut boiler = Device::with_type("Boiler".to_string(), None, DeviceType::HeatSource);
+    boiler.set_demand(true);
+    devices.add(Uuid::new_v4(), boiler);
+    let mut valve = Device::new("STRV".to_string(), Some(zone_uuid));
+    valve.set_valve_position(60);
+    devices.add(Uuid::new_v4(), valve);
+
+    let energy = new_energy();
+    let now = clock::now();
+    energy.lock().unwrap().sample(now - 120, &zones, &devices);
+    energy.lock().unwrap().sample(now, &zones, &devices);
+    energy
+}
+
+fn get_energy<'c>(client: &'c Client, query: &str) -> LocalResponse<'c> {
+    client.get(format!("/energy{}", query)).header(ContentType::JSON).dispatch()
+}
+
+fn put_tariff<'c>(client: &'c Client, tariff: JsonValue) -> LocalResponse<'c> {
+    client
+        .put("/energy/tariff")
+        .header(ContentType::JSON)
+        .body(tariff.to_string())
+        .dispatch()
+}
+
+#[test]
+fn get_energy_returns_a_week_of_daily_reports_by_default() {
+    let client = create_client(new_energy());
+
+    let mut response = get_energy(&client, "");
+
+    assert_eq!(Status::Ok, response.status());
+    let body = response_body_json(&mut response);
+    assert_eq!("day", body["period"]);
+    assert_eq!(7, body["reports"].as_array().unwrap().len());
+    assert_eq!(0.0, body["reports"][0]["total"]["energy_kwh"]);
+}
+
+#[test]
+fn monthly_report_apportions_energy_to_the_zone() {
+    let client = create_client(energy_with_boiler_run());
+
+    // Two months, in case the run was split over the turn of a month.
+    let mut response = get_energy(&client, "?period=month&count=2");
+
+    let body = response_body_json(&mut response);
+    let reports = body["reports"].as_array().unwrap();
+    assert_eq!(2, reports.len());
+    let zone_energy: f64 = reports
+        .iter()
+        .filter_map(|report| report["zones"][ZONE_UUID]["energy_kwh"].as_f64())
+        .sum();
+    assert!((zone_energy - 0.8).abs() < 1e-9, "{}", zone_energy);
+}
+
+#[test]
+fn get_energy_with_invalid_count_returns_422() {
+    let client = create_client(new_energy());
+
+    let response = get_energy(&client, "?period=week&count=0");
+
+    assert_eq!(Status::UnprocessableEntity, response.status());
+}
+
+#[test]
+fn put_tariff_replaces_it() {
+    let client = create_client(new_energy());
+    let tariff = json!({
+        "heat_source_kw": 18,
+        "price_per_kwh": 0.12,
+        "periods": [{ "from": "00:30", "to": "04:30", "price_per_kwh": 0.07 }]
+    });
+
+    let response = put_tariff(&client, tariff);
+
+    assert_eq!(Status::Ok, response.status());
+    let mut response = client.get("/energy/tariff").header(ContentType::JSON).dispatch();
+    let body = response_body_json(&mut response);
+    assert_eq!(18.0, body["heat_source_kw"]);
+    assert_eq!("04:30", body["periods"][0]["to"]);
+}
+
+#[test]
+fn put_negative_price_returns_422() {
+    let client = create_client(new_energy());
+
+    let response = put_tariff(&client, json!({ "price_per_kwh": -0.1 }));
+
+    assert_eq!(Status::UnprocessableEntity, response.status());
+    let mut response = client.get("/energy/tariff").header(ContentType::JSON).dispatch();
+    assert_eq!(0.1, response_body_json(&mut response)["price_per_kwh"]);
+}
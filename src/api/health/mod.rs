@@ -0,0 +1,108 @@
+use alert::AlertEngineState;
+use calendar::CalendarState;
+use clock::{self, Timestamp};
+use device::DeviceCollectionState;
+use energy::EnergyLedgerState;
+use hal::HalState;
+use history::ReadingHistoryState;
+use presence::PresenceState;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Rocket, State};
+use rocket_contrib::json::JsonValue;
+use rule::RuleEngineState;
+use serde_json::{Map, Value};
+use std::sync::Mutex;
+use weather::WeatherCompensationState;
+use zone::ZoneCollectionState;
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/health", routes![get_live, get_ready])
+}
+
+fn status_str(up: bool) -> &'static str {
+    if up {
+        "up"
+    } else {
+        "down"
+    }
+}
+
+fn job<T>(state: &Mutex<T>, check: impl Fn(&T) -> (bool, Option<Timestamp>)) -> (bool, Option<Timestamp>) {
+    state.lock().map_or((false, None), |state| check(&state))
+}
+
+#[get("/live")]
+fn get_live() -> JsonValue {
+    json!({ "status": "up" })
+}
+
+#[get("/ready")]
+fn get_ready(
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    hal: State<HalState>,
+    alerts: State<AlertEngineState>,
+    rules: State<RuleEngineState>,
+    presence: State<PresenceState>,
+    weather: State<WeatherCompensationState>,
+    calendar: State<CalendarState>,
+    energy: State<EnergyLedgerState>,
+    history: State<ReadingHistoryState>,
+) -> status::Custom<JsonValue> {
+    let storage_up = zones.lock().is_ok() && devices.lock().is_ok();
+
+    let (hal_up, relays, faults) = match hal.lock() {
+        Ok(hal) => {
+            let mut relays = 0;
+            let mut faults = Map::new();
+            for (device_uuid, relay) in hal.relays() {
+                relays += 1;
+                if let Some(fault) = relay.fault() {
+                    faults.insert(device_uuid.to_string(), Value::String(fault.to_string()));
+                }
+            }
+            (faults.is_empty(), relays, faults)
+        }
+        Err(_) => (false, 0, Map::new()),
+    };
+
+    let now = clock::now();
+    let mut jobs = vec![
+        ("alerts", job(&alerts, |job| (job.is_evaluating(now), job.last_evaluated()))),
+        ("rules", job(&rules, |job| (job.is_evaluating(now), job.last_evaluated()))),
+        ("presence", job(&presence, |job| (job.is_evaluating(now), job.last_evaluated()))),
+        ("calendar", job(&calendar, |job| (job.is_evaluating(now), job.last_evaluated()))),
+        ("energy", job(&energy, |job| (job.is_sampling(now), job.last_sampled()))),
+        ("history", job(&history, |job| (job.is_sampling(now), job.last_sampled()))),
+    ];
+    if weather.lock().map_or(true, |weather| weather.has_evaluator()) {
+        jobs.push(("weather", job(&weather, |job| (job.is_evaluating(now), job.last_evaluated()))));
+    }
+    let scheduler_up = jobs.iter().all(|&(_, (up, _))| up);
+    // The least recent run, or null if any job has not run yet.
+    let last_run = jobs.iter().map(|&(_, (_, last_run))| last_run).min().unwrap_or(None);
+    let jobs: Map<String, Value> = jobs
+        .into_iter()
+        .map(|(name, (up, last_run))| {
+            let job = json!({ "status": status_str(up), "last_run": last_run });
+            (name.to_string(), job.into_inner())
+        })
+        .collect();
+
+    let up = storage_up && hal_up && scheduler_up;
+    let body = json!({
+        "status": status_str(up),
+        "checks": {
+            "storage": { "status": status_str(storage_up) },
+            "hal": { "status": status_str(hal_up), "relays": relays, "faults": faults },
+            "scheduler": { "status": status_str(scheduler_up), "jobs": jobs, "last_run": last_run },
+        }
+    });
+
+    let status = if up { Status::Ok } else { Status::ServiceUnavailable };
+    status::Custom(status, body)
+}
+
+#[cfg(test)]
+mod tests;
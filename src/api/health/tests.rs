@@ -0,0 +1,158 @@
+use super::*;
+use alert::AlertEngine;
+use calendar::{Calendar, CalendarConfig};
+use device::{DeviceCollection, HealthPolicy};
+use energy::{EnergyLedger, Tariff};
+use hal::{Hal, HalError, OutputDriver, Relay};
+use history::{HistoryRetention, ReadingHistory};
+use presence::{GracePeriods, Presence};
+use rocket::http::{ContentType, Status};
+use rocket::local::Client;
+use rule::{Home, RuleEngine};
+use scene::SceneCollection;
+use uuid::Uuid;
+use weather::{CompensationCurve, WeatherCompensation};
+use zone::ZoneCollection;
+
+struct BrokenOutput;
+
+impl OutputDriver for BrokenOutput {
+    fn write(&mut self, _: bool) -> Result<(), HalError> {
+        Err(HalError::Gpio("line busy".to_string()))
+    }
+}
+
+fn evaluated_engine() -> AlertEngine {
+    let mut engine = AlertEngine::new(AlertEngine::default_rules());
+    engine.evaluate(
+        clock::now(),
+        &ZoneCollection::new(),
+        &DeviceCollection::new(),
+        &HealthPolicy::default(),
+    );
+    engine
+}
+
+fn evaluated_rules() -> RuleEngine {
+    let mut rules = RuleEngine::new();
+    rules.evaluate(
+        clock::now(),
+        &mut Home {
+            zones: &mut ZoneCollection::new(),
+            devices: &mut DeviceCollection::new(),
+            scenes: &mut SceneCollection::new(),
+            policy: &HealthPolicy::default(),
+        },
+    );
+    rules
+}
+
+fn create_client_with_mounts(hal: Hal, engine: AlertEngine) -> Client {
+    create_client_with_mounts_and_rules(hal, engine, evaluated_rules())
+}
+
+fn create_client_with_mounts_and_rules(hal: Hal, engine: AlertEngine, rules: RuleEngine) -> Client {
+    let now = clock::now();
+    let (mut zones, devices) = (ZoneCollection::new(), DeviceCollection::new());
+    let mut presence = Presence::new(GracePeriods::default());
+    presence.evaluate(now, &mut zones);
+    let mut calendar = Calendar::new(CalendarConfig::default());
+    calendar.evaluate(now, &mut zones);
+    let mut energy = EnergyLedger::new(Tariff::default());
+    energy.sample(now, &zones, &devices);
+    let mut history = ReadingHistory::new(HistoryRetention::default());
+    history.sample(now, &zones, &devices);
+
+    let rocket = rocket::ignite()
+        .manage(ZoneCollectionState::new(Mutex::new(zones)))
+        .manage(DeviceCollectionState::new(Mutex::new(devices)))
+        .manage(HalState::new(hal))
+        .manage(AlertEngineState::new(Mutex::new(engine)))
+        .manage(RuleEngineState::new(Mutex::new(rules)))
+        .manage(PresenceState::new(Mutex::new(presence)))
+        .manage(WeatherCompensationState::new(Mutex::new(WeatherCompensation::new(CompensationCurve::default()))))
+        .manage(CalendarState::new(Mutex::new(calendar)))
+        .manage(EnergyLedgerState::new(Mutex::new(energy)))
+        .manage(ReadingHistoryState::new(Mutex::new(history)));
+    let rocket = mount(rocket);
+    Client::new(rocket).unwrap()
+}
+
+fn get_ready_return_status_and_body(client: &Client) -> (Status, JsonValue) {
+    let mut response = client.get("/health/ready").header(ContentType::JSON).dispatch();
+    let body = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+    (response.status(), body)
+}
+
+#[test]
+fn live_returns_up() {
+    let client = create_client_with_mounts(Hal::new(), evaluated_engine());
+
+    let mut response = client.get("/health/live").header(ContentType::JSON).dispatch();
+    let body: JsonValue = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(json!({ "status": "up" }), body);
+}
+
+#[test]
+fn ready_with_everything_up_returns_200() {
+    let client = create_client_with_mounts(Hal::new(), evaluated_engine());
+
+    let (status, body) = get_ready_return_status_and_body(&client);
+
+    assert_eq!(Status::Ok, status);
+    assert_eq!("up", body["status"]);
+    assert_eq!("up", body["checks"]["storage"]["status"]);
+    assert_eq!("up", body["checks"]["hal"]["status"]);
+    assert_eq!("up", body["checks"]["scheduler"]["status"]);
+}
+
+#[test]
+fn ready_before_scheduler_has_run_returns_503() {
+    let client = create_client_with_mounts(Hal::new(), AlertEngine::new(AlertEngine::default_rules()));
+
+    let (status, body) = get_ready_return_status_and_body(&client);
+
+    assert_eq!(Status::ServiceUnavailable, status);
+    assert_eq!("down", body["status"]);
+    assert_eq!("down", body["checks"]["scheduler"]["status"]);
+    assert!(body["checks"]["scheduler"]["last_run"].is_null());
+}
+
+#[test]
+fn ready_before_rules_have_run_returns_503_listing_each_job() {
+    let client = create_client_with_mounts_and_rules(Hal::new(), evaluated_engine(), RuleEngine::new());
+
+    let (status, body) = get_ready_return_status_and_body(&client);
+
+    let jobs = &body["checks"]["scheduler"]["jobs"];
+    assert_eq!(Status::ServiceUnavailable, status);
+    assert_eq!("down", body["checks"]["scheduler"]["status"]);
+    assert!(body["checks"]["scheduler"]["last_run"].is_null());
+    assert_eq!("down", jobs["rules"]["status"]);
+    assert!(jobs["rules"]["last_run"].is_null());
+    for job in &["alerts", "presence", "calendar", "energy", "history"] {
+        assert_eq!("up", jobs[job]["status"]);
+        assert!(jobs[job]["last_run"].is_u64());
+    }
+    // Without an outdoor temperature source weather compensation never runs.
+    assert!(jobs["weather"].is_null());
+}
+
+#[test]
+fn ready_with_faulty_relay_returns_503_listing_fault() {
+    let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+    let mut relay = Relay::new(Box::new(BrokenOutput), false);
+    let _ = relay.switch(true);
+    let mut hal = Hal::new();
+    hal.attach_relay(device_uuid, relay);
+    let client = create_client_with_mounts(hal, evaluated_engine());
+
+    let (status, body) = get_ready_return_status_and_body(&client);
+
+    assert_eq!(Status::ServiceUnavailable, status);
+    assert_eq!("down", body["checks"]["hal"]["status"]);
+    assert_eq!(1, body["checks"]["hal"]["relays"]);
+    assert_eq!("GPIO error: line busy", body["checks"]["hal"]["faults"][device_uuid.to_string()]);
+}
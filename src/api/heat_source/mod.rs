@@ -0,0 +1,56 @@
+use api::preferences::UnitJson;
+use device::{DeviceCollectionState, DeviceType};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Rocket, State};
+use rocket_contrib::json::Json;
+use uuid::Uuid;
+use weather::{Compensation, CompensationCurve, WeatherCompensationState};
+
+pub fn mount(rocket: Rocket, weather: WeatherCompensationState) -> Rocket {
+    rocket
+        .mount("/heat_source", routes![get_heat_source, put_compensation_curve])
+        .manage(weather)
+}
+
+#[derive(Serialize)]
+struct HeatSource {
+    #[serde(flatten)]
+    compensation: Compensation,
+    devices: Vec<Uuid>,
+}
+
+#[get("/", format = "application/json")]
+fn get_heat_source(
+    devices: State<DeviceCollectionState>,
+    weather: State<WeatherCompensationState>,
+) -> Json<HeatSource> {
+    let mut devices: Vec<Uuid> = devices
+        .lock()
+        .unwrap()
+        .get_all_with_type(DeviceType::HeatSource)
+        .iter()
+        .map(|(uuid, _)| *uuid)
+        .collect();
+    devices.sort();
+    Json(HeatSource {
+        compensation: weather.lock().unwrap().compensation(),
+        devices,
+    })
+}
+
+#[put("/curve", format = "application/json", data = "<curve>")]
+fn put_compensation_curve(
+    curve: UnitJson<CompensationCurve>,
+    weather: State<WeatherCompensationState>,
+) -> Result<Json<CompensationCurve>, status::Custom<String>> {
+    weather
+        .lock()
+        .unwrap()
+        .set_curve(*curve)
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    Ok(Json(*curve))
+}
+
+#[cfg(test)]
+mod tests;
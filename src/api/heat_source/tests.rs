@@ -0,0 +1,90 @@
+use super::*;
+use device::{Device, DeviceCollection};
+use fixtures::response_body_json;
+use rocket::http::ContentType;
+use rocket::local::{Client, LocalResponse};
+use rocket_contrib::json::JsonValue;
+use std::sync::Mutex;
+use weather::WeatherCompensation;
+use zone::ZoneCollection;
+
+const BOILER_UUID: &str = "56fd8e82-d260-46df-809f-662f070e9e1e";
+
+fn create_client(weather: WeatherCompensationState) -> Client {
+    let mut devices = DeviceCollection::new();
+    devices.add(
+        Uuid::parse_str(BOILER_UUID).unwrap(),
+        Device::with_type("Boiler".to_string(), None, DeviceType::HeatSource),
+    );
+    devices.add(Uuid::new_v4(), Device::new("STRV".to_string(), None));
+    let rocket = rocket::ignite().manage(DeviceCollectionState::new(Mutex::new(devices)));
+    Client::new(mount(rocket, weather)).unwrap()
+}
+
+fn new_weather() -> WeatherCompensationState {
+    WeatherCompensationState::new(Mutex::new(WeatherCompensation::new(CompensationCurve::default())))
+}
+
+fn get_heat_source(client: &Client) -> JsonValue {
+    let mut response = client.get("/heat_source").header(ContentType::JSON).dispatch();
+    response_body_json(&mut response)
+}
+
+fn put_curve<'c>(client: &'c Client, curve: JsonValue) -> LocalResponse<'c> {
+    client
+        .put("/heat_source/curve")
+        .header(ContentType::JSON)
+        .body(curve.to_string())
+        .dispatch()
+}
+
+#[test]
+fn before_reading_has_no_flow_temperature() {
+    let client = create_client(new_weather());
+
+    let heat_source = get_heat_source(&client);
+
+    assert!(heat_source["outdoor"].is_null());
+    assert!(heat_source["flow_temperature"].is_null());
+    assert_eq!(1, heat_source["devices"].as_array().unwrap().len());
+    assert_eq!(BOILER_UUID, heat_source["devices"][0]);
+}
+
+#[test]
+fn flow_temperature_follows_outdoor_reading() {
+    let weather = new_weather();
+    weather
+        .lock()
+        .unwrap()
+        .record(1000, Ok(6.5), &mut ZoneCollection::new());
+    let client = create_client(weather);
+
+    let heat_source = get_heat_source(&client);
+
+    assert_eq!(6.5, heat_source["outdoor"]["temperature"]);
+    assert_eq!(50.0, heat_source["flow_temperature"]);
+}
+
+#[test]
+fn put_curve_replaces_it() {
+    let client = create_client(new_weather());
+    let curve = json!({ "mild_outdoor": 16.0, "design_outdoor": -10.0, "min_flow": 25.0, "max_flow": 55.0 });
+
+    let response = put_curve(&client, curve);
+
+    assert_eq!(Status::Ok, response.status());
+    let heat_source = get_heat_source(&client);
+    assert_eq!(55.0, heat_source["curve"]["max_flow"]);
+    assert_eq!(0.0, heat_source["curve"]["max_setpoint_offset"]);
+}
+
+#[test]
+fn put_inverted_curve_returns_422() {
+    let client = create_client(new_weather());
+    let curve = json!({ "mild_outdoor": -5.0, "design_outdoor": 18.0, "min_flow": 30.0, "max_flow": 70.0 });
+
+    let response = put_curve(&client, curve);
+
+    assert_eq!(Status::UnprocessableEntity, response.status());
+    assert_eq!(18.0, get_heat_source(&client)["curve"]["mild_outdoor"]);
+}
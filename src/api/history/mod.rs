@@ -0,0 +1,53 @@
+use clock::Timestamp;
+use device::DeviceCollectionState;
+use history::ReadingHistoryState;
+use reading::Quantity;
+use rocket::http::RawStr;
+use rocket::request::FromFormValue;
+use rocket::{Rocket, State};
+use rocket_contrib::json::JsonValue;
+use rocket_contrib::uuid::Uuid as RocketUuid;
+use zone::ZoneCollectionState;
+
+pub fn mount(rocket: Rocket, history: ReadingHistoryState) -> Rocket {
+    rocket.mount("/history", routes![get_history]).manage(history)
+}
+
+impl<'v> FromFormValue<'v> for Quantity {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<Quantity, &'v RawStr> {
+        Quantity::ALL
+            .iter()
+            .find(|quantity| quantity.as_str() == form_value.as_str())
+            .cloned()
+            .ok_or(form_value)
+    }
+}
+
+#[get("/<uuid>?<quantity>&<since>", format = "application/json")]
+fn get_history(
+    uuid: RocketUuid,
+    quantity: Option<Quantity>,
+    since: Option<Timestamp>,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    history: State<ReadingHistoryState>,
+) -> Option<JsonValue> {
+    let uuid = uuid.into_inner();
+    if zones.lock().unwrap().get(&uuid).is_none() && devices.lock().unwrap().get(&uuid).is_none() {
+        return None;
+    }
+
+    let quantity = quantity.unwrap_or_default();
+    let samples = history.lock().unwrap().samples(&uuid, quantity, since.unwrap_or(0));
+    Some(json!({
+        "subject_uuid": uuid.to_string(),
+        "quantity": quantity,
+        "unit": quantity.unit(),
+        "samples": samples,
+    }))
+}
+
+#[cfg(test)]
+mod tests;
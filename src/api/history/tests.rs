@@ -0,0 +1,89 @@
+use super::*;
+use device::{Device, DeviceCollection};
+use fixtures::response_body_json;
+use history::{HistoryRetention, ReadingHistory};
+use rocket::http::{ContentType, Status};
+use rocket::local::{Client, LocalResponse};
+use std::sync::Mutex;
+use uuid::Uuid;
+use zone::{Zone, ZoneCollection};
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+const DEVICE_UUID: &str = "1d4a0ddc-7e86-4b47-a5d3-a2ba4fa7c2a9";
+
+fn create_client() -> Client {
+    let zone_uuid = Uuid::parse_str(ZONE_UUID).unwrap();
+    let mut zones = ZoneCollection::new();
+    zones.add(zone_uuid, Zone::new("Bathroom".to_string()));
+    let mut devices = DeviceCollection::new();
+    devices.add(
+        Uuid::parse_str(DEVICE_UUID).unwrap(),
+        Device::new("Sensor".to_string(), Some(zone_uuid)),
+    );
+
+    let mut history = ReadingHistory::new(HistoryRetention::default());
+    for &(at, humidity) in &[(1000, 60.0), (1300, 80.0)] {
+        devices
+            .get_mut(&Uuid::parse_str(DEVICE_UUID).unwrap())
+            .unwrap()
+            .set_reading(Quantity::Humidity, humidity);
+        history.sample(at, &zones, &devices);
+    }
+
+    let rocket = rocket::ignite()
+        .manage(ZoneCollectionState::new(Mutex::new(zones)))
+        .manage(DeviceCollectionState::new(Mutex::new(devices)));
+    Client::new(mount(rocket, ReadingHistoryState::new(Mutex::new(history)))).unwrap()
+}
+
+fn get_history<'c>(client: &'c Client, uri: &str) -> LocalResponse<'c> {
+    client.get(uri).header(ContentType::JSON).dispatch()
+}
+
+#[test]
+fn returns_samples_of_zone_with_unit() {
+    let client = create_client();
+
+    let mut response = get_history(&client, &format!("/history/{}?quantity=humidity", ZONE_UUID));
+
+    assert_eq!(Status::Ok, response.status());
+    let expected = json!({
+        "subject_uuid": ZONE_UUID,
+        "quantity": "humidity",
+        "unit": "%",
+        "samples": [{ "at": 1000, "value": 60.0 }, { "at": 1300, "value": 80.0 }]
+    });
+    assert_eq!(expected, response_body_json(&mut response));
+}
+
+#[test]
+fn returns_samples_of_device_since() {
+    let client = create_client();
+
+    let uri = format!("/history/{}?quantity=humidity&since=1100", DEVICE_UUID);
+    let mut response = get_history(&client, &uri);
+
+    let body = response_body_json(&mut response);
+    assert_eq!(1, body["samples"].as_array().unwrap().len());
+    assert_eq!(80.0, body["samples"][0]["value"]);
+}
+
+#[test]
+fn defaults_to_temperature() {
+    let client = create_client();
+
+    let mut response = get_history(&client, &format!("/history/{}", ZONE_UUID));
+
+    let body = response_body_json(&mut response);
+    assert_eq!("temperature", body["quantity"]);
+    assert!(body["samples"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn unknown_subject_returns_error_not_found() {
+    let client = create_client();
+
+    let response = get_history(&client, "/history/84fa1356-d5de-11e8-9f8b-f2801f1b9fd1");
+
+    assert_eq!(Status::NotFound, response.status());
+}
@@ -0,0 +1,117 @@
+use clock::Timestamp;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::status;
+use rocket::Outcome;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+const REPLAYABLE_FOR: Timestamp = 24 * 60 * 60;
+
+pub struct IdempotencyKey(Option<String>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for IdempotencyKey {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<IdempotencyKey, ()> {
+        let key = request.headers().get_one("Idempotency-Key").map(String::from);
+        Outcome::Success(IdempotencyKey(key))
+    }
+}
+
+struct Created<T> {
+    request: Value,
+    uuid: Uuid,
+    resource: T,
+    created_at: Timestamp,
+}
+
+pub type ReplaysState<T> = Arc<Mutex<Replays<T>>>;
+
+pub struct Replays<T> {
+    created: HashMap<String, Created<T>>,
+}
+
+impl<T: Clone + Serialize> Replays<T> {
+    pub fn new() -> Replays<T> {
+        Replays {
+            created: HashMap::new(),
+        }
+    }
+
+    pub fn replay(
+        &mut self,
+        key: &IdempotencyKey,
+        request: &T,
+        now: Timestamp,
+    ) -> Result<Option<(Uuid, T)>, status::Custom<String>> {
+        self.created
+            .retain(|_, created| now.saturating_sub(created.created_at) < REPLAYABLE_FOR);
+
+        let key = match key.0 {
+            Some(ref key) => key,
+            None => return Ok(None),
+        };
+        match self.created.get(key) {
+            Some(created) if created.request == serde_json::to_value(request).unwrap_or(Value::Null) => {
+                Ok(Some((created.uuid, created.resource.clone())))
+            }
+            Some(_) => Err(status::Custom(
+                Status::UnprocessableEntity,
+                "the Idempotency-Key was already used for another request".to_string(),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    pub fn record(&mut self, key: &IdempotencyKey, request: &T, uuid: Uuid, resource: &T, now: Timestamp) {
+        if let Some(ref key) = key.0 {
+            let created = Created {
+                request: serde_json::to_value(request).unwrap_or(Value::Null),
+                uuid,
+                resource: resource.clone(),
+                created_at: now,
+            };
+            self.created.insert(key.clone(), created);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> IdempotencyKey {
+        IdempotencyKey(Some(key.to_string()))
+    }
+
+    #[test]
+    fn replays_resource_created_with_the_same_key() {
+        let mut replays = Replays::new();
+        let uuid = Uuid::new_v4();
+        replays.record(&key("a"), &"Kitchen", uuid, &"Kitchen", 1000);
+
+        assert_eq!(Some((uuid, "Kitchen")), replays.replay(&key("a"), &"Kitchen", 1010).unwrap());
+        assert_eq!(None, replays.replay(&key("b"), &"Kitchen", 1010).unwrap());
+        assert_eq!(None, replays.replay(&IdempotencyKey(None), &"Kitchen", 1010).unwrap());
+    }
+
+    #[test]
+    fn key_reused_for_another_request_is_an_error() {
+        let mut replays = Replays::new();
+        replays.record(&key("a"), &"Kitchen", Uuid::new_v4(), &"Kitchen", 1000);
+
+        assert!(replays.replay(&key("a"), &"Attic", 1010).is_err());
+    }
+
+    #[test]
+    fn replays_expire() {
+        let mut replays = Replays::new();
+        replays.record(&key("a"), &"Kitchen", Uuid::new_v4(), &"Kitchen", 1000);
+
+        assert_eq!(None, replays.replay(&key("a"), &"Kitchen", 1000 + REPLAYABLE_FOR).unwrap());
+    }
+}
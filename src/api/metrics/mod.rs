@@ -0,0 +1,97 @@
+use clock;
+use device::{DeviceCollectionState, HealthPolicy};
+use metrics::{escape_label, HttpMetricsFairing, HttpMetricsState};
+use rocket::response::content;
+use rocket::{Rocket, State};
+use std::fmt::Write;
+use uuid::Uuid;
+use zone::ZoneCollectionState;
+
+pub fn mount(rocket: Rocket, metrics: HttpMetricsState) -> Rocket {
+    rocket
+        .mount("/metrics", routes![get_metrics])
+        .attach(HttpMetricsFairing::new(metrics.clone()))
+        .manage(metrics)
+}
+
+fn write_gauge_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
+
+#[get("/")]
+fn get_metrics(
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    policy: State<HealthPolicy>,
+    http_metrics: State<HttpMetricsState>,
+) -> content::Plain<String> {
+    let zones = zones.lock().unwrap();
+    let devices = devices.lock().unwrap();
+    let now = clock::now();
+    let mut out = String::new();
+
+    let mut zones: Vec<_> = zones.iter().collect();
+    zones.sort_by_key(|&(uuid, _)| *uuid);
+    let zone_labels = |uuid: &Uuid, name: &str| format!("zone=\"{}\",name=\"{}\"", uuid, escape_label(name));
+
+    write_gauge_header(&mut out, "smart_home_zone_setpoint_celsius", "Setpoint of the zone.");
+    for &(uuid, zone) in &zones {
+        let labels = zone_labels(uuid, zone.name());
+        let _ = writeln!(out, "smart_home_zone_setpoint_celsius{{{}}} {}", labels, zone.setpoint().celsius());
+    }
+
+    let help = "Average temperature reported in the zone.";
+    write_gauge_header(&mut out, "smart_home_zone_temperature_celsius", help);
+    for &(uuid, zone) in &zones {
+        if let Some(temperature) = devices.zone_temperature(uuid) {
+            let labels = zone_labels(uuid, zone.name());
+            let _ = writeln!(out, "smart_home_zone_temperature_celsius{{{}}} {}", labels, temperature);
+        }
+    }
+
+    let help = "Whether the zone is below its setpoint (1) or not (0).";
+    write_gauge_header(&mut out, "smart_home_zone_demand", help);
+    for &(uuid, zone) in &zones {
+        let demand = devices
+            .zone_temperature(uuid)
+            .map_or(false, |temperature| temperature < zone.setpoint().celsius());
+        let labels = zone_labels(uuid, zone.name());
+        let _ = writeln!(out, "smart_home_zone_demand{{{}}} {}", labels, demand as u8);
+    }
+
+    let mut devices: Vec<_> = devices.iter().collect();
+    devices.sort_by_key(|&(uuid, _)| *uuid);
+    let device_labels = |uuid: &Uuid, name: &str, device_type: &str| {
+        format!(
+            "device=\"{}\",name=\"{}\",type=\"{}\"",
+            uuid,
+            escape_label(name),
+            device_type
+        )
+    };
+
+    let help = "Last battery level reported by the device.";
+    write_gauge_header(&mut out, "smart_home_device_battery_percent", help);
+    for &(uuid, device) in &devices {
+        if let Some(battery) = device.health().battery {
+            let labels = device_labels(uuid, device.name(), device.device_type().as_str());
+            let _ = writeln!(out, "smart_home_device_battery_percent{{{}}} {}", labels, battery);
+        }
+    }
+
+    let help = "Whether the device has reported recently (1) or not (0).";
+    write_gauge_header(&mut out, "smart_home_device_online", help);
+    for &(uuid, device) in &devices {
+        if let Some(online) = device.is_online(now, &policy) {
+            let labels = device_labels(uuid, device.name(), device.device_type().as_str());
+            let _ = writeln!(out, "smart_home_device_online{{{}}} {}", labels, online as u8);
+        }
+    }
+
+    http_metrics.lock().unwrap().write_to(&mut out);
+    content::Plain(out)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,79 @@
+use super::*;
+use api::{devices, zones};
+use device::{Device, DeviceCollection};
+use hal::{Hal, HalState};
+use metrics::HttpMetrics;
+use reading::Quantity;
+use rocket::http::ContentType;
+use rocket::local::Client;
+use std::sync::{Arc, Mutex};
+use temperature::Temperature;
+use zone::{Zone, ZoneCollection};
+
+fn create_client_with_mounts(zones: ZoneCollection, devices: DeviceCollection) -> Client {
+    let rocket = rocket::ignite().manage(HalState::new(Hal::new()));
+    let rocket = zones::mount(rocket, Arc::new(Mutex::new(zones)));
+    let rocket = devices::mount(rocket, Arc::new(Mutex::new(devices)));
+    let rocket = mount(rocket, Arc::new(Mutex::new(HttpMetrics::new())));
+    Client::new(rocket).unwrap()
+}
+
+fn get_metrics_body(client: &Client) -> String {
+    client.get("/metrics").dispatch().body_string().unwrap()
+}
+
+#[test]
+fn exposes_zone_gauges() {
+    let zone_uuid = Uuid::parse_str("c00727d8-eee8-4a0e-850e-b81a74440e78").unwrap();
+    let mut zone = Zone::new("Living Room".to_string());
+    zone.set_setpoint(Temperature::from_celsius(21.0));
+    let mut zones = ZoneCollection::new();
+    zones.add(zone_uuid, zone);
+    let mut device = Device::new("STRV".to_string(), Some(zone_uuid));
+    device.set_reading(Quantity::Temperature, 19.5);
+    let mut devices = DeviceCollection::new();
+    devices.add(Uuid::new_v4(), device);
+    let client = create_client_with_mounts(zones, devices);
+
+    let body = get_metrics_body(&client);
+
+    let labels = format!("zone=\"{}\",name=\"Living Room\"", zone_uuid);
+    assert!(body.contains(&format!("smart_home_zone_setpoint_celsius{{{}}} 21\n", labels)));
+    assert!(body.contains(&format!("smart_home_zone_temperature_celsius{{{}}} 19.5\n", labels)));
+    assert!(body.contains(&format!("smart_home_zone_demand{{{}}} 1\n", labels)));
+}
+
+#[test]
+fn exposes_device_gauges() {
+    let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+    let mut device = Device::new("STRV \"One\"".to_string(), None);
+    device.report(Some(42), None, None, clock::now());
+    let mut devices = DeviceCollection::new();
+    devices.add(device_uuid, device);
+    let client = create_client_with_mounts(ZoneCollection::new(), devices);
+
+    let body = get_metrics_body(&client);
+
+    let labels = format!("device=\"{}\",name=\"STRV \\\"One\\\"\",type=\"radiator_valve\"", device_uuid);
+    assert!(body.contains(&format!("smart_home_device_battery_percent{{{}}} 42\n", labels)));
+    assert!(body.contains(&format!("smart_home_device_online{{{}}} 1\n", labels)));
+}
+
+#[test]
+fn counts_requests_per_route() {
+    let zone_uuid = Uuid::parse_str("c00727d8-eee8-4a0e-850e-b81a74440e78").unwrap();
+    let mut zones = ZoneCollection::new();
+    zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+    let client = create_client_with_mounts(zones, DeviceCollection::new());
+
+    for _ in 0..2 {
+        client
+            .get(format!("/zones/{}", zone_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+    }
+    let body = get_metrics_body(&client);
+
+    assert!(body.contains("http_requests_total{method=\"GET\",route=\"/zones/<uuid>\",status=\"200\"} 2\n"));
+    assert!(body.contains("http_request_duration_seconds_count{method=\"GET\",route=\"/zones/<uuid>\"} 2\n"));
+}
@@ -1,15 +1,66 @@
+use page::Sort;
+use rocket::http::RawStr;
+use rocket::request::FromFormValue;
 use rocket::Rocket;
+use rocket_contrib::json::JsonValue;
 
+pub mod alerts;
+pub mod audit;
+pub mod backup;
+pub mod batch;
+pub mod calendar;
+mod conditional;
 pub mod devices;
+pub mod docs;
+pub mod energy;
+pub mod health;
+pub mod heat_source;
+pub mod history;
+mod idempotency;
+pub mod metrics;
+pub mod preferences;
+pub mod presence;
+pub mod rules;
+pub mod scenes;
 pub mod zones;
 
 pub fn mount(rocket: Rocket) -> Rocket {
     rocket.mount("/", routes![index])
 }
 
+impl<'v> FromFormValue<'v> for Sort {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<Sort, &'v RawStr> {
+        form_value.as_str().parse().map_err(|_| form_value)
+    }
+}
+
 #[get("/")]
-fn index() -> &'static str {
-    "Hello, World!"
+fn index() -> JsonValue {
+    json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "links": {
+            "zones": "/zones",
+            "devices": "/devices",
+            "scenes": "/scenes",
+            "rules": "/rules",
+            "presence": "/presence",
+            "heat_source": "/heat_source",
+            "calendar": "/calendar",
+            "energy": "/energy",
+            "history": "/history",
+            "alerts": "/alerts",
+            "audit": "/audit",
+            "preferences": "/preferences",
+            "backup": "/backup",
+            "metrics": "/metrics",
+            "health": "/health/ready",
+            "openapi": "/openapi.yaml",
+            "docs": "/docs"
+        }
+    })
 }
 
 #[cfg(test)]
@@ -25,11 +76,15 @@ mod tests {
     }
 
     #[test]
-    fn get_index_returns_content() {
+    fn get_index_returns_version_and_links() {
         let client = create_client_with_mounts();
         let mut response = client.get("/").header(ContentType::JSON).dispatch();
-        let body = response.body_string().unwrap();
+        let body: JsonValue = serde_json::from_str(&response.body_string().unwrap()).unwrap();
 
-        assert_eq!("Hello, World!", body);
+        assert_eq!(env!("CARGO_PKG_VERSION"), body["version"]);
+        assert_eq!("/zones", body["links"]["zones"]);
+        assert_eq!("/devices", body["links"]["devices"]);
+        assert_eq!("/openapi.yaml", body["links"]["openapi"]);
+        assert_eq!("/docs", body["links"]["docs"]);
     }
 }
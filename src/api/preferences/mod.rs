@@ -0,0 +1,443 @@
+use api::audit::Actor;
+use reading::Quantity;
+use rocket::data::{self, Data, FromDataSimple};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, Method, RawStr, Status};
+use rocket::request::{self, FromFormValue, FromRequest, Request};
+use rocket::response::Response;
+use rocket::{Outcome, Rocket, State};
+use rocket_contrib::json::Json;
+use serde::de::{Deserialize, DeserializeOwned, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::Value;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::ops::Deref;
+use temperature::{TemperatureUnit, UnitPreferencesState};
+
+const JSON_LIMIT: u64 = 1 << 20;
+const CONVERTED_PATHS: [&str; 13] = [
+    "/zones",
+    "/devices",
+    "/scenes",
+    "/rules",
+    "/presence",
+    "/calendar",
+    "/batch",
+    "/history",
+    "/heat_source",
+    "/alerts",
+    "/audit",
+    "/backup",
+    "/restore",
+];
+const TEMPERATURES: [&str; 12] = [
+    "setpoint",
+    "temperature",
+    "comfort_setpoint",
+    "eco_setpoint",
+    "away_setpoint",
+    "before",
+    "after",
+    "mild_outdoor",
+    "design_outdoor",
+    "min_flow",
+    "max_flow",
+    "flow_temperature",
+];
+const THRESHOLDS: [&str; 2] = ["above", "below"];
+const DIFFERENCES: [&str; 4] = ["heat_up_rate", "delta", "max_setpoint_offset", "setpoint_offset"];
+
+pub fn mount(rocket: Rocket, preferences: UnitPreferencesState) -> Rocket {
+    rocket
+        .mount("/preferences", routes![get_preferences, put_preferences, delete_preferences])
+        .manage(preferences)
+        .attach(TemperatureUnitFairing)
+}
+
+impl<'v> FromFormValue<'v> for TemperatureUnit {
+    type Error = &'v RawStr;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<TemperatureUnit, &'v RawStr> {
+        form_value.as_str().parse().map_err(|_| form_value)
+    }
+}
+
+struct RequestedUnit(Result<TemperatureUnit, String>);
+
+fn unit_of(request: &Request) -> Result<TemperatureUnit, String> {
+    request.local_cache(|| RequestedUnit(requested_unit(request))).0.clone()
+}
+
+fn requested_unit(request: &Request) -> Result<TemperatureUnit, String> {
+    if let Some(unit) = request.get_query_value::<TemperatureUnit>("unit") {
+        return unit.map_err(|unit| format!("unknown temperature unit {}", unit));
+    }
+    if let Some(unit) = request.headers().get_one("X-Temperature-Unit") {
+        return unit.parse();
+    }
+
+    let preferences = request.guard::<State<UnitPreferencesState>>().succeeded();
+    let actor = request.guard::<Actor>().succeeded();
+    Ok(match (preferences, actor) {
+        (Some(preferences), Some(actor)) => preferences.lock().unwrap().unit(actor.name()),
+        _ => TemperatureUnit::default(),
+    })
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for TemperatureUnit {
+    type Error = String;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<TemperatureUnit, String> {
+        match unit_of(request) {
+            Ok(unit) => Outcome::Success(unit),
+            Err(error) => Outcome::Failure((Status::BadRequest, error)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Conversion {
+    ToCelsius(TemperatureUnit),
+    FromCelsius(TemperatureUnit),
+}
+
+impl Conversion {
+    fn degrees(self, degrees: f64) -> f64 {
+        match self {
+            Conversion::ToCelsius(unit) => unit.to_celsius(degrees),
+            Conversion::FromCelsius(unit) => (unit.convert(degrees) * 100.0).round() / 100.0,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Conversion::ToCelsius(_) => TemperatureUnit::Celsius.symbol(),
+            Conversion::FromCelsius(unit) => unit.symbol(),
+        }
+    }
+
+    fn difference(self, difference: f64) -> f64 {
+        match self {
+            Conversion::ToCelsius(unit) => unit.difference_to_celsius(difference),
+            Conversion::FromCelsius(unit) => (unit.convert_difference(difference) * 100.0).round() / 100.0,
+        }
+    }
+}
+
+enum OrderedValue {
+    Object(Vec<(String, OrderedValue)>),
+    Array(Vec<OrderedValue>),
+    Scalar(Value),
+}
+
+impl OrderedValue {
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            OrderedValue::Scalar(value) => value.as_f64(),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            OrderedValue::Scalar(value) => value.as_str(),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for OrderedValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            OrderedValue::Object(members) => {
+                let mut map = serializer.serialize_map(Some(members.len()))?;
+                for (key, value) in members {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            OrderedValue::Array(values) => serializer.collect_seq(values),
+            OrderedValue::Scalar(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OrderedValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OrderedValue, D::Error> {
+        deserializer.deserialize_any(OrderedValueVisitor)
+    }
+}
+
+struct OrderedValueVisitor;
+
+impl<'de> Visitor<'de> for OrderedValueVisitor {
+    type Value = OrderedValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Scalar(Value::from(value)))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Scalar(Value::from(value)))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Scalar(Value::from(value)))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Scalar(Value::from(value)))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Scalar(Value::from(value)))
+    }
+
+    fn visit_unit<E>(self) -> Result<OrderedValue, E> {
+        Ok(OrderedValue::Scalar(Value::Null))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<OrderedValue, A::Error> {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(OrderedValue::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OrderedValue, A::Error> {
+        let mut members = Vec::new();
+        while let Some(member) = map.next_entry()? {
+            members.push(member);
+        }
+        Ok(OrderedValue::Object(members))
+    }
+}
+
+fn convert_temperatures(value: &mut OrderedValue, conversion: Conversion) {
+    match value {
+        OrderedValue::Object(members) => {
+            let of_temperature = match members.iter().find(|(key, _)| key == "quantity") {
+                Some((_, quantity)) => quantity.as_str() == Some(Quantity::Temperature.as_str()),
+                None => members.iter().any(|(key, _)| THRESHOLDS.contains(&key.as_str())),
+            };
+            for (key, value) in members.iter_mut() {
+                convert_member(key, value, of_temperature, conversion);
+            }
+        }
+        OrderedValue::Array(values) => {
+            for value in values {
+                convert_temperatures(value, conversion);
+            }
+        }
+        OrderedValue::Scalar(_) => {}
+    }
+}
+
+fn convert_member(key: &str, value: &mut OrderedValue, of_temperature: bool, conversion: Conversion) {
+    match value.as_f64() {
+        Some(degrees) if TEMPERATURES.contains(&key) || (of_temperature && THRESHOLDS.contains(&key)) => {
+            *value = OrderedValue::Scalar(Value::from(conversion.degrees(degrees)));
+        }
+        Some(difference) if DIFFERENCES.contains(&key) => {
+            *value = OrderedValue::Scalar(Value::from(conversion.difference(difference)));
+        }
+        None if key == "temperature" => convert_reading(value, conversion),
+        None if key == "changes" => convert_changes(value, conversion),
+        None if of_temperature && key == "unit" => {
+            *value = OrderedValue::Scalar(Value::from(conversion.symbol()));
+        }
+        None if of_temperature && key == "samples" => convert_samples(value, conversion),
+        _ => convert_temperatures(value, conversion),
+    }
+}
+
+// Audit entries map each field that changed to its value before and after, which convert as the field would.
+fn convert_changes(changes: &mut OrderedValue, conversion: Conversion) {
+    match changes {
+        OrderedValue::Object(fields) => {
+            for (field, change) in fields.iter_mut() {
+                if let OrderedValue::Object(values) = change {
+                    for (_, value) in values.iter_mut() {
+                        convert_member(field, value, false, conversion);
+                    }
+                }
+            }
+        }
+        changes => convert_temperatures(changes, conversion),
+    }
+}
+
+fn convert_reading(reading: &mut OrderedValue, conversion: Conversion) {
+    if let OrderedValue::Object(members) = reading {
+        let degrees = members
+            .iter()
+            .find(|(key, _)| key == "value")
+            .and_then(|(_, value)| value.as_f64());
+        if let Some(degrees) = degrees {
+            for (key, member) in members.iter_mut() {
+                match key.as_str() {
+                    "value" => *member = OrderedValue::Scalar(Value::from(conversion.degrees(degrees))),
+                    "unit" => *member = OrderedValue::Scalar(Value::from(conversion.symbol())),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn convert_samples(samples: &mut OrderedValue, conversion: Conversion) {
+    if let OrderedValue::Array(samples) = samples {
+        for sample in samples {
+            if let OrderedValue::Object(members) = sample {
+                for (key, member) in members.iter_mut() {
+                    if let (Some(degrees), "value") = (member.as_f64(), key.as_str()) {
+                        *member = OrderedValue::Scalar(Value::from(conversion.degrees(degrees)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct UnitJson<T>(pub T);
+
+impl<T> UnitJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for UnitJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned> FromDataSimple for UnitJson<T> {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<UnitJson<T>, String> {
+        let unit = match unit_of(request) {
+            Ok(unit) => unit,
+            Err(error) => return Outcome::Failure((Status::BadRequest, error)),
+        };
+        let limit = request.limits().get("json").unwrap_or(JSON_LIMIT);
+        let mut body = String::new();
+        if let Err(error) = data.open().take(limit).read_to_string(&mut body) {
+            return Outcome::Failure((Status::BadRequest, error.to_string()));
+        }
+
+        let mut value: OrderedValue = match serde_json::from_str(&body) {
+            Ok(value) => value,
+            Err(error) => return Outcome::Failure((Status::BadRequest, error.to_string())),
+        };
+        if unit != TemperatureUnit::Celsius {
+            convert_temperatures(&mut value, Conversion::ToCelsius(unit));
+        }
+        match serde_json::to_value(&value).and_then(serde_json::from_value) {
+            Ok(value) => Outcome::Success(UnitJson(value)),
+            Err(error) => Outcome::Failure((Status::UnprocessableEntity, error.to_string())),
+        }
+    }
+}
+
+fn is_converted(request: &Request) -> bool {
+    let path = request.uri().path();
+    CONVERTED_PATHS
+        .iter()
+        .any(|prefix| path == *prefix || path.starts_with(&format!("{}/", prefix)))
+}
+
+struct TemperatureUnitFairing;
+
+impl Fairing for TemperatureUnitFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Temperature unit",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        // No route answers TRACE, so a request in an unknown unit reaches no handler and is answered below.
+        if is_converted(request) && unit_of(request).is_err() {
+            request.set_method(Method::Trace);
+        }
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        if !is_converted(request) {
+            return;
+        }
+
+        let unit = match unit_of(request) {
+            Ok(unit) => unit,
+            Err(error) => {
+                response.set_status(Status::BadRequest);
+                response.set_header(ContentType::Plain);
+                response.set_sized_body(Cursor::new(error));
+                return;
+            }
+        };
+        response.set_header(Header::new("X-Temperature-Unit", unit.as_str()));
+        if unit == TemperatureUnit::Celsius || response.content_type() != Some(ContentType::JSON) {
+            return;
+        }
+
+        let body = match response.body_string() {
+            Some(body) => body,
+            None => return,
+        };
+        let parsed = serde_json::from_str::<OrderedValue>(&body);
+        let body = match parsed {
+            Ok(mut value) => {
+                convert_temperatures(&mut value, Conversion::FromCelsius(unit));
+                serde_json::to_string(&value).unwrap_or(body)
+            }
+            Err(_) => body,
+        };
+        response.set_sized_body(Cursor::new(body));
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Preferences {
+    temperature_unit: TemperatureUnit,
+}
+
+#[get("/", format = "application/json")]
+fn get_preferences(actor: Actor, preferences: State<UnitPreferencesState>) -> Json<Preferences> {
+    Json(Preferences {
+        temperature_unit: preferences.lock().unwrap().unit(actor.name()),
+    })
+}
+
+#[put("/", format = "application/json", data = "<chosen>")]
+fn put_preferences(
+    chosen: Json<Preferences>,
+    actor: Actor,
+    preferences: State<UnitPreferencesState>,
+) -> Json<Preferences> {
+    preferences
+        .lock()
+        .unwrap()
+        .set_unit(actor.name(), chosen.temperature_unit);
+    chosen
+}
+
+#[delete("/", format = "application/json")]
+fn delete_preferences(actor: Actor, preferences: State<UnitPreferencesState>) -> Status {
+    preferences.lock().unwrap().remove(actor.name());
+    Status::NoContent
+}
+
+#[cfg(test)]
+mod tests;
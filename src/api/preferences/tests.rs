@@ -0,0 +1,330 @@
+use super::*;
+use alert::{AlertEngine, AlertEngineState};
+use api::{alerts, devices, heat_source, rules, zones};
+use device::{Device, DeviceCollection, HealthPolicy};
+use fixtures::{self, response_body_json, Home};
+use reading::Quantity;
+use rocket::local::{Client, LocalResponse};
+use rocket_contrib::json::JsonValue;
+use rule::{RuleEngine, RuleEngineState};
+use scene::{SceneCollection, SceneCollectionState};
+use std::sync::Mutex;
+use temperature::{Temperature, UnitPreferences};
+use uuid::Uuid;
+use weather::{CompensationCurve, WeatherCompensation, WeatherCompensationState};
+use zone::{Zone, ZoneCollection};
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+const VALVE_UUID: &str = "88f573e2-d5de-11e8-9f8b-f2801f1b9fd1";
+
+fn create_home() -> Home {
+    let zone_uuid = Uuid::parse_str(ZONE_UUID).unwrap();
+    let mut zones = ZoneCollection::new();
+    let mut zone = Zone::new("Living Room".to_string());
+    zone.set_setpoint(Temperature::from_celsius(20.0));
+    zones.add(zone_uuid, zone);
+    let mut devices = DeviceCollection::new();
+    let mut valve = Device::new("Valve".to_string(), Some(zone_uuid));
+    valve.set_reading(Quantity::Temperature, 19.5);
+    devices.add(Uuid::parse_str(VALVE_UUID).unwrap(), valve);
+
+    fixtures::create_home(zones, devices, |rocket, zone_collection, device_collection| {
+        let rocket = rocket
+            .manage(SceneCollectionState::new(Mutex::new(SceneCollection::new())))
+            .manage(HealthPolicy::default());
+        let rocket = zones::mount(rocket, zone_collection);
+        let rocket = devices::mount(rocket, device_collection);
+        let rocket = rules::mount(rocket, RuleEngineState::new(Mutex::new(RuleEngine::new())));
+        let weather = WeatherCompensation::new(CompensationCurve::default());
+        let rocket = heat_source::mount(rocket, WeatherCompensationState::new(Mutex::new(weather)));
+        let rocket = alerts::mount(rocket, AlertEngineState::new(Mutex::new(AlertEngine::new(Vec::new()))));
+        mount(rocket, UnitPreferencesState::new(Mutex::new(UnitPreferences::new())))
+    })
+}
+
+fn living_room_setpoint(home: &Home) -> f64 {
+    let zones = home.zones.lock().unwrap();
+    zones.get(&Uuid::parse_str(ZONE_UUID).unwrap()).unwrap().setpoint().celsius()
+}
+
+fn put_preferences_for<'c>(client: &'c Client, actor: &str, unit: &str) -> LocalResponse<'c> {
+    client
+        .put("/preferences")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Actor", actor.to_string()))
+        .body(format!(r#"{{"temperature_unit":"{}"}}"#, unit))
+        .dispatch()
+}
+
+#[test]
+fn preferences_default_to_celsius_and_are_kept_per_user() {
+    let home = create_home();
+
+    let mut response = put_preferences_for(&home.client, "phone", "fahrenheit");
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!("fahrenheit", response_body_json(&mut response)["temperature_unit"]);
+
+    let get_preferences_for = |actor: &str| {
+        let mut response = home
+            .client
+            .get("/preferences")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Actor", actor.to_string()))
+            .dispatch();
+        response_body_json(&mut response)["temperature_unit"].clone()
+    };
+    assert_eq!("fahrenheit", get_preferences_for("phone"));
+    assert_eq!("celsius", get_preferences_for("tablet"));
+
+    let response = home
+        .client
+        .delete("/preferences")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Actor", "phone"))
+        .dispatch();
+    assert_eq!(Status::NoContent, response.status());
+    assert_eq!("celsius", get_preferences_for("phone"));
+}
+
+#[test]
+fn zone_is_served_in_unit_of_query() {
+    let home = create_home();
+
+    let mut response = home
+        .client
+        .get(format!("/zones/{}?unit=fahrenheit", ZONE_UUID))
+        .header(ContentType::JSON)
+        .dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(Some("fahrenheit"), response.headers().get_one("X-Temperature-Unit"));
+    assert_eq!(68.0, response_body_json(&mut response)["setpoint"]);
+}
+
+#[test]
+fn zone_sent_in_fahrenheit_is_stored_in_celsius() {
+    let home = create_home();
+
+    let mut response = home
+        .client
+        .patch(format!("/zones/{}", ZONE_UUID))
+        .header(ContentType::JSON)
+        .header(Header::new("X-Temperature-Unit", "F"))
+        .body(r#"{"setpoint":71.6}"#)
+        .dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(71.6, response_body_json(&mut response)["setpoint"]);
+    assert!((living_room_setpoint(&home) - 22.0).abs() < 1e-9);
+}
+
+#[test]
+fn preferred_unit_applies_when_request_names_none() {
+    let home = create_home();
+    put_preferences_for(&home.client, "phone", "fahrenheit");
+
+    let mut response = home
+        .client
+        .get("/zones")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Actor", "phone"))
+        .dispatch();
+    assert_eq!(68.0, response_body_json(&mut response)["zones"][ZONE_UUID]["setpoint"]);
+
+    let mut response = home
+        .client
+        .get("/zones?unit=celsius")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Actor", "phone"))
+        .dispatch();
+    assert_eq!(20.0, response_body_json(&mut response)["zones"][ZONE_UUID]["setpoint"]);
+}
+
+#[test]
+fn device_temperatures_and_readings_are_converted() {
+    let home = create_home();
+
+    let mut response = home
+        .client
+        .get(format!("/devices/{}?unit=fahrenheit", VALVE_UUID))
+        .header(ContentType::JSON)
+        .dispatch();
+
+    let body = response_body_json(&mut response);
+    assert_eq!(67.1, body["temperature"]);
+    assert_eq!(67.1, body["readings"]["temperature"]["value"]);
+    assert_eq!("°F", body["readings"]["temperature"]["unit"]);
+    assert_eq!(60.8, body["setpoint"]);
+}
+
+#[test]
+fn setpoint_filter_is_in_unit_of_request() {
+    let home = create_home();
+
+    let mut response = home
+        .client
+        .get("/zones?setpoint_min=60&unit=fahrenheit")
+        .header(ContentType::JSON)
+        .dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert!(response_body_json(&mut response)["zones"].get(ZONE_UUID).is_some());
+}
+
+#[test]
+fn converted_page_keeps_its_order() {
+    let home = create_home();
+    let mut bedroom = Zone::new("Bedroom".to_string());
+    bedroom.set_setpoint(Temperature::from_celsius(18.0));
+    // Sorts before the living room by UUID, so that the page is only in order by setpoint if the order is kept.
+    let bedroom_uuid = Uuid::parse_str("1c4bf2a4-5e3e-4c8e-9d0b-3b1f6f0e2a77").unwrap();
+    home.zones.lock().unwrap().add(bedroom_uuid, bedroom);
+
+    let mut response = home
+        .client
+        .get("/zones?sort=-setpoint&unit=fahrenheit")
+        .header(ContentType::JSON)
+        .dispatch();
+
+    let body = response.body_string().unwrap();
+    let living_room = body.find("Living Room").unwrap();
+    let bedroom = body.find("Bedroom").unwrap();
+    assert!(living_room < bedroom, "zones out of order in {}", body);
+    assert!(body.contains("64.4"), "bedroom setpoint not converted in {}", body);
+}
+
+#[test]
+fn rule_sent_in_fahrenheit_is_stored_in_celsius() {
+    let home = create_home();
+    let rule = json!({
+        "name": "Cold",
+        "trigger": { "type": "reading", "subject_uuid": ZONE_UUID, "below": 59.0 },
+        "actions": [{ "type": "set_setpoint", "subject_uuid": ZONE_UUID, "setpoint": 71.6 }]
+    });
+
+    let response = home
+        .client
+        .post("/rules?unit=fahrenheit")
+        .header(ContentType::JSON)
+        .body(rule.to_string())
+        .dispatch();
+    assert_eq!(Status::Created, response.status());
+    let location = response.headers().get_one("Location").unwrap().to_string();
+
+    let mut response = home
+        .client
+        .get(format!("{}?unit=celsius", location))
+        .header(ContentType::JSON)
+        .dispatch();
+    let rule = response_body_json(&mut response);
+    assert!((rule["trigger"]["below"].as_f64().unwrap() - 15.0).abs() < 1e-9);
+    assert!((rule["actions"][0]["setpoint"].as_f64().unwrap() - 22.0).abs() < 1e-9);
+}
+
+#[test]
+fn compensation_curve_is_served_in_unit_of_query() {
+    let home = create_home();
+    let curve = json!({
+        "mild_outdoor": 15.0,
+        "design_outdoor": -10.0,
+        "min_flow": 30.0,
+        "max_flow": 70.0,
+        "max_setpoint_offset": 2.0
+    });
+
+    let response = home
+        .client
+        .put("/heat_source/curve")
+        .header(ContentType::JSON)
+        .body(curve.to_string())
+        .dispatch();
+    assert_eq!(Status::Ok, response.status());
+
+    let mut response = home
+        .client
+        .get("/heat_source?unit=fahrenheit")
+        .header(ContentType::JSON)
+        .dispatch();
+
+    let body = response_body_json(&mut response);
+    assert_eq!(59.0, body["curve"]["mild_outdoor"]);
+    assert_eq!(14.0, body["curve"]["design_outdoor"]);
+    assert_eq!(158.0, body["curve"]["max_flow"]);
+    assert_eq!(3.6, body["curve"]["max_setpoint_offset"]);
+}
+
+#[test]
+fn alert_rules_sent_in_fahrenheit_are_stored_in_celsius() {
+    let home = create_home();
+    let rules = json!({ "rules": [{ "kind": "reading_out_of_range", "quantity": "temperature", "above": 77.0 }] });
+
+    let response = home
+        .client
+        .put("/alerts/rules?unit=fahrenheit")
+        .header(ContentType::JSON)
+        .body(rules.to_string())
+        .dispatch();
+    assert_eq!(Status::Ok, response.status());
+
+    let mut response = home
+        .client
+        .get("/alerts/rules?unit=celsius")
+        .header(ContentType::JSON)
+        .dispatch();
+    assert!((response_body_json(&mut response)["rules"][0]["above"].as_f64().unwrap() - 25.0).abs() < 1e-9);
+}
+
+#[test]
+fn audited_changes_are_served_in_unit_of_query() {
+    let home = create_home();
+    home.client
+        .patch(format!("/zones/{}", ZONE_UUID))
+        .header(ContentType::JSON)
+        .body(r#"{"setpoint":22.0}"#)
+        .dispatch();
+
+    let mut response = home
+        .client
+        .get("/audit?unit=fahrenheit")
+        .header(ContentType::JSON)
+        .dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    let body = response_body_json(&mut response);
+    assert_eq!(68.0, body["entries"][0]["changes"]["setpoint"]["before"]);
+    assert_eq!(71.6, body["entries"][0]["changes"]["setpoint"]["after"]);
+}
+
+#[test]
+fn unknown_unit_returns_400_and_changes_nothing() {
+    let home = create_home();
+
+    let response = home
+        .client
+        .get(format!("/zones/{}?unit=kelvin", ZONE_UUID))
+        .header(ContentType::JSON)
+        .dispatch();
+    assert_eq!(Status::BadRequest, response.status());
+
+    let response = home
+        .client
+        .patch(format!("/zones/{}", ZONE_UUID))
+        .header(ContentType::JSON)
+        .header(Header::new("X-Temperature-Unit", "kelvin"))
+        .body(r#"{"setpoint":295.15}"#)
+        .dispatch();
+    assert_eq!(Status::BadRequest, response.status());
+    assert!((living_room_setpoint(&home) - 20.0).abs() < 1e-9);
+
+    let mut response = home
+        .client
+        .delete(format!("/zones/{}?unit=kelvin", ZONE_UUID))
+        .header(ContentType::JSON)
+        .dispatch();
+    assert_eq!(Status::BadRequest, response.status());
+    assert_eq!(Some("unknown temperature unit kelvin".to_string()), response.body_string());
+    assert!(home.zones.lock().unwrap().get(&Uuid::parse_str(ZONE_UUID).unwrap()).is_some());
+
+    let response = home.client.get("/rules?unit=kelvin").header(ContentType::JSON).dispatch();
+    assert_eq!(Status::BadRequest, response.status());
+}
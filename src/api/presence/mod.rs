@@ -0,0 +1,127 @@
+use api::audit::Actor;
+use api::preferences::UnitJson;
+use audit::AuditLogState;
+use clock::{self, Timestamp};
+use device::{DeviceCollection, DeviceCollectionState};
+use presence::{Presence, PresenceSetpoints, PresenceState, PresenceZone, Report};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Rocket, State};
+use rocket_contrib::json::Json;
+use rocket_contrib::uuid::Uuid as RocketUuid;
+use zone::{ZoneCollection, ZoneCollectionState};
+
+pub fn mount(rocket: Rocket, presence: PresenceState) -> Rocket {
+    rocket
+        .mount(
+            "/presence",
+            routes![
+                get_presence,
+                post_report,
+                delete_agent,
+                put_presence_zone,
+                delete_presence_zone
+            ],
+        )
+        .manage(presence)
+}
+
+fn update(
+    now: Timestamp,
+    actor: &Actor,
+    zones: &mut ZoneCollection,
+    devices: &DeviceCollection,
+    presence: &mut Presence,
+    audit: &AuditLogState,
+) {
+    let zones_before = zones.clone();
+    if !presence.update(now, zones).is_empty() {
+        audit
+            .lock()
+            .unwrap()
+            .record_changes(now, actor.name(), (&zones_before, devices), (&*zones, devices));
+    }
+}
+
+#[get("/", format = "application/json")]
+fn get_presence(presence: State<PresenceState>) -> Json<Presence> {
+    Json(presence.lock().unwrap().clone())
+}
+
+#[post("/agents/<agent>", format = "application/json", data = "<report>")]
+fn post_report(
+    agent: String,
+    report: Json<Report>,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    presence: State<PresenceState>,
+    audit: State<AuditLogState>,
+) -> Result<Status, status::Custom<String>> {
+    let now = clock::now();
+    let mut zones = zones.lock().unwrap();
+    if let Some(zone_uuid) = report.zone_uuid {
+        if zones.get(&zone_uuid).is_none() {
+            let error = format!("zone {} does not exist", zone_uuid);
+            return Err(status::Custom(Status::UnprocessableEntity, error));
+        }
+    }
+
+    let devices = devices.lock().unwrap();
+    let mut presence = presence.lock().unwrap();
+    presence.report(&agent, *report, now);
+    update(now, &actor, &mut zones, &devices, &mut presence, &audit);
+    Ok(Status::NoContent)
+}
+
+#[delete("/agents/<agent>", format = "application/json")]
+fn delete_agent(
+    agent: String,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    presence: State<PresenceState>,
+    audit: State<AuditLogState>,
+) -> Status {
+    let now = clock::now();
+    let mut zones = zones.lock().unwrap();
+    let devices = devices.lock().unwrap();
+    let mut presence = presence.lock().unwrap();
+    presence.forget_agent(&agent);
+    update(now, &actor, &mut zones, &devices, &mut presence, &audit);
+    Status::NoContent
+}
+
+#[put("/zones/<uuid>", format = "application/json", data = "<setpoints>")]
+fn put_presence_zone(
+    uuid: RocketUuid,
+    setpoints: UnitJson<PresenceSetpoints>,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    presence: State<PresenceState>,
+    audit: State<AuditLogState>,
+) -> Result<status::Custom<Json<PresenceZone>>, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let now = clock::now();
+    let mut zones = zones.lock().unwrap();
+    if zones.get(&uuid).is_none() {
+        return Err(status::Custom(Status::UnprocessableEntity, format!("zone {} does not exist", uuid)));
+    }
+
+    let devices = devices.lock().unwrap();
+    let mut presence = presence.lock().unwrap();
+    let status = if presence.zone(&uuid).is_some() { Status::Ok } else { Status::Created };
+    presence.set_zone(uuid, *setpoints, now);
+    update(now, &actor, &mut zones, &devices, &mut presence, &audit);
+    Ok(status::Custom(status, Json(*presence.zone(&uuid).unwrap())))
+}
+
+#[delete("/zones/<uuid>", format = "application/json")]
+fn delete_presence_zone(uuid: RocketUuid, presence: State<PresenceState>) -> Status {
+    presence.lock().unwrap().remove_zone(&uuid.into_inner());
+    Status::NoContent
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,177 @@
+use super::*;
+use fixtures::{self, response_body_json, Home};
+use presence::GracePeriods;
+use rocket::http::ContentType;
+use rocket::local::{Client, LocalResponse};
+use rocket_contrib::json::JsonValue;
+use std::sync::Mutex;
+use temperature::Temperature;
+use uuid::Uuid;
+use zone::Zone;
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+
+fn create_home() -> Home {
+    let mut zones = ZoneCollection::new();
+    zones.add(Uuid::parse_str(ZONE_UUID).unwrap(), Zone::new("Living Room".to_string()));
+
+    fixtures::create_home(zones, DeviceCollection::new(), |rocket, zones, devices| {
+        let rocket = rocket.manage(zones).manage(devices);
+        mount(rocket, PresenceState::new(Mutex::new(Presence::new(GracePeriods::default()))))
+    })
+}
+
+fn living_room(home: &Home) -> Zone {
+    let zones = home.zones.lock().unwrap();
+    zones.get(&Uuid::parse_str(ZONE_UUID).unwrap()).cloned().unwrap()
+}
+
+fn living_room_at(setpoint: f64) -> Zone {
+    let mut zone = Zone::new("Living Room".to_string());
+    zone.set_setpoint(Temperature::from_celsius(setpoint));
+    zone
+}
+
+fn get_presence(client: &Client) -> JsonValue {
+    let mut response = client.get("/presence").header(ContentType::JSON).dispatch();
+    response_body_json(&mut response)
+}
+
+fn put_living_room<'c>(client: &'c Client) -> LocalResponse<'c> {
+    client
+        .put(format!("/presence/zones/{}", ZONE_UUID))
+        .header(ContentType::JSON)
+        .body(r#"{"comfort_setpoint":21.0,"eco_setpoint":17.0}"#)
+        .dispatch()
+}
+
+fn report<'c>(client: &'c Client, agent: &str, report: JsonValue) -> LocalResponse<'c> {
+    client
+        .post(format!("/presence/agents/{}", agent))
+        .header(ContentType::JSON)
+        .body(report.to_string())
+        .dispatch()
+}
+
+mod report_presence {
+    use super::*;
+
+    #[test]
+    fn arrival_occupies_home() {
+        let home = create_home();
+        let client = &home.client;
+
+        let response = report(client, "phone", json!({ "movement": "arrive" }));
+
+        assert_eq!(Status::NoContent, response.status());
+        let presence = get_presence(client);
+        assert_eq!(Some(true), presence["home"]["occupied"].as_bool());
+        assert_eq!(Some(true), presence["agents"]["phone"]["present"].as_bool());
+    }
+
+    #[test]
+    fn arrival_switches_zone_to_comfort_and_records_it() {
+        let home = create_home();
+        let client = &home.client;
+        put_living_room(client);
+
+        report(client, "phone", json!({ "movement": "arrive", "zone_uuid": ZONE_UUID }));
+
+        assert_eq!(living_room_at(21.0), living_room(&home));
+        assert_eq!("comfort", get_presence(client)["zones"][ZONE_UUID]["mode"]);
+        let mut response = client.get("/audit").header(ContentType::JSON).dispatch();
+        assert_eq!(1, response_body_json(&mut response)["entries"].as_array().unwrap().len());
+    }
+
+    #[test]
+    fn leaving_keeps_comfort_during_grace_period() {
+        let home = create_home();
+        let client = &home.client;
+        put_living_room(client);
+        report(client, "phone", json!({ "movement": "arrive" }));
+
+        report(client, "phone", json!({ "movement": "leave" }));
+
+        assert_eq!(living_room_at(21.0), living_room(&home));
+        let presence = get_presence(client);
+        assert_eq!(Some(false), presence["home"]["occupied"].as_bool());
+        assert_eq!(Some(false), presence["zones"][ZONE_UUID]["occupied"].as_bool());
+    }
+
+    #[test]
+    fn unknown_zone_returns_422() {
+        let home = create_home();
+
+        let response = report(
+            &home.client,
+            "phone",
+            json!({ "movement": "arrive", "zone_uuid": "00000000-0000-0000-0000-000000000000" }),
+        );
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn forgotten_agent_is_not_home() {
+        let home = create_home();
+        let client = &home.client;
+        report(client, "phone", json!({ "movement": "arrive" }));
+
+        client
+            .delete("/presence/agents/phone")
+            .header(ContentType::JSON)
+            .dispatch();
+
+        let presence = get_presence(client);
+        assert_eq!(Some(false), presence["home"]["occupied"].as_bool());
+        assert!(presence["agents"].as_object().unwrap().is_empty());
+    }
+}
+
+mod presence_zones {
+    use super::*;
+
+    #[test]
+    fn put_creates_then_replaces_setpoints() {
+        let home = create_home();
+        let client = &home.client;
+
+        assert_eq!(Status::Created, put_living_room(client).status());
+        let mut response = put_living_room(client);
+
+        assert_eq!(Status::Ok, response.status());
+        let zone = response_body_json(&mut response);
+        assert_eq!(21.0, zone["comfort_setpoint"]);
+        assert_eq!(Some(false), zone["occupied"].as_bool());
+    }
+
+    #[test]
+    fn put_unknown_zone_returns_422() {
+        let home = create_home();
+
+        let response = home
+            .client
+            .put("/presence/zones/00000000-0000-0000-0000-000000000000")
+            .header(ContentType::JSON)
+            .body(r#"{"comfort_setpoint":21.0,"eco_setpoint":17.0}"#)
+            .dispatch();
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn deleted_zone_stops_following_presence() {
+        let home = create_home();
+        let client = &home.client;
+        put_living_room(client);
+
+        client
+            .delete(format!("/presence/zones/{}", ZONE_UUID))
+            .header(ContentType::JSON)
+            .dispatch();
+        report(client, "phone", json!({ "movement": "arrive" }));
+
+        assert!(get_presence(client)["zones"].as_object().unwrap().is_empty());
+        assert_eq!(living_room_at(16.0), living_room(&home));
+    }
+}
@@ -0,0 +1,143 @@
+use api::audit::Actor;
+use api::preferences::UnitJson;
+use audit::AuditLogState;
+use clock;
+use device::{DeviceCollectionState, HealthPolicy};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Rocket, State};
+use rocket_contrib::json::{Json, JsonValue};
+use rocket_contrib::uuid::Uuid as RocketUuid;
+use rule::{DryRun, Home, Rule, RuleEngineState};
+use scene::SceneCollectionState;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+use zone::ZoneCollectionState;
+
+pub fn mount(rocket: Rocket, engine: RuleEngineState) -> Rocket {
+    rocket
+        .mount(
+            "/rules",
+            routes![
+                get_rules,
+                post_rules,
+                get_rule_from_uuid,
+                put_rule_from_uuid,
+                delete_rule_from_uuid,
+                dry_run_rule,
+                post_event
+            ],
+        )
+        .manage(engine)
+}
+
+#[get("/", format = "application/json")]
+fn get_rules(engine: State<RuleEngineState>) -> JsonValue {
+    let engine = engine.lock().unwrap();
+    let rules: BTreeMap<&Uuid, &Rule> = engine.iter().collect();
+    json!({ "rules": rules })
+}
+
+#[post("/", format = "application/json", data = "<rule>")]
+fn post_rules(
+    rule: UnitJson<Rule>,
+    engine: State<RuleEngineState>,
+) -> Result<status::Created<Json<Rule>>, status::Custom<String>> {
+    let rule = rule.into_inner();
+    let uuid = Uuid::new_v4();
+    engine
+        .lock()
+        .unwrap()
+        .add(uuid, rule.clone())
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    Ok(status::Created(format!("/rules/{}", uuid), Some(Json(rule))))
+}
+
+#[get("/<uuid>", format = "application/json")]
+fn get_rule_from_uuid(uuid: RocketUuid, engine: State<RuleEngineState>) -> Option<Json<Rule>> {
+    engine.lock().unwrap().get(&uuid.into_inner()).cloned().map(Json)
+}
+
+#[put("/<uuid>", format = "application/json", data = "<rule>")]
+fn put_rule_from_uuid(
+    uuid: RocketUuid,
+    rule: UnitJson<Rule>,
+    engine: State<RuleEngineState>,
+) -> Result<status::Custom<Json<Rule>>, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let rule = rule.into_inner();
+    let mut engine = engine.lock().unwrap();
+    let status = if engine.get(&uuid).is_some() { Status::Ok } else { Status::Created };
+    engine
+        .add(uuid, rule.clone())
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    Ok(status::Custom(status, Json(rule)))
+}
+
+#[delete("/<uuid>", format = "application/json")]
+fn delete_rule_from_uuid(uuid: RocketUuid, engine: State<RuleEngineState>) -> Status {
+    engine.lock().unwrap().remove(&uuid.into_inner());
+    Status::NoContent
+}
+
+#[post("/dry_run?<event>", format = "application/json", data = "<rule>")]
+fn dry_run_rule(
+    rule: UnitJson<Rule>,
+    event: Option<String>,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    scenes: State<SceneCollectionState>,
+    policy: State<HealthPolicy>,
+) -> Result<Json<DryRun>, status::Custom<String>> {
+    rule.validate()
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+
+    let mut zones = zones.lock().unwrap();
+    let mut devices = devices.lock().unwrap();
+    let mut scenes = scenes.lock().unwrap();
+    let home = Home {
+        zones: &mut zones,
+        devices: &mut devices,
+        scenes: &mut scenes,
+        policy: &policy,
+    };
+    Ok(Json(rule.dry_run(event.as_ref().map(String::as_str), clock::now(), &home)))
+}
+
+#[post("/events/<name>", format = "application/json")]
+fn post_event(
+    name: String,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    scenes: State<SceneCollectionState>,
+    policy: State<HealthPolicy>,
+    engine: State<RuleEngineState>,
+    audit: State<AuditLogState>,
+) -> JsonValue {
+    let now = clock::now();
+    let mut zones = zones.lock().unwrap();
+    let mut devices = devices.lock().unwrap();
+    let mut scenes = scenes.lock().unwrap();
+    let (zones_before, devices_before) = (zones.clone(), devices.clone());
+
+    let acted = {
+        let mut home = Home {
+            zones: &mut zones,
+            devices: &mut devices,
+            scenes: &mut scenes,
+            policy: &policy,
+        };
+        engine.lock().unwrap().fire_event(&name, now, &mut home)
+    };
+    audit.lock().unwrap().record_changes(
+        now,
+        actor.name(),
+        (&zones_before, &devices_before),
+        (&*zones, &*devices),
+    );
+    json!({ "rules": acted })
+}
+
+#[cfg(test)]
+mod tests;
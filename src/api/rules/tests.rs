@@ -0,0 +1,230 @@
+use super::*;
+use device::{Device, DeviceCollection};
+use fixtures::{self, response_body_json, Home};
+use rocket::http::ContentType;
+use rocket::local::{Client, LocalResponse};
+use rule::RuleEngine;
+use scene::SceneCollection;
+use std::sync::Mutex;
+use temperature::Temperature;
+use zone::{Zone, ZoneCollection};
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+const VALVE_UUID: &str = "84fa1356-d5de-11e8-9f8b-f2801f1b9fd1";
+
+fn create_home() -> Home {
+    let zone_uuid = Uuid::parse_str(ZONE_UUID).unwrap();
+    let mut zones = ZoneCollection::new();
+    zones.add(zone_uuid, Zone::new("Bathroom".to_string()));
+    let mut devices = DeviceCollection::new();
+    devices.add(
+        Uuid::parse_str(VALVE_UUID).unwrap(),
+        Device::new("STRV".to_string(), Some(zone_uuid)),
+    );
+
+    fixtures::create_home(zones, devices, |rocket, zones, devices| {
+        let rocket = rocket
+            .manage(zones)
+            .manage(devices)
+            .manage(SceneCollectionState::new(Mutex::new(SceneCollection::new())))
+            .manage(HealthPolicy::default());
+        mount(rocket, RuleEngineState::new(Mutex::new(RuleEngine::new())))
+    })
+}
+
+fn bathroom(home: &Home) -> Zone {
+    let zones = home.zones.lock().unwrap();
+    zones.get(&Uuid::parse_str(ZONE_UUID).unwrap()).cloned().unwrap()
+}
+
+fn bathroom_at(setpoint: f64) -> Zone {
+    let mut zone = Zone::new("Bathroom".to_string());
+    zone.set_setpoint(Temperature::from_celsius(setpoint));
+    zone
+}
+
+fn get_json(client: &Client, path: &str) -> JsonValue {
+    let mut response = client.get(path).header(ContentType::JSON).dispatch();
+    response_body_json(&mut response)
+}
+
+fn shower_boost() -> String {
+    json!({
+        "name": "Shower boost",
+        "trigger": { "type": "event", "name": "shower" },
+        "actions": [{ "type": "set_setpoint", "subject_uuid": ZONE_UUID, "setpoint": 24.0, "minutes": 30 }]
+    })
+    .to_string()
+}
+
+fn post_rule(client: &Client, rule: &str) -> String {
+    let response = client.post("/rules").header(ContentType::JSON).body(rule).dispatch();
+    assert_eq!(Status::Created, response.status());
+    response.headers().get_one("Location").unwrap()["/rules/".len()..].to_string()
+}
+
+mod save_rule {
+    use super::*;
+
+    #[test]
+    fn post_adds_rule() {
+        let home = create_home();
+        let client = &home.client;
+
+        let uuid = post_rule(client, &shower_boost());
+
+        let rules = get_json(client, "/rules");
+        assert_eq!("Shower boost", rules["rules"][&uuid]["name"]);
+        assert_eq!(Some(true), rules["rules"][&uuid]["enabled"].as_bool());
+        assert_eq!("shower", rules["rules"][&uuid]["trigger"]["name"]);
+    }
+
+    #[test]
+    fn without_actions_returns_422() {
+        let home = create_home();
+        let rule = json!({ "name": "Nothing", "trigger": { "type": "event", "name": "shower" }, "actions": [] });
+
+        let response = home
+            .client
+            .post("/rules")
+            .header(ContentType::JSON)
+            .body(rule.to_string())
+            .dispatch();
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn reading_trigger_without_threshold_returns_422() {
+        let home = create_home();
+        let rule = json!({
+            "name": "Cold",
+            "trigger": { "type": "reading", "subject_uuid": ZONE_UUID },
+            "actions": [{ "type": "adjust_setpoint", "delta": 1.0 }]
+        });
+
+        let response = home
+            .client
+            .post("/rules")
+            .header(ContentType::JSON)
+            .body(rule.to_string())
+            .dispatch();
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn put_creates_then_replaces_rule() {
+        let home = create_home();
+        let client = &home.client;
+        let path = format!("/rules/{}", ZONE_UUID);
+        let put = |rule: String| client.put(path.clone()).header(ContentType::JSON).body(rule).dispatch();
+
+        assert_eq!(Status::Created, put(shower_boost()).status());
+        let disabled = json!({
+            "name": "Shower boost",
+            "enabled": false,
+            "trigger": { "type": "event", "name": "shower" },
+            "actions": [{ "type": "adjust_setpoint", "delta": 2.0 }]
+        });
+        assert_eq!(Status::Ok, put(disabled.to_string()).status());
+
+        let rule = get_json(client, &path);
+        assert_eq!(Some(false), rule["enabled"].as_bool());
+        assert_eq!("adjust_setpoint", rule["actions"][0]["type"]);
+    }
+
+    #[test]
+    fn delete_removes_rule() {
+        let home = create_home();
+        let client = &home.client;
+        let uuid = post_rule(client, &shower_boost());
+
+        let response = client
+            .delete(format!("/rules/{}", uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::NoContent, response.status());
+        let response = client.get(format!("/rules/{}", uuid)).header(ContentType::JSON).dispatch();
+        assert_eq!(Status::NotFound, response.status());
+    }
+}
+
+mod run_rule {
+    use super::*;
+
+    fn post_event<'c>(client: &'c Client, name: &str) -> LocalResponse<'c> {
+        client
+            .post(format!("/rules/events/{}", name))
+            .header(ContentType::JSON)
+            .dispatch()
+    }
+
+    #[test]
+    fn dry_run_reports_changes_without_making_them() {
+        let home = create_home();
+
+        let mut response = home
+            .client
+            .post("/rules/dry_run?event=shower")
+            .header(ContentType::JSON)
+            .body(shower_boost())
+            .dispatch();
+
+        assert_eq!(Status::Ok, response.status());
+        let dry_run = response_body_json(&mut response);
+        assert_eq!(Some(true), dry_run["trigger_holds"].as_bool());
+        assert_eq!(Some(true), dry_run["conditions_hold"].as_bool());
+        assert_eq!(ZONE_UUID, dry_run["changes"][0]["subject_uuid"]);
+        assert_eq!(24.0, dry_run["changes"][0]["after"]);
+        assert_eq!(bathroom_at(16.0), bathroom(&home));
+    }
+
+    #[test]
+    fn dry_run_of_other_event_does_not_trigger() {
+        let home = create_home();
+
+        let mut response = home
+            .client
+            .post("/rules/dry_run?event=doorbell")
+            .header(ContentType::JSON)
+            .body(shower_boost())
+            .dispatch();
+
+        assert_eq!(Some(false), response_body_json(&mut response)["trigger_holds"].as_bool());
+    }
+
+    #[test]
+    fn event_runs_matching_rules_and_records_changes() {
+        let home = create_home();
+        let client = &home.client;
+        let uuid = post_rule(client, &shower_boost());
+
+        let mut response = post_event(client, "shower");
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(uuid, response_body_json(&mut response)["rules"][0]);
+        assert_eq!(bathroom_at(24.0), bathroom(&home));
+        let audit = get_json(client, "/audit");
+        assert_eq!(1, audit["entries"].as_array().unwrap().len());
+    }
+
+    #[test]
+    fn event_skips_disabled_rules() {
+        let home = create_home();
+        let client = &home.client;
+        let rule = json!({
+            "name": "Shower boost",
+            "enabled": false,
+            "trigger": { "type": "event", "name": "shower" },
+            "actions": [{ "type": "set_setpoint", "subject_uuid": ZONE_UUID, "setpoint": 24.0 }]
+        });
+        post_rule(client, &rule.to_string());
+
+        let mut response = post_event(client, "shower");
+
+        assert!(response_body_json(&mut response)["rules"].as_array().unwrap().is_empty());
+        assert_eq!(bathroom_at(16.0), bathroom(&home));
+    }
+}
@@ -0,0 +1,121 @@
+use api::audit::Actor;
+use api::preferences::UnitJson;
+use audit::AuditLogState;
+use clock;
+use device::DeviceCollectionState;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::{Rocket, State};
+use rocket_contrib::json::{Json, JsonValue};
+use rocket_contrib::uuid::Uuid as RocketUuid;
+use scene::{Scene, SceneCollectionState};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+use zone::ZoneCollectionState;
+
+pub fn mount(rocket: Rocket, scenes: SceneCollectionState) -> Rocket {
+    rocket
+        .mount(
+            "/scenes",
+            routes![
+                get_scenes,
+                post_scenes,
+                get_scene_from_uuid,
+                put_scene_from_uuid,
+                delete_scene_from_uuid,
+                activate_scene
+            ],
+        )
+        .manage(scenes)
+}
+
+#[get("/", format = "application/json")]
+fn get_scenes(scenes: State<SceneCollectionState>) -> JsonValue {
+    let scenes = scenes.lock().unwrap();
+    let active = scenes.active();
+    let scenes: BTreeMap<&Uuid, &Scene> = scenes.iter().collect();
+    json!({ "scenes": scenes, "active": active })
+}
+
+#[post("/", format = "application/json", data = "<scene>")]
+fn post_scenes(
+    scene: UnitJson<Scene>,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    scenes: State<SceneCollectionState>,
+) -> Result<status::Created<Json<Scene>>, status::Custom<String>> {
+    let scene = scene.into_inner();
+    scene
+        .check_targets(&zones.lock().unwrap(), &devices.lock().unwrap())
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+
+    let uuid = Uuid::new_v4();
+    scenes.lock().unwrap().add(uuid, scene.clone());
+    Ok(status::Created(format!("/scenes/{}", uuid), Some(Json(scene))))
+}
+
+#[get("/<uuid>", format = "application/json")]
+fn get_scene_from_uuid(uuid: RocketUuid, scenes: State<SceneCollectionState>) -> Option<Json<Scene>> {
+    scenes.lock().unwrap().get(&uuid.into_inner()).cloned().map(Json)
+}
+
+#[put("/<uuid>", format = "application/json", data = "<scene>")]
+fn put_scene_from_uuid(
+    uuid: RocketUuid,
+    scene: UnitJson<Scene>,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    scenes: State<SceneCollectionState>,
+) -> Result<status::Custom<Json<Scene>>, status::Custom<String>> {
+    let scene = scene.into_inner();
+    scene
+        .check_targets(&zones.lock().unwrap(), &devices.lock().unwrap())
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+
+    let uuid = uuid.into_inner();
+    let mut scenes = scenes.lock().unwrap();
+    let status = if scenes.get(&uuid).is_some() { Status::Ok } else { Status::Created };
+    scenes.add(uuid, scene.clone());
+    Ok(status::Custom(status, Json(scene)))
+}
+
+#[delete("/<uuid>", format = "application/json")]
+fn delete_scene_from_uuid(uuid: RocketUuid, scenes: State<SceneCollectionState>) -> Status {
+    scenes.lock().unwrap().remove(&uuid.into_inner());
+    Status::NoContent
+}
+
+#[post("/<uuid>/activate", format = "application/json")]
+fn activate_scene(
+    uuid: RocketUuid,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    scenes: State<SceneCollectionState>,
+    audit: State<AuditLogState>,
+) -> Result<Option<Status>, status::Custom<String>> {
+    let mut zones = zones.lock().unwrap();
+    let mut devices = devices.lock().unwrap();
+    let (zones_before, devices_before) = (zones.clone(), devices.clone());
+
+    match scenes
+        .lock()
+        .unwrap()
+        .activate(&uuid.into_inner(), &mut zones, &mut devices)
+    {
+        Some(Ok(())) => {}
+        Some(Err(error)) => return Err(status::Custom(Status::Conflict, error)),
+        None => return Ok(None),
+    }
+
+    audit.lock().unwrap().record_changes(
+        clock::now(),
+        actor.name(),
+        (&zones_before, &devices_before),
+        (&*zones, &*devices),
+    );
+    Ok(Some(Status::NoContent))
+}
+
+#[cfg(test)]
+mod tests;
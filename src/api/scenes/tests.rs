@@ -0,0 +1,209 @@
+use super::*;
+use device::{Device, DeviceCollection, DeviceType};
+use fixtures::{self, response_body_json, Home};
+use rocket::http::ContentType;
+use rocket::local::{Client, LocalResponse};
+use scene::SceneCollection;
+use std::sync::Mutex;
+use temperature::Temperature;
+use zone::{Zone, ZoneCollection};
+
+const ZONE_UUID: &str = "8a70d6f5-a877-4331-8f15-0f20e4d34c0f";
+const VALVE_UUID: &str = "84fa1356-d5de-11e8-9f8b-f2801f1b9fd1";
+const SENSOR_UUID: &str = "88f573e2-d5de-11e8-9f8b-f2801f1b9fd1";
+
+fn create_home() -> Home {
+    let zone_uuid = Uuid::parse_str(ZONE_UUID).unwrap();
+    let mut zones = ZoneCollection::new();
+    zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+    let mut devices = DeviceCollection::new();
+    devices.add(
+        Uuid::parse_str(VALVE_UUID).unwrap(),
+        Device::new("STRV".to_string(), Some(zone_uuid)),
+    );
+    let sensor = Device::with_type("Sensor".to_string(), Some(zone_uuid), DeviceType::TemperatureSensor);
+    devices.add(Uuid::parse_str(SENSOR_UUID).unwrap(), sensor);
+
+    fixtures::create_home(zones, devices, |rocket, zones, devices| {
+        let rocket = rocket.manage(zones).manage(devices);
+        mount(rocket, SceneCollectionState::new(Mutex::new(SceneCollection::new())))
+    })
+}
+
+fn living_room(home: &Home) -> Zone {
+    let zones = home.zones.lock().unwrap();
+    zones.get(&Uuid::parse_str(ZONE_UUID).unwrap()).cloned().unwrap()
+}
+
+fn living_room_at(setpoint: f64) -> Zone {
+    let mut zone = Zone::new("Living Room".to_string());
+    zone.set_setpoint(Temperature::from_celsius(setpoint));
+    zone
+}
+
+fn movie_night() -> String {
+    json!({
+        "name": "Movie night",
+        "zones": { ZONE_UUID: { "setpoint": 21.0 } },
+        "devices": { VALVE_UUID: { "setpoint": 22.5 } }
+    })
+    .to_string()
+}
+
+fn post_scene(client: &Client, scene: &str) -> String {
+    let response = client.post("/scenes").header(ContentType::JSON).body(scene).dispatch();
+    assert_eq!(Status::Created, response.status());
+    response.headers().get_one("Location").unwrap()["/scenes/".len()..].to_string()
+}
+
+fn get_json(client: &Client, path: &str) -> JsonValue {
+    let mut response = client.get(path).header(ContentType::JSON).dispatch();
+    response_body_json(&mut response)
+}
+
+mod save_scene {
+    use super::*;
+
+    #[test]
+    fn post_adds_scene() {
+        let home = create_home();
+        let client = &home.client;
+
+        let uuid = post_scene(client, &movie_night());
+
+        let scenes = get_json(client, "/scenes");
+        assert_eq!("Movie night", scenes["scenes"][&uuid]["name"]);
+        assert_eq!(21.0, scenes["scenes"][&uuid]["zones"][ZONE_UUID]["setpoint"]);
+        assert!(scenes["active"].is_null());
+    }
+
+    #[test]
+    fn unknown_zone_returns_422() {
+        let home = create_home();
+        let client = &home.client;
+        let scene = json!({ "name": "Guests staying", "zones": { VALVE_UUID: { "setpoint": 19.0 } } });
+
+        let response = client
+            .post("/scenes")
+            .header(ContentType::JSON)
+            .body(scene.to_string())
+            .dispatch();
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn device_without_setpoint_returns_422() {
+        let home = create_home();
+        let client = &home.client;
+        let scene = json!({ "name": "Evening", "devices": { SENSOR_UUID: { "setpoint": 19.0 } } });
+
+        let response = client
+            .post("/scenes")
+            .header(ContentType::JSON)
+            .body(scene.to_string())
+            .dispatch();
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn put_replaces_scene() {
+        let home = create_home();
+        let client = &home.client;
+        let uuid = post_scene(client, &movie_night());
+
+        let response = client
+            .put(format!("/scenes/{}", uuid))
+            .header(ContentType::JSON)
+            .body(json!({ "name": "Evening" }).to_string())
+            .dispatch();
+
+        assert_eq!(Status::Ok, response.status());
+        let scene = get_json(client, &format!("/scenes/{}", uuid));
+        assert_eq!("Evening", scene["name"]);
+        assert!(scene["zones"].as_object().unwrap().is_empty());
+    }
+}
+
+mod activate_scene {
+    use super::*;
+
+    fn activate<'c>(client: &'c Client, uuid: &str) -> LocalResponse<'c> {
+        client
+            .post(format!("/scenes/{}/activate", uuid))
+            .header(ContentType::JSON)
+            .dispatch()
+    }
+
+    #[test]
+    fn applies_targets_and_records_active_scene() {
+        let home = create_home();
+        let client = &home.client;
+        let uuid = post_scene(client, &movie_night());
+
+        let response = activate(client, &uuid);
+
+        assert_eq!(Status::NoContent, response.status());
+        assert_eq!(living_room_at(21.0), living_room(&home));
+        let devices = home.devices.lock().unwrap();
+        let valve = devices.get(&Uuid::parse_str(VALVE_UUID).unwrap()).unwrap();
+        assert_eq!(Some(Temperature::from_celsius(22.5)), valve.setpoint());
+        drop(devices);
+        assert_eq!(uuid, get_json(client, "/scenes")["active"]["scene_uuid"]);
+    }
+
+    #[test]
+    fn records_changes_in_audit_log() {
+        let home = create_home();
+        let client = &home.client;
+        let uuid = post_scene(client, &movie_night());
+
+        activate(client, &uuid);
+
+        let audit = get_json(client, "/audit");
+        assert_eq!(2, audit["entries"].as_array().unwrap().len());
+    }
+
+    #[test]
+    fn with_removed_device_returns_409_and_changes_nothing() {
+        let home = create_home();
+        let client = &home.client;
+        let uuid = post_scene(client, &movie_night());
+        let valve_uuid = Uuid::parse_str(VALVE_UUID).unwrap();
+        home.devices.lock().unwrap().remove(&valve_uuid, false);
+
+        let response = activate(client, &uuid);
+
+        assert_eq!(Status::Conflict, response.status());
+        assert_eq!(living_room_at(16.0), living_room(&home));
+        assert!(get_json(client, "/scenes")["active"].is_null());
+    }
+
+    #[test]
+    fn unknown_scene_returns_404() {
+        let home = create_home();
+        let client = &home.client;
+
+        let response = activate(client, "00000000-0000-0000-0000-000000000000");
+
+        assert_eq!(Status::NotFound, response.status());
+    }
+
+    #[test]
+    fn deleting_active_scene_clears_it() {
+        let home = create_home();
+        let client = &home.client;
+        let uuid = post_scene(client, &movie_night());
+        activate(client, &uuid);
+
+        client
+            .delete(format!("/scenes/{}", uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+
+        let scenes = get_json(client, "/scenes");
+        assert!(scenes["scenes"].as_object().unwrap().is_empty());
+        assert!(scenes["active"].is_null());
+    }
+}
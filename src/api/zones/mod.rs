@@ -1,15 +1,29 @@
+use alert::AlertEngineState;
+use api::audit::Actor;
+use api::conditional::{Preconditions, Versioned};
+use api::devices::{list_devices, DeviceQuery};
+use api::idempotency::{IdempotencyKey, Replays, ReplaysState};
+use api::preferences::UnitJson;
+use audit::AuditLogState;
+use clock;
+use device::{Device, DeviceCollectionState, HealthPolicy};
+use page::{self, Page, Sort, SortKey};
+use reading::Readings;
 use rocket::http::Status;
+use rocket::request::Form;
 use rocket::response::status;
 use rocket::{Rocket, State};
 use rocket_contrib::json::{Json, JsonValue};
 use rocket_contrib::uuid::Uuid as RocketUuid;
+use rule::{RuleEngine, RuleEngineState};
+use scene::{SceneCollection, SceneCollectionState};
+use serde::ser::{Serialize, Serializer};
 use std::sync::Mutex;
+use temperature::{Temperature, TemperatureUnit};
 use uuid::Uuid;
-use zone::{Zone, ZoneCollection};
+use zone::{Zone, ZoneCollection, ZoneCollectionState};
 
-type ZoneCollectionState = Mutex<ZoneCollection>;
-
-pub fn mount(rocket: Rocket, zones: ZoneCollection) -> Rocket {
+pub fn mount(rocket: Rocket, zones: ZoneCollectionState) -> Rocket {
     rocket
         .mount(
             "/zones",
@@ -17,39 +31,227 @@ pub fn mount(rocket: Rocket, zones: ZoneCollection) -> Rocket {
                 get_zones,
                 post_zones,
                 get_zone_from_uuid,
+                get_zone_devices,
+                get_zone_readings,
+                put_zone_from_uuid,
                 patch_zone_from_uuid,
                 delete_zone_from_uuid
             ],
         )
-        .manage(ZoneCollectionState::new(zones))
+        .manage(zones)
+        .manage(ReplaysState::<Zone>::new(Mutex::new(Replays::new())))
+}
+
+#[derive(FromForm)]
+struct ZoneQuery {
+    name: Option<String>,
+    setpoint_min: Option<f64>,
+    setpoint_max: Option<f64>,
+    sort: Option<Sort>,
+    cursor: Option<String>,
+    limit: Option<usize>,
+    tree: Option<bool>,
+    #[form(field = "unit")]
+    _unit: Option<TemperatureUnit>,
+}
+
+#[derive(Serialize)]
+struct ZoneTree {
+    #[serde(flatten)]
+    zone: Zone,
+    children: ZoneForest,
 }
 
-#[get("/", format = "application/json")]
-fn get_zones(zones: State<ZoneCollectionState>) -> Json<ZoneCollection> {
+struct ZoneForest(Vec<(Uuid, ZoneTree)>);
+
+impl Serialize for ZoneForest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(uuid, tree)| (uuid, tree)))
+    }
+}
+
+#[derive(Serialize)]
+struct Zones {
+    zones: ZoneForest,
+}
+
+fn zone_forest(zones: &ZoneCollection, parent_uuid: Option<Uuid>, sort: Sort) -> ZoneForest {
+    let mut trees: Vec<(SortKey, Uuid, ZoneTree)> = zones
+        .iter()
+        .filter(|(_, zone)| zone.parent_uuid() == parent_uuid)
+        .map(|(uuid, zone)| {
+            let key = sort.key(zone.name(), Some(zone.setpoint().celsius()), zones.created_at(uuid));
+            let tree = ZoneTree {
+                zone: zone.clone(),
+                children: zone_forest(zones, Some(*uuid), sort),
+            };
+            (key, *uuid, tree)
+        })
+        .collect();
+    sort.sort(&mut trees);
+    ZoneForest(trees.into_iter().map(|(_, uuid, tree)| (uuid, tree)).collect())
+}
+
+fn get_zone_tree(query: &ZoneQuery, zones: &ZoneCollection) -> Result<Json<Zones>, status::Custom<String>> {
+    let filtered = query.name.is_some() || query.setpoint_min.is_some() || query.setpoint_max.is_some();
+    if filtered || query.cursor.is_some() || query.limit.is_some() {
+        let error = "tree cannot be combined with filters, cursor or limit".to_string();
+        return Err(status::Custom(Status::BadRequest, error));
+    }
+
+    let sort = query.sort.unwrap_or_default();
+    Ok(Json(Zones {
+        zones: zone_forest(zones, None, sort),
+    }))
+}
+
+#[get("/?<query..>", format = "application/json")]
+fn get_zones(
+    query: Form<ZoneQuery>,
+    unit: TemperatureUnit,
+    zones: State<ZoneCollectionState>,
+) -> Result<Result<Json<Page<Zone>>, Json<Zones>>, status::Custom<String>> {
     let zones = zones.lock().unwrap();
-    Json(zones.clone())
+    if query.tree == Some(true) {
+        return get_zone_tree(&query, &zones).map(Err);
+    }
+    let sort = query.sort.unwrap_or_default();
+    let name = query.name.as_ref().map(String::as_str);
+
+    let items = zones
+        .iter()
+        .filter(|(_, zone)| page::name_matches(zone.name(), name))
+        .filter(|(_, zone)| {
+            page::in_range(Some(zone.setpoint().in_unit(unit)), query.setpoint_min, query.setpoint_max)
+        })
+        .map(|(uuid, zone)| {
+            let key = sort.key(zone.name(), Some(zone.setpoint().celsius()), zones.created_at(uuid));
+            (key, *uuid, zone.clone())
+        })
+        .collect();
+
+    Page::new("zones", items, sort, query.cursor.as_ref().map(String::as_str), query.limit)
+        .map(|page| Ok(Json(page)))
+        .map_err(|error| status::Custom(Status::BadRequest, error))
 }
 
 #[post("/", format = "application/json", data = "<zone>")]
-fn post_zones(zone: Json<Zone>, zones: State<ZoneCollectionState>) -> status::Created<Json<Zone>> {
+fn post_zones(
+    zone: UnitJson<Zone>,
+    idempotency_key: IdempotencyKey,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    replays: State<ReplaysState<Zone>>,
+    audit: State<AuditLogState>,
+) -> Result<status::Created<Json<Zone>>, status::Custom<String>> {
+    let now = clock::now();
     let mut zones = zones.lock().unwrap();
+    let mut replays = replays.lock().unwrap();
+    if let Some((uuid, zone)) = replays.replay(&idempotency_key, &zone, now)? {
+        return Ok(status::Created(format!("/zones/{}", uuid), Some(Json(zone))));
+    }
+
     let uuid = Uuid::new_v4();
+    zones
+        .check_parent(&uuid, &zone)
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    let zones_before = zones.clone();
     zones.add(uuid, zone.clone());
+    let created = zones.get(&uuid).cloned().unwrap_or_else(|| zone.clone());
+    replays.record(&idempotency_key, &zone, uuid, &created, now);
+    audit
+        .lock()
+        .unwrap()
+        .record_zone_changes(now, actor.name(), &zones_before, &zones);
 
-    status::Created(format!("/zones/{}", uuid), Some(zone))
+    Ok(status::Created(format!("/zones/{}", uuid), Some(Json(created))))
 }
 
 #[get("/<uuid>", format = "application/json")]
-fn get_zone_from_uuid(uuid: RocketUuid, zones: State<ZoneCollectionState>) -> Option<Json<Zone>> {
-    if let Some(zone) = zones.lock().unwrap().get(&uuid.into_inner()) {
-        Some(Json(zone.clone()))
-    } else {
-        None
+fn get_zone_from_uuid(
+    uuid: RocketUuid,
+    preconditions: Preconditions,
+    zones: State<ZoneCollectionState>,
+) -> Option<Versioned<Json<Zone>>> {
+    let uuid = uuid.into_inner();
+    let zones = zones.lock().unwrap();
+    let zone = zones.get(&uuid)?;
+    Some(preconditions.respond(Json(zone.clone()), zones.version(&uuid)?))
+}
+
+#[get("/<uuid>/devices?<query..>", format = "application/json")]
+fn get_zone_devices(
+    uuid: RocketUuid,
+    query: Form<DeviceQuery>,
+    unit: TemperatureUnit,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+    policy: State<HealthPolicy>,
+) -> Result<Option<Json<Page<Device>>>, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let zones = zones.lock().unwrap();
+    if zones.get(&uuid).is_none() {
+        return Ok(None);
     }
+
+    let devices = devices.lock().unwrap().get_all_with_zone(Some(uuid));
+    list_devices(&devices, &query, unit, &policy).map(|page| Some(Json(page)))
 }
 
-fn patch_zone_with_json(zone: &mut Zone, patch_json: &JsonValue) {
-    let patch_json = patch_json.as_object().unwrap();
+#[get("/<uuid>/readings", format = "application/json")]
+fn get_zone_readings(
+    uuid: RocketUuid,
+    zones: State<ZoneCollectionState>,
+    devices: State<DeviceCollectionState>,
+) -> Option<Json<Readings>> {
+    let uuid = uuid.into_inner();
+    zones.lock().unwrap().get(&uuid)?;
+    Some(Json(devices.lock().unwrap().zone_readings(&uuid)))
+}
+
+#[put("/<uuid>", format = "application/json", data = "<zone>")]
+fn put_zone_from_uuid(
+    uuid: RocketUuid,
+    preconditions: Preconditions,
+    actor: Actor,
+    zone: UnitJson<Zone>,
+    zones: State<ZoneCollectionState>,
+    audit: State<AuditLogState>,
+) -> Result<status::Custom<Versioned<Json<Zone>>>, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let mut zones = zones.lock().unwrap();
+    preconditions.check_put(zones.version(&uuid))?;
+
+    zones
+        .check_parent(&uuid, &zone)
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+
+    let zones_before = zones.clone();
+    let zone = zone.into_inner();
+    zones.add(uuid, zone.clone());
+    let zone = zones.get(&uuid).cloned().unwrap_or(zone);
+    audit
+        .lock()
+        .unwrap()
+        .record_zone_changes(clock::now(), actor.name(), &zones_before, &zones);
+
+    let status = if zones_before.get(&uuid).is_some() { Status::Ok } else { Status::Created };
+    let version = zones.version(&uuid).unwrap_or_default();
+    Ok(status::Custom(status, Versioned::Body(Json(zone), version)))
+}
+
+pub fn patch_zone_with_json(zone: &mut Zone, patch_json: &JsonValue) -> Result<(), String> {
+    let patch_json = patch_json.as_object().ok_or_else(|| "patch must be a JSON object".to_string())?;
+
+    if patch_json.contains_key("parent_uuid") {
+        let parent_uuid = match patch_json["parent_uuid"].as_str() {
+            Some(parent_uuid) => {
+                Some(Uuid::parse_str(parent_uuid).map_err(|_| format!("invalid parent_uuid {}", parent_uuid))?)
+            }
+            None => None,
+        };
+        zone.set_parent_uuid(parent_uuid);
+    }
 
     if patch_json.contains_key("name") {
         if let Some(patch_name) = patch_json["name"].as_str() {
@@ -59,29 +261,84 @@ fn patch_zone_with_json(zone: &mut Zone, patch_json: &JsonValue) {
 
     if patch_json.contains_key("setpoint") {
         if let Some(patch_setpoint) = patch_json["setpoint"].as_f64() {
-            zone.set_setpoint(patch_setpoint);
+            zone.set_setpoint(Temperature::from_celsius(patch_setpoint));
+        } else if patch_json["setpoint"].is_null() {
+            zone.inherit_setpoint();
         }
     }
+    Ok(())
 }
 
 #[patch("/<uuid>", format = "application/json", data = "<patch_json>")]
 fn patch_zone_from_uuid(
     uuid: RocketUuid,
-    patch_json: Json<JsonValue>,
+    preconditions: Preconditions,
+    actor: Actor,
+    patch_json: UnitJson<JsonValue>,
     zones: State<ZoneCollectionState>,
-) -> Option<Json<Zone>> {
-    if let Some(zone) = zones.lock().unwrap().get_mut(&uuid.into_inner()) {
-        patch_zone_with_json(zone, &patch_json);
-        Some(Json(zone.clone()))
-    } else {
-        None
+    audit: State<AuditLogState>,
+) -> Result<Option<Versioned<Json<Zone>>>, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let mut zones = zones.lock().unwrap();
+    match zones.version(&uuid) {
+        Some(version) => preconditions.check_if_match(version)?,
+        None => return Ok(None),
+    }
+
+    let before = match zones.get(&uuid) {
+        Some(zone) => zone.clone(),
+        None => return Ok(None),
+    };
+    let mut zone = before.clone();
+    patch_zone_with_json(&mut zone, &patch_json)
+        .and_then(|_| zones.check_parent(&uuid, &zone))
+        .map_err(|error| status::Custom(Status::UnprocessableEntity, error))?;
+    let zones_before = zones.clone();
+    zones.add(uuid, zone);
+    let zone = zones.get(&uuid).cloned().unwrap_or(before);
+    audit
+        .lock()
+        .unwrap()
+        .record_zone_changes(clock::now(), actor.name(), &zones_before, &zones);
+    Ok(zones.version(&uuid).map(|version| Versioned::Body(Json(zone), version)))
+}
+
+pub fn check_unused(uuid: &Uuid, scenes: &SceneCollection, rules: &RuleEngine) -> Result<(), String> {
+    if let Some((scene_uuid, _)) = scenes.iter().find(|(_, scene)| scene.targets_zone(uuid)) {
+        return Err(format!("zone {} is a target of scene {}", uuid, scene_uuid));
     }
+    if let Some((rule_uuid, _)) = rules.iter().find(|(_, rule)| rule.refers_to(uuid)) {
+        return Err(format!("zone {} is used by rule {}", uuid, rule_uuid));
+    }
+    Ok(())
 }
 
 #[delete("/<uuid>", format = "application/json")]
-fn delete_zone_from_uuid(uuid: RocketUuid, zones: State<ZoneCollectionState>) -> Status {
-    zones.lock().unwrap().remove(&uuid);
-    Status::NoContent
+fn delete_zone_from_uuid(
+    uuid: RocketUuid,
+    preconditions: Preconditions,
+    actor: Actor,
+    zones: State<ZoneCollectionState>,
+    scenes: State<SceneCollectionState>,
+    rules: State<RuleEngineState>,
+    alerts: State<AlertEngineState>,
+    audit: State<AuditLogState>,
+) -> Result<Status, status::Custom<String>> {
+    let uuid = uuid.into_inner();
+    let mut zones = zones.lock().unwrap();
+    if let Some(version) = zones.version(&uuid) {
+        preconditions.check_if_match(version)?;
+        check_unused(&uuid, &scenes.lock().unwrap(), &rules.lock().unwrap())
+            .map_err(|error| status::Custom(Status::Conflict, error))?;
+        let zones_before = zones.clone();
+        zones.remove(&uuid);
+        alerts.lock().unwrap().forget_subject(&uuid);
+        audit
+            .lock()
+            .unwrap()
+            .record_zone_changes(clock::now(), actor.name(), &zones_before, &zones);
+    }
+    Ok(Status::NoContent)
 }
 
 #[cfg(test)]
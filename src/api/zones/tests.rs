@@ -1,14 +1,38 @@
 use super::*;
+use alert::AlertEngine;
+use api::audit::mount as mount_audit;
+use audit::{AuditLog, AuditRetention};
 use rocket::http::{ContentType, Status};
 use rocket::local::{Client, LocalResponse};
 use rocket_contrib::json::JsonValue;
 use serde_json::map::Values;
 use serde_json::Value;
+use std::sync::Mutex;
 use uuid::Uuid;
+use device::DeviceCollection;
+use zone::ZoneCollection;
 
 fn create_client_with_mounts(zones: ZoneCollection) -> Client {
-    let rocket = rocket::ignite();
-    let rocket = mount(rocket, zones);
+    create_client_with_mounts_and_devices(zones, DeviceCollection::new())
+}
+
+fn create_client_with_mounts_and_devices(zones: ZoneCollection, devices: DeviceCollection) -> Client {
+    create_client_with_mounts_and_scenes(zones, devices, SceneCollection::new())
+}
+
+fn create_client_with_mounts_and_scenes(
+    zones: ZoneCollection,
+    devices: DeviceCollection,
+    scenes: SceneCollection,
+) -> Client {
+    let rocket = rocket::ignite()
+        .manage(DeviceCollectionState::new(Mutex::new(devices)))
+        .manage(SceneCollectionState::new(Mutex::new(scenes)))
+        .manage(RuleEngineState::new(Mutex::new(RuleEngine::new())))
+        .manage(AlertEngineState::new(Mutex::new(AlertEngine::new(AlertEngine::default_rules()))))
+        .manage(HealthPolicy::default());
+    let rocket = mount_audit(rocket, AuditLogState::new(Mutex::new(AuditLog::new(AuditRetention::default()))));
+    let rocket = mount(rocket, ZoneCollectionState::new(Mutex::new(zones)));
     Client::new(rocket).unwrap()
 }
 
@@ -68,6 +92,82 @@ mod get_zones {
     }
 }
 
+mod get_zones_with_query {
+    use super::*;
+
+    fn create_client_with_three_zones() -> Client {
+        let mut zones = ZoneCollection::new();
+        for (uuid, name, setpoint) in &[
+            ("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1", "Living Room", 20.0),
+            ("88f573e2-d5de-11e8-9f8b-f2801f1b9fd1", "Bedroom", 18.0),
+            ("8a70d6f5-a877-4331-8f15-0f20e4d34c0f", "Guest Bedroom", 15.0),
+        ] {
+            let mut zone = Zone::new(name.to_string());
+            zone.set_setpoint(Temperature::from_celsius(*setpoint));
+            zones.add(Uuid::parse_str(uuid).unwrap(), zone);
+        }
+        create_client_with_mounts(zones)
+    }
+
+    fn get_zones_return_status_and_body(client: &Client, query: &str) -> (Status, String) {
+        let mut response = client
+            .get(format!("/zones?{}", query))
+            .header(ContentType::JSON)
+            .dispatch();
+        (response.status(), response.body_string().unwrap_or_default())
+    }
+
+    fn zone_names_in_order(body: &str) -> Vec<String> {
+        let json: Value = serde_json::from_str(body).unwrap();
+        let mut names: Vec<(usize, String)> = json["zones"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(uuid, zone)| (body.find(uuid.as_str()).unwrap(), zone["name"].as_str().unwrap().to_string()))
+            .collect();
+        names.sort();
+        names.into_iter().map(|(_, name)| name).collect()
+    }
+
+    #[test]
+    fn name_filter_matches_substring_ignoring_case() {
+        let client = create_client_with_three_zones();
+        let (_, body) = get_zones_return_status_and_body(&client, "name=bedroom&sort=name");
+
+        assert_eq!(vec!["Bedroom", "Guest Bedroom"], zone_names_in_order(&body));
+    }
+
+    #[test]
+    fn setpoint_range_is_inclusive() {
+        let client = create_client_with_three_zones();
+        let (_, body) = get_zones_return_status_and_body(&client, "setpoint_min=15.0&setpoint_max=18.0&sort=setpoint");
+
+        assert_eq!(vec!["Guest Bedroom", "Bedroom"], zone_names_in_order(&body));
+    }
+
+    #[test]
+    fn limit_and_cursor_page_through_sorted_zones() {
+        let client = create_client_with_three_zones();
+        let (_, first) = get_zones_return_status_and_body(&client, "sort=-setpoint&limit=2");
+        let cursor: Value = serde_json::from_str(&first).unwrap();
+        let cursor = cursor["next_cursor"].as_str().unwrap();
+        let query = format!("sort=-setpoint&limit=2&cursor={}", cursor);
+        let (_, second) = get_zones_return_status_and_body(&client, &query);
+
+        assert_eq!(vec!["Living Room", "Bedroom"], zone_names_in_order(&first));
+        assert_eq!(vec!["Guest Bedroom"], zone_names_in_order(&second));
+        assert!(!second.contains("next_cursor"));
+    }
+
+    #[test]
+    fn invalid_cursor_returns_400_bad_request() {
+        let client = create_client_with_three_zones();
+        let (status, _) = get_zones_return_status_and_body(&client, "cursor=not-a-cursor");
+
+        assert_eq!(Status::BadRequest, status);
+    }
+}
+
 mod get_zone {
     use super::*;
 
@@ -260,6 +360,273 @@ mod post_zone {
     }
 }
 
+mod get_zone_devices {
+    use super::*;
+
+    fn get_zone_devices_return_response<'c>(client: &'c Client, zone_uuid: &str) -> LocalResponse<'c> {
+        client
+            .get(format!("/zones/{}/devices", zone_uuid))
+            .header(ContentType::JSON)
+            .dispatch()
+    }
+
+    #[test]
+    fn returns_only_devices_in_the_zone() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let device_uuid = Uuid::parse_str("88f573e2-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut zones = ZoneCollection::new();
+        zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+        let mut devices = DeviceCollection::new();
+        devices.add(device_uuid, Device::new("STRV".to_string(), Some(zone_uuid)));
+        devices.add(Uuid::new_v4(), Device::new("Unassigned".to_string(), None));
+        let client = create_client_with_mounts_and_devices(zones, devices);
+
+        let mut response = get_zone_devices_return_response(&client, &zone_uuid.to_string());
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        let expected = json!({
+            "devices": {
+                device_uuid.to_string(): {
+                    "name": "STRV",
+                    "type": "radiator_valve",
+                    "setpoint": 16.0,
+                    "zone_uuid": zone_uuid
+                }
+            }
+        });
+        assert_eq!(expected, body);
+    }
+
+    #[test]
+    fn zone_without_devices_returns_empty_devices() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut zones = ZoneCollection::new();
+        zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+        let client = create_client_with_mounts(zones);
+
+        let mut response = get_zone_devices_return_response(&client, &zone_uuid.to_string());
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(json!({ "devices": {} }), body);
+    }
+
+    #[test]
+    fn unknown_zone_returns_error_not_found() {
+        let client = create_client_with_mounts(ZoneCollection::new());
+
+        let response = get_zone_devices_return_response(&client, "84fa1356-d5de-11e8-9f8b-f2801f1b9fd1");
+
+        assert_eq!(Status::NotFound, response.status());
+    }
+}
+
+mod get_zone_readings {
+    use super::*;
+    use reading::Quantity;
+
+    fn get_zone_readings_return_response<'c>(client: &'c Client, zone_uuid: &str) -> LocalResponse<'c> {
+        client
+            .get(format!("/zones/{}/readings", zone_uuid))
+            .header(ContentType::JSON)
+            .dispatch()
+    }
+
+    #[test]
+    fn returns_average_readings_of_devices_in_the_zone() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut zones = ZoneCollection::new();
+        zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+        let mut devices = DeviceCollection::new();
+        for &(temperature, humidity) in &[(19.0, Some(40.0)), (21.0, None)] {
+            let mut device = Device::new("Sensor".to_string(), Some(zone_uuid));
+            device.set_reading(Quantity::Temperature, temperature);
+            if let Some(humidity) = humidity {
+                device.set_reading(Quantity::Humidity, humidity);
+            }
+            devices.add(Uuid::new_v4(), device);
+        }
+        let client = create_client_with_mounts_and_devices(zones, devices);
+
+        let mut response = get_zone_readings_return_response(&client, &zone_uuid.to_string());
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        let expected = json!({
+            "temperature": { "value": 20.0, "unit": "°C" },
+            "humidity": { "value": 40.0, "unit": "%" }
+        });
+        assert_eq!(expected, body);
+    }
+
+    #[test]
+    fn unknown_zone_returns_error_not_found() {
+        let client = create_client_with_mounts(ZoneCollection::new());
+
+        let response = get_zone_readings_return_response(&client, "84fa1356-d5de-11e8-9f8b-f2801f1b9fd1");
+
+        assert_eq!(Status::NotFound, response.status());
+    }
+}
+
+mod put_zone {
+    use super::*;
+    use rocket::http::Header;
+    use zone::OptimumStart;
+
+    const ZONE_UUID: &str = "84fa1356-d5de-11e8-9f8b-f2801f1b9fd1";
+
+    fn put_zone_return_response<'c>(client: &'c Client, zone: &Zone) -> LocalResponse<'c> {
+        client
+            .put(format!("/zones/{}", ZONE_UUID))
+            .body(json!(zone).to_string())
+            .header(ContentType::JSON)
+            .dispatch()
+    }
+
+    #[test]
+    fn with_new_uuid_creates_zone_with_that_uuid() {
+        let client = create_client_with_mounts(ZoneCollection::new());
+
+        let response = put_zone_return_response(&client, &Zone::new("Attic".to_string()));
+
+        assert_eq!(Status::Created, response.status());
+        assert!(response.headers().get_one("ETag").is_some());
+        let body = get_zone_return_response_body_string(&client, ZONE_UUID);
+        assert_eq!(json!({ "name": "Attic", "setpoint": 16.0 }).to_string(), body);
+    }
+
+    #[test]
+    fn with_existing_uuid_replaces_zone() {
+        let mut zones = ZoneCollection::new();
+        zones.add(Uuid::parse_str(ZONE_UUID).unwrap(), Zone::new("Living Room".to_string()));
+        let client = create_client_with_mounts(zones);
+        let mut zone = Zone::new("Lounge".to_string());
+        zone.set_setpoint(Temperature::from_celsius(20.0));
+
+        let response = put_zone_return_response(&client, &zone);
+
+        assert_eq!(Status::Ok, response.status());
+        let body = get_zone_return_response_body_string(&client, ZONE_UUID);
+        assert_eq!(json!({ "name": "Lounge", "setpoint": 20.0 }).to_string(), body);
+    }
+
+    #[test]
+    fn keeps_optimum_start_which_clients_cannot_set() {
+        let uuid = Uuid::parse_str(ZONE_UUID).unwrap();
+        let mut zones = ZoneCollection::new();
+        zones.add(uuid, Zone::new("Living Room".to_string()));
+        let optimum_start = OptimumStart {
+            heat_up_rate: Some(1.5),
+            preheat_start: None,
+            target_at: None,
+        };
+        zones.set_optimum_start(&uuid, Some(optimum_start));
+        let client = create_client_with_mounts(zones);
+
+        client
+            .put(format!("/zones/{}", ZONE_UUID))
+            .body(r#"{"name":"Lounge","setpoint":20.0,"optimum_start":{"heat_up_rate":9.0}}"#)
+            .header(ContentType::JSON)
+            .dispatch();
+
+        let body: Value = serde_json::from_str(&get_zone_return_response_body_string(&client, ZONE_UUID)).unwrap();
+        assert_eq!("Lounge", body["name"]);
+        assert_eq!(1.5, body["optimum_start"]["heat_up_rate"]);
+        assert!(body["optimum_start"].get("target_at").is_none());
+    }
+
+    #[test]
+    fn is_idempotent() {
+        let client = create_client_with_mounts(ZoneCollection::new());
+        let zone = Zone::new("Attic".to_string());
+
+        put_zone_return_response(&client, &zone);
+        put_zone_return_response(&client, &zone);
+
+        let mut response = client.get("/zones").header(ContentType::JSON).dispatch();
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(1, body["zones"].as_object().unwrap().len());
+    }
+
+    #[test]
+    fn with_if_none_match_star_on_existing_zone_returns_412() {
+        let mut zones = ZoneCollection::new();
+        zones.add(Uuid::parse_str(ZONE_UUID).unwrap(), Zone::new("Living Room".to_string()));
+        let client = create_client_with_mounts(zones);
+
+        let response = client
+            .put(format!("/zones/{}", ZONE_UUID))
+            .body(json!(Zone::new("Lounge".to_string())).to_string())
+            .header(ContentType::JSON)
+            .header(Header::new("If-None-Match", "*"))
+            .dispatch();
+
+        assert_eq!(Status::PreconditionFailed, response.status());
+    }
+
+    #[test]
+    fn with_if_match_on_missing_zone_returns_412() {
+        let client = create_client_with_mounts(ZoneCollection::new());
+
+        let response = client
+            .put(format!("/zones/{}", ZONE_UUID))
+            .body(json!(Zone::new("Lounge".to_string())).to_string())
+            .header(ContentType::JSON)
+            .header(Header::new("If-Match", "\"1\""))
+            .dispatch();
+
+        assert_eq!(Status::PreconditionFailed, response.status());
+    }
+}
+
+mod post_zone_with_idempotency_key {
+    use super::*;
+    use rocket::http::Header;
+
+    fn post_zone_with_key<'c>(client: &'c Client, name: &str, key: &str) -> LocalResponse<'c> {
+        client
+            .post("/zones")
+            .body(json!(Zone::new(name.to_string())).to_string())
+            .header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", key.to_string()))
+            .dispatch()
+    }
+
+    #[test]
+    fn retry_returns_the_original_zone_without_creating_another() {
+        let client = create_client_with_mounts(ZoneCollection::new());
+
+        let first = post_zone_with_key(&client, "Attic", "3f1c");
+        let retry = post_zone_with_key(&client, "Attic", "3f1c");
+
+        assert_eq!(Status::Created, retry.status());
+        assert_eq!(first.headers().get_one("Location"), retry.headers().get_one("Location"));
+        let mut response = client.get("/zones").header(ContentType::JSON).dispatch();
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(1, body["zones"].as_object().unwrap().len());
+    }
+
+    #[test]
+    fn different_keys_create_different_zones() {
+        let client = create_client_with_mounts(ZoneCollection::new());
+
+        let first = post_zone_with_key(&client, "Attic", "3f1c");
+        let second = post_zone_with_key(&client, "Attic", "9a7e");
+
+        assert_ne!(first.headers().get_one("Location"), second.headers().get_one("Location"));
+    }
+
+    #[test]
+    fn key_reused_for_another_zone_returns_422() {
+        let client = create_client_with_mounts(ZoneCollection::new());
+
+        post_zone_with_key(&client, "Attic", "3f1c");
+        let response = post_zone_with_key(&client, "Cellar", "3f1c");
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+}
+
 mod patch_zone {
     use super::*;
 
@@ -342,7 +709,7 @@ mod patch_zone {
         let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
         let zone_name = "Zone Name".to_string();
         let mut zone = Zone::new(zone_name.clone());
-        zone.set_setpoint(32.0);
+        zone.set_setpoint(Temperature::from_celsius(32.0));
         let mut zones = ZoneCollection::new();
         zones.add(zone_uuid, zone);
         let client = create_client_with_mounts(zones);
@@ -360,6 +727,132 @@ mod patch_zone {
         });
         assert_eq!(expected, body);
     }
+
+    #[test]
+    fn patch_that_is_not_an_object_returns_422() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut zones = ZoneCollection::new();
+        zones.add(zone_uuid, Zone::new("Zone Name".to_string()));
+        let client = create_client_with_mounts(zones);
+
+        for patch_json in vec![json!([]), json!(5)] {
+            let response = patch_zone_return_response(&client, zone_uuid, patch_json);
+            assert_eq!(Status::UnprocessableEntity, response.status());
+        }
+
+        let mut response = client.get(format!("/zones/{}", zone_uuid)).dispatch();
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!("Zone Name", body["name"]);
+    }
+}
+
+mod conditional_requests {
+    use super::*;
+    use rocket::http::Header;
+    use zone::OptimumStart;
+
+    fn create_client_with_zone(zone_uuid: Uuid) -> Client {
+        let mut zones = ZoneCollection::new();
+        zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+        create_client_with_mounts(zones)
+    }
+
+    fn get_zone_etag(client: &Client, zone_uuid: Uuid) -> String {
+        let response = client
+            .get(format!("/zones/{}", zone_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+        response.headers().get_one("ETag").unwrap().to_string()
+    }
+
+    fn patch_zone_if_match<'c>(client: &'c Client, zone_uuid: Uuid, etag: &str) -> LocalResponse<'c> {
+        client
+            .patch(format!("/zones/{}", zone_uuid))
+            .body(json!({ "setpoint": 28.0 }).to_string())
+            .header(ContentType::JSON)
+            .header(Header::new("If-Match", etag.to_string()))
+            .dispatch()
+    }
+
+    #[test]
+    fn get_with_current_etag_in_if_none_match_returns_304_not_modified() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let client = create_client_with_zone(zone_uuid);
+        let etag = get_zone_etag(&client, zone_uuid);
+
+        let mut response = client
+            .get(format!("/zones/{}", zone_uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+
+        assert_eq!(Status::NotModified, response.status());
+        assert_eq!(Some(etag.as_str()), response.headers().get_one("ETag"));
+        assert_eq!(None, response.body_string());
+    }
+
+    #[test]
+    fn patch_with_current_etag_updates_zone_and_etag() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let client = create_client_with_zone(zone_uuid);
+        let etag = get_zone_etag(&client, zone_uuid);
+
+        let response = patch_zone_if_match(&client, zone_uuid, &etag);
+
+        assert_eq!(Status::Ok, response.status());
+        assert_ne!(Some(etag.as_str()), response.headers().get_one("ETag"));
+    }
+
+    #[test]
+    fn patch_with_stale_etag_returns_412_and_keeps_zone() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let client = create_client_with_zone(zone_uuid);
+        let stale_etag = get_zone_etag(&client, zone_uuid);
+        patch_zone_if_match(&client, zone_uuid, &stale_etag);
+
+        let response = patch_zone_if_match(&client, zone_uuid, &stale_etag);
+        let body = get_zone_return_response_body_string(&client, &zone_uuid.to_string());
+        let body: Value = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(Status::PreconditionFailed, response.status());
+        assert_eq!(28.0, body["setpoint"]);
+    }
+
+    #[test]
+    fn delete_with_stale_etag_returns_412_and_keeps_zone() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let client = create_client_with_zone(zone_uuid);
+
+        let response = client
+            .delete(format!("/zones/{}", zone_uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("If-Match", "\"0\""))
+            .dispatch();
+
+        let get_response = client
+            .get(format!("/zones/{}", zone_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::PreconditionFailed, response.status());
+        assert_eq!(Status::Ok, get_response.status());
+    }
+    #[test]
+    fn optimum_start_keeps_etag() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut zones = ZoneCollection::new();
+        zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+        let version = zones.version(&zone_uuid);
+        let optimum_start = OptimumStart {
+            heat_up_rate: Some(1.5),
+            preheat_start: None,
+            target_at: None,
+        };
+
+        zones.set_optimum_start(&zone_uuid, Some(optimum_start));
+
+        assert_eq!(version, zones.version(&zone_uuid));
+    }
 }
 
 mod delete_zone {
@@ -416,4 +909,175 @@ mod delete_zone {
         assert_eq!(expected, body);
     }
 
+    #[test]
+    fn target_of_scene_returns_409_and_keeps_zone() {
+        let zone_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut zones = ZoneCollection::new();
+        zones.add(zone_uuid, Zone::new("Living Room".to_string()));
+        let mut scenes = SceneCollection::new();
+        let scene = format!(r#"{{"name":"Cosy","zones":{{"{}":{{"setpoint":22.0}}}}}}"#, zone_uuid);
+        scenes.add(Uuid::new_v4(), serde_json::from_str(&scene).unwrap());
+        let client = create_client_with_mounts_and_scenes(zones, DeviceCollection::new(), scenes);
+
+        let response = delete_zone_return_response(&client, zone_uuid);
+        let get_response = client
+            .get(format!("/zones/{}", zone_uuid))
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::Conflict, response.status());
+        assert_eq!(Status::Ok, get_response.status());
+    }
+
+}
+
+mod zone_hierarchy {
+    use super::*;
+    use fixtures::uuid;
+
+    fn create_client_with_floor() -> Client {
+        let mut zones = ZoneCollection::new();
+        zones.add(uuid(1), Zone::new("First Floor".to_string()));
+        let mut room = Zone::new("Bedroom".to_string());
+        room.set_parent_uuid(Some(uuid(1)));
+        zones.add(uuid(2), room);
+        let mut cupboard = Zone::new("Airing Cupboard".to_string());
+        cupboard.set_parent_uuid(Some(uuid(2)));
+        zones.add(uuid(3), cupboard);
+        create_client_with_mounts(zones)
+    }
+
+    fn patch_zone<'c>(client: &'c Client, uuid: Uuid, patch_json: JsonValue) -> LocalResponse<'c> {
+        client
+            .patch(format!("/zones/{}", uuid))
+            .body(patch_json.to_string())
+            .header(ContentType::JSON)
+            .dispatch()
+    }
+
+    fn get_zone(client: &Client, uuid: Uuid) -> Value {
+        serde_json::from_str(&get_zone_return_response_body_string(client, &uuid.to_string())).unwrap()
+    }
+
+    #[test]
+    fn setpoint_on_parent_cascades_to_children() {
+        let client = create_client_with_floor();
+
+        patch_zone(&client, uuid(1), json!({ "setpoint": 19.5 }));
+
+        assert_eq!(19.5, get_zone(&client, uuid(2))["setpoint"]);
+        assert_eq!(19.5, get_zone(&client, uuid(3))["setpoint"]);
+    }
+
+    #[test]
+    fn setpoint_cascaded_to_children_is_audited() {
+        let client = create_client_with_floor();
+
+        patch_zone(&client, uuid(1), json!({ "setpoint": 19.5 }));
+
+        let mut response = client.get("/audit").header(ContentType::JSON).dispatch();
+        let audit: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        let audited: Vec<String> = audit["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["resource_uuid"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(vec![uuid(1).to_string(), uuid(2).to_string(), uuid(3).to_string()], audited);
+    }
+
+    #[test]
+    fn overridden_setpoint_is_kept_for_the_zone_and_the_zones_inside_it() {
+        let client = create_client_with_floor();
+
+        patch_zone(&client, uuid(2), json!({ "setpoint": 21.0 }));
+        patch_zone(&client, uuid(1), json!({ "setpoint": 18.0 }));
+
+        let room = get_zone(&client, uuid(2));
+        assert_eq!(21.0, room["setpoint"]);
+        assert_eq!(true, room["overrides_setpoint"]);
+        assert_eq!(21.0, get_zone(&client, uuid(3))["setpoint"]);
+    }
+
+    #[test]
+    fn null_setpoint_follows_the_parent_again() {
+        let client = create_client_with_floor();
+        patch_zone(&client, uuid(2), json!({ "setpoint": 21.0 }));
+
+        patch_zone(&client, uuid(2), json!({ "setpoint": null }));
+
+        let room = get_zone(&client, uuid(2));
+        assert_eq!(16.0, room["setpoint"]);
+        assert_eq!(Value::Null, room["overrides_setpoint"]);
+    }
+
+    #[test]
+    fn moving_a_zone_inside_itself_returns_422_and_keeps_zone() {
+        let client = create_client_with_floor();
+
+        let response = patch_zone(&client, uuid(1), json!({ "parent_uuid": uuid(3).to_string() }));
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+        assert_eq!(Value::Null, get_zone(&client, uuid(1))["parent_uuid"]);
+    }
+
+    #[test]
+    fn missing_parent_returns_422() {
+        let client = create_client_with_floor();
+        let mut zone = Zone::new("Loft".to_string());
+        zone.set_parent_uuid(Some(uuid(9)));
+
+        let response = client
+            .post("/zones")
+            .body(serde_json::to_string(&zone).unwrap())
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[test]
+    fn tree_nests_zones_inside_their_parents() {
+        let client = create_client_with_floor();
+
+        let mut response = client.get("/zones?tree=true").header(ContentType::JSON).dispatch();
+        let body: Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        let floor = &body["zones"][uuid(1).to_string()];
+        let room = &floor["children"][uuid(2).to_string()];
+        assert_eq!("First Floor", floor["name"]);
+        assert_eq!(1, body["zones"].as_object().unwrap().len());
+        assert_eq!("Airing Cupboard", room["children"][uuid(3).to_string()]["name"]);
+    }
+
+    #[test]
+    fn tree_with_limit_returns_400() {
+        let client = create_client_with_floor();
+
+        let response = client.get("/zones?tree=true&limit=1").header(ContentType::JSON).dispatch();
+
+        assert_eq!(Status::BadRequest, response.status());
+    }
+
+    #[test]
+    fn deleting_a_zone_moves_the_zones_inside_it_to_its_parent() {
+        let client = create_client_with_floor();
+
+        client.delete(format!("/zones/{}", uuid(2))).header(ContentType::JSON).dispatch();
+
+        assert_eq!(uuid(1).to_string(), get_zone(&client, uuid(3))["parent_uuid"]);
+    }
+
+    #[test]
+    fn zone_moved_to_the_top_by_deleting_its_parent_no_longer_overrides_a_setpoint() {
+        let client = create_client_with_floor();
+        patch_zone(&client, uuid(2), json!({ "setpoint": 21.0 }));
+
+        client.delete(format!("/zones/{}", uuid(1))).header(ContentType::JSON).dispatch();
+
+        let room = get_zone(&client, uuid(2));
+        assert_eq!(Value::Null, room["parent_uuid"]);
+        assert_eq!(21.0, room["setpoint"]);
+        assert_eq!(Value::Null, room["overrides_setpoint"]);
+    }
 }
@@ -0,0 +1,294 @@
+use clock::{Timestamp, DAY};
+use device::DeviceCollection;
+use rocket::config::Config;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+use zone::ZoneCollection;
+
+pub type AuditLogState = Arc<Mutex<AuditLog>>;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceType {
+    Zone,
+    Device,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct Change {
+    before: Value,
+    after: Value,
+}
+
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct AuditEntry {
+    id: u64,
+    timestamp: Timestamp,
+    actor: String,
+    action: Action,
+    resource_type: ResourceType,
+    resource_uuid: Uuid,
+    changes: BTreeMap<String, Change>,
+}
+
+impl AuditEntry {
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    pub fn resource_uuid(&self) -> Uuid {
+        self.resource_uuid
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AuditRetention {
+    pub max_age: u64,
+    pub max_entries: usize,
+}
+
+impl AuditRetention {
+    pub fn from_config(config: &Config) -> AuditRetention {
+        let mut retention = AuditRetention::default();
+        if let Ok(table) = config.get_table("audit") {
+            if let Some(days) = table.get("retention_days").and_then(|value| value.as_integer()) {
+                match u64::try_from(days).ok().and_then(|days| days.checked_mul(DAY)) {
+                    Some(max_age) => retention.max_age = max_age,
+                    None => warn!("Ignoring invalid audit retention_days: {}", days),
+                }
+            }
+            if let Some(max_entries) = table.get("max_entries").and_then(|value| value.as_integer()) {
+                match usize::try_from(max_entries) {
+                    Ok(max_entries) => retention.max_entries = max_entries,
+                    Err(_) => warn!("Ignoring invalid audit max_entries: {}", max_entries),
+                }
+            }
+        }
+        retention
+    }
+}
+
+impl Default for AuditRetention {
+    fn default() -> AuditRetention {
+        AuditRetention {
+            max_age: 90 * DAY,
+            max_entries: 10_000,
+        }
+    }
+}
+
+fn diff(before: &Value, after: &Value) -> BTreeMap<String, Change> {
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    before
+        .keys()
+        .chain(after.keys())
+        .filter_map(|field| {
+            let change = Change {
+                before: before.get(field).cloned().unwrap_or(Value::Null),
+                after: after.get(field).cloned().unwrap_or(Value::Null),
+            };
+            if change.before == change.after {
+                None
+            } else {
+                Some((field.clone(), change))
+            }
+        })
+        .collect()
+}
+
+pub struct AuditLog {
+    entries: VecDeque<AuditEntry>,
+    last_id: u64,
+    retention: AuditRetention,
+}
+
+impl AuditLog {
+    pub fn new(retention: AuditRetention) -> AuditLog {
+        AuditLog {
+            entries: VecDeque::new(),
+            last_id: 0,
+            retention,
+        }
+    }
+
+    pub fn record<T: Serialize>(
+        &mut self,
+        now: Timestamp,
+        actor: &str,
+        resource_type: ResourceType,
+        resource_uuid: Uuid,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) {
+        let action = match (before, after) {
+            (None, Some(_)) => Action::Create,
+            (Some(_), Some(_)) => Action::Update,
+            (Some(_), None) => Action::Delete,
+            (None, None) => return,
+        };
+        let to_value = |resource: Option<&T>| {
+            resource
+                .and_then(|resource| serde_json::to_value(resource).ok())
+                .unwrap_or(Value::Null)
+        };
+        let changes = diff(&to_value(before), &to_value(after));
+        if action == Action::Update && changes.is_empty() {
+            return;
+        }
+
+        self.last_id += 1;
+        self.entries.push_back(AuditEntry {
+            id: self.last_id,
+            timestamp: now,
+            actor: actor.to_string(),
+            action,
+            resource_type,
+            resource_uuid,
+            changes,
+        });
+        self.apply_retention(now);
+    }
+
+    pub fn record_changes(
+        &mut self,
+        now: Timestamp,
+        actor: &str,
+        before: (&ZoneCollection, &DeviceCollection),
+        after: (&ZoneCollection, &DeviceCollection),
+    ) -> (Vec<Uuid>, Vec<Uuid>) {
+        let removed_zones = self.record_zone_changes(now, actor, before.0, after.0);
+
+        let mut removed_devices = Vec::new();
+        let device_uuids: BTreeSet<Uuid> = before.1.iter().chain(after.1.iter()).map(|(uuid, _)| *uuid).collect();
+        for uuid in device_uuids {
+            let (device_before, device_after) = (before.1.get(&uuid), after.1.get(&uuid));
+            if device_after.is_none() {
+                removed_devices.push(uuid);
+            }
+            self.record(now, actor, ResourceType::Device, uuid, device_before, device_after);
+        }
+
+        (removed_zones, removed_devices)
+    }
+
+    pub fn record_zone_changes(
+        &mut self,
+        now: Timestamp,
+        actor: &str,
+        before: &ZoneCollection,
+        after: &ZoneCollection,
+    ) -> Vec<Uuid> {
+        let mut removed_zones = Vec::new();
+        let zone_uuids: BTreeSet<Uuid> = before.iter().chain(after.iter()).map(|(uuid, _)| *uuid).collect();
+        for uuid in zone_uuids {
+            let (zone_before, zone_after) = (before.get(&uuid), after.get(&uuid));
+            if zone_after.is_none() {
+                removed_zones.push(uuid);
+            }
+            self.record(now, actor, ResourceType::Zone, uuid, zone_before, zone_after);
+        }
+        removed_zones
+    }
+
+    fn apply_retention(&mut self, now: Timestamp) {
+        while self.entries.len() > self.retention.max_entries {
+            self.entries.pop_front();
+        }
+        while self
+            .entries
+            .front()
+            .map_or(false, |entry| now.saturating_sub(entry.timestamp) > self.retention.max_age)
+        {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use temperature::Temperature;
+    use zone::Zone;
+
+    fn zone_uuid() -> Uuid {
+        Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap()
+    }
+
+    #[test]
+    fn update_records_only_changed_fields() {
+        let mut log = AuditLog::new(AuditRetention::default());
+        let before = Zone::new("Living Room".to_string());
+        let mut after = before.clone();
+        after.set_setpoint(Temperature::from_celsius(28.0));
+
+        log.record(1000, "phone", ResourceType::Zone, zone_uuid(), Some(&before), Some(&after));
+
+        let entry = log.entries().next().unwrap();
+        assert_eq!(Action::Update, entry.action);
+        assert_eq!(1, entry.changes.len());
+        assert_eq!(Value::from(16.0), entry.changes["setpoint"].before);
+        assert_eq!(Value::from(28.0), entry.changes["setpoint"].after);
+    }
+
+    #[test]
+    fn update_without_changes_is_not_recorded() {
+        let mut log = AuditLog::new(AuditRetention::default());
+        let zone = Zone::new("Living Room".to_string());
+
+        log.record(1000, "phone", ResourceType::Zone, zone_uuid(), Some(&zone), Some(&zone));
+
+        assert_eq!(0, log.entries().count());
+    }
+
+    #[test]
+    fn delete_records_every_field_as_removed() {
+        let mut log = AuditLog::new(AuditRetention::default());
+        let zone = Zone::new("Living Room".to_string());
+
+        log.record(1000, "phone", ResourceType::Zone, zone_uuid(), Some(&zone), None);
+
+        let entry = log.entries().next().unwrap();
+        assert_eq!(Action::Delete, entry.action);
+        assert_eq!(Value::Null, entry.changes["name"].after);
+    }
+
+    #[test]
+    fn retention_removes_oldest_entries() {
+        let mut log = AuditLog::new(AuditRetention {
+            max_age: 100,
+            max_entries: 2,
+        });
+        let zone = Zone::new("Living Room".to_string());
+
+        for now in &[0, 10, 20] {
+            log.record(*now, "phone", ResourceType::Zone, zone_uuid(), None, Some(&zone));
+        }
+        assert_eq!(vec![2, 3], log.entries().map(|entry| entry.id).collect::<Vec<_>>());
+
+        log.record(115, "phone", ResourceType::Zone, zone_uuid(), None, Some(&zone));
+        assert_eq!(vec![3, 4], log.entries().map(|entry| entry.id).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,422 @@
+use alert::{AlertEngine, AlertRule};
+use calendar::{Calendar, CalendarSource, CheckedSource};
+use clock::Timestamp;
+use device::{Device, DeviceCollection};
+use energy::{EnergyLedger, Tariff};
+use presence::{Presence, PresenceSetpoints};
+use rule::{Rule, RuleEngine};
+use scene::{Scene, SceneCollection};
+use std::collections::{BTreeMap, HashMap};
+use temperature::{TemperatureUnit, UnitPreferences};
+use uuid::Uuid;
+use weather::{CompensationCurve, WeatherCompensation};
+use zone::{self, Zone, ZoneCollection};
+
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RestoreMode {
+    Replace,
+    Merge,
+}
+
+pub struct Home<'a> {
+    pub zones: &'a mut ZoneCollection,
+    pub devices: &'a mut DeviceCollection,
+    pub scenes: &'a mut SceneCollection,
+    pub rules: &'a mut RuleEngine,
+    pub presence: &'a mut Presence,
+    pub weather: &'a mut WeatherCompensation,
+    pub calendar: &'a mut Calendar,
+    pub energy: &'a mut EnergyLedger,
+    pub preferences: &'a mut UnitPreferences,
+    pub alerts: &'a mut AlertEngine,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Backup {
+    format_version: u32,
+    created_at: Timestamp,
+    zones: BTreeMap<Uuid, Zone>,
+    devices: BTreeMap<Uuid, Device>,
+    scenes: BTreeMap<Uuid, Scene>,
+    rules: BTreeMap<Uuid, Rule>,
+    presence_zones: BTreeMap<Uuid, PresenceSetpoints>,
+    compensation_curve: CompensationCurve,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    calendar: Option<CalendarSource>,
+    tariff: Tariff,
+    unit_preferences: BTreeMap<String, TemperatureUnit>,
+    alert_rules: Vec<AlertRule>,
+}
+
+impl Backup {
+    pub fn new(now: Timestamp, home: &Home) -> Backup {
+        Backup {
+            format_version: FORMAT_VERSION,
+            created_at: now,
+            zones: home.zones.iter().map(|(uuid, zone)| (*uuid, zone.clone())).collect(),
+            devices: home.devices.iter().map(|(uuid, device)| (*uuid, device.clone())).collect(),
+            scenes: home.scenes.iter().map(|(uuid, scene)| (*uuid, scene.clone())).collect(),
+            rules: home.rules.iter().map(|(uuid, rule)| (*uuid, rule.clone())).collect(),
+            presence_zones: home
+                .presence
+                .zones()
+                .map(|(uuid, zone)| (*uuid, zone.setpoints()))
+                .collect(),
+            compensation_curve: home.weather.curve(),
+            calendar: home.calendar.source(),
+            tariff: home.energy.tariff().clone(),
+            unit_preferences: home.preferences.iter().map(|(actor, unit)| (actor.clone(), *unit)).collect(),
+            alert_rules: home.alerts.rules().to_vec(),
+        }
+    }
+
+    fn validate(&mut self, mode: RestoreMode, home: &Home) -> Result<Option<CheckedSource>, String> {
+        if self.format_version != FORMAT_VERSION {
+            return Err(format!(
+                "backup format version {} is not supported, expected {}",
+                self.format_version, FORMAT_VERSION
+            ));
+        }
+
+        let mut parents: HashMap<Uuid, Option<Uuid>> = HashMap::new();
+        if mode == RestoreMode::Merge {
+            parents.extend(home.zones.iter().map(|(uuid, zone)| (*uuid, zone.parent_uuid())));
+        }
+        parents.extend(self.zones.iter().map(|(uuid, zone)| (*uuid, zone.parent_uuid())));
+        zone::check_hierarchy(&parents)?;
+        let zone_exists = |uuid: &Uuid| parents.contains_key(uuid);
+
+        for (uuid, device) in &mut self.devices {
+            device.validate().map_err(|error| format!("device {}: {}", uuid, error))?;
+
+            if let Some(zone_uuid) = device.zone_uuid() {
+                if !zone_exists(&zone_uuid) {
+                    return Err(format!("device {} is in zone {} which does not exist", uuid, zone_uuid));
+                }
+            }
+        }
+
+        let devices = &self.devices;
+        let device = |uuid: &Uuid| match devices.get(uuid) {
+            Some(device) => Some(device),
+            None if mode == RestoreMode::Merge => home.devices.get(uuid),
+            None => None,
+        };
+        for (uuid, scene) in &self.scenes {
+            scene
+                .check_targets_with(&zone_exists, &device)
+                .map_err(|error| format!("scene {}: {}", uuid, error))?;
+        }
+        for (uuid, rule) in &self.rules {
+            rule.validate().map_err(|error| format!("rule {}: {}", uuid, error))?;
+        }
+        if let Some(uuid) = self.presence_zones.keys().find(|uuid| !zone_exists(uuid)) {
+            return Err(format!("presence zone {} does not exist", uuid));
+        }
+        self.compensation_curve.validate()?;
+        let calendar = match self.calendar {
+            Some(ref source) => Some(home.calendar.check_source(source)?),
+            None => None,
+        };
+        self.tariff.validate()?;
+        for rule in &self.alert_rules {
+            rule.validate()?;
+        }
+        Ok(calendar)
+    }
+
+    pub fn restore(mut self, now: Timestamp, mode: RestoreMode, home: &mut Home) -> Result<(), String> {
+        // Everything that can fail is checked before the home is touched, so a backup is restored whole or not at all.
+        let calendar = self.validate(mode, home)?;
+
+        if mode == RestoreMode::Replace {
+            let removed_zones: Vec<Uuid> = home
+                .zones
+                .iter()
+                .map(|(uuid, _)| *uuid)
+                .filter(|uuid| !self.zones.contains_key(uuid))
+                .collect();
+            for uuid in removed_zones {
+                home.zones.remove(&uuid);
+            }
+
+            let removed_devices: Vec<Uuid> = home
+                .devices
+                .iter()
+                .map(|(uuid, _)| *uuid)
+                .filter(|uuid| !self.devices.contains_key(uuid))
+                .collect();
+            for uuid in removed_devices {
+                home.devices.remove(&uuid, false);
+            }
+
+            let removed_scenes: Vec<Uuid> = home
+                .scenes
+                .iter()
+                .map(|(uuid, _)| *uuid)
+                .filter(|uuid| !self.scenes.contains_key(uuid))
+                .collect();
+            for uuid in removed_scenes {
+                home.scenes.remove(&uuid);
+            }
+
+            let removed_rules: Vec<Uuid> = home
+                .rules
+                .iter()
+                .map(|(uuid, _)| *uuid)
+                .filter(|uuid| !self.rules.contains_key(uuid))
+                .collect();
+            for uuid in removed_rules {
+                home.rules.remove(&uuid);
+            }
+
+            let removed_presence_zones: Vec<Uuid> = home
+                .presence
+                .zones()
+                .map(|(uuid, _)| *uuid)
+                .filter(|uuid| !self.presence_zones.contains_key(uuid))
+                .collect();
+            for uuid in removed_presence_zones {
+                home.presence.remove_zone(&uuid);
+            }
+
+            let removed_actors: Vec<String> = home
+                .preferences
+                .iter()
+                .map(|(actor, _)| actor.clone())
+                .filter(|actor| !self.unit_preferences.contains_key(actor))
+                .collect();
+            for actor in removed_actors {
+                home.preferences.remove(&actor);
+            }
+            if calendar.is_none() {
+                home.calendar.clear();
+            }
+        }
+
+        for (uuid, zone) in self.zones {
+            home.zones.add(uuid, zone);
+        }
+        for (uuid, mut device) in self.devices {
+            if let Some(previous) = home.devices.get(&uuid) {
+                device.keep_reports_from(previous);
+            }
+            home.devices.add(uuid, device);
+        }
+        for (uuid, scene) in self.scenes {
+            home.scenes.add(uuid, scene);
+        }
+        for (uuid, rule) in self.rules {
+            home.rules.add_validated(uuid, rule);
+        }
+        for (uuid, setpoints) in self.presence_zones {
+            home.presence.set_zone(uuid, setpoints, now);
+        }
+        home.weather.set_validated_curve(self.compensation_curve);
+        if let Some(calendar) = calendar {
+            home.calendar.set_checked_source(now, calendar);
+        }
+        home.energy.set_validated_tariff(self.tariff);
+        for (actor, unit) in self.unit_preferences {
+            home.preferences.set_unit(&actor, unit);
+        }
+        home.alerts.set_validated_rules(self.alert_rules);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calendar::CalendarConfig;
+    use fixtures::uuid;
+    use presence::GracePeriods;
+
+    struct Parts {
+        zones: ZoneCollection,
+        devices: DeviceCollection,
+        scenes: SceneCollection,
+        rules: RuleEngine,
+        presence: Presence,
+        weather: WeatherCompensation,
+        calendar: Calendar,
+        energy: EnergyLedger,
+        preferences: UnitPreferences,
+        alerts: AlertEngine,
+    }
+
+    impl Parts {
+        fn empty() -> Parts {
+            Parts {
+                zones: ZoneCollection::new(),
+                devices: DeviceCollection::new(),
+                scenes: SceneCollection::new(),
+                rules: RuleEngine::new(),
+                presence: Presence::new(GracePeriods::default()),
+                weather: WeatherCompensation::new(CompensationCurve::default()),
+                calendar: Calendar::new(CalendarConfig::default()),
+                energy: EnergyLedger::new(Tariff::default()),
+                preferences: UnitPreferences::new(),
+                alerts: AlertEngine::new(Vec::new()),
+            }
+        }
+
+        fn home(&mut self) -> Home {
+            Home {
+                zones: &mut self.zones,
+                devices: &mut self.devices,
+                scenes: &mut self.scenes,
+                rules: &mut self.rules,
+                presence: &mut self.presence,
+                weather: &mut self.weather,
+                calendar: &mut self.calendar,
+                energy: &mut self.energy,
+                preferences: &mut self.preferences,
+                alerts: &mut self.alerts,
+            }
+        }
+    }
+
+    fn home() -> Parts {
+        let mut parts = Parts::empty();
+        parts.zones.add(uuid(1), Zone::new("Kitchen".to_string()));
+        parts.devices.add(uuid(2), Device::new("STRV".to_string(), Some(uuid(1))));
+        parts.alerts = AlertEngine::new(AlertEngine::default_rules());
+        parts
+    }
+
+    fn configured_home() -> Parts {
+        let mut parts = home();
+        let scene = r#"{"name":"Cosy","zones":{"01010101-0101-0101-0101-010101010101":{"setpoint":22.0}}}"#;
+        parts.scenes.add(uuid(3), serde_json::from_str(scene).unwrap());
+        let rule = json!({
+            "name": "Shower",
+            "trigger": { "type": "event", "name": "shower" },
+            "actions": [{ "type": "set_setpoint", "subject_uuid": uuid(1), "setpoint": 24.0 }]
+        });
+        parts.rules.add(uuid(4), serde_json::from_value(rule.into_inner()).unwrap()).unwrap();
+        let setpoints = r#"{"comfort_setpoint":21.0,"eco_setpoint":17.0}"#;
+        parts.presence.set_zone(uuid(1), serde_json::from_str(setpoints).unwrap(), 0);
+        let curve = CompensationCurve {
+            max_setpoint_offset: 1.0,
+            ..CompensationCurve::default()
+        };
+        parts.weather.set_curve(curve).unwrap();
+        let ics = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+        parts.calendar.upload(0, ics).unwrap();
+        let tariff = Tariff {
+            price_per_kwh: 0.2,
+            ..Tariff::default()
+        };
+        parts.energy.set_tariff(tariff).unwrap();
+        parts.preferences.set_unit("phone", TemperatureUnit::Fahrenheit);
+        parts
+    }
+
+    #[test]
+    fn restore_into_empty_home_keeps_uuids_and_configuration() {
+        let mut original = configured_home();
+        let backup = Backup::new(1000, &original.home());
+
+        let mut restored = Parts::empty();
+        Backup::new(1000, &original.home())
+            .restore(1000, RestoreMode::Replace, &mut restored.home())
+            .unwrap();
+
+        assert_eq!(backup, Backup::new(1000, &restored.home()));
+        assert_eq!(original.zones.get(&uuid(1)), restored.zones.get(&uuid(1)));
+    }
+
+    #[test]
+    fn merge_keeps_what_is_not_in_the_backup() {
+        let mut parts = configured_home();
+        let backup = Backup::new(1000, &Parts::empty().home());
+
+        backup.restore(1000, RestoreMode::Merge, &mut parts.home()).unwrap();
+
+        assert!(parts.zones.get(&uuid(1)).is_some());
+        assert!(parts.devices.get(&uuid(2)).is_some());
+        assert!(parts.scenes.get(&uuid(3)).is_some());
+        assert!(parts.calendar.source().is_some());
+    }
+
+    #[test]
+    fn replace_removes_what_is_not_in_the_backup() {
+        let mut parts = configured_home();
+        let backup = Backup::new(1000, &Parts::empty().home());
+
+        backup.restore(1000, RestoreMode::Replace, &mut parts.home()).unwrap();
+
+        assert_eq!(0, parts.zones.iter().count());
+        assert_eq!(0, parts.devices.iter().count());
+        assert_eq!(0, parts.scenes.iter().count());
+        assert_eq!(0, parts.rules.iter().count());
+        assert_eq!(0, parts.presence.zones().count());
+        assert_eq!(0, parts.preferences.iter().count());
+        assert_eq!(None, parts.calendar.source());
+        assert_eq!(CompensationCurve::default(), parts.weather.curve());
+        assert_eq!(&Tariff::default(), parts.energy.tariff());
+    }
+
+    #[test]
+    fn device_in_missing_zone_is_rejected_without_changes() {
+        let mut parts = home();
+        let mut backup = Backup::new(1000, &parts.home());
+        backup.zones.clear();
+
+        let mut empty = Parts::empty();
+        empty.zones.add(uuid(1), Zone::new("Kitchen".to_string()));
+        let result = backup.restore(1000, RestoreMode::Replace, &mut empty.home());
+
+        assert!(result.is_err());
+        assert!(empty.zones.get(&uuid(1)).is_some());
+        assert_eq!(0, empty.devices.iter().count());
+        assert!(empty.alerts.rules().is_empty());
+    }
+
+    #[test]
+    fn scene_or_presence_zone_in_missing_zone_is_rejected() {
+        let mut parts = configured_home();
+        let mut backup = Backup::new(1000, &parts.home());
+        backup.zones.clear();
+        backup.devices.clear();
+        let result = backup.restore(1000, RestoreMode::Replace, &mut Parts::empty().home());
+        assert!(result.unwrap_err().starts_with("scene"));
+
+        let mut backup = Backup::new(1000, &parts.home());
+        backup.zones.clear();
+        backup.devices.clear();
+        backup.scenes.clear();
+        let result = backup.restore(1000, RestoreMode::Replace, &mut Parts::empty().home());
+        assert!(result.unwrap_err().starts_with("presence zone"));
+    }
+
+    #[test]
+    fn unreadable_calendar_is_rejected_without_changes() {
+        let mut parts = configured_home();
+        let mut backup = Backup::new(1000, &Parts::empty().home());
+        backup.calendar = Some(CalendarSource::File {
+            path: "home.ics".to_string(),
+        });
+
+        let result = backup.restore(1000, RestoreMode::Replace, &mut parts.home());
+
+        assert!(result.is_err());
+        assert!(parts.zones.get(&uuid(1)).is_some());
+        assert_eq!(1, parts.rules.iter().count());
+        assert!(parts.calendar.source().is_some());
+        assert_ne!(&Tariff::default(), parts.energy.tariff());
+    }
+
+    #[test]
+    fn unsupported_format_version_is_rejected() {
+        let mut backup = Backup::new(1000, &home().home());
+        backup.format_version = FORMAT_VERSION + 1;
+
+        let result = backup.restore(1000, RestoreMode::Merge, &mut Parts::empty().home());
+
+        assert!(result.unwrap_err().contains("not supported"));
+    }
+}
@@ -0,0 +1,395 @@
+use clock::{days_from_civil, Timestamp, DAY};
+
+const UTC_ZONES: [&str; 4] = ["UTC", "Etc/UTC", "GMT", "Etc/GMT"];
+
+const MAX_INTERVAL: u64 = 1000;
+
+const MAX_COUNT: u64 = 10_000;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Event {
+    pub uid: Option<String>,
+    pub summary: String,
+    pub categories: Vec<String>,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub recurrence: Option<Recurrence>,
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Frequency {
+    Daily,
+    Weekly,
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<Timestamp>,
+}
+
+impl Recurrence {
+    fn parse(value: &str, start: Timestamp) -> Result<Recurrence, String> {
+        let unsupported = |part: &str| {
+            format!(
+                "{} in recurrence rule {} is not supported, only FREQ=DAILY or FREQ=WEEKLY with INTERVAL, COUNT and \
+                 UNTIL are",
+                part, value
+            )
+        };
+        let mut recurrence = Recurrence {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+        };
+        let mut frequency = None;
+        let mut by_day = None;
+        for part in value.trim().split(';').filter(|part| !part.is_empty()) {
+            let mut name_value = part.splitn(2, '=');
+            let name = name_value.next().unwrap_or_default().to_ascii_uppercase();
+            let part_value = name_value.next().unwrap_or_default();
+            let number = |max: u64| match part_value.parse::<u64>() {
+                Ok(number) if number > 0 && number <= max => Ok(number),
+                _ => Err(format!("{} in recurrence rule {} is not a number from 1 to {}", part, value, max)),
+            };
+            match name.as_str() {
+                "FREQ" => frequency = Some(part_value.to_ascii_uppercase()),
+                "INTERVAL" => recurrence.interval = number(MAX_INTERVAL)?,
+                "COUNT" => recurrence.count = Some(number(MAX_COUNT)?),
+                "UNTIL" => recurrence.until = Some(parse_date_time(part_value)?.0),
+                "BYDAY" => by_day = Some(part_value.to_ascii_uppercase()),
+                "WKST" => {}
+                _ => return Err(unsupported(part)),
+            }
+        }
+        recurrence.frequency = match frequency.as_ref().map(String::as_str) {
+            Some("DAILY") => Frequency::Daily,
+            Some("WEEKLY") => Frequency::Weekly,
+            Some(frequency) => return Err(unsupported(&format!("FREQ={}", frequency))),
+            None => return Err(format!("recurrence rule {} has no FREQ", value)),
+        };
+        // 1970-01-01 was a Thursday.
+        let weekday = ["TH", "FR", "SA", "SU", "MO", "TU", "WE"][(start / DAY % 7) as usize];
+        match by_day {
+            Some(ref days) if recurrence.frequency == Frequency::Weekly && days == weekday => {}
+            Some(days) => return Err(unsupported(&format!("BYDAY={}", days))),
+            None => {}
+        }
+        let last_by_count = recurrence
+            .checked_period()
+            .and_then(|period| period.checked_mul(recurrence.count.unwrap_or(1) - 1))
+            .and_then(|offset| start.checked_add(offset));
+        if last_by_count.is_none() {
+            return Err(format!("recurrence rule {} repeats too far into the future", value));
+        }
+        Ok(recurrence)
+    }
+
+    fn checked_period(&self) -> Option<Timestamp> {
+        let days = match self.frequency {
+            Frequency::Daily => 1,
+            Frequency::Weekly => 7,
+        };
+        self.interval.checked_mul(days * DAY)
+    }
+
+    // Only parsed recurrences exist and parsing rejects the ones whose period or last start overflow.
+    fn period(&self) -> Timestamp {
+        self.checked_period().unwrap()
+    }
+
+    pub fn last_start(&self, start: Timestamp) -> Option<Timestamp> {
+        let period = self.period();
+        let by_count = self.count.map(|count| start + (count - 1) * period);
+        let by_until = self
+            .until
+            .map(|until| start + until.saturating_sub(start) / period * period);
+        match (by_count, by_until) {
+            (Some(by_count), Some(by_until)) => Some(by_count.min(by_until)),
+            (by_count, by_until) => by_count.or(by_until),
+        }
+    }
+
+    pub fn latest_start(&self, start: Timestamp, now: Timestamp) -> Option<Timestamp> {
+        if now < start {
+            return None;
+        }
+        let period = self.period();
+        let latest = start + (now - start) / period * period;
+        Some(self.last_start(start).map_or(latest, |last| latest.min(last)))
+    }
+}
+
+#[derive(Default)]
+struct EventBuilder {
+    uid: Option<String>,
+    summary: String,
+    categories: Vec<String>,
+    start: Option<(Timestamp, bool)>,
+    end: Option<Timestamp>,
+    rule: Option<String>,
+}
+
+impl EventBuilder {
+    fn build(self) -> Result<Option<Event>, String> {
+        let (start, all_day) = match self.start {
+            Some(start) => start,
+            None => return Ok(None),
+        };
+        let end = self.end.unwrap_or(if all_day { start + DAY } else { start });
+        let recurrence = match self.rule {
+            Some(ref rule) => Some(Recurrence::parse(rule, start)?),
+            None => None,
+        };
+        Ok(Some(Event {
+            uid: self.uid,
+            summary: self.summary,
+            categories: self.categories,
+            start,
+            end,
+            recurrence,
+        }))
+    }
+}
+
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        lines.push(line.to_string());
+    }
+    lines
+}
+
+fn split_property(line: &str) -> Option<(String, &str, &str)> {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => {
+                let head = &line[..index];
+                let mut name_parameters = head.splitn(2, ';');
+                let name = name_parameters.next().unwrap_or(head);
+                let parameters = name_parameters.next().unwrap_or_default();
+                return Some((name.to_ascii_uppercase(), parameters, &line[index + 1..]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_property_time(parameters: &str, value: &str) -> Result<(Timestamp, bool), String> {
+    let time_zone = parameters.split(';').find_map(|parameter| {
+        let mut name_value = parameter.splitn(2, '=');
+        match (name_value.next(), name_value.next()) {
+            (Some(name), Some(value)) if name.eq_ignore_ascii_case("TZID") => Some(value.trim_matches('"')),
+            _ => None,
+        }
+    });
+    match time_zone {
+        Some(time_zone) if !UTC_ZONES.iter().any(|utc| utc.eq_ignore_ascii_case(time_zone)) => Err(format!(
+            "time zone {} of {} is not supported, times must be in UTC",
+            time_zone, value
+        )),
+        _ => parse_date_time(value),
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+fn parse_date_time(value: &str) -> Result<(Timestamp, bool), String> {
+    let error = || format!("{} is not a date or date and time", value);
+    let value = value.trim().trim_end_matches('Z');
+    let all_day = value.len() == 8;
+    if !value.is_ascii() || !(all_day || (value.len() == 15 && &value[8..9] == "T")) {
+        return Err(error());
+    }
+    let number = |from: usize, to: usize| -> Result<i64, String> {
+        let digits = &value[from..to];
+        if digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            digits.parse().map_err(|_| error())
+        } else {
+            Err(error())
+        }
+    };
+
+    let (year, month, day) = (number(0, 4)?, number(4, 6)?, number(6, 8)?);
+    let (hour, minute, second) = if all_day {
+        (0, 0, 0)
+    } else {
+        (number(9, 11)?, number(11, 13)?, number(13, 15)?)
+    };
+    let date_valid = year >= 1970 && (1..=12).contains(&month) && (1..=31).contains(&day);
+    if !date_valid || hour > 23 || minute > 59 || second > 60 {
+        return Err(error());
+    }
+    let seconds = days_from_civil(year, month, day) * DAY as i64 + hour * 3600 + minute * 60 + second;
+    Ok((seconds as Timestamp, all_day))
+}
+
+pub fn parse(ics: &str) -> Result<Vec<Event>, String> {
+    let lines = unfold(ics);
+    if lines.first().map(|line| line.trim().to_ascii_uppercase()) != Some("BEGIN:VCALENDAR".to_string()) {
+        return Err("not an iCalendar file, which starts with BEGIN:VCALENDAR".to_string());
+    }
+
+    let mut events = Vec::new();
+    let mut event: Option<EventBuilder> = None;
+    // Components inside the event being read, such as alarms, whose properties are not the event's.
+    let mut nested = 0;
+    for line in &lines {
+        let (name, parameters, value) = match split_property(line) {
+            Some(property) => property,
+            None => continue,
+        };
+        if event.is_none() {
+            if name == "BEGIN" && value.trim().eq_ignore_ascii_case("VEVENT") {
+                event = Some(EventBuilder::default());
+            }
+            continue;
+        }
+
+        let mut finished = false;
+        if let Some(ref mut builder) = event {
+            match name.as_str() {
+                "BEGIN" => nested += 1,
+                "END" if nested > 0 => nested -= 1,
+                "END" => finished = value.trim().eq_ignore_ascii_case("VEVENT"),
+                _ if nested > 0 => {}
+                "UID" => builder.uid = Some(value.trim().to_string()),
+                "SUMMARY" => builder.summary = unescape(value.trim()),
+                "CATEGORIES" => builder
+                    .categories
+                    .extend(value.split(',').map(|category| unescape(category.trim()))),
+                "DTSTART" => builder.start = Some(parse_property_time(parameters, value)?),
+                "DTEND" => builder.end = Some(parse_property_time(parameters, value)?.0),
+                "RRULE" => builder.rule = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        if finished {
+            if let Some(builder) = event.take() {
+                events.extend(builder.build()?);
+            }
+        }
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r\n\
+        VERSION:2.0\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:ski-2026@family\r\n\
+        SUMMARY:Away skiing\\, back \r\n Saturday\r\n\
+        CATEGORIES:Holiday,Family\r\n\
+        DTSTART;VALUE=DATE:20261224\r\n\
+        DTEND;VALUE=DATE:20261227\r\n\
+        BEGIN:VALARM\r\n\
+        SUMMARY:Pack\r\n\
+        END:VALARM\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        SUMMARY:Guest Room: 21\r\n\
+        DTSTART;TZID=\"Etc/UTC\":20261224T073000\r\n\
+        DTEND:20261224T090000Z\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    #[test]
+    fn parses_events_with_folded_and_escaped_text() {
+        let events = parse(CALENDAR).unwrap();
+
+        assert_eq!(2, events.len());
+        assert_eq!(Some("ski-2026@family".to_string()), events[0].uid);
+        assert_eq!("Away skiing, back Saturday", events[0].summary);
+        assert_eq!(vec!["Holiday".to_string(), "Family".to_string()], events[0].categories);
+        assert_eq!("Guest Room: 21", events[1].summary);
+    }
+
+    #[test]
+    fn parses_dates_and_times_as_utc() {
+        let events = parse(CALENDAR).unwrap();
+
+        assert_eq!(1_798_070_400, events[0].start);
+        assert_eq!(1_798_070_400 + 3 * DAY, events[0].end);
+        assert_eq!(1_798_070_400 + 7 * 3600 + 30 * 60, events[1].start);
+        assert_eq!(1_798_070_400 + 9 * 3600, events[1].end);
+    }
+
+    #[test]
+    fn all_day_event_without_end_lasts_the_day() {
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Away\nDTSTART:19700102\nEND:VEVENT\nEND:VCALENDAR\n";
+
+        let events = parse(ics).unwrap();
+
+        assert_eq!(DAY, events[0].start);
+        assert_eq!(2 * DAY, events[0].end);
+    }
+
+    #[test]
+    fn parses_daily_and_weekly_recurrences() {
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Away\nDTSTART:19700101T060000Z\n\
+                   RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=TH;UNTIL=19700201\nEND:VEVENT\nEND:VCALENDAR\n";
+
+        let recurrence = parse(ics).unwrap()[0].recurrence.unwrap();
+
+        assert_eq!(Frequency::Weekly, recurrence.frequency);
+        assert_eq!(Some(28 * DAY + 6 * 3600), recurrence.last_start(6 * 3600));
+        assert_eq!(Some(14 * DAY + 6 * 3600), recurrence.latest_start(6 * 3600, 20 * DAY));
+        assert_eq!(Some(28 * DAY + 6 * 3600), recurrence.latest_start(6 * 3600, 100 * DAY));
+        assert_eq!(None, recurrence.latest_start(6 * 3600, 0));
+    }
+
+    #[test]
+    fn rejects_unsupported_recurrences_and_time_zones() {
+        let event = |properties: &str| {
+            format!("BEGIN:VCALENDAR\nBEGIN:VEVENT\n{}\nEND:VEVENT\nEND:VCALENDAR\n", properties)
+        };
+
+        assert!(parse(&event("DTSTART:19700101\nRRULE:FREQ=YEARLY")).is_err());
+        assert!(parse(&event("DTSTART:19700101\nRRULE:FREQ=WEEKLY;BYDAY=MO,FR")).is_err());
+        assert!(parse(&event("DTSTART:19700101\nRRULE:FREQ=WEEKLY;INTERVAL=99999999999999")).is_err());
+        assert!(parse(&event("DTSTART:19700101\nRRULE:FREQ=DAILY;COUNT=18446744073709551615")).is_err());
+        assert!(parse(&event("DTSTART:19700101\nRRULE:FREQ=WEEKLY;INTERVAL=1000;COUNT=10000")).is_ok());
+        assert!(parse(&event("DTSTART;TZID=Europe/London:19700101T060000")).is_err());
+        assert!(parse(&event("DTSTART;TZID=UTC:19700101T060000")).is_ok());
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_calendars_and_invalid_dates() {
+        assert!(parse("SUMMARY:Away").is_err());
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nDTSTART:2026-12-24\nEND:VEVENT\nEND:VCALENDAR\n";
+        assert!(parse(ics).is_err());
+    }
+}
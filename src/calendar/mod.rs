@@ -0,0 +1,633 @@
+use audit::AuditLogState;
+use clock::{self, Timestamp};
+use device::DeviceCollectionState;
+use rocket::config::Config;
+use std::fs::{self, File};
+use std::io::Read;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use temperature::Temperature;
+use uuid::Uuid;
+use zone::{Zone, ZoneCollection, ZoneCollectionState};
+
+mod ics;
+
+pub type CalendarState = Arc<Mutex<Calendar>>;
+
+const EVALUATION_INTERVAL: u64 = 60;
+const AUDIT_ACTOR: &str = "calendar";
+pub const SIZE_LIMIT: u64 = 1024 * 1024;
+const AWAY_WORDS: [&str; 3] = ["away", "holiday", "vacation"];
+
+#[derive(Clone, Serialize, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Effect {
+    Away,
+    ZoneOverride { zone: String, setpoint: Temperature },
+}
+
+impl Effect {
+    fn of(event: &ics::Event) -> Option<Effect> {
+        let is_away = |word: &str| {
+            let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+            AWAY_WORDS.iter().any(|away| word.eq_ignore_ascii_case(away))
+        };
+        let first_word = event.summary.split_whitespace().next().unwrap_or_default();
+        if event.categories.iter().any(|category| is_away(category)) || is_away(first_word) {
+            return Some(Effect::Away);
+        }
+
+        let colon = event.summary.rfind(':')?;
+        let zone = event.summary[..colon].trim();
+        let setpoint = event.summary[colon + 1..]
+            .trim()
+            .trim_end_matches("°C")
+            .trim_end_matches('°')
+            .parse()
+            .map(Temperature::from_celsius)
+            .ok()?;
+        if zone.is_empty() {
+            return None;
+        }
+        Some(Effect::ZoneOverride {
+            zone: zone.to_string(),
+            setpoint,
+        })
+    }
+}
+
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct CalendarEvent {
+    uid: String,
+    summary: String,
+    start: Timestamp,
+    end: Timestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recurrence: Option<ics::Recurrence>,
+    effect: Effect,
+}
+
+impl CalendarEvent {
+    fn is_on(&self, now: Timestamp) -> bool {
+        let start = match self.recurrence {
+            Some(recurrence) => recurrence.latest_start(self.start, now),
+            None => Some(self.start).filter(|start| *start <= now),
+        };
+        start.map_or(false, |start| now < start + (self.end - self.start))
+    }
+
+    fn is_over(&self, now: Timestamp) -> bool {
+        let last_start = match self.recurrence {
+            Some(recurrence) => recurrence.last_start(self.start),
+            None => Some(self.start),
+        };
+        last_start.map_or(false, |start| start + (self.end - self.start) <= now)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CalendarSource {
+    File { path: String },
+    Upload { ics: String },
+}
+
+pub struct CheckedSource {
+    source: CalendarSource,
+    modified: Option<SystemTime>,
+    events: Vec<CalendarEvent>,
+}
+
+#[derive(Debug)]
+struct Applied {
+    uid: String,
+    setpoint: Temperature,
+    previous: Vec<(Uuid, Temperature, bool)>,
+}
+
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct CalendarStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    loaded_at: Option<Timestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    away_setpoint: Temperature,
+    events: Vec<CalendarEvent>,
+    active: Vec<String>,
+}
+
+pub struct Calendar {
+    away_setpoint: Temperature,
+    file: Option<String>,
+    directory: Option<PathBuf>,
+    uploaded: Option<String>,
+    modified: Option<SystemTime>,
+    loaded_at: Option<Timestamp>,
+    error: Option<String>,
+    events: Vec<CalendarEvent>,
+    applied: Vec<Applied>,
+    last_evaluated: Option<Timestamp>,
+}
+
+impl Calendar {
+    pub fn new(config: CalendarConfig) -> Calendar {
+        Calendar {
+            away_setpoint: config.away_setpoint,
+            file: config.file,
+            directory: config.directory.map(PathBuf::from),
+            uploaded: None,
+            modified: None,
+            loaded_at: None,
+            error: None,
+            events: Vec::new(),
+            applied: Vec::new(),
+            last_evaluated: None,
+        }
+    }
+
+    pub fn last_evaluated(&self) -> Option<Timestamp> {
+        self.last_evaluated
+    }
+
+    pub fn is_evaluating(&self, now: Timestamp) -> bool {
+        clock::ran_recently(self.last_evaluated, EVALUATION_INTERVAL, now)
+    }
+
+    pub fn status(&self, now: Timestamp) -> CalendarStatus {
+        CalendarStatus {
+            file: self.file.clone(),
+            loaded_at: self.loaded_at,
+            error: self.error.clone(),
+            away_setpoint: self.away_setpoint,
+            events: self.events.iter().filter(|event| !event.is_over(now)).cloned().collect(),
+            active: self.applied.iter().map(|applied| applied.uid.clone()).collect(),
+        }
+    }
+
+    fn set_events(&mut self, now: Timestamp, events: Vec<CalendarEvent>) {
+        self.events = events;
+        self.loaded_at = Some(now);
+        self.error = None;
+    }
+
+    pub fn upload(&mut self, now: Timestamp, ics: &str) -> Result<(), String> {
+        self.set_events(now, parse_events(ics)?);
+        self.uploaded = Some(ics.to_string());
+        self.file = None;
+        self.modified = None;
+        Ok(())
+    }
+
+    pub fn file_in_directory(&self, path: &str) -> Result<String, String> {
+        let directory = self
+            .directory
+            .as_ref()
+            .ok_or_else(|| "no calendar directory is configured".to_string())?;
+        let outside = || format!("{} is not a file in the calendar directory", path);
+        let directory = fs::canonicalize(directory).map_err(|_| outside())?;
+        let file = fs::canonicalize(directory.join(path)).map_err(|_| outside())?;
+        if !file.starts_with(&directory) || !file.is_file() {
+            return Err(outside());
+        }
+        file.to_str().map(str::to_string).ok_or_else(outside)
+    }
+
+    pub fn watch_file(&mut self, now: Timestamp, path: &str) -> Result<(), String> {
+        let (modified, ics) = read_file(path)?;
+        self.set_events(now, parse_events(&ics)?);
+        self.uploaded = None;
+        self.file = Some(path.to_string());
+        self.modified = Some(modified);
+        Ok(())
+    }
+
+    pub fn source(&self) -> Option<CalendarSource> {
+        match (&self.file, &self.uploaded) {
+            (Some(path), _) => Some(CalendarSource::File { path: path.clone() }),
+            (None, Some(ics)) => Some(CalendarSource::Upload { ics: ics.clone() }),
+            (None, None) => None,
+        }
+    }
+
+    pub fn check_source(&self, source: &CalendarSource) -> Result<CheckedSource, String> {
+        match source {
+            CalendarSource::File { path } => {
+                let path = self.file_in_directory(path)?;
+                let (modified, ics) = read_file(&path)?;
+                Ok(CheckedSource {
+                    events: parse_events(&ics)?,
+                    source: CalendarSource::File { path },
+                    modified: Some(modified),
+                })
+            }
+            CalendarSource::Upload { ics } => Ok(CheckedSource {
+                events: parse_events(ics)?,
+                source: source.clone(),
+                modified: None,
+            }),
+        }
+    }
+
+    pub fn set_checked_source(&mut self, now: Timestamp, checked: CheckedSource) {
+        self.set_events(now, checked.events);
+        match checked.source {
+            CalendarSource::File { path } => {
+                self.uploaded = None;
+                self.file = Some(path);
+            }
+            CalendarSource::Upload { ics } => {
+                self.uploaded = Some(ics);
+                self.file = None;
+            }
+        }
+        self.modified = checked.modified;
+    }
+
+    pub fn reload_if_changed(&mut self, now: Timestamp) {
+        let path = match self.file.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_some() && modified == self.modified {
+            return;
+        }
+        if let Err(error) = self.watch_file(now, &path) {
+            if self.error.as_ref() != Some(&error) {
+                error!("Failed to read the calendar: {}", error);
+            }
+            self.error = Some(error);
+            self.modified = modified;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.file = None;
+        self.uploaded = None;
+        self.modified = None;
+        self.loaded_at = None;
+        self.error = None;
+        self.events.clear();
+    }
+
+    pub fn evaluate(&mut self, now: Timestamp, zones: &mut ZoneCollection) -> bool {
+        self.last_evaluated = Some(now);
+        self.update(now, zones)
+    }
+
+    pub fn update(&mut self, now: Timestamp, zones: &mut ZoneCollection) -> bool {
+        let on: Vec<CalendarEvent> = self
+            .events
+            .iter()
+            .filter(|event| event.is_on(now))
+            .cloned()
+            .collect();
+        let applied = mem::replace(&mut self.applied, Vec::new());
+        let (still_on, over): (Vec<Applied>, Vec<Applied>) = applied
+            .into_iter()
+            .partition(|applied| on.iter().any(|event| event.uid == applied.uid));
+
+        let mut changed = false;
+        for applied in over.into_iter().rev() {
+            for (uuid, setpoint, overrides_setpoint) in applied.previous {
+                changed |= put_back_setpoint(zones, uuid, applied.setpoint, setpoint, overrides_setpoint);
+            }
+        }
+
+        self.applied = still_on;
+        for event in on {
+            if self.applied.iter().any(|applied| applied.uid == event.uid) {
+                continue;
+            }
+            let (uuids, setpoint): (Vec<Uuid>, Temperature) = match event.effect {
+                Effect::Away => (
+                    zones
+                        .iter()
+                        .filter(|(_, zone)| zone.parent_uuid().is_none())
+                        .map(|(uuid, _)| *uuid)
+                        .collect(),
+                    self.away_setpoint,
+                ),
+                Effect::ZoneOverride { ref zone, setpoint } => (
+                    zones
+                        .iter()
+                        .filter(|(_, candidate)| candidate.name().eq_ignore_ascii_case(zone))
+                        .map(|(uuid, _)| *uuid)
+                        .collect(),
+                    setpoint,
+                ),
+            };
+            let mut previous = Vec::new();
+            for uuid in uuids {
+                if let Some(before) = zones.get(&uuid).map(|zone| (uuid, zone.setpoint(), zone.overrides_setpoint())) {
+                    previous.push(before);
+                    changed |= set_setpoint(zones, uuid, setpoint);
+                }
+            }
+            self.applied.push(Applied {
+                uid: event.uid,
+                setpoint,
+                previous,
+            });
+        }
+        changed
+    }
+}
+
+fn parse_events(ics: &str) -> Result<Vec<CalendarEvent>, String> {
+    let mut events: Vec<CalendarEvent> = Vec::new();
+    for event in ics::parse(ics)? {
+        let effect = match Effect::of(&event) {
+            Some(effect) => effect,
+            None => continue,
+        };
+        let uid = match event.uid {
+            Some(uid) => uid,
+            None => format!("{}-{}", event.start, event.summary),
+        };
+        // Later events with the same UID change single occurrences of recurring events, which are not followed.
+        if event.end > event.start && events.iter().all(|existing| existing.uid != uid) {
+            events.push(CalendarEvent {
+                uid,
+                summary: event.summary,
+                start: event.start,
+                end: event.end,
+                recurrence: event.recurrence,
+                effect,
+            });
+        }
+    }
+    events.sort_by_key(|event| event.start);
+    Ok(events)
+}
+
+fn read_file(path: &str) -> Result<(SystemTime, String), String> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let ics = read_limited(Path::new(path)).map_err(|e| format!("{}: {}", path, e))?;
+    Ok((modified, ics))
+}
+
+fn read_limited(path: &Path) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(SIZE_LIMIT + 1).read_to_end(&mut bytes))
+        .map_err(|e| e.to_string())?;
+    if bytes.len() as u64 > SIZE_LIMIT {
+        return Err(format!("larger than {} bytes", SIZE_LIMIT));
+    }
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+fn set_setpoint(zones: &mut ZoneCollection, uuid: Uuid, setpoint: Temperature) -> bool {
+    match zones.get(&uuid).cloned() {
+        Some(mut zone) => {
+            zone.set_setpoint(setpoint);
+            zones.add(uuid, zone);
+            true
+        }
+        None => false,
+    }
+}
+
+// Keeps a setpoint that was changed while the event was on.
+fn put_back_setpoint(
+    zones: &mut ZoneCollection,
+    uuid: Uuid,
+    applied: Temperature,
+    setpoint: Temperature,
+    overrides_setpoint: bool,
+) -> bool {
+    match zones.get(&uuid).cloned() {
+        Some(mut zone) if (zone.setpoint().celsius() - applied.celsius()).abs() < std::f64::EPSILON => {
+            zone.set_setpoint(setpoint);
+            if !overrides_setpoint {
+                zone.inherit_setpoint();
+            }
+            zones.add(uuid, zone);
+            true
+        }
+        _ => false,
+    }
+}
+
+pub struct CalendarConfig {
+    pub file: Option<String>,
+    pub directory: Option<String>,
+    pub away_setpoint: Temperature,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> CalendarConfig {
+        CalendarConfig {
+            file: None,
+            directory: None,
+            away_setpoint: Temperature::from_celsius(12.0),
+        }
+    }
+}
+
+impl CalendarConfig {
+    pub fn from_config(config: &Config) -> CalendarConfig {
+        let default = CalendarConfig::default();
+        let table = match config.get_table("calendar") {
+            Ok(table) => table,
+            Err(_) => return default,
+        };
+
+        let file = table
+            .get("file")
+            .and_then(|value| value.as_str())
+            .map(str::to_string);
+        let directory = table
+            .get("directory")
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+            .or_else(|| {
+                let parent = Path::new(file.as_ref()?).parent()?;
+                parent.to_str().map(str::to_string)
+            });
+        CalendarConfig {
+            file,
+            directory,
+            away_setpoint: table
+                .get("away_setpoint")
+                .and_then(|value| value.as_float())
+                .map(Temperature::from_celsius)
+                .unwrap_or(default.away_setpoint),
+        }
+    }
+}
+
+pub fn spawn_evaluator(
+    calendar: CalendarState,
+    zones: ZoneCollectionState,
+    devices: DeviceCollectionState,
+    audit: AuditLogState,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        calendar.lock().unwrap().reload_if_changed(clock::now());
+        {
+            let now = clock::now();
+            let mut zones = zones.lock().unwrap();
+            let devices = devices.lock().unwrap();
+            let zones_before = zones.clone();
+            if calendar.lock().unwrap().evaluate(now, &mut zones) {
+                audit
+                    .lock()
+                    .unwrap()
+                    .record_changes(now, AUDIT_ACTOR, (&zones_before, &*devices), (&*zones, &*devices));
+            }
+        }
+
+        thread::sleep(Duration::from_secs(EVALUATION_INTERVAL));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::DAY;
+    use fixtures::{house, setpoint, uuid};
+    use std::env;
+
+    const HOUR: Timestamp = 60 * 60;
+
+    fn ics(events: &[(&str, &str, &str)]) -> String {
+        let mut ics = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n".to_string();
+        for (summary, start, end) in events {
+            ics += &format!(
+                "BEGIN:VEVENT\r\nSUMMARY:{}\r\nDTSTART:{}\r\nDTEND:{}\r\nEND:VEVENT\r\n",
+                summary, start, end
+            );
+        }
+        ics + "END:VCALENDAR\r\n"
+    }
+
+    fn house_with_cool_guest_room() -> ZoneCollection {
+        let mut zones = house();
+        let mut guest_room = zones.get(&uuid(2)).unwrap().clone();
+        guest_room.set_setpoint(Temperature::from_celsius(18.0));
+        zones.add(uuid(2), guest_room);
+        zones
+    }
+
+    #[test]
+    fn events_map_to_away_periods_and_zone_overrides() {
+        let mut calendar = Calendar::new(CalendarConfig::default());
+        let events = ics(&[
+            ("Holiday in Spain", "19700101", "19700103"),
+            ("Guest Room: 21.5°C", "19700101T060000Z", "19700101T090000Z"),
+            ("Dentist", "19700101T100000Z", "19700101T110000Z"),
+        ]);
+
+        calendar.upload(0, &events).unwrap();
+
+        let effects: Vec<Effect> = calendar.status(0).events.into_iter().map(|event| event.effect).collect();
+        let guest_room = Effect::ZoneOverride {
+            zone: "Guest Room".to_string(),
+            setpoint: Temperature::from_celsius(21.5),
+        };
+        assert_eq!(vec![Effect::Away, guest_room], effects);
+    }
+
+    #[test]
+    fn away_period_sets_top_level_zones_until_it_is_over() {
+        let mut calendar = Calendar::new(CalendarConfig::default());
+        let mut zones = house_with_cool_guest_room();
+        calendar.upload(0, &ics(&[("Away", "19700101T060000Z", "19700101T090000Z")])).unwrap();
+
+        assert!(!calendar.update(5 * HOUR, &mut zones));
+        assert!(calendar.update(6 * HOUR, &mut zones));
+        assert_eq!(Some(12.0), setpoint(&zones, 1));
+        assert_eq!(Some(18.0), setpoint(&zones, 2));
+
+        assert!(calendar.update(9 * HOUR, &mut zones));
+        assert_eq!(Some(20.0), setpoint(&zones, 1));
+    }
+
+    #[test]
+    fn overrides_are_put_back_when_event_leaves_the_calendar() {
+        let mut calendar = Calendar::new(CalendarConfig::default());
+        let mut zones = house_with_cool_guest_room();
+        let visit = ("guest room: 22", "19700101T060000Z", "19700101T090000Z");
+        calendar.upload(0, &ics(&[visit])).unwrap();
+        calendar.update(7 * HOUR, &mut zones);
+        assert_eq!(Some(22.0), setpoint(&zones, 2));
+        assert_eq!(vec!["21600-guest room: 22".to_string()], calendar.status(7 * HOUR).active);
+
+        calendar.upload(7 * HOUR, &ics(&[])).unwrap();
+        calendar.update(7 * HOUR, &mut zones);
+
+        assert_eq!(Some(18.0), setpoint(&zones, 2));
+        assert!(calendar.status(7 * HOUR).active.is_empty());
+
+        let mut guest_room = zones.get(&uuid(2)).unwrap().clone();
+        guest_room.inherit_setpoint();
+        zones.add(uuid(2), guest_room);
+        calendar.upload(7 * HOUR, &ics(&[visit])).unwrap();
+        calendar.update(7 * HOUR, &mut zones);
+        calendar.upload(7 * HOUR, &ics(&[])).unwrap();
+        calendar.update(7 * HOUR, &mut zones);
+
+        assert_eq!(Some(false), zones.get(&uuid(2)).map(Zone::overrides_setpoint));
+        let mut house = zones.get(&uuid(1)).unwrap().clone();
+        house.set_setpoint(Temperature::from_celsius(19.0));
+        zones.add(uuid(1), house);
+        assert_eq!(Some(19.0), setpoint(&zones, 2));
+    }
+
+    #[test]
+    fn recurring_event_is_applied_on_each_occurrence() {
+        let mut calendar = Calendar::new(CalendarConfig::default());
+        let mut zones = house_with_cool_guest_room();
+        let ics = ics(&[("Guest Room: 22", "19700101T060000Z", "19700101T090000Z")])
+            .replace("DTEND", "RRULE:FREQ=DAILY;COUNT=2\r\nDTEND");
+        calendar.upload(0, &ics).unwrap();
+
+        calendar.update(DAY + 7 * HOUR, &mut zones);
+        assert_eq!(Some(22.0), setpoint(&zones, 2));
+        calendar.update(DAY + 9 * HOUR, &mut zones);
+        assert_eq!(Some(18.0), setpoint(&zones, 2));
+        calendar.update(2 * DAY + 7 * HOUR, &mut zones);
+        assert_eq!(Some(18.0), setpoint(&zones, 2));
+        assert!(calendar.status(2 * DAY).events.is_empty());
+    }
+
+    #[test]
+    fn setpoint_changed_while_event_is_on_is_kept() {
+        let mut calendar = Calendar::new(CalendarConfig::default());
+        let mut zones = house_with_cool_guest_room();
+        calendar.upload(0, &ics(&[("Guest Room: 22", "19700101T060000Z", "19700101T090000Z")])).unwrap();
+        calendar.update(7 * HOUR, &mut zones);
+
+        let mut guest_room = zones.get(&uuid(2)).unwrap().clone();
+        guest_room.set_setpoint(Temperature::from_celsius(20.5));
+        zones.add(uuid(2), guest_room);
+        calendar.update(9 * HOUR, &mut zones);
+
+        assert_eq!(Some(20.5), setpoint(&zones, 2));
+        assert!(calendar.status(9 * HOUR).active.is_empty());
+    }
+
+    #[test]
+    fn file_is_read_and_kept_when_it_cannot_be_read_again() {
+        let path = env::temp_dir().join(format!("calendar-{}.ics", std::process::id()));
+        fs::write(&path, ics(&[("Away", "19700101", "19700102")])).unwrap();
+        let mut calendar = Calendar::new(CalendarConfig::default());
+
+        calendar.watch_file(0, path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        calendar.reload_if_changed(HOUR);
+
+        let status = calendar.status(HOUR);
+        assert_eq!(1, status.events.len());
+        assert!(status.error.is_some());
+        assert!(calendar.watch_file(HOUR, "/nonexistent/calendar.ics").is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub type Timestamp = u64;
+
+pub fn now() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn ran_recently(last_run: Option<Timestamp>, interval: u64, now: Timestamp) -> bool {
+    last_run.map_or(false, |last_run| now.saturating_sub(last_run) <= 3 * interval)
+}
+
+pub const DAY: Timestamp = 24 * 60 * 60;
+
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_between_dates_and_days() {
+        assert_eq!(0, days_from_civil(1970, 1, 1));
+        assert_eq!(20_744, days_from_civil(2026, 10, 18));
+        assert_eq!((2024, 2, 29), civil_from_days(days_from_civil(2024, 2, 29)));
+        assert_eq!((2026, 3, 1), civil_from_days(days_from_civil(2026, 2, 28) + 1));
+    }
+}
@@ -1,22 +1,245 @@
+use clock::{self, Timestamp};
+use reading::{Quantity, Readings};
+use rocket::config::Config;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use temperature::{Temperature, DEFAULT_SETPOINT};
 use uuid::Uuid;
 
+pub type DeviceCollectionState = Arc<Mutex<DeviceCollection>>;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceType {
+    RadiatorValve,
+    WallThermostat,
+    TemperatureSensor,
+    Relay,
+    HeatSource,
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct Capabilities {
+    pub has_setpoint: bool,
+    pub reports_temperature: bool,
+    pub reports_battery: bool,
+    pub reports_valve_position: bool,
+    pub is_actuator: bool,
+    pub readings: &'static [Quantity],
+}
+
+impl DeviceType {
+    pub const ALL: [DeviceType; 5] = [
+        DeviceType::RadiatorValve,
+        DeviceType::WallThermostat,
+        DeviceType::TemperatureSensor,
+        DeviceType::Relay,
+        DeviceType::HeatSource,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeviceType::RadiatorValve => "radiator_valve",
+            DeviceType::WallThermostat => "wall_thermostat",
+            DeviceType::TemperatureSensor => "temperature_sensor",
+            DeviceType::Relay => "relay",
+            DeviceType::HeatSource => "heat_source",
+        }
+    }
+
+    pub fn capabilities(self) -> Capabilities {
+        let (has_setpoint, reports_temperature, reports_battery, reports_valve_position, is_actuator) = match self {
+            DeviceType::RadiatorValve => (true, true, true, true, true),
+            DeviceType::WallThermostat => (true, true, true, false, false),
+            DeviceType::TemperatureSensor => (false, true, true, false, false),
+            DeviceType::Relay => (false, false, false, false, true),
+            DeviceType::HeatSource => (false, false, false, false, true),
+        };
+        let readings: &'static [Quantity] = match self {
+            DeviceType::RadiatorValve => &[Quantity::Temperature],
+            DeviceType::WallThermostat => &[Quantity::Temperature, Quantity::Humidity],
+            DeviceType::TemperatureSensor => &Quantity::ALL,
+            DeviceType::Relay | DeviceType::HeatSource => &[],
+        };
+
+        Capabilities {
+            has_setpoint,
+            reports_temperature,
+            reports_battery,
+            reports_valve_position,
+            is_actuator,
+            readings,
+        }
+    }
+}
+
+impl Default for DeviceType {
+    fn default() -> DeviceType {
+        DeviceType::RadiatorValve
+    }
+}
+
+impl FromStr for DeviceType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<DeviceType, ()> {
+        DeviceType::ALL
+            .iter()
+            .cloned()
+            .find(|device_type| device_type.as_str() == s)
+            .ok_or(())
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Health {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub battery: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rssi: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_quality: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault: Option<String>,
+}
+
+impl Health {
+    pub fn is_empty(&self) -> bool {
+        *self == Health::default()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HealthPolicy {
+    pub offline_after: u64,
+    pub low_battery: u8,
+}
+
+impl HealthPolicy {
+    pub fn from_config(config: &Config) -> HealthPolicy {
+        let mut policy = HealthPolicy::default();
+        if let Ok(offline_after) = config.get_int("device_offline_after") {
+            match u64::try_from(offline_after) {
+                Ok(offline_after) => policy.offline_after = offline_after,
+                Err(_) => warn!("Ignoring invalid device_offline_after: {}", offline_after),
+            }
+        }
+        if let Ok(low_battery) = config.get_int("device_low_battery") {
+            match u8::try_from(low_battery) {
+                Ok(low_battery) if low_battery <= 100 => policy.low_battery = low_battery,
+                _ => warn!("Ignoring invalid device_low_battery: {}", low_battery),
+            }
+        }
+        policy
+    }
+}
+
+impl Default for HealthPolicy {
+    fn default() -> HealthPolicy {
+        HealthPolicy {
+            offline_after: 60 * 60,
+            low_battery: 20,
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 pub struct Device {
     name: String,
-    setpoint: f64,
+    #[serde(rename = "type", default)]
+    device_type: DeviceType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    setpoint: Option<Temperature>,
     zone_uuid: Option<Uuid>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    temperature: Option<Temperature>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Readings::is_empty")]
+    readings: Readings,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    valve_position: Option<u8>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    demand: Option<bool>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Health::is_empty")]
+    health: Health,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hardware_id: Option<String>,
 }
 
 impl Device {
     pub fn new(name: String, zone_uuid: Option<Uuid>) -> Device {
+        Device::with_type(name, zone_uuid, DeviceType::default())
+    }
+
+    pub fn with_type(name: String, zone_uuid: Option<Uuid>, device_type: DeviceType) -> Device {
+        let setpoint = if device_type.capabilities().has_setpoint {
+            Some(DEFAULT_SETPOINT)
+        } else {
+            None
+        };
+
         Device {
             name,
+            device_type,
+            setpoint,
             zone_uuid,
-            setpoint: 16.0,
+            temperature: None,
+            readings: Readings::new(),
+            valve_position: None,
+            demand: None,
+            health: Health::default(),
+            hardware_id: None,
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn device_type(&self) -> DeviceType {
+        self.device_type
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.device_type.capabilities()
+    }
+
+    pub fn zone_uuid(&self) -> Option<Uuid> {
+        self.zone_uuid
+    }
+
+    pub fn setpoint(&self) -> Option<Temperature> {
+        self.setpoint
+    }
+
+    pub fn reading(&self, quantity: Quantity) -> Option<f64> {
+        self.readings.get(quantity)
+    }
+
+    pub fn readings(&self) -> &Readings {
+        &self.readings
+    }
+
+    pub fn valve_position(&self) -> Option<u8> {
+        self.valve_position
+    }
+
+    pub fn demand(&self) -> Option<bool> {
+        self.demand
+    }
+
+    pub fn validate(&mut self) -> Result<(), String> {
+        if self.capabilities().has_setpoint {
+            self.setpoint.get_or_insert(DEFAULT_SETPOINT);
+        } else if self.setpoint.is_some() {
+            return Err(format!("devices of type {} have no setpoint", self.device_type.as_str()));
+        }
+        Ok(())
+    }
+
     pub fn set_name(&mut self, name: &str) {
         self.name = name.to_string();
     }
@@ -25,42 +248,198 @@ impl Device {
         self.zone_uuid = zone_uuid;
     }
 
-    pub fn set_setpoint(&mut self, setpoint: f64) {
-        self.setpoint = setpoint;
+    pub fn set_setpoint(&mut self, setpoint: Temperature) {
+        self.setpoint = Some(setpoint);
+    }
+
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    pub fn report(&mut self, battery: Option<u8>, rssi: Option<i16>, link_quality: Option<u8>, seen_at: Timestamp) {
+        self.health.battery = battery.or(self.health.battery);
+        self.health.rssi = rssi.or(self.health.rssi);
+        self.health.link_quality = link_quality.or(self.health.link_quality);
+        self.health.last_seen = Some(seen_at);
+    }
+
+    pub fn set_reading(&mut self, quantity: Quantity, value: f64) {
+        self.readings.set(quantity, value);
+        self.temperature = self.readings.get(Quantity::Temperature).map(Temperature::from_celsius);
+    }
+
+    pub fn set_valve_position(&mut self, valve_position: u8) {
+        self.valve_position = Some(valve_position);
+    }
+
+    pub fn set_demand(&mut self, demand: bool) {
+        self.demand = Some(demand);
+    }
+
+    pub fn set_fault(&mut self, fault: Option<String>) {
+        self.health.fault = fault;
+    }
+
+    pub fn keep_reports_from(&mut self, previous: &Device) {
+        self.readings = previous.readings.clone();
+        self.temperature = self.readings.get(Quantity::Temperature).map(Temperature::from_celsius);
+        self.valve_position = previous.valve_position;
+        self.demand = previous.demand;
+        self.health = previous.health.clone();
+    }
+
+    pub fn is_online(&self, now: Timestamp, policy: &HealthPolicy) -> Option<bool> {
+        self.health
+            .last_seen
+            .map(|last_seen| now.saturating_sub(last_seen) <= policy.offline_after)
+    }
+
+    pub fn has_low_battery(&self, policy: &HealthPolicy) -> bool {
+        self.health.battery.map_or(false, |battery| battery <= policy.low_battery)
     }
 }
 
-#[derive(Serialize)]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Tombstone {
+    name: String,
+    zone_uuid: Option<Uuid>,
+}
+
+#[derive(Clone, Serialize)]
 pub struct DeviceCollection {
     devices: HashMap<Uuid, Device>,
+    #[serde(skip)]
+    created_at: HashMap<Uuid, Timestamp>,
+    #[serde(skip)]
+    versions: HashMap<Uuid, u64>,
+    #[serde(skip)]
+    last_version: u64,
+    #[serde(skip)]
+    tombstones: HashMap<String, Tombstone>,
 }
 
 impl DeviceCollection {
     pub fn new() -> DeviceCollection {
         DeviceCollection {
             devices: HashMap::new(),
+            created_at: HashMap::new(),
+            versions: HashMap::new(),
+            last_version: 0,
+            tombstones: HashMap::new(),
         }
     }
 
+    fn bump_version(&mut self, uuid: Uuid) {
+        self.last_version += 1;
+        self.versions.insert(uuid, self.last_version);
+    }
+
     pub fn add(&mut self, uuid: Uuid, device: Device) {
+        self.created_at.entry(uuid).or_insert_with(clock::now);
+        self.bump_version(uuid);
         self.devices.insert(uuid, device);
     }
 
+    pub fn version(&self, uuid: &Uuid) -> Option<u64> {
+        self.versions.get(uuid).cloned()
+    }
+
+    pub fn created_at(&self, uuid: &Uuid) -> Timestamp {
+        self.created_at.get(uuid).cloned().unwrap_or(0)
+    }
+
+    pub fn remove(&mut self, uuid: &Uuid, keep_tombstone: bool) -> Option<Device> {
+        let device = self.devices.remove(uuid)?;
+        self.created_at.remove(uuid);
+        self.versions.remove(uuid);
+
+        if keep_tombstone {
+            if let Some(ref hardware_id) = device.hardware_id {
+                let tombstone = Tombstone {
+                    name: device.name.clone(),
+                    zone_uuid: device.zone_uuid,
+                };
+                self.tombstones.insert(hardware_id.clone(), tombstone);
+            }
+        }
+        Some(device)
+    }
+
+    pub fn restore_from_tombstone(&mut self, device: &mut Device) {
+        if let Some(tombstone) = device.hardware_id.as_ref().and_then(|id| self.tombstones.remove(id)) {
+            device.name = tombstone.name;
+            device.zone_uuid = tombstone.zone_uuid;
+        }
+    }
+
     pub fn get(&self, uuid: &Uuid) -> Option<&Device> {
         self.devices.get(uuid)
     }
 
     pub fn get_mut(&mut self, uuid: &Uuid) -> Option<&mut Device> {
+        if self.devices.contains_key(uuid) {
+            self.bump_version(*uuid);
+        }
         self.devices.get_mut(uuid)
     }
 
-    pub fn get_all_with_zone(&self, zone_uuid: Uuid) -> Option<DeviceCollection> {
-        let mut devices = self.devices.clone();
-        devices.retain(|_, device| device.zone_uuid == Some(zone_uuid));
-        if devices.is_empty() {
+    pub fn iter(&self) -> impl Iterator<Item = (&Uuid, &Device)> {
+        self.devices.iter()
+    }
+
+    pub fn get_all_with_zone(&self, zone_uuid: Option<Uuid>) -> DeviceCollection {
+        self.filter(|device| device.zone_uuid == zone_uuid)
+    }
+
+    pub fn zone_temperature(&self, zone_uuid: &Uuid) -> Option<f64> {
+        self.zone_reading(zone_uuid, Quantity::Temperature)
+    }
+
+    pub fn zone_reading(&self, zone_uuid: &Uuid, quantity: Quantity) -> Option<f64> {
+        let values: Vec<f64> = self
+            .devices
+            .values()
+            .filter(|device| device.zone_uuid == Some(*zone_uuid))
+            .filter_map(|device| device.reading(quantity))
+            .collect();
+
+        if values.is_empty() {
             None
         } else {
-            Some(DeviceCollection { devices })
+            Some(values.iter().sum::<f64>() / values.len() as f64)
+        }
+    }
+
+    pub fn zone_readings(&self, zone_uuid: &Uuid) -> Readings {
+        let mut readings = Readings::new();
+        for quantity in Quantity::ALL.iter().cloned() {
+            if let Some(value) = self.zone_reading(zone_uuid, quantity) {
+                readings.set(quantity, value);
+            }
+        }
+        readings
+    }
+
+    pub fn get_all_with_type(&self, device_type: DeviceType) -> DeviceCollection {
+        self.filter(|device| device.device_type == device_type)
+    }
+
+    pub fn filter<F>(&self, predicate: F) -> DeviceCollection
+    where
+        F: Fn(&Device) -> bool,
+    {
+        let mut devices = self.devices.clone();
+        devices.retain(|_, device| predicate(device));
+        let mut created_at = self.created_at.clone();
+        created_at.retain(|uuid, _| devices.contains_key(uuid));
+        let mut versions = self.versions.clone();
+        versions.retain(|uuid, _| devices.contains_key(uuid));
+        DeviceCollection {
+            devices,
+            created_at,
+            versions,
+            last_version: self.last_version,
+            tombstones: HashMap::new(),
         }
     }
 }
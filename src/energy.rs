@@ -0,0 +1,499 @@
+use clock::{self, civil_from_days, days_from_civil, Timestamp, DAY};
+use device::{DeviceCollection, DeviceCollectionState, DeviceType};
+use rocket::config::{Config, Value};
+use rule::TimeOfDay;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+use zone::{ZoneCollection, ZoneCollectionState};
+
+pub type EnergyLedgerState = Arc<Mutex<EnergyLedger>>;
+
+const SAMPLE_INTERVAL: u64 = 60;
+const MAX_GAP: Timestamp = 5 * 60;
+const RETENTION_DAYS: i64 = 400;
+const HOUR: f64 = 3600.0;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub struct TariffPeriod {
+    pub from: TimeOfDay,
+    pub to: TimeOfDay,
+    pub price_per_kwh: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct Tariff {
+    pub heat_source_kw: f64,
+    pub price_per_kwh: f64,
+    pub periods: Vec<TariffPeriod>,
+}
+
+impl Tariff {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.heat_source_kw < 0.0 {
+            return Err("heat_source_kw must not be negative".to_string());
+        }
+        if self.price_per_kwh < 0.0 || self.periods.iter().any(|period| period.price_per_kwh < 0.0) {
+            return Err("price_per_kwh must not be negative".to_string());
+        }
+        if self.periods.iter().any(|period| period.from == period.to) {
+            return Err("tariff periods must not start and end at the same time".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn price_at(&self, timestamp: Timestamp) -> f64 {
+        self.periods
+            .iter()
+            .find(|period| TimeOfDay::contains(period.from, period.to, timestamp))
+            .map_or(self.price_per_kwh, |period| period.price_per_kwh)
+    }
+
+    pub fn from_config(config: &Config) -> Tariff {
+        let table = match config.get_table("energy") {
+            Ok(table) => table,
+            Err(_) => return Tariff::default(),
+        };
+        let tariff = Value::Table(table.clone())
+            .try_into::<Tariff>()
+            .map_err(|error| error.to_string())
+            .and_then(|tariff| tariff.validate().map(|_| tariff));
+        tariff.unwrap_or_else(|error| {
+            warn!("Ignoring invalid energy tariff: {}", error);
+            Tariff::default()
+        })
+    }
+}
+
+impl Default for Tariff {
+    fn default() -> Tariff {
+        Tariff {
+            heat_source_kw: 24.0,
+            price_per_kwh: 0.10,
+            periods: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, PartialEq, Debug)]
+pub struct Usage {
+    pub energy_kwh: f64,
+    pub cost: f64,
+}
+
+impl Usage {
+    fn add(&mut self, usage: Usage) {
+        self.energy_kwh += usage.energy_kwh;
+        self.cost += usage.cost;
+    }
+
+    fn scaled(self, factor: f64) -> Usage {
+        Usage {
+            energy_kwh: self.energy_kwh * factor,
+            cost: self.cost * factor,
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+struct Day {
+    heat_source_seconds: Timestamp,
+    actuator_seconds: BTreeMap<Uuid, Timestamp>,
+    total: Usage,
+    zones: BTreeMap<Uuid, Usage>,
+    unallocated: Usage,
+}
+
+#[derive(Clone, Debug)]
+struct Snapshot {
+    taken_at: Timestamp,
+    heat_source_on: bool,
+    actuators_on: BTreeSet<Uuid>,
+    zone_demands: BTreeMap<Uuid, f64>,
+}
+
+impl Snapshot {
+    fn take(now: Timestamp, zones: &ZoneCollection, devices: &DeviceCollection) -> Snapshot {
+        let heat_source_on = devices
+            .iter()
+            .any(|(_, device)| device.device_type() == DeviceType::HeatSource && device.demand() == Some(true));
+        let actuators_on = devices
+            .iter()
+            .filter(|(_, device)| device.capabilities().is_actuator)
+            .filter(|(_, device)| device.demand() == Some(true) || device.valve_position().map_or(false, |p| p > 0))
+            .map(|(uuid, _)| *uuid)
+            .collect();
+
+        let mut zone_demands = BTreeMap::new();
+        for (uuid, zone) in zones.iter() {
+            let positions: Vec<u8> = devices
+                .iter()
+                .filter(|(_, device)| device.zone_uuid() == Some(*uuid))
+                .filter_map(|(_, device)| device.valve_position())
+                .collect();
+            let below_setpoint = devices
+                .zone_temperature(uuid)
+                .map_or(false, |temperature| temperature < zone.setpoint().celsius());
+            let demand: f64 = if !positions.is_empty() {
+                positions.iter().map(|position| f64::from(*position) / 100.0).sum()
+            } else if below_setpoint {
+                1.0
+            } else {
+                0.0
+            };
+            if demand > 0.0 {
+                zone_demands.insert(*uuid, demand);
+            }
+        }
+
+        Snapshot {
+            taken_at: now,
+            heat_source_on,
+            actuators_on,
+            zone_demands,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    fn bounds(self, day: i64) -> (i64, i64) {
+        match self {
+            Period::Day => (day, day + 1),
+            // 1970-01-01 was a Thursday.
+            Period::Week => {
+                let monday = day - (day + 3) % 7;
+                (monday, monday + 7)
+            }
+            Period::Month => {
+                let (year, month, _) = civil_from_days(day);
+                let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                (days_from_civil(year, month, 1), days_from_civil(next_year, next_month, 1))
+            }
+        }
+    }
+
+    pub fn default_count(self) -> usize {
+        match self {
+            Period::Day => 7,
+            Period::Week => 4,
+            Period::Month => 12,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct EnergyReport {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub heat_source_hours: f64,
+    pub total: Usage,
+    pub zones: BTreeMap<Uuid, Usage>,
+    pub unallocated: Usage,
+    pub actuator_hours: BTreeMap<Uuid, f64>,
+}
+
+pub struct EnergyLedger {
+    tariff: Tariff,
+    days: BTreeMap<i64, Day>,
+    last: Option<Snapshot>,
+    last_sampled: Option<Timestamp>,
+}
+
+impl EnergyLedger {
+    pub fn new(tariff: Tariff) -> EnergyLedger {
+        EnergyLedger {
+            tariff,
+            days: BTreeMap::new(),
+            last: None,
+            last_sampled: None,
+        }
+    }
+
+    pub fn last_sampled(&self) -> Option<Timestamp> {
+        self.last_sampled
+    }
+
+    pub fn is_sampling(&self, now: Timestamp) -> bool {
+        clock::ran_recently(self.last_sampled, SAMPLE_INTERVAL, now)
+    }
+
+    pub fn tariff(&self) -> &Tariff {
+        &self.tariff
+    }
+
+    pub fn set_tariff(&mut self, tariff: Tariff) -> Result<(), String> {
+        tariff.validate()?;
+        self.set_validated_tariff(tariff);
+        Ok(())
+    }
+
+    pub fn set_validated_tariff(&mut self, tariff: Tariff) {
+        self.tariff = tariff;
+    }
+
+    pub fn sample(&mut self, now: Timestamp, zones: &ZoneCollection, devices: &DeviceCollection) {
+        self.last_sampled = Some(now);
+        if let Some(last) = self.last.take() {
+            if now > last.taken_at && now - last.taken_at <= MAX_GAP {
+                self.account(&last, now);
+            }
+        }
+        self.last = Some(Snapshot::take(now, zones, devices));
+
+        let oldest = (now / DAY) as i64 - RETENTION_DAYS;
+        self.days = self.days.split_off(&oldest);
+    }
+
+    fn account(&mut self, snapshot: &Snapshot, to: Timestamp) {
+        let tariff = &self.tariff;
+        let total_demand: f64 = snapshot.zone_demands.values().sum();
+        let mut from = snapshot.taken_at;
+        while from < to {
+            let until = to.min((from / DAY + 1) * DAY);
+            let seconds = until - from;
+            let day = self.days.entry((from / DAY) as i64).or_insert_with(Day::default);
+            for uuid in &snapshot.actuators_on {
+                *day.actuator_seconds.entry(*uuid).or_insert(0) += seconds;
+            }
+            if snapshot.heat_source_on {
+                let energy_kwh = tariff.heat_source_kw * seconds as f64 / HOUR;
+                let usage = Usage {
+                    energy_kwh,
+                    cost: energy_kwh * tariff.price_at(snapshot.taken_at),
+                };
+                day.heat_source_seconds += seconds;
+                day.total.add(usage);
+                if total_demand > 0.0 {
+                    for (uuid, demand) in &snapshot.zone_demands {
+                        day.zones.entry(*uuid).or_default().add(usage.scaled(demand / total_demand));
+                    }
+                } else {
+                    day.unallocated.add(usage);
+                }
+            }
+            from = until;
+        }
+    }
+
+    pub fn report(&self, period: Period, now: Timestamp, count: usize) -> Vec<EnergyReport> {
+        let mut reports = Vec::with_capacity(count);
+        let mut day = (now / DAY) as i64;
+        for _ in 0..count {
+            let (start, end) = period.bounds(day);
+            reports.push(self.report_between(start, end));
+            day = start - 1;
+        }
+        reports
+    }
+
+    fn report_between(&self, start: i64, end: i64) -> EnergyReport {
+        let mut report = EnergyReport {
+            start: start as Timestamp * DAY,
+            end: end as Timestamp * DAY,
+            heat_source_hours: 0.0,
+            total: Usage::default(),
+            zones: BTreeMap::new(),
+            unallocated: Usage::default(),
+            actuator_hours: BTreeMap::new(),
+        };
+        for day in self.days.range(start..end).map(|(_, day)| day) {
+            report.heat_source_hours += day.heat_source_seconds as f64 / HOUR;
+            report.total.add(day.total);
+            report.unallocated.add(day.unallocated);
+            for (uuid, usage) in &day.zones {
+                report.zones.entry(*uuid).or_default().add(*usage);
+            }
+            for (uuid, seconds) in &day.actuator_seconds {
+                *report.actuator_hours.entry(*uuid).or_insert(0.0) += *seconds as f64 / HOUR;
+            }
+        }
+        report
+    }
+}
+
+pub fn spawn_evaluator(
+    energy: EnergyLedgerState,
+    zones: ZoneCollectionState,
+    devices: DeviceCollectionState,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        {
+            let zones = zones.lock().unwrap();
+            let devices = devices.lock().unwrap();
+            energy.lock().unwrap().sample(clock::now(), &zones, &devices);
+        }
+
+        thread::sleep(Duration::from_secs(SAMPLE_INTERVAL));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device::Device;
+    use fixtures::uuid;
+    use zone::Zone;
+
+    const SUNDAY: Timestamp = 20_744 * DAY;
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    fn home(boiler_on: bool, positions: [u8; 2]) -> (ZoneCollection, DeviceCollection) {
+        let mut zones = ZoneCollection::new();
+        let mut devices = DeviceCollection::new();
+        let mut boiler = Device::with_type("Boiler".to_string(), None, DeviceType::HeatSource);
+        boiler.set_demand(boiler_on);
+        devices.add(uuid(9), boiler);
+        for (n, position) in positions.iter().enumerate() {
+            let zone_uuid = uuid(n as u8 + 1);
+            zones.add(zone_uuid, Zone::new(format!("Zone {}", n)));
+            let mut valve = Device::new("STRV".to_string(), Some(zone_uuid));
+            valve.set_valve_position(*position);
+            devices.add(uuid(n as u8 + 11), valve);
+        }
+        (zones, devices)
+    }
+
+    #[test]
+    fn shares_heat_source_energy_between_zones_by_valve_position() {
+        let (zones, devices) = home(true, [75, 25]);
+        let mut ledger = EnergyLedger::new(Tariff::default());
+
+        ledger.sample(SUNDAY, &zones, &devices);
+        ledger.sample(SUNDAY + 300, &zones, &devices);
+        let report = &ledger.report(Period::Day, SUNDAY, 1)[0];
+
+        assert_close(300.0 / 3600.0, report.heat_source_hours);
+        assert_close(2.0, report.total.energy_kwh);
+        assert_close(0.2, report.total.cost);
+        assert_close(1.5, report.zones[&uuid(1)].energy_kwh);
+        assert_close(0.5, report.zones[&uuid(2)].energy_kwh);
+        assert_close(300.0 / 3600.0, report.actuator_hours[&uuid(9)]);
+        assert_close(300.0 / 3600.0, report.actuator_hours[&uuid(11)]);
+    }
+
+    #[test]
+    fn energy_while_no_zone_asks_for_heat_is_unallocated() {
+        let (zones, devices) = home(true, [0, 0]);
+        let mut ledger = EnergyLedger::new(Tariff::default());
+
+        ledger.sample(SUNDAY, &zones, &devices);
+        ledger.sample(SUNDAY + 300, &zones, &devices);
+        let report = &ledger.report(Period::Day, SUNDAY, 1)[0];
+
+        assert!(report.zones.is_empty());
+        assert_close(2.0, report.unallocated.energy_kwh);
+        assert!(!report.actuator_hours.contains_key(&uuid(11)));
+    }
+
+    #[test]
+    fn prices_energy_by_the_tariff_period_it_was_used_in() {
+        let (zones, devices) = home(true, [100, 0]);
+        let mut tariff = Tariff::default();
+        tariff.periods.push(TariffPeriod {
+            from: "23:30".parse().unwrap(),
+            to: "05:30".parse().unwrap(),
+            price_per_kwh: 0.05,
+        });
+        let mut ledger = EnergyLedger::new(tariff);
+
+        ledger.sample(SUNDAY + 5 * 3600, &zones, &devices);
+        ledger.sample(SUNDAY + 5 * 3600 + 300, &zones, &devices);
+        ledger.sample(SUNDAY + 6 * 3600, &zones, &devices);
+        ledger.sample(SUNDAY + 6 * 3600 + 300, &zones, &devices);
+        let report = &ledger.report(Period::Day, SUNDAY, 1)[0];
+
+        assert_close(4.0, report.total.energy_kwh);
+        assert_close(2.0 * 0.05 + 2.0 * 0.10, report.total.cost);
+    }
+
+    #[test]
+    fn ignores_gaps_between_samples() {
+        let (zones, devices) = home(true, [100, 0]);
+        let mut ledger = EnergyLedger::new(Tariff::default());
+
+        ledger.sample(SUNDAY, &zones, &devices);
+        ledger.sample(SUNDAY + 3600, &zones, &devices);
+        let report = &ledger.report(Period::Day, SUNDAY, 1)[0];
+
+        assert_close(0.0, report.heat_source_hours);
+        assert_close(0.0, report.total.energy_kwh);
+    }
+
+    #[test]
+    fn splits_runtime_over_midnight_between_days() {
+        let (zones, devices) = home(true, [100, 0]);
+        let mut ledger = EnergyLedger::new(Tariff::default());
+
+        ledger.sample(SUNDAY - 120, &zones, &devices);
+        ledger.sample(SUNDAY + 180, &zones, &devices);
+        let reports = ledger.report(Period::Day, SUNDAY, 2);
+
+        assert_eq!(SUNDAY, reports[0].start);
+        assert_close(180.0 / 3600.0, reports[0].heat_source_hours);
+        assert_eq!(SUNDAY - DAY, reports[1].start);
+        assert_close(120.0 / 3600.0, reports[1].heat_source_hours);
+    }
+
+    #[test]
+    fn weeks_start_on_monday_and_months_on_the_first() {
+        let day = 20_744;
+
+        assert_eq!((20_738, 20_745), Period::Week.bounds(day));
+        assert_eq!(
+            (days_from_civil(2026, 10, 1), days_from_civil(2026, 11, 1)),
+            Period::Month.bounds(day)
+        );
+        assert_eq!(
+            (days_from_civil(2026, 12, 1), days_from_civil(2027, 1, 1)),
+            Period::Month.bounds(days_from_civil(2026, 12, 31))
+        );
+    }
+
+    #[test]
+    fn reports_sum_the_days_of_the_period() {
+        let (zones, devices) = home(true, [50, 50]);
+        let mut ledger = EnergyLedger::new(Tariff::default());
+        for day in 0..3 {
+            ledger.sample(SUNDAY - day * DAY, &zones, &devices);
+            ledger.sample(SUNDAY - day * DAY + 300, &zones, &devices);
+        }
+
+        let reports = ledger.report(Period::Week, SUNDAY, 2);
+
+        assert_eq!(2, reports.len());
+        assert_eq!(20_738 * DAY, reports[0].start);
+        assert_eq!(SUNDAY + DAY, reports[0].end);
+        assert_close(6.0, reports[0].total.energy_kwh);
+        assert_close(3.0, reports[0].zones[&uuid(1)].energy_kwh);
+        assert_close(0.0, reports[1].total.energy_kwh);
+    }
+
+    #[test]
+    fn rejects_invalid_tariffs() {
+        let mut tariff = Tariff::default();
+        tariff.price_per_kwh = -0.1;
+        assert!(tariff.validate().is_err());
+
+        let mut tariff = Tariff::default();
+        tariff.periods.push(TariffPeriod {
+            from: "07:00".parse().unwrap(),
+            to: "07:00".parse().unwrap(),
+            price_per_kwh: 0.05,
+        });
+        assert!(tariff.validate().is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use api::audit::mount as mount_audit;
+use audit::{AuditLog, AuditLogState, AuditRetention};
+use device::{DeviceCollection, DeviceCollectionState};
+use rocket::local::{Client, LocalResponse};
+use rocket::Rocket;
+use rocket_contrib::json::JsonValue;
+use std::sync::Mutex;
+use temperature::Temperature;
+use uuid::Uuid;
+use zone::{Zone, ZoneCollection, ZoneCollectionState};
+
+pub struct Home {
+    pub client: Client,
+    pub zones: ZoneCollectionState,
+    pub devices: DeviceCollectionState,
+}
+
+pub fn uuid(n: u8) -> Uuid {
+    Uuid::from_bytes([n; 16])
+}
+
+pub fn house() -> ZoneCollection {
+    let mut house = Zone::new("House".to_string());
+    house.set_setpoint(Temperature::from_celsius(20.0));
+    let mut guest_room = Zone::new("Guest Room".to_string());
+    guest_room.set_parent_uuid(Some(uuid(1)));
+    let mut zones = ZoneCollection::new();
+    zones.add(uuid(1), house);
+    zones.add(uuid(2), guest_room);
+    zones
+}
+
+pub fn setpoint(zones: &ZoneCollection, n: u8) -> Option<f64> {
+    zones.get(&uuid(n)).map(|zone| zone.setpoint().celsius())
+}
+
+pub fn response_body_json(response: &mut LocalResponse) -> JsonValue {
+    serde_json::from_str(&response.body_string().unwrap()).unwrap()
+}
+
+pub fn create_home<F>(zones: ZoneCollection, devices: DeviceCollection, mount: F) -> Home
+where
+    F: FnOnce(Rocket, ZoneCollectionState, DeviceCollectionState) -> Rocket,
+{
+    let zones = ZoneCollectionState::new(Mutex::new(zones));
+    let devices = DeviceCollectionState::new(Mutex::new(devices));
+    let audit = AuditLogState::new(Mutex::new(AuditLog::new(AuditRetention::default())));
+    let rocket = mount(mount_audit(rocket::ignite(), audit), zones.clone(), devices.clone());
+    Home {
+        client: Client::new(rocket).unwrap(),
+        zones,
+        devices,
+    }
+}
@@ -0,0 +1,26 @@
+use super::{HalError, OutputDriver};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone)]
+pub struct FakeOutput {
+    level: Arc<Mutex<Option<bool>>>,
+}
+
+impl FakeOutput {
+    pub fn new() -> FakeOutput {
+        FakeOutput {
+            level: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn level(&self) -> Option<bool> {
+        *self.level.lock().unwrap()
+    }
+}
+
+impl OutputDriver for FakeOutput {
+    fn write(&mut self, high: bool) -> Result<(), HalError> {
+        *self.level.lock().unwrap() = Some(high);
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use super::{HalError, OutputDriver};
+use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONSUMER: &str = "smart-home";
+const SYSFS_GPIO: &str = "/sys/class/gpio";
+
+pub struct CdevOutput {
+    handle: LineHandle,
+}
+
+impl CdevOutput {
+    pub fn open(chip: &str, line: u32, high: bool) -> Result<CdevOutput, HalError> {
+        let mut chip = Chip::new(chip).map_err(|e| HalError::Gpio(e.to_string()))?;
+        let handle = chip
+            .get_line(line)
+            .and_then(|line| line.request(LineRequestFlags::OUTPUT, high as u8, CONSUMER))
+            .map_err(|e| HalError::Gpio(e.to_string()))?;
+        Ok(CdevOutput { handle })
+    }
+}
+
+impl OutputDriver for CdevOutput {
+    fn write(&mut self, high: bool) -> Result<(), HalError> {
+        self.handle
+            .set_value(high as u8)
+            .map_err(|e| HalError::Gpio(e.to_string()))
+    }
+}
+
+pub struct SysfsOutput {
+    value: PathBuf,
+}
+
+impl SysfsOutput {
+    pub fn open(chip: &str, line: u32, high: bool) -> Result<SysfsOutput, HalError> {
+        let gpio = sysfs_number(chip, line)?;
+        let dir = PathBuf::from(SYSFS_GPIO).join(format!("gpio{}", gpio));
+        if !dir.exists() {
+            if let Err(error) = fs::write(PathBuf::from(SYSFS_GPIO).join("export"), gpio.to_string()) {
+                // Another process may have exported the line between the check and the write.
+                if !dir.exists() {
+                    return Err(HalError::Io(error));
+                }
+            }
+        }
+        fs::write(dir.join("direction"), if high { "high" } else { "low" })?;
+
+        Ok(SysfsOutput {
+            value: dir.join("value"),
+        })
+    }
+}
+
+fn sysfs_number(chip: &str, line: u32) -> Result<u32, HalError> {
+    let name = Path::new(chip).file_name().and_then(|name| name.to_str()).unwrap_or(chip);
+    for entry in fs::read_dir(SYSFS_GPIO)? {
+        let dir = entry?.path();
+        let listed = dir
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .map_or(false, |file_name| file_name.starts_with("gpiochip"));
+        if !listed || !dir.join("device").join(name).exists() {
+            continue;
+        }
+        if line >= read_number(&dir.join("ngpio"))? {
+            return Err(HalError::Gpio(format!("{} has no line {}", chip, line)));
+        }
+        return Ok(read_number(&dir.join("base"))? + line);
+    }
+    Err(HalError::Gpio(format!("{} is not listed in {}", chip, SYSFS_GPIO)))
+}
+
+fn read_number(path: &Path) -> Result<u32, HalError> {
+    let contents = fs::read_to_string(path)?;
+    contents
+        .trim()
+        .parse()
+        .map_err(|_| HalError::Gpio(format!("{} does not hold a number", path.display())))
+}
+
+impl OutputDriver for SysfsOutput {
+    fn write(&mut self, high: bool) -> Result<(), HalError> {
+        fs::write(&self.value, if high { "1" } else { "0" })?;
+        Ok(())
+    }
+}
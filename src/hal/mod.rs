@@ -0,0 +1,241 @@
+use rocket::config::{Table, Value};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+#[cfg(test)]
+mod fake;
+mod gpio;
+
+#[cfg(test)]
+pub use self::fake::FakeOutput;
+pub use self::gpio::{CdevOutput, SysfsOutput};
+
+pub type HalState = Mutex<Hal>;
+
+#[derive(Debug)]
+pub enum HalError {
+    Io(io::Error),
+    Gpio(String),
+}
+
+impl fmt::Display for HalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HalError::Io(error) => write!(f, "I/O error: {}", error),
+            HalError::Gpio(error) => write!(f, "GPIO error: {}", error),
+        }
+    }
+}
+
+impl From<io::Error> for HalError {
+    fn from(error: io::Error) -> HalError {
+        HalError::Io(error)
+    }
+}
+
+pub trait OutputDriver: Send {
+    fn write(&mut self, high: bool) -> Result<(), HalError>;
+}
+
+pub struct Relay {
+    driver: Box<dyn OutputDriver>,
+    active_low: bool,
+    on: bool,
+    fault: Option<String>,
+}
+
+impl Relay {
+    pub fn new(driver: Box<dyn OutputDriver>, active_low: bool) -> Relay {
+        Relay {
+            driver,
+            active_low,
+            on: false,
+            fault: None,
+        }
+    }
+
+    pub fn switch(&mut self, on: bool) -> Result<(), HalError> {
+        if let Err(error) = self.driver.write(on != self.active_low) {
+            self.fault = Some(error.to_string());
+            return Err(error);
+        }
+        self.on = on;
+        self.fault = None;
+        Ok(())
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    pub fn fault(&self) -> Option<&str> {
+        self.fault.as_ref().map(String::as_str)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RelayConfig {
+    pub device_uuid: Uuid,
+    pub chip: String,
+    pub line: u32,
+    pub active_low: bool,
+}
+
+impl RelayConfig {
+    pub fn from_table(table: &Table) -> Option<RelayConfig> {
+        let device_uuid = table.get("device_uuid").and_then(Value::as_str)?;
+        let line = table.get("line").and_then(Value::as_integer)?;
+        let line = match u32::try_from(line) {
+            Ok(line) => line,
+            Err(_) => {
+                warn!("Ignoring relay on invalid line {}", line);
+                return None;
+            }
+        };
+
+        Some(RelayConfig {
+            device_uuid: Uuid::parse_str(device_uuid).ok()?,
+            chip: table
+                .get("chip")
+                .and_then(Value::as_str)
+                .unwrap_or("/dev/gpiochip0")
+                .to_string(),
+            line,
+            active_low: table.get("active_low").and_then(Value::as_bool).unwrap_or(false),
+        })
+    }
+
+    pub fn open(&self) -> Result<Relay, HalError> {
+        let off = self.active_low;
+        let driver: Box<dyn OutputDriver> = match CdevOutput::open(&self.chip, self.line, off) {
+            Ok(output) => Box::new(output),
+            Err(_) => Box::new(SysfsOutput::open(&self.chip, self.line, off)?),
+        };
+        let mut relay = Relay::new(driver, self.active_low);
+        relay.switch(false)?;
+        Ok(relay)
+    }
+}
+
+pub struct Hal {
+    relays: HashMap<Uuid, Relay>,
+}
+
+impl Hal {
+    pub fn new() -> Hal {
+        Hal { relays: HashMap::new() }
+    }
+
+    pub fn attach_relay(&mut self, device_uuid: Uuid, relay: Relay) {
+        self.relays.insert(device_uuid, relay);
+    }
+
+    pub fn relays(&self) -> impl Iterator<Item = (&Uuid, &Relay)> {
+        self.relays.iter()
+    }
+
+    pub fn set_demand(&mut self, device_uuid: &Uuid, demand: bool) -> Result<(), HalError> {
+        if let Some(relay) = self.relays.get_mut(device_uuid) {
+            relay.switch(demand)?;
+        }
+        Ok(())
+    }
+
+    pub fn unpair(&mut self, device_uuid: &Uuid) -> Result<(), HalError> {
+        self.set_demand(device_uuid, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relay_drives_line_high_when_on() {
+        let output = FakeOutput::new();
+        let mut relay = Relay::new(Box::new(output.clone()), false);
+
+        relay.switch(true).unwrap();
+
+        assert!(relay.is_on());
+        assert_eq!(Some(true), output.level());
+    }
+
+    #[test]
+    fn active_low_relay_drives_line_low_when_on() {
+        let output = FakeOutput::new();
+        let mut relay = Relay::new(Box::new(output.clone()), true);
+
+        relay.switch(true).unwrap();
+        assert_eq!(Some(false), output.level());
+
+        relay.switch(false).unwrap();
+        assert_eq!(Some(true), output.level());
+    }
+
+    #[test]
+    fn set_demand_switches_relay_attached_to_device() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let output = FakeOutput::new();
+        let mut hal = Hal::new();
+        hal.attach_relay(device_uuid, Relay::new(Box::new(output.clone()), false));
+
+        hal.set_demand(&device_uuid, true).unwrap();
+
+        assert_eq!(Some(true), output.level());
+    }
+
+    #[test]
+    fn set_demand_without_relay_does_nothing() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let mut hal = Hal::new();
+
+        assert!(hal.set_demand(&device_uuid, true).is_ok());
+    }
+
+    #[test]
+    fn unpair_switches_relay_off_and_keeps_it() {
+        let device_uuid = Uuid::parse_str("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1").unwrap();
+        let output = FakeOutput::new();
+        let mut hal = Hal::new();
+        hal.attach_relay(device_uuid, Relay::new(Box::new(output.clone()), false));
+        hal.set_demand(&device_uuid, true).unwrap();
+
+        hal.unpair(&device_uuid).unwrap();
+
+        assert_eq!(Some(false), output.level());
+        assert_eq!(1, hal.relays().count());
+    }
+
+    #[test]
+    fn relay_config_from_table_applies_defaults() {
+        let mut table = Table::new();
+        table.insert(
+            "device_uuid".to_string(),
+            Value::String("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1".to_string()),
+        );
+        table.insert("line".to_string(), Value::Integer(17));
+
+        let config = RelayConfig::from_table(&table).unwrap();
+
+        assert_eq!("/dev/gpiochip0", config.chip);
+        assert_eq!(17, config.line);
+        assert!(!config.active_low);
+    }
+
+    #[test]
+    fn relay_on_negative_line_is_ignored() {
+        let mut table = Table::new();
+        table.insert(
+            "device_uuid".to_string(),
+            Value::String("84fa1356-d5de-11e8-9f8b-f2801f1b9fd1".to_string()),
+        );
+        table.insert("line".to_string(), Value::Integer(-1));
+
+        assert!(RelayConfig::from_table(&table).is_none());
+    }
+}
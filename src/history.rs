@@ -0,0 +1,188 @@
+use clock::{self, Timestamp, DAY};
+use device::{DeviceCollection, DeviceCollectionState};
+use reading::Quantity;
+use rocket::config::Config;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+use zone::{ZoneCollection, ZoneCollectionState};
+
+pub type ReadingHistoryState = Arc<Mutex<ReadingHistory>>;
+
+const SAMPLE_INTERVAL: u64 = 5 * 60;
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct Sample {
+    pub at: Timestamp,
+    pub value: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HistoryRetention {
+    pub max_age: Timestamp,
+}
+
+impl HistoryRetention {
+    pub fn from_config(config: &Config) -> HistoryRetention {
+        let mut retention = HistoryRetention::default();
+        if let Ok(table) = config.get_table("history") {
+            if let Some(days) = table.get("retention_days").and_then(|value| value.as_integer()) {
+                match Timestamp::try_from(days).ok().and_then(|days| days.checked_mul(DAY)) {
+                    Some(max_age) => retention.max_age = max_age,
+                    None => warn!("Ignoring invalid history retention_days: {}", days),
+                }
+            }
+        }
+        retention
+    }
+}
+
+impl Default for HistoryRetention {
+    fn default() -> HistoryRetention {
+        HistoryRetention { max_age: 7 * DAY }
+    }
+}
+
+pub struct ReadingHistory {
+    retention: HistoryRetention,
+    series: HashMap<(Uuid, Quantity), VecDeque<Sample>>,
+    last_sampled: Option<Timestamp>,
+}
+
+impl ReadingHistory {
+    pub fn new(retention: HistoryRetention) -> ReadingHistory {
+        ReadingHistory {
+            retention,
+            series: HashMap::new(),
+            last_sampled: None,
+        }
+    }
+
+    pub fn last_sampled(&self) -> Option<Timestamp> {
+        self.last_sampled
+    }
+
+    pub fn is_sampling(&self, now: Timestamp) -> bool {
+        clock::ran_recently(self.last_sampled, SAMPLE_INTERVAL, now)
+    }
+
+    fn record(&mut self, subject_uuid: Uuid, quantity: Quantity, sample: Sample) {
+        self.series
+            .entry((subject_uuid, quantity))
+            .or_insert_with(VecDeque::new)
+            .push_back(sample);
+    }
+
+    pub fn sample(&mut self, now: Timestamp, zones: &ZoneCollection, devices: &DeviceCollection) {
+        self.last_sampled = Some(now);
+        for (uuid, device) in devices.iter() {
+            for (quantity, value) in device.readings().iter() {
+                self.record(*uuid, quantity, Sample { at: now, value });
+            }
+        }
+        for (uuid, _) in zones.iter() {
+            for (quantity, value) in devices.zone_readings(uuid).iter() {
+                self.record(*uuid, quantity, Sample { at: now, value });
+            }
+        }
+
+        let oldest = now.saturating_sub(self.retention.max_age);
+        self.series.retain(|&(uuid, _), samples| {
+            while samples.front().map_or(false, |sample| sample.at < oldest) {
+                samples.pop_front();
+            }
+            !samples.is_empty() && (zones.get(&uuid).is_some() || devices.get(&uuid).is_some())
+        });
+    }
+
+    pub fn samples(&self, subject_uuid: &Uuid, quantity: Quantity, since: Timestamp) -> Vec<Sample> {
+        self.series
+            .get(&(*subject_uuid, quantity))
+            .map_or_else(Vec::new, |samples| {
+                samples.iter().filter(|sample| sample.at >= since).cloned().collect()
+            })
+    }
+}
+
+pub fn spawn_sampler(
+    history: ReadingHistoryState,
+    zones: ZoneCollectionState,
+    devices: DeviceCollectionState,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        {
+            let zones = zones.lock().unwrap();
+            let devices = devices.lock().unwrap();
+            history.lock().unwrap().sample(clock::now(), &zones, &devices);
+        }
+
+        thread::sleep(Duration::from_secs(SAMPLE_INTERVAL));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device::Device;
+    use fixtures::uuid;
+    use zone::Zone;
+
+    fn kitchen(humidities: [f64; 2]) -> (ZoneCollection, DeviceCollection) {
+        let mut zones = ZoneCollection::new();
+        zones.add(uuid(1), Zone::new("Kitchen".to_string()));
+        let mut devices = DeviceCollection::new();
+        for (n, humidity) in humidities.iter().enumerate() {
+            let mut sensor = Device::new("Sensor".to_string(), Some(uuid(1)));
+            sensor.set_reading(Quantity::Humidity, *humidity);
+            devices.add(uuid(n as u8 + 2), sensor);
+        }
+        (zones, devices)
+    }
+
+    #[test]
+    fn records_readings_of_devices_and_averages_of_zones() {
+        let (zones, devices) = kitchen([40.0, 60.0]);
+        let mut history = ReadingHistory::new(HistoryRetention::default());
+
+        history.sample(1000, &zones, &devices);
+
+        let sample = Sample { at: 1000, value: 50.0 };
+        assert_eq!(vec![sample], history.samples(&uuid(1), Quantity::Humidity, 0));
+        let sample = Sample { at: 1000, value: 60.0 };
+        assert_eq!(vec![sample], history.samples(&uuid(3), Quantity::Humidity, 0));
+        assert!(history.samples(&uuid(1), Quantity::Co2, 0).is_empty());
+    }
+
+    #[test]
+    fn returns_samples_since_and_forgets_old_ones() {
+        let (zones, devices) = kitchen([40.0, 60.0]);
+        let mut history = ReadingHistory::new(HistoryRetention { max_age: 600 });
+
+        history.sample(1000, &zones, &devices);
+        history.sample(1300, &zones, &devices);
+        assert_eq!(1, history.samples(&uuid(2), Quantity::Humidity, 1100).len());
+
+        history.sample(1700, &zones, &devices);
+        let times: Vec<Timestamp> = history
+            .samples(&uuid(2), Quantity::Humidity, 0)
+            .iter()
+            .map(|sample| sample.at)
+            .collect();
+        assert_eq!(vec![1300, 1700], times);
+    }
+
+    #[test]
+    fn forgets_removed_devices() {
+        let (zones, mut devices) = kitchen([40.0, 60.0]);
+        let mut history = ReadingHistory::new(HistoryRetention::default());
+        history.sample(1000, &zones, &devices);
+
+        devices.remove(&uuid(2), false);
+        history.sample(1300, &zones, &devices);
+
+        assert!(history.samples(&uuid(2), Quantity::Humidity, 0).is_empty());
+    }
+}
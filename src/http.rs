@@ -0,0 +1,71 @@
+use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT_SECS: u64 = 10;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct HttpUrl {
+    pub address: String,
+    pub host: String,
+    pub path: String,
+}
+
+impl HttpUrl {
+    pub fn parse(url: &str) -> Option<HttpUrl> {
+        if !url.starts_with("http://") {
+            return None;
+        }
+        let url = &url["http://".len()..];
+        let (host, path) = match url.find('/') {
+            Some(index) => (&url[..index], &url[index..]),
+            None => (url, "/"),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:80", host)
+        };
+
+        Some(HttpUrl {
+            address,
+            host: host.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+pub fn connect(address: &str) -> Result<TcpStream, String> {
+    let stream = TcpStream::connect(address).map_err(|e| format!("{}: {}", address, e))?;
+    let timeout = Some(Duration::from_secs(TIMEOUT_SECS));
+    stream.set_read_timeout(timeout).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(timeout).map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_without_port_uses_port_80() {
+        let url = HttpUrl::parse("http://hub.local/alerts").unwrap();
+
+        assert_eq!("hub.local:80", url.address);
+        assert_eq!("hub.local", url.host);
+        assert_eq!("/alerts", url.path);
+    }
+
+    #[test]
+    fn url_without_path_requests_root() {
+        assert_eq!("/", HttpUrl::parse("http://hub.local:8080").unwrap().path);
+    }
+
+    #[test]
+    fn non_http_urls_are_rejected() {
+        assert!(HttpUrl::parse("https://hub.local/alerts").is_none());
+        assert!(HttpUrl::parse("http:///alerts").is_none());
+    }
+}
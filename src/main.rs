@@ -4,23 +4,125 @@
 extern crate rocket;
 #[macro_use]
 extern crate rocket_contrib;
+extern crate gpio_cdev;
+#[macro_use]
+extern crate log;
 extern crate serde;
 extern crate serde_json;
+extern crate serde_yaml;
 #[macro_use]
 extern crate serde_derive;
 extern crate uuid;
 
+mod alert;
 mod api;
+mod audit;
+mod backup;
+mod calendar;
+mod clock;
 mod device;
+mod energy;
+#[cfg(test)]
+mod fixtures;
+mod hal;
+mod history;
+mod http;
+mod metrics;
+mod optimum;
+mod page;
+mod presence;
+mod reading;
+mod rule;
+mod scene;
+mod temperature;
+mod weather;
 mod zone;
 
+use std::sync::{Arc, Mutex};
+
 fn main() {
-    let zones = zone::ZoneCollection::new();
-    let devices = device::DeviceCollection::new();
+    let zones = Arc::new(Mutex::new(zone::ZoneCollection::new()));
+    let devices = Arc::new(Mutex::new(device::DeviceCollection::new()));
+    let scenes = Arc::new(Mutex::new(scene::SceneCollection::new()));
+    let rules = Arc::new(Mutex::new(rule::RuleEngine::new()));
+
+    let alerts = Arc::new(Mutex::new(alert::AlertEngine::new(alert::AlertEngine::default_rules())));
 
     let rocket = rocket::ignite();
+    let hal = create_hal(&rocket);
+
+    alert::spawn_evaluator(
+        alerts.clone(),
+        zones.clone(),
+        devices.clone(),
+        device::HealthPolicy::from_config(rocket.config()),
+        alert::AlertConfig::from_config(rocket.config()),
+    );
+    let audit = audit::AuditLog::new(audit::AuditRetention::from_config(rocket.config()));
+    let audit = Arc::new(Mutex::new(audit));
+    rule::spawn_evaluator(
+        rules.clone(),
+        zones.clone(),
+        devices.clone(),
+        scenes.clone(),
+        audit.clone(),
+        device::HealthPolicy::from_config(rocket.config()),
+    );
+    let grace = presence::GracePeriods::from_config(rocket.config());
+    let presence = Arc::new(Mutex::new(presence::Presence::new(grace)));
+    presence::spawn_evaluator(presence.clone(), zones.clone(), devices.clone(), audit.clone());
+    let weather = Arc::new(Mutex::new(weather::WeatherCompensation::new(weather::CompensationCurve::default())));
+    match weather::WeatherConfig::from_config(rocket.config()) {
+        Some(config) => {
+            weather::spawn_evaluator(weather.clone(), zones.clone(), devices.clone(), audit.clone(), config);
+        }
+        None => info!("No outdoor temperature source configured, weather compensation is off"),
+    }
+    let calendar = calendar::Calendar::new(calendar::CalendarConfig::from_config(rocket.config()));
+    let calendar = Arc::new(Mutex::new(calendar));
+    calendar::spawn_evaluator(calendar.clone(), zones.clone(), devices.clone(), audit.clone());
+    let energy = Arc::new(Mutex::new(energy::EnergyLedger::new(energy::Tariff::from_config(rocket.config()))));
+    energy::spawn_evaluator(energy.clone(), zones.clone(), devices.clone());
+    let history = history::ReadingHistory::new(history::HistoryRetention::from_config(rocket.config()));
+    let history = Arc::new(Mutex::new(history));
+    history::spawn_sampler(history.clone(), zones.clone(), devices.clone());
+
+    let rocket = rocket.manage(hal::HalState::new(hal));
     let rocket = api::mount(rocket);
+    let rocket = api::docs::mount(rocket);
+    let rocket = api::health::mount(rocket);
+    let rocket = api::backup::mount(rocket);
+    let rocket = api::batch::mount(rocket);
+    let rocket = api::audit::mount(rocket, audit);
     let rocket = api::zones::mount(rocket, zones);
     let rocket = api::devices::mount(rocket, devices);
+    let rocket = api::scenes::mount(rocket, scenes);
+    let rocket = api::rules::mount(rocket, rules);
+    let rocket = api::presence::mount(rocket, presence);
+    let rocket = api::heat_source::mount(rocket, weather);
+    let rocket = api::calendar::mount(rocket, calendar);
+    let rocket = api::energy::mount(rocket, energy);
+    let rocket = api::history::mount(rocket, history);
+    let rocket = api::preferences::mount(rocket, Arc::new(Mutex::new(temperature::UnitPreferences::new())));
+    let rocket = api::alerts::mount(rocket, alerts);
+    let rocket = api::metrics::mount(rocket, Arc::new(Mutex::new(metrics::HttpMetrics::new())));
     rocket.launch();
 }
+
+fn create_hal(rocket: &rocket::Rocket) -> hal::Hal {
+    let mut hal = hal::Hal::new();
+
+    if let Some(config) = rocket
+        .config()
+        .get_table("relay")
+        .ok()
+        .and_then(hal::RelayConfig::from_table)
+    {
+        match config.open() {
+            Ok(relay) => hal.attach_relay(config.device_uuid, relay),
+            Err(error) => error!("Failed to open relay on {} line {}: {}", config.chip, config.line, error),
+        }
+    }
+
+    hal
+}
@@ -0,0 +1,136 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+pub type HttpMetricsState = Arc<Mutex<HttpMetrics>>;
+
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+struct Latency {
+    buckets: [u64; 11],
+    sum: f64,
+    count: u64,
+}
+
+pub struct HttpMetrics {
+    requests: BTreeMap<(String, String, u16), u64>,
+    latencies: BTreeMap<(String, String), Latency>,
+}
+
+impl HttpMetrics {
+    pub fn new() -> HttpMetrics {
+        HttpMetrics {
+            requests: BTreeMap::new(),
+            latencies: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, method: &str, route: &str, status: u16, seconds: f64) {
+        *self
+            .requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_insert(0) += 1;
+
+        let latency = self
+            .latencies
+            .entry((method.to_string(), route.to_string()))
+            .or_insert(Latency {
+                buckets: [0; 11],
+                sum: 0.0,
+                count: 0,
+            });
+        for (bucket, upper_bound) in latency.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+            if seconds <= *upper_bound {
+                *bucket += 1;
+            }
+        }
+        latency.sum += seconds;
+        latency.count += 1;
+    }
+
+    pub fn write_to(&self, out: &mut String) {
+        out.push_str("# HELP http_requests_total Number of HTTP requests handled, by route and status.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for ((method, route, status), count) in &self.requests {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method,
+                escape_label(route),
+                status,
+                count
+            );
+        }
+
+        out.push_str("# HELP http_request_duration_seconds Time taken to handle HTTP requests, by route.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for ((method, route), latency) in &self.latencies {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, escape_label(route));
+            for (bucket, upper_bound) in latency.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, upper_bound, bucket
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, latency.count
+            );
+            let _ = writeln!(out, "http_request_duration_seconds_sum{{{}}} {}", labels, latency.sum);
+            let _ = writeln!(out, "http_request_duration_seconds_count{{{}}} {}", labels, latency.count);
+        }
+    }
+}
+
+pub fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+struct RequestStart(Instant);
+
+pub struct HttpMetricsFairing {
+    metrics: HttpMetricsState,
+}
+
+impl HttpMetricsFairing {
+    pub fn new(metrics: HttpMetricsState) -> HttpMetricsFairing {
+        HttpMetricsFairing { metrics }
+    }
+}
+
+impl Fairing for HttpMetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "HTTP metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let route = match request.route() {
+            Some(route) => route,
+            None => return,
+        };
+        let elapsed = request.local_cache(|| RequestStart(Instant::now())).0.elapsed();
+        let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+
+        self.metrics.lock().unwrap().record(
+            request.method().as_str(),
+            route.uri.path(),
+            response.status().code,
+            seconds,
+        );
+    }
+}
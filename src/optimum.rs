@@ -0,0 +1,153 @@
+use clock::Timestamp;
+use device::DeviceCollection;
+use std::collections::HashMap;
+use uuid::Uuid;
+use zone::ZoneCollection;
+
+const HOUR: Timestamp = 60 * 60;
+const DEFAULT_RATE: f64 = 2.0;
+const MAX_LEAD_TIME: Timestamp = 3 * HOUR;
+const HEATING_MARGIN: f64 = 0.3;
+const MIN_RISE: f64 = 0.5;
+const MIN_DURATION: Timestamp = 15 * 60;
+const MAX_DURATION: Timestamp = 6 * HOUR;
+const LEARNING_WEIGHT: f64 = 0.3;
+
+#[derive(Clone, Copy, Debug)]
+struct HeatUp {
+    started_at: Timestamp,
+    start_temperature: f64,
+}
+
+pub struct HeatUpLearner {
+    heat_ups: HashMap<Uuid, HeatUp>,
+    rates: HashMap<Uuid, f64>,
+}
+
+impl HeatUpLearner {
+    pub fn new() -> HeatUpLearner {
+        HeatUpLearner {
+            heat_ups: HashMap::new(),
+            rates: HashMap::new(),
+        }
+    }
+
+    pub fn sample(&mut self, now: Timestamp, zones: &ZoneCollection, devices: &DeviceCollection) {
+        self.heat_ups.retain(|uuid, _| zones.get(uuid).is_some());
+        self.rates.retain(|uuid, _| zones.get(uuid).is_some());
+
+        for (uuid, zone) in zones.iter() {
+            let temperature = match devices.zone_temperature(uuid) {
+                Some(temperature) => temperature,
+                None => {
+                    self.heat_ups.remove(uuid);
+                    continue;
+                }
+            };
+            let heating = temperature < zone.setpoint().celsius() - HEATING_MARGIN;
+            let started = HeatUp {
+                started_at: now,
+                start_temperature: temperature,
+            };
+            match self.heat_ups.get(uuid).cloned() {
+                None if heating => {
+                    self.heat_ups.insert(*uuid, started);
+                }
+                Some(heat_up) if !heating => {
+                    self.heat_ups.remove(uuid);
+                    self.learn(*uuid, heat_up, now, temperature);
+                }
+                Some(heat_up) if now - heat_up.started_at > MAX_DURATION => {
+                    self.heat_ups.insert(*uuid, started);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn learn(&mut self, uuid: Uuid, heat_up: HeatUp, now: Timestamp, temperature: f64) {
+        let rise = temperature - heat_up.start_temperature;
+        let duration = now - heat_up.started_at;
+        if rise < MIN_RISE || duration < MIN_DURATION {
+            return;
+        }
+        let rate = rise * HOUR as f64 / duration as f64;
+        let learned = self
+            .rates
+            .get(&uuid)
+            .map_or(rate, |learned| learned + LEARNING_WEIGHT * (rate - learned));
+        self.rates.insert(uuid, learned);
+    }
+
+    pub fn rate(&self, uuid: &Uuid) -> Option<f64> {
+        self.rates.get(uuid).cloned()
+    }
+
+    pub fn lead_time(&self, uuid: &Uuid, from: f64, to: f64) -> Timestamp {
+        let rise = to - from;
+        if rise <= 0.0 {
+            return 0;
+        }
+        let rate = self.rate(uuid).unwrap_or(DEFAULT_RATE);
+        let minutes = (rise / rate * 60.0).ceil() as Timestamp;
+        (minutes * 60).min(MAX_LEAD_TIME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device::Device;
+    use fixtures::uuid;
+    use reading::Quantity;
+    use temperature::Temperature;
+    use zone::Zone;
+
+    fn living_room() -> (ZoneCollection, DeviceCollection) {
+        let mut zone = Zone::new("Living Room".to_string());
+        zone.set_setpoint(Temperature::from_celsius(21.0));
+        let mut zones = ZoneCollection::new();
+        zones.add(uuid(1), zone);
+        let mut devices = DeviceCollection::new();
+        devices.add(uuid(2), Device::new("STRV".to_string(), Some(uuid(1))));
+        (zones, devices)
+    }
+
+    fn sample_at(learner: &mut HeatUpLearner, now: Timestamp, temperature: f64) {
+        let (zones, mut devices) = living_room();
+        devices.get_mut(&uuid(2)).unwrap().set_reading(Quantity::Temperature, temperature);
+        learner.sample(now, &zones, &devices);
+    }
+
+    #[test]
+    fn learns_rate_once_setpoint_is_reached() {
+        let mut learner = HeatUpLearner::new();
+
+        sample_at(&mut learner, 0, 18.0);
+        sample_at(&mut learner, HOUR, 19.5);
+        assert_eq!(None, learner.rate(&uuid(1)));
+
+        sample_at(&mut learner, 2 * HOUR, 21.0);
+        assert_eq!(Some(1.5), learner.rate(&uuid(1)));
+        assert_eq!(2 * HOUR, learner.lead_time(&uuid(1), 18.0, 21.0));
+    }
+
+    #[test]
+    fn short_heat_ups_are_not_learned_from() {
+        let mut learner = HeatUpLearner::new();
+
+        sample_at(&mut learner, 0, 20.5);
+        sample_at(&mut learner, 5 * 60, 21.0);
+
+        assert_eq!(None, learner.rate(&uuid(1)));
+    }
+
+    #[test]
+    fn lead_time_uses_default_rate_and_is_capped() {
+        let learner = HeatUpLearner::new();
+
+        assert_eq!(HOUR / 2, learner.lead_time(&uuid(1), 20.0, 21.0));
+        assert_eq!(0, learner.lead_time(&uuid(1), 22.0, 21.0));
+        assert_eq!(MAX_LEAD_TIME, learner.lead_time(&uuid(1), 5.0, 21.0));
+    }
+}
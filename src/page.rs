@@ -0,0 +1,281 @@
+use clock::Timestamp;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::cmp::Ordering;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SortField {
+    Name,
+    Setpoint,
+    Created,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sort {
+    field: SortField,
+    descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Sort {
+        Sort {
+            field: SortField::Created,
+            descending: false,
+        }
+    }
+}
+
+impl FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Sort, String> {
+        let (descending, field) = if s.starts_with('-') {
+            (true, &s[1..])
+        } else {
+            (false, s)
+        };
+        let field = match field {
+            "name" => SortField::Name,
+            "setpoint" => SortField::Setpoint,
+            "created" => SortField::Created,
+            _ => return Err(format!("cannot sort by {}", field)),
+        };
+
+        Ok(Sort { field, descending })
+    }
+}
+
+#[derive(Clone, PartialEq, PartialOrd, Debug)]
+pub enum SortKey {
+    Text(String),
+    Number(Option<f64>),
+    Time(Timestamp),
+}
+
+impl Sort {
+    pub fn key(self, name: &str, setpoint: Option<f64>, created_at: Timestamp) -> SortKey {
+        match self.field {
+            SortField::Name => SortKey::Text(name.to_lowercase()),
+            SortField::Setpoint => SortKey::Number(setpoint),
+            SortField::Created => SortKey::Time(created_at),
+        }
+    }
+
+    fn compare(self, a: (&SortKey, &Uuid), b: (&SortKey, &Uuid)) -> Ordering {
+        let ordering = a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    pub fn sort<T>(self, items: &mut [(SortKey, Uuid, T)]) {
+        items.sort_by(|a, b| self.compare((&a.0, &a.1), (&b.0, &b.1)));
+    }
+
+    fn parse_key(self, key: &str) -> Option<SortKey> {
+        match self.field {
+            SortField::Name => Some(SortKey::Text(key.to_string())),
+            SortField::Setpoint if key.is_empty() => Some(SortKey::Number(None)),
+            SortField::Setpoint => key.parse().ok().map(|setpoint| SortKey::Number(Some(setpoint))),
+            SortField::Created => key.parse().ok().map(SortKey::Time),
+        }
+    }
+}
+
+pub fn name_matches(name: &str, filter: Option<&str>) -> bool {
+    filter.map_or(true, |filter| name.to_lowercase().contains(&filter.to_lowercase()))
+}
+
+pub fn in_range(value: Option<f64>, min: Option<f64>, max: Option<f64>) -> bool {
+    match value {
+        Some(value) => min.map_or(true, |min| value >= min) && max.map_or(true, |max| value <= max),
+        None => min.is_none() && max.is_none(),
+    }
+}
+
+fn encode_cursor(key: &SortKey, uuid: &Uuid) -> String {
+    let key = match key {
+        SortKey::Text(text) => text.clone(),
+        SortKey::Number(Some(number)) => number.to_string(),
+        SortKey::Number(None) => String::new(),
+        SortKey::Time(time) => time.to_string(),
+    };
+    format!("{}.{}", uuid, key)
+        .bytes()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn decode_cursor(cursor: &str, sort: Sort) -> Option<(SortKey, Uuid)> {
+    if cursor.len() % 2 != 0 {
+        return None;
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(cursor.get(index..index + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let cursor = String::from_utf8(bytes).ok()?;
+
+    let mut parts = cursor.splitn(2, '.');
+    let uuid = Uuid::parse_str(parts.next()?).ok()?;
+    let key = sort.parse_key(parts.next()?)?;
+    Some((key, uuid))
+}
+
+pub struct Page<T> {
+    name: &'static str,
+    items: Vec<(Uuid, T)>,
+    next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn new(
+        name: &'static str,
+        mut items: Vec<(SortKey, Uuid, T)>,
+        sort: Sort,
+        cursor: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<T>, String> {
+        if limit == Some(0) {
+            return Err("limit must be at least 1".to_string());
+        }
+
+        sort.sort(&mut items);
+
+        if let Some(cursor) = cursor {
+            let after = decode_cursor(cursor, sort).ok_or_else(|| "invalid cursor".to_string())?;
+            items.retain(|(key, uuid, _)| sort.compare((key, uuid), (&after.0, &after.1)) == Ordering::Greater);
+        }
+
+        let mut next_cursor = None;
+        if let Some(limit) = limit {
+            if items.len() > limit {
+                items.truncate(limit);
+                next_cursor = items.last().map(|(key, uuid, _)| encode_cursor(key, uuid));
+            }
+        }
+
+        Ok(Page {
+            name,
+            items: items.into_iter().map(|(_, uuid, item)| (uuid, item)).collect(),
+            next_cursor,
+        })
+    }
+
+    pub fn items(&self) -> &[(Uuid, T)] {
+        &self.items
+    }
+
+    pub fn next_cursor(&self) -> Option<&str> {
+        self.next_cursor.as_ref().map(String::as_str)
+    }
+}
+
+struct OrderedItems<'a, T: 'a>(&'a [(Uuid, T)]);
+
+impl<'a, T: Serialize> Serialize for OrderedItems<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(uuid, item)| (uuid, item)))
+    }
+}
+
+impl<T: Serialize> Serialize for Page<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry(self.name, &OrderedItems(&self.items))?;
+        if let Some(ref next_cursor) = self.next_cursor {
+            map.serialize_entry("next_cursor", next_cursor)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixtures::uuid;
+
+    fn items() -> Vec<(SortKey, Uuid, &'static str)> {
+        let sort = "name".parse::<Sort>().unwrap();
+        vec![
+            (sort.key("Kitchen", None, 0), uuid(1), "Kitchen"),
+            (sort.key("bedroom", None, 0), uuid(2), "bedroom"),
+            (sort.key("Attic", None, 0), uuid(3), "Attic"),
+        ]
+    }
+
+    fn names(page: &Page<&'static str>) -> Vec<&'static str> {
+        page.items().iter().map(|(_, name)| *name).collect()
+    }
+
+    #[test]
+    fn sort_parses_descending_fields_and_rejects_unknown_ones() {
+        let sort: Sort = "-setpoint".parse().unwrap();
+
+        assert_eq!(SortField::Setpoint, sort.field);
+        assert!(sort.descending);
+        assert!("colour".parse::<Sort>().is_err());
+    }
+
+    #[test]
+    fn page_sorts_names_case_insensitively() {
+        let sort = "name".parse().unwrap();
+        let page = Page::new("zones", items(), sort, None, None).unwrap();
+
+        assert_eq!(vec!["Attic", "bedroom", "Kitchen"], names(&page));
+        assert_eq!(None, page.next_cursor());
+    }
+
+    #[test]
+    fn page_in_descending_order_is_reversed() {
+        let sort = "-name".parse().unwrap();
+        let page = Page::new("zones", items(), sort, None, None).unwrap();
+
+        assert_eq!(vec!["Kitchen", "bedroom", "Attic"], names(&page));
+    }
+
+    #[test]
+    fn next_cursor_continues_after_the_last_item() {
+        let sort = "name".parse().unwrap();
+        let first = Page::new("zones", items(), sort, None, Some(2)).unwrap();
+        let second = Page::new("zones", items(), sort, first.next_cursor(), Some(2)).unwrap();
+
+        assert_eq!(vec!["Attic", "bedroom"], names(&first));
+        assert_eq!(vec!["Kitchen"], names(&second));
+        assert_eq!(None, second.next_cursor());
+    }
+
+    #[test]
+    fn cursor_survives_removal_of_the_item_it_points_at() {
+        let sort = "name".parse().unwrap();
+        let first = Page::new("zones", items(), sort, None, Some(2)).unwrap();
+        let mut remaining = items();
+        remaining.retain(|(_, _, name)| *name != "bedroom");
+        let second = Page::new("zones", remaining, sort, first.next_cursor(), Some(2)).unwrap();
+
+        assert_eq!(vec!["Kitchen"], names(&second));
+    }
+
+    #[test]
+    fn invalid_cursor_and_zero_limit_are_errors() {
+        let sort = Sort::default();
+
+        assert!(Page::new("zones", items(), sort, Some("zz"), None).is_err());
+        assert!(Page::new("zones", items(), sort, None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn page_serializes_items_in_order() {
+        let sort = "-name".parse().unwrap();
+        let page = Page::new("zones", items(), sort, None, Some(2)).unwrap();
+        let json = serde_json::to_string(&page).unwrap();
+
+        let kitchen = json.find(&uuid(1).to_string()).unwrap();
+        let bedroom = json.find(&uuid(2).to_string()).unwrap();
+        assert!(kitchen < bedroom);
+        assert!(json.contains("\"next_cursor\""));
+    }
+}
@@ -0,0 +1,394 @@
+use audit::AuditLogState;
+use clock::{self, Timestamp};
+use device::DeviceCollectionState;
+use rocket::config::Config;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use temperature::Temperature;
+use uuid::Uuid;
+use zone::{ZoneCollection, ZoneCollectionState};
+
+pub type PresenceState = Arc<Mutex<Presence>>;
+
+const EVALUATION_INTERVAL: Timestamp = 60;
+const AUDIT_ACTOR: &str = "presence";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GracePeriods {
+    pub arrive: Timestamp,
+    pub leave: Timestamp,
+}
+
+impl GracePeriods {
+    pub fn from_config(config: &Config) -> GracePeriods {
+        let mut grace = GracePeriods::default();
+        if let Ok(table) = config.get_table("presence") {
+            if let Some(arrive) = table.get("arrive_grace").and_then(|value| value.as_integer()) {
+                match Timestamp::try_from(arrive) {
+                    Ok(arrive) => grace.arrive = arrive,
+                    Err(_) => warn!("Ignoring invalid presence arrive_grace: {}", arrive),
+                }
+            }
+            if let Some(leave) = table.get("leave_grace").and_then(|value| value.as_integer()) {
+                match Timestamp::try_from(leave) {
+                    Ok(leave) => grace.leave = leave,
+                    Err(_) => warn!("Ignoring invalid presence leave_grace: {}", leave),
+                }
+            }
+        }
+        grace
+    }
+}
+
+impl Default for GracePeriods {
+    fn default() -> GracePeriods {
+        GracePeriods {
+            arrive: 0,
+            leave: 15 * 60,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Movement {
+    Arrive,
+    Leave,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Report {
+    pub movement: Movement,
+    #[serde(default)]
+    pub zone_uuid: Option<Uuid>,
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct Agent {
+    present: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zone_uuid: Option<Uuid>,
+    since: Timestamp,
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct Occupancy {
+    occupied: bool,
+    since: Timestamp,
+}
+
+impl Occupancy {
+    fn update(&mut self, occupied: bool, now: Timestamp) {
+        if occupied != self.occupied {
+            *self = Occupancy { occupied, since: now };
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Comfort,
+    Eco,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub struct PresenceSetpoints {
+    comfort_setpoint: Temperature,
+    eco_setpoint: Temperature,
+}
+
+impl PresenceSetpoints {
+    fn setpoint(&self, mode: Mode) -> Temperature {
+        match mode {
+            Mode::Comfort => self.comfort_setpoint,
+            Mode::Eco => self.eco_setpoint,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct PresenceZone {
+    #[serde(flatten)]
+    setpoints: PresenceSetpoints,
+    #[serde(flatten)]
+    occupancy: Occupancy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<Mode>,
+}
+
+impl PresenceZone {
+    pub fn setpoints(&self) -> PresenceSetpoints {
+        self.setpoints
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct Presence {
+    home: Occupancy,
+    agents: BTreeMap<String, Agent>,
+    zones: HashMap<Uuid, PresenceZone>,
+    #[serde(skip)]
+    grace: GracePeriods,
+    #[serde(skip)]
+    last_evaluated: Option<Timestamp>,
+}
+
+impl Presence {
+    pub fn new(grace: GracePeriods) -> Presence {
+        Presence {
+            home: Occupancy {
+                occupied: false,
+                since: clock::now(),
+            },
+            agents: BTreeMap::new(),
+            zones: HashMap::new(),
+            grace,
+            last_evaluated: None,
+        }
+    }
+
+    pub fn last_evaluated(&self) -> Option<Timestamp> {
+        self.last_evaluated
+    }
+
+    pub fn is_evaluating(&self, now: Timestamp) -> bool {
+        clock::ran_recently(self.last_evaluated, EVALUATION_INTERVAL, now)
+    }
+
+    pub fn report(&mut self, agent: &str, report: Report, now: Timestamp) {
+        let present = report.movement == Movement::Arrive;
+        self.agents.insert(
+            agent.to_string(),
+            Agent {
+                present,
+                zone_uuid: if present { report.zone_uuid } else { None },
+                since: now,
+            },
+        );
+    }
+
+    pub fn forget_agent(&mut self, agent: &str) -> Option<Agent> {
+        self.agents.remove(agent)
+    }
+
+    pub fn zone(&self, uuid: &Uuid) -> Option<&PresenceZone> {
+        self.zones.get(uuid)
+    }
+
+    pub fn zones(&self) -> impl Iterator<Item = (&Uuid, &PresenceZone)> {
+        self.zones.iter()
+    }
+
+    pub fn set_zone(&mut self, uuid: Uuid, setpoints: PresenceSetpoints, now: Timestamp) {
+        let zone = self.zones.entry(uuid).or_insert(PresenceZone {
+            setpoints,
+            occupancy: Occupancy {
+                occupied: false,
+                since: now,
+            },
+            mode: None,
+        });
+        zone.setpoints = setpoints;
+        zone.mode = None;
+    }
+
+    pub fn remove_zone(&mut self, uuid: &Uuid) -> Option<PresenceZone> {
+        self.zones.remove(uuid)
+    }
+
+    fn is_in(agent_zone: Option<Uuid>, zone_uuid: &Uuid, zones: &ZoneCollection) -> bool {
+        let mut ancestor = agent_zone;
+        while let Some(ancestor_uuid) = ancestor {
+            if ancestor_uuid == *zone_uuid {
+                return true;
+            }
+            ancestor = zones.get(&ancestor_uuid).and_then(|zone| zone.parent_uuid());
+        }
+        false
+    }
+
+    pub fn evaluate(&mut self, now: Timestamp, zones: &mut ZoneCollection) -> Vec<Uuid> {
+        self.last_evaluated = Some(now);
+        self.update(now, zones)
+    }
+
+    pub fn update(&mut self, now: Timestamp, zones: &mut ZoneCollection) -> Vec<Uuid> {
+        self.zones.retain(|uuid, _| zones.get(uuid).is_some());
+        let present: Vec<&Agent> = self.agents.values().filter(|agent| agent.present).collect();
+        self.home.update(!present.is_empty(), now);
+
+        let mut switched = Vec::new();
+        for (uuid, presence_zone) in &mut self.zones {
+            let occupied = present
+                .iter()
+                .any(|agent| agent.zone_uuid.is_none() || Presence::is_in(agent.zone_uuid, uuid, zones));
+            presence_zone.occupancy.update(occupied, now);
+
+            let elapsed = now.saturating_sub(presence_zone.occupancy.since);
+            let mode = match occupied {
+                true if elapsed >= self.grace.arrive => Mode::Comfort,
+                false if elapsed >= self.grace.leave => Mode::Eco,
+                _ => continue,
+            };
+            if presence_zone.mode == Some(mode) {
+                continue;
+            }
+            let mut zone = zones.get(uuid).cloned().unwrap();
+            zone.set_setpoint(presence_zone.setpoints.setpoint(mode));
+            zones.add(*uuid, zone);
+            presence_zone.mode = Some(mode);
+            switched.push(*uuid);
+        }
+        switched
+    }
+}
+
+pub fn spawn_evaluator(
+    presence: PresenceState,
+    zones: ZoneCollectionState,
+    devices: DeviceCollectionState,
+    audit: AuditLogState,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        {
+            let now = clock::now();
+            let mut zones = zones.lock().unwrap();
+            let devices = devices.lock().unwrap();
+            let zones_before = zones.clone();
+            if !presence.lock().unwrap().evaluate(now, &mut zones).is_empty() {
+                audit
+                    .lock()
+                    .unwrap()
+                    .record_changes(now, AUDIT_ACTOR, (&zones_before, &*devices), (&*zones, &*devices));
+            }
+        }
+
+        thread::sleep(Duration::from_secs(EVALUATION_INTERVAL));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixtures::uuid;
+    use zone::Zone;
+
+    const MINUTE: Timestamp = 60;
+
+    fn arrive(zone_uuid: Option<Uuid>) -> Report {
+        Report {
+            movement: Movement::Arrive,
+            zone_uuid,
+        }
+    }
+
+    fn leave() -> Report {
+        Report {
+            movement: Movement::Leave,
+            zone_uuid: None,
+        }
+    }
+
+    fn upstairs() -> (Presence, ZoneCollection) {
+        let mut zones = ZoneCollection::new();
+        zones.add(uuid(1), Zone::new("Landing".to_string()));
+        let mut bedroom = Zone::new("Bedroom".to_string());
+        bedroom.set_parent_uuid(Some(uuid(1)));
+        zones.add(uuid(2), bedroom);
+
+        let mut presence = Presence::new(GracePeriods {
+            arrive: 0,
+            leave: 15 * MINUTE,
+        });
+        let setpoints = PresenceSetpoints {
+            comfort_setpoint: Temperature::from_celsius(21.0),
+            eco_setpoint: Temperature::from_celsius(17.0),
+        };
+        presence.set_zone(uuid(1), setpoints, 0);
+        presence.set_zone(uuid(2), setpoints, 0);
+        (presence, zones)
+    }
+
+    fn setpoint_is(zones: &ZoneCollection, uuid: &Uuid, setpoint: f64) -> bool {
+        (zones.get(uuid).unwrap().setpoint().celsius() - setpoint).abs() < std::f64::EPSILON
+    }
+
+    #[test]
+    fn arrival_switches_to_comfort_at_once() {
+        let (mut presence, mut zones) = upstairs();
+
+        presence.report("phone", arrive(None), 100);
+        let switched = presence.update(100, &mut zones);
+
+        assert_eq!(2, switched.len());
+        assert!(setpoint_is(&zones, &uuid(1), 21.0));
+        assert!(setpoint_is(&zones, &uuid(2), 21.0));
+        assert!(presence.home.occupied);
+    }
+
+    #[test]
+    fn leaving_switches_to_eco_after_grace_period() {
+        let (mut presence, mut zones) = upstairs();
+        presence.report("phone", arrive(None), 100);
+        presence.update(100, &mut zones);
+
+        presence.report("phone", leave(), 200);
+        assert!(presence.update(200 + 14 * MINUTE, &mut zones).is_empty());
+        assert!(setpoint_is(&zones, &uuid(1), 21.0));
+
+        presence.update(200 + 15 * MINUTE, &mut zones);
+        assert!(setpoint_is(&zones, &uuid(1), 17.0));
+        assert!(!presence.home.occupied);
+    }
+
+    #[test]
+    fn returning_within_grace_period_keeps_comfort() {
+        let (mut presence, mut zones) = upstairs();
+        presence.report("phone", arrive(None), 100);
+        presence.update(100, &mut zones);
+
+        presence.report("phone", leave(), 200);
+        presence.update(200, &mut zones);
+        presence.report("phone", arrive(None), 200 + 5 * MINUTE);
+
+        assert!(presence.update(200 + 5 * MINUTE, &mut zones).is_empty());
+        assert!(presence.update(200 + 30 * MINUTE, &mut zones).is_empty());
+        assert!(setpoint_is(&zones, &uuid(1), 21.0));
+    }
+
+    #[test]
+    fn agent_in_zone_occupies_it_and_the_zones_around_it() {
+        let (mut presence, mut zones) = upstairs();
+        presence.report("phone", arrive(Some(uuid(1))), 0);
+        presence.update(0, &mut zones);
+        presence.report("phone", arrive(Some(uuid(2))), 100);
+
+        presence.update(100 + 15 * MINUTE, &mut zones);
+
+        assert!(presence.zone(&uuid(1)).unwrap().occupancy.occupied);
+        assert!(presence.zone(&uuid(2)).unwrap().occupancy.occupied);
+        presence.report("phone", arrive(Some(uuid(1))), 2000);
+        presence.update(2000, &mut zones);
+        presence.update(2000 + 15 * MINUTE, &mut zones);
+        assert!(setpoint_is(&zones, &uuid(2), 17.0));
+        assert!(setpoint_is(&zones, &uuid(1), 21.0));
+    }
+
+    #[test]
+    fn setpoint_set_by_hand_is_kept_until_occupancy_changes() {
+        let (mut presence, mut zones) = upstairs();
+        presence.report("phone", arrive(None), 100);
+        presence.update(100, &mut zones);
+
+        let mut landing = zones.get(&uuid(1)).cloned().unwrap();
+        landing.set_setpoint(Temperature::from_celsius(23.0));
+        zones.add(uuid(1), landing);
+
+        assert!(presence.update(200, &mut zones).is_empty());
+        assert!(setpoint_is(&zones, &uuid(1), 23.0));
+    }
+}
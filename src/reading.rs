@@ -0,0 +1,155 @@
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Quantity {
+    Temperature,
+    Humidity,
+    Co2,
+    Pressure,
+    Illuminance,
+}
+
+impl Quantity {
+    pub const ALL: [Quantity; 5] = [
+        Quantity::Temperature,
+        Quantity::Humidity,
+        Quantity::Co2,
+        Quantity::Pressure,
+        Quantity::Illuminance,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Quantity::Temperature => "temperature",
+            Quantity::Humidity => "humidity",
+            Quantity::Co2 => "co2",
+            Quantity::Pressure => "pressure",
+            Quantity::Illuminance => "illuminance",
+        }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self {
+            Quantity::Temperature => "°C",
+            Quantity::Humidity => "%",
+            Quantity::Co2 => "ppm",
+            Quantity::Pressure => "hPa",
+            Quantity::Illuminance => "lx",
+        }
+    }
+
+    pub fn validate(self, value: f64) -> Result<(), String> {
+        let valid = match self {
+            _ if !value.is_finite() => false,
+            Quantity::Temperature => true,
+            Quantity::Humidity => (0.0..=100.0).contains(&value),
+            Quantity::Pressure => value > 0.0,
+            Quantity::Co2 | Quantity::Illuminance => value >= 0.0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("{} {} is not a valid {} reading", value, self.unit(), self.as_str()))
+        }
+    }
+}
+
+impl Default for Quantity {
+    fn default() -> Quantity {
+        Quantity::Temperature
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Readings(BTreeMap<Quantity, f64>);
+
+#[derive(Serialize)]
+struct Reading {
+    value: f64,
+    unit: &'static str,
+}
+
+#[derive(Deserialize)]
+struct StoredReading {
+    value: f64,
+}
+
+impl Readings {
+    pub fn new() -> Readings {
+        Readings(BTreeMap::new())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, quantity: Quantity) -> Option<f64> {
+        self.0.get(&quantity).cloned()
+    }
+
+    pub fn set(&mut self, quantity: Quantity, value: f64) {
+        self.0.insert(quantity, value);
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Quantity, f64)> + 'a {
+        self.0.iter().map(|(quantity, value)| (*quantity, *value))
+    }
+}
+
+impl Serialize for Readings {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (quantity, value) in self.iter() {
+            map.serialize_entry(
+                quantity.as_str(),
+                &Reading {
+                    value,
+                    unit: quantity.unit(),
+                },
+            )?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Readings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Readings, D::Error> {
+        let readings = BTreeMap::<Quantity, StoredReading>::deserialize(deserializer)?;
+        Ok(Readings(
+            readings.into_iter().map(|(quantity, reading)| (quantity, reading.value)).collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_values_a_quantity_cannot_have() {
+        assert!(Quantity::Humidity.validate(45.0).is_ok());
+        assert!(Quantity::Humidity.validate(120.0).is_err());
+        assert!(Quantity::Co2.validate(-1.0).is_err());
+        assert!(Quantity::Pressure.validate(0.0).is_err());
+        assert!(Quantity::Temperature.validate(-12.5).is_ok());
+        assert!(Quantity::Temperature.validate(std::f64::NAN).is_err());
+    }
+
+    #[test]
+    fn serializes_readings_with_their_units_and_reads_them_back() {
+        let mut readings = Readings::new();
+        readings.set(Quantity::Co2, 800.0);
+        readings.set(Quantity::Temperature, 19.5);
+
+        let json = serde_json::to_string(&readings).unwrap();
+
+        assert_eq!(
+            r#"{"temperature":{"value":19.5,"unit":"°C"},"co2":{"value":800.0,"unit":"ppm"}}"#,
+            json
+        );
+        assert_eq!(readings, serde_json::from_str::<Readings>(&json).unwrap());
+    }
+}
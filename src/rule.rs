@@ -0,0 +1,1026 @@
+use audit::AuditLogState;
+use clock::{self, Timestamp, DAY};
+use device::{DeviceCollection, DeviceCollectionState, HealthPolicy};
+use optimum::HeatUpLearner;
+use reading::Quantity;
+use scene::{SceneCollection, SceneCollectionState};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::mem;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use temperature::Temperature;
+use uuid::Uuid;
+use zone::{OptimumStart, ZoneCollection, ZoneCollectionState};
+
+pub type RuleEngineState = Arc<Mutex<RuleEngine>>;
+
+const EVALUATION_INTERVAL: Timestamp = 60;
+const AUDIT_ACTOR: &str = "rules";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimeOfDay {
+    seconds: Timestamp,
+}
+
+impl TimeOfDay {
+    fn of(timestamp: Timestamp) -> Timestamp {
+        timestamp % DAY
+    }
+
+    fn next_after(self, timestamp: Timestamp) -> Timestamp {
+        let next = timestamp - TimeOfDay::of(timestamp) + self.seconds;
+        if next <= timestamp {
+            next + DAY
+        } else {
+            next
+        }
+    }
+
+    fn passed_between(self, since: Timestamp, now: Timestamp) -> bool {
+        self.next_after(since) <= now
+    }
+
+    pub fn contains(from: TimeOfDay, to: TimeOfDay, timestamp: Timestamp) -> bool {
+        let time = TimeOfDay::of(timestamp);
+        if from.seconds <= to.seconds {
+            from.seconds <= time && time < to.seconds
+        } else {
+            from.seconds <= time || time < to.seconds
+        }
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TimeOfDay, String> {
+        let error = || format!("{} is not a time of day such as 07:30", s);
+        let mut parts = s.splitn(2, ':');
+        let hours: Timestamp = parts.next().and_then(|hours| hours.parse().ok()).ok_or_else(error)?;
+        let minutes: Timestamp = parts.next().and_then(|minutes| minutes.parse().ok()).ok_or_else(error)?;
+        if hours >= 24 || minutes >= 60 {
+            return Err(error());
+        }
+        Ok(TimeOfDay {
+            seconds: (hours * 60 + minutes) * 60,
+        })
+    }
+}
+
+impl Serialize for TimeOfDay {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let minutes = self.seconds / 60;
+        serializer.serialize_str(&format!("{:02}:{:02}", minutes / 60, minutes % 60))
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TimeOfDay, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Threshold {
+    subject_uuid: Uuid,
+    #[serde(default)]
+    quantity: Quantity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    above: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    below: Option<f64>,
+}
+
+impl Threshold {
+    fn validate(&self) -> Result<(), String> {
+        if self.above.is_none() && self.below.is_none() {
+            return Err("reading thresholds need above, below or both".to_string());
+        }
+        Ok(())
+    }
+
+    fn holds(&self, zones: &ZoneCollection, devices: &DeviceCollection) -> bool {
+        let reading = if zones.get(&self.subject_uuid).is_some() {
+            devices.zone_reading(&self.subject_uuid, self.quantity)
+        } else {
+            devices
+                .get(&self.subject_uuid)
+                .and_then(|device| device.reading(self.quantity))
+        };
+        reading.map_or(false, |reading| {
+            self.above.map_or(true, |above| reading > above) && self.below.map_or(true, |below| reading < below)
+        })
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceState {
+    Online,
+    Offline,
+    Fault,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct StateCheck {
+    device_uuid: Uuid,
+    state: DeviceState,
+}
+
+impl StateCheck {
+    fn holds(&self, now: Timestamp, devices: &DeviceCollection, policy: &HealthPolicy) -> bool {
+        devices.get(&self.device_uuid).map_or(false, |device| match self.state {
+            DeviceState::Online => device.is_online(now, policy) == Some(true),
+            DeviceState::Offline => device.is_online(now, policy) == Some(false),
+            DeviceState::Fault => device.health().fault.is_some(),
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    Time { at: TimeOfDay },
+    Reading(Threshold),
+    State(StateCheck),
+    Event { name: String },
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    Reading(Threshold),
+    State(StateCheck),
+    TimeBetween { from: TimeOfDay, to: TimeOfDay },
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    SetSetpoint {
+        subject_uuid: Uuid,
+        setpoint: Temperature,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minutes: Option<u64>,
+    },
+    AdjustSetpoint {
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        subject_uuids: Vec<Uuid>,
+        delta: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minutes: Option<u64>,
+    },
+    ActivateScene { scene_uuid: Uuid },
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Rule {
+    name: String,
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+    trigger: Trigger,
+    #[serde(default)]
+    conditions: Vec<Condition>,
+    actions: Vec<Action>,
+}
+
+impl Rule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.actions.is_empty() {
+            return Err("rules need at least one action".to_string());
+        }
+        match self.trigger {
+            Trigger::Reading(ref threshold) => threshold.validate()?,
+            Trigger::Event { ref name } if name.is_empty() => return Err("events need a name".to_string()),
+            _ => {}
+        }
+        for condition in &self.conditions {
+            if let Condition::Reading(ref threshold) = condition {
+                threshold.validate()?;
+            }
+        }
+        for action in &self.actions {
+            match action {
+                Action::SetSetpoint { minutes: Some(0), .. } | Action::AdjustSetpoint { minutes: Some(0), .. } => {
+                    return Err("actions need a positive number of minutes".to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn refers_to(&self, uuid: &Uuid) -> bool {
+        let trigger = match self.trigger {
+            Trigger::Reading(ref threshold) => threshold.subject_uuid == *uuid,
+            Trigger::State(ref check) => check.device_uuid == *uuid,
+            Trigger::Time { .. } | Trigger::Event { .. } => false,
+        };
+        let condition = self.conditions.iter().any(|condition| match condition {
+            Condition::Reading(threshold) => threshold.subject_uuid == *uuid,
+            Condition::State(check) => check.device_uuid == *uuid,
+            Condition::TimeBetween { .. } => false,
+        });
+        let action = self.actions.iter().any(|action| match action {
+            Action::SetSetpoint { subject_uuid, .. } => subject_uuid == uuid,
+            Action::AdjustSetpoint { subject_uuids, .. } => subject_uuids.contains(uuid),
+            Action::ActivateScene { .. } => false,
+        });
+        trigger || condition || action
+    }
+
+    pub fn dry_run(&self, event: Option<&str>, now: Timestamp, home: &Home) -> DryRun {
+        let trigger_holds = match self.trigger {
+            Trigger::Time { at } => at.passed_between(now.saturating_sub(EVALUATION_INTERVAL), now),
+            Trigger::Reading(ref threshold) => threshold.holds(home.zones, home.devices),
+            Trigger::State(ref check) => check.holds(now, home.devices, home.policy),
+            Trigger::Event { ref name } => event == Some(name.as_str()),
+        };
+
+        let (mut zones, mut devices, mut scenes) = (home.zones.clone(), home.devices.clone(), home.scenes.clone());
+        let mut after = Home {
+            zones: &mut zones,
+            devices: &mut devices,
+            scenes: &mut scenes,
+            policy: home.policy,
+        };
+        self.act(&self.actions, now, &mut after);
+
+        let zone_changes = home.zones.iter().filter_map(|(uuid, zone)| {
+            let after = after.zones.get(uuid)?.setpoint();
+            Some((*uuid, zone.setpoint(), after))
+        });
+        let device_changes = home.devices.iter().filter_map(|(uuid, device)| {
+            let after = after.devices.get(uuid)?.setpoint()?;
+            Some((*uuid, device.setpoint()?, after))
+        });
+        let mut changes: Vec<SetpointChange> = zone_changes
+            .chain(device_changes)
+            .filter(|(_, before, after)| (before.celsius() - after.celsius()).abs() > std::f64::EPSILON)
+            .map(|(subject_uuid, before, after)| SetpointChange {
+                subject_uuid,
+                before,
+                after,
+            })
+            .collect();
+        changes.sort_by_key(|change| change.subject_uuid);
+
+        DryRun {
+            trigger_holds,
+            conditions_hold: self.conditions_hold(now, home),
+            changes,
+        }
+    }
+
+    fn conditions_hold(&self, now: Timestamp, home: &Home) -> bool {
+        self.conditions.iter().all(|condition| match condition {
+            Condition::Reading(threshold) => threshold.holds(home.zones, home.devices),
+            Condition::State(check) => check.holds(now, home.devices, home.policy),
+            Condition::TimeBetween { from, to } => TimeOfDay::contains(*from, *to, now),
+        })
+    }
+
+    fn act<'r, I: IntoIterator<Item = &'r Action>>(&self, actions: I, now: Timestamp, home: &mut Home) -> Vec<Restore> {
+        let mut restores = Vec::new();
+        for action in actions {
+            match action {
+                Action::SetSetpoint {
+                    subject_uuid,
+                    setpoint,
+                    minutes,
+                } => {
+                    if let Some(change) = home.set_setpoint(subject_uuid, |_| *setpoint) {
+                        restores.extend(minutes.map(|minutes| Restore::new(now, minutes, *subject_uuid, change)));
+                    }
+                }
+                Action::AdjustSetpoint {
+                    subject_uuids,
+                    delta,
+                    minutes,
+                } => {
+                    // Zones following their parent's setpoint are left to follow the adjusted parent.
+                    let subject_uuids = if subject_uuids.is_empty() {
+                        home.zones
+                            .iter()
+                            .filter(|(_, zone)| zone.parent_uuid().is_none() || zone.overrides_setpoint())
+                            .map(|(uuid, _)| *uuid)
+                            .collect()
+                    } else {
+                        subject_uuids.clone()
+                    };
+                    for subject_uuid in subject_uuids {
+                        let adjusted = |setpoint: Temperature| Temperature::from_celsius(setpoint.celsius() + delta);
+                        if let Some(change) = home.set_setpoint(&subject_uuid, adjusted) {
+                            restores.extend(minutes.map(|minutes| Restore::new(now, minutes, subject_uuid, change)));
+                        }
+                    }
+                }
+                Action::ActivateScene { scene_uuid } => {
+                    match home.scenes.activate(scene_uuid, home.zones, home.devices) {
+                        Some(Ok(())) => {}
+                        Some(Err(error)) => warn!("Rule {} could not activate a scene: {}", self.name, error),
+                        None => warn!("Rule {} activates scene {} which does not exist", self.name, scene_uuid),
+                    }
+                }
+            }
+        }
+        restores
+    }
+}
+
+pub struct Home<'a> {
+    pub zones: &'a mut ZoneCollection,
+    pub devices: &'a mut DeviceCollection,
+    pub scenes: &'a mut SceneCollection,
+    pub policy: &'a HealthPolicy,
+}
+
+impl<'a> Home<'a> {
+    fn set_setpoint<F: Fn(Temperature) -> Temperature>(&mut self, uuid: &Uuid, setpoint: F) -> Option<Change> {
+        if let Some(mut zone) = self.zones.get(uuid).cloned() {
+            let change = Change {
+                previous: zone.setpoint(),
+                setpoint: setpoint(zone.setpoint()),
+                overrides_setpoint: zone.overrides_setpoint(),
+            };
+            zone.set_setpoint(change.setpoint);
+            self.zones.add(*uuid, zone);
+            return Some(change);
+        }
+        let previous = self.devices.get(uuid)?.setpoint()?;
+        let change = Change {
+            previous,
+            setpoint: setpoint(previous),
+            overrides_setpoint: false,
+        };
+        self.devices.get_mut(uuid)?.set_setpoint(change.setpoint);
+        Some(change)
+    }
+
+    fn restore(&mut self, restore: &Restore) {
+        let change = restore.change;
+        let unchanged =
+            |setpoint: Temperature| (setpoint.celsius() - change.setpoint.celsius()).abs() < std::f64::EPSILON;
+        if let Some(mut zone) = self.zones.get(&restore.subject_uuid).cloned() {
+            if unchanged(zone.setpoint()) {
+                zone.set_setpoint(change.previous);
+                if !change.overrides_setpoint {
+                    zone.inherit_setpoint();
+                }
+                self.zones.add(restore.subject_uuid, zone);
+            }
+        } else if self
+            .devices
+            .get(&restore.subject_uuid)
+            .and_then(|device| device.setpoint())
+            .map_or(false, unchanged)
+        {
+            if let Some(device) = self.devices.get_mut(&restore.subject_uuid) {
+                device.set_setpoint(change.previous);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Change {
+    previous: Temperature,
+    setpoint: Temperature,
+    overrides_setpoint: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Restore {
+    at: Timestamp,
+    subject_uuid: Uuid,
+    change: Change,
+}
+
+impl Restore {
+    fn new(now: Timestamp, minutes: u64, subject_uuid: Uuid, change: Change) -> Restore {
+        Restore {
+            at: now + minutes * 60,
+            subject_uuid,
+            change,
+        }
+    }
+}
+
+fn preheat(
+    rule: &Rule,
+    target_at: Timestamp,
+    heat_up: &HeatUpLearner,
+    zones: &ZoneCollection,
+    devices: &DeviceCollection,
+) -> Option<(Timestamp, Vec<usize>)> {
+    let starts: Vec<(usize, Timestamp)> = rule
+        .actions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, action)| match action {
+            Action::SetSetpoint {
+                subject_uuid,
+                setpoint,
+                minutes: None,
+            } => {
+                zones.get(subject_uuid)?;
+                let temperature = devices.zone_temperature(subject_uuid)?;
+                match heat_up.lead_time(subject_uuid, temperature, setpoint.celsius()) {
+                    0 => None,
+                    lead_time => Some((index, target_at - lead_time)),
+                }
+            }
+            _ => None,
+        })
+        .collect();
+    let start = starts.iter().map(|&(_, start)| start).min()?;
+    Some((start, starts.into_iter().map(|(index, _)| index).collect()))
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct SetpointChange {
+    subject_uuid: Uuid,
+    before: Temperature,
+    after: Temperature,
+}
+
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct DryRun {
+    trigger_holds: bool,
+    conditions_hold: bool,
+    changes: Vec<SetpointChange>,
+}
+
+pub struct RuleEngine {
+    rules: HashMap<Uuid, Rule>,
+    holding: HashMap<Uuid, bool>,
+    restores: Vec<Restore>,
+    last_evaluated: Option<Timestamp>,
+    heat_up: HeatUpLearner,
+    preheated: HashMap<Uuid, (Timestamp, Vec<usize>)>,
+}
+
+impl RuleEngine {
+    pub fn new() -> RuleEngine {
+        RuleEngine {
+            rules: HashMap::new(),
+            holding: HashMap::new(),
+            restores: Vec::new(),
+            last_evaluated: None,
+            heat_up: HeatUpLearner::new(),
+            preheated: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, uuid: Uuid, rule: Rule) -> Result<(), String> {
+        rule.validate()?;
+        self.add_validated(uuid, rule);
+        Ok(())
+    }
+
+    pub fn add_validated(&mut self, uuid: Uuid, rule: Rule) {
+        self.holding.remove(&uuid);
+        self.preheated.remove(&uuid);
+        self.rules.insert(uuid, rule);
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> Option<&Rule> {
+        self.rules.get(uuid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Uuid, &Rule)> {
+        self.rules.iter()
+    }
+
+    pub fn remove(&mut self, uuid: &Uuid) -> Option<Rule> {
+        self.holding.remove(uuid);
+        self.preheated.remove(uuid);
+        self.rules.remove(uuid)
+    }
+
+    pub fn last_evaluated(&self) -> Option<Timestamp> {
+        self.last_evaluated
+    }
+
+    pub fn is_evaluating(&self, now: Timestamp) -> bool {
+        clock::ran_recently(self.last_evaluated, EVALUATION_INTERVAL, now)
+    }
+
+    pub fn evaluate(&mut self, now: Timestamp, home: &mut Home) -> Vec<Uuid> {
+        self.heat_up.sample(now, home.zones, home.devices);
+
+        let restores = mem::replace(&mut self.restores, Vec::new());
+        let (due, pending): (Vec<Restore>, Vec<Restore>) = restores.into_iter().partition(|restore| restore.at <= now);
+        self.restores = pending;
+        for restore in due {
+            home.restore(&restore);
+        }
+
+        let since = self.last_evaluated.unwrap_or(now);
+        self.last_evaluated = Some(now);
+        let mut acted = Vec::new();
+        for (uuid, rule) in &self.rules {
+            let every_action = || (0..rule.actions.len()).collect();
+            let mut preheat_for = None;
+            let actions: Vec<usize> = match rule.trigger {
+                Trigger::Time { at } if at.passed_between(since, now) => match self.preheated.remove(uuid) {
+                    Some((target_at, ref early)) if target_at == at.next_after(since) => {
+                        (0..rule.actions.len()).filter(|index| !early.contains(index)).collect()
+                    }
+                    _ => every_action(),
+                },
+                Trigger::Time { at } => {
+                    let target_at = at.next_after(now);
+                    let preheating = self.preheated.get(uuid).map(|&(preheated_for, _)| preheated_for);
+                    match preheat(rule, target_at, &self.heat_up, home.zones, home.devices) {
+                        Some((start, early)) if start <= now && preheating != Some(target_at) => {
+                            preheat_for = Some((target_at, early.clone()));
+                            early
+                        }
+                        _ => Vec::new(),
+                    }
+                }
+                Trigger::Reading(ref threshold) => {
+                    let holds = threshold.holds(home.zones, home.devices);
+                    if !self.holding.insert(*uuid, holds).unwrap_or(false) && holds {
+                        every_action()
+                    } else {
+                        Vec::new()
+                    }
+                }
+                Trigger::State(ref check) => {
+                    let holds = check.holds(now, home.devices, home.policy);
+                    if !self.holding.insert(*uuid, holds).unwrap_or(false) && holds {
+                        every_action()
+                    } else {
+                        Vec::new()
+                    }
+                }
+                Trigger::Event { .. } => Vec::new(),
+            };
+            if !actions.is_empty() && rule.enabled && rule.conditions_hold(now, home) {
+                self.restores
+                    .extend(rule.act(actions.iter().map(|&index| &rule.actions[index]), now, home));
+                if let Some(preheat_for) = preheat_for {
+                    self.preheated.insert(*uuid, preheat_for);
+                }
+                acted.push(*uuid);
+            }
+        }
+        acted
+    }
+
+    pub fn publish_optimum_start(&self, now: Timestamp, zones: &mut ZoneCollection, devices: &DeviceCollection) {
+        let mut scheduled: HashMap<Uuid, (Timestamp, Timestamp)> = HashMap::new();
+        for rule in self.rules.values().filter(|rule| rule.enabled) {
+            let target_at = match rule.trigger {
+                Trigger::Time { at } => at.next_after(now),
+                _ => continue,
+            };
+            for action in &rule.actions {
+                if let Action::SetSetpoint {
+                    subject_uuid,
+                    setpoint,
+                    minutes: None,
+                } = action
+                {
+                    let lead_time = devices.zone_temperature(subject_uuid).map_or(0, |temperature| {
+                        self.heat_up.lead_time(subject_uuid, temperature, setpoint.celsius())
+                    });
+                    let next = (target_at, target_at - lead_time);
+                    let earliest = scheduled.entry(*subject_uuid).or_insert(next);
+                    if next < *earliest {
+                        *earliest = next;
+                    }
+                }
+            }
+        }
+
+        let uuids: Vec<Uuid> = zones.iter().map(|(uuid, _)| *uuid).collect();
+        for uuid in uuids {
+            let heat_up_rate = self.heat_up.rate(&uuid);
+            let next = scheduled.get(&uuid).cloned();
+            let optimum_start = if heat_up_rate.is_none() && next.is_none() {
+                None
+            } else {
+                Some(OptimumStart {
+                    heat_up_rate,
+                    preheat_start: next.map(|(_, start)| start),
+                    target_at: next.map(|(target_at, _)| target_at),
+                })
+            };
+            zones.set_optimum_start(&uuid, optimum_start);
+        }
+    }
+
+    pub fn fire_event(&mut self, name: &str, now: Timestamp, home: &mut Home) -> Vec<Uuid> {
+        let mut acted = Vec::new();
+        for (uuid, rule) in &self.rules {
+            let triggered = match rule.trigger {
+                Trigger::Event { name: ref event } => event == name,
+                _ => false,
+            };
+            if triggered && rule.enabled && rule.conditions_hold(now, home) {
+                self.restores.extend(rule.act(&rule.actions, now, home));
+                acted.push(*uuid);
+            }
+        }
+        acted
+    }
+}
+
+pub fn spawn_evaluator(
+    engine: RuleEngineState,
+    zones: ZoneCollectionState,
+    devices: DeviceCollectionState,
+    scenes: SceneCollectionState,
+    audit: AuditLogState,
+    policy: HealthPolicy,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        {
+            let now = clock::now();
+            let mut zones = zones.lock().unwrap();
+            let mut devices = devices.lock().unwrap();
+            let mut scenes = scenes.lock().unwrap();
+            let (zones_before, devices_before) = (zones.clone(), devices.clone());
+            let mut home = Home {
+                zones: &mut zones,
+                devices: &mut devices,
+                scenes: &mut scenes,
+                policy: &policy,
+            };
+            engine.lock().unwrap().evaluate(now, &mut home);
+            audit.lock().unwrap().record_changes(
+                now,
+                AUDIT_ACTOR,
+                (&zones_before, &devices_before),
+                (&*home.zones, &*home.devices),
+            );
+            // Published after recording, as it is worked out rather than changed by the rules.
+            engine
+                .lock()
+                .unwrap()
+                .publish_optimum_start(now, home.zones, home.devices);
+        }
+
+        thread::sleep(Duration::from_secs(EVALUATION_INTERVAL));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device::Device;
+    use fixtures::uuid;
+    use zone::Zone;
+
+    const HOUR: Timestamp = 60 * 60;
+
+    fn bathroom(temperature: f64) -> (ZoneCollection, DeviceCollection) {
+        let mut zones = ZoneCollection::new();
+        zones.add(uuid(1), Zone::new("Bathroom".to_string()));
+        let mut sensor = Device::new("STRV".to_string(), Some(uuid(1)));
+        sensor.set_reading(Quantity::Temperature, temperature);
+        let mut devices = DeviceCollection::new();
+        devices.add(uuid(2), sensor);
+        (zones, devices)
+    }
+
+    fn boost_rule(trigger: Trigger) -> Rule {
+        Rule {
+            name: "Boost".to_string(),
+            enabled: true,
+            trigger,
+            conditions: Vec::new(),
+            actions: vec![Action::SetSetpoint {
+                subject_uuid: uuid(1),
+                setpoint: Temperature::from_celsius(24.0),
+                minutes: Some(30),
+            }],
+        }
+    }
+
+    fn engine_with(rule: Rule) -> RuleEngine {
+        let mut engine = RuleEngine::new();
+        engine.add(uuid(9), rule).unwrap();
+        engine
+    }
+
+    fn evaluate(engine: &mut RuleEngine, now: Timestamp, zones: &mut ZoneCollection, devices: &mut DeviceCollection) {
+        let mut scenes = SceneCollection::new();
+        let policy = HealthPolicy::default();
+        let mut home = Home {
+            zones,
+            devices,
+            scenes: &mut scenes,
+            policy: &policy,
+        };
+        engine.evaluate(now, &mut home);
+    }
+
+    fn zone_at(setpoint: f64) -> Zone {
+        let mut zone = Zone::new("Bathroom".to_string());
+        zone.set_setpoint(Temperature::from_celsius(setpoint));
+        zone
+    }
+
+    #[test]
+    fn time_of_day_parses_and_serializes_as_hours_and_minutes() {
+        let time: TimeOfDay = "07:30".parse().unwrap();
+
+        assert_eq!(7 * HOUR + 30 * 60, time.seconds);
+        assert_eq!("\"07:30\"", serde_json::to_string(&time).unwrap());
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("7".parse::<TimeOfDay>().is_err());
+    }
+
+    #[test]
+    fn reading_trigger_fires_once_when_threshold_is_crossed() {
+        let (mut zones, mut devices) = bathroom(25.0);
+        let trigger = Trigger::Reading(Threshold {
+            subject_uuid: uuid(2),
+            quantity: Quantity::Temperature,
+            above: Some(24.5),
+            below: None,
+        });
+        let mut engine = engine_with(boost_rule(trigger));
+
+        evaluate(&mut engine, 0, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(24.0)), zones.get(&uuid(1)));
+
+        zones.add(uuid(1), zone_at(20.0));
+        evaluate(&mut engine, 60, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(20.0)), zones.get(&uuid(1)));
+    }
+
+    #[test]
+    fn timed_action_keeps_setpoint_changed_meanwhile() {
+        let (mut zones, mut devices) = bathroom(25.0);
+        let trigger = Trigger::Reading(Threshold {
+            subject_uuid: uuid(1),
+            quantity: Quantity::Temperature,
+            above: Some(24.5),
+            below: None,
+        });
+        let mut engine = engine_with(boost_rule(trigger));
+
+        evaluate(&mut engine, 0, &mut zones, &mut devices);
+        zones.add(uuid(1), zone_at(19.0));
+        evaluate(&mut engine, HOUR / 2, &mut zones, &mut devices);
+
+        assert_eq!(Some(&zone_at(19.0)), zones.get(&uuid(1)));
+    }
+
+    #[test]
+    fn timed_action_restores_previous_setpoint() {
+        let (mut zones, mut devices) = bathroom(25.0);
+        let trigger = Trigger::Reading(Threshold {
+            subject_uuid: uuid(1),
+            quantity: Quantity::Temperature,
+            above: Some(24.5),
+            below: None,
+        });
+        let mut engine = engine_with(boost_rule(trigger));
+
+        evaluate(&mut engine, 0, &mut zones, &mut devices);
+        evaluate(&mut engine, HOUR / 4, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(24.0)), zones.get(&uuid(1)));
+
+        evaluate(&mut engine, HOUR / 2, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(16.0)), zones.get(&uuid(1)));
+    }
+
+    #[test]
+    fn timed_action_on_child_zone_lets_it_follow_its_parent_again() {
+        let (mut zones, mut devices) = bathroom(25.0);
+        zones.add(uuid(3), Zone::new("House".to_string()));
+        let mut bathroom = zones.get(&uuid(1)).unwrap().clone();
+        bathroom.set_parent_uuid(Some(uuid(3)));
+        zones.add(uuid(1), bathroom);
+        let trigger = Trigger::Reading(Threshold {
+            subject_uuid: uuid(1),
+            quantity: Quantity::Temperature,
+            above: Some(24.5),
+            below: None,
+        });
+        let mut engine = engine_with(boost_rule(trigger));
+
+        evaluate(&mut engine, 0, &mut zones, &mut devices);
+        let mut house = Zone::new("House".to_string());
+        house.set_setpoint(Temperature::from_celsius(18.0));
+        zones.add(uuid(3), house);
+        assert_eq!(Temperature::from_celsius(24.0), zones.get(&uuid(1)).unwrap().setpoint());
+
+        evaluate(&mut engine, HOUR / 2, &mut zones, &mut devices);
+        let bathroom = zones.get(&uuid(1)).unwrap();
+        assert_eq!(Temperature::from_celsius(18.0), bathroom.setpoint());
+        assert!(!bathroom.overrides_setpoint());
+    }
+
+    #[test]
+    fn reading_trigger_follows_other_quantities_of_zone() {
+        let (mut zones, mut devices) = bathroom(20.0);
+        let trigger = Trigger::Reading(Threshold {
+            subject_uuid: uuid(1),
+            quantity: Quantity::Humidity,
+            above: Some(70.0),
+            below: None,
+        });
+        let mut engine = engine_with(boost_rule(trigger));
+
+        evaluate(&mut engine, 0, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(16.0)), zones.get(&uuid(1)));
+
+        devices.get_mut(&uuid(2)).unwrap().set_reading(Quantity::Humidity, 85.0);
+        evaluate(&mut engine, 60, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(24.0)), zones.get(&uuid(1)));
+    }
+
+    #[test]
+    fn time_trigger_fires_when_its_time_passes() {
+        let (mut zones, mut devices) = bathroom(18.0);
+        let mut engine = engine_with(boost_rule(Trigger::Time {
+            at: "06:00".parse().unwrap(),
+        }));
+
+        evaluate(&mut engine, 5 * HOUR, &mut zones, &mut devices);
+        evaluate(&mut engine, 6 * HOUR - 60, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(16.0)), zones.get(&uuid(1)));
+
+        evaluate(&mut engine, 6 * HOUR, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(24.0)), zones.get(&uuid(1)));
+    }
+
+    fn morning_rule() -> Rule {
+        Rule {
+            actions: vec![Action::SetSetpoint {
+                subject_uuid: uuid(1),
+                setpoint: Temperature::from_celsius(21.0),
+                minutes: None,
+            }],
+            ..boost_rule(Trigger::Time {
+                at: "06:00".parse().unwrap(),
+            })
+        }
+    }
+
+    #[test]
+    fn time_trigger_fires_early_for_zone_to_warm_up_by_its_time() {
+        let (mut zones, mut devices) = bathroom(18.0);
+        let mut engine = engine_with(morning_rule());
+
+        evaluate(&mut engine, 4 * HOUR, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(16.0)), zones.get(&uuid(1)));
+
+        evaluate(&mut engine, 4 * HOUR + HOUR / 2, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(21.0)), zones.get(&uuid(1)));
+
+        zones.add(uuid(1), zone_at(19.0));
+        evaluate(&mut engine, 6 * HOUR, &mut zones, &mut devices);
+        assert_eq!(Some(&zone_at(19.0)), zones.get(&uuid(1)));
+    }
+
+    #[test]
+    fn actions_not_warming_up_a_zone_run_on_time() {
+        let (mut zones, mut devices) = bathroom(18.0);
+        zones.add(uuid(3), Zone::new("Kitchen".to_string()));
+        let mut rule = morning_rule();
+        rule.actions.push(Action::SetSetpoint {
+            subject_uuid: uuid(3),
+            setpoint: Temperature::from_celsius(18.0),
+            minutes: None,
+        });
+        let mut engine = engine_with(rule);
+
+        evaluate(&mut engine, 4 * HOUR + HOUR / 2, &mut zones, &mut devices);
+        assert_eq!(Some(21.0), zones.get(&uuid(1)).map(|zone| zone.setpoint().celsius()));
+        assert_eq!(Some(16.0), zones.get(&uuid(3)).map(|zone| zone.setpoint().celsius()));
+
+        zones.add(uuid(1), zone_at(19.0));
+        evaluate(&mut engine, 6 * HOUR, &mut zones, &mut devices);
+        assert_eq!(Some(19.0), zones.get(&uuid(1)).map(|zone| zone.setpoint().celsius()));
+        assert_eq!(Some(18.0), zones.get(&uuid(3)).map(|zone| zone.setpoint().celsius()));
+    }
+
+    #[test]
+    fn optimum_start_is_published_on_zones() {
+        let (mut zones, mut devices) = bathroom(18.0);
+        let mut engine = engine_with(morning_rule());
+        evaluate(&mut engine, 4 * HOUR, &mut zones, &mut devices);
+
+        engine.publish_optimum_start(4 * HOUR, &mut zones, &devices);
+
+        let zone = serde_json::to_value(zones.get(&uuid(1)).unwrap()).unwrap();
+        let optimum_start = &zone["optimum_start"];
+        assert!(optimum_start["heat_up_rate"].is_null());
+        assert_eq!(Some(4 * HOUR + HOUR / 2), optimum_start["preheat_start"].as_u64());
+        assert_eq!(Some(6 * HOUR), optimum_start["target_at"].as_u64());
+    }
+
+    #[test]
+    fn rule_does_not_act_unless_its_conditions_hold() {
+        let (mut zones, mut devices) = bathroom(18.0);
+        let mut rule = boost_rule(Trigger::Time {
+            at: "06:00".parse().unwrap(),
+        });
+        rule.conditions.push(Condition::TimeBetween {
+            from: "22:00".parse().unwrap(),
+            to: "05:00".parse().unwrap(),
+        });
+        let mut engine = engine_with(rule);
+
+        evaluate(&mut engine, 5 * HOUR, &mut zones, &mut devices);
+        evaluate(&mut engine, 6 * HOUR, &mut zones, &mut devices);
+
+        assert_eq!(Some(&zone_at(16.0)), zones.get(&uuid(1)));
+    }
+
+    #[test]
+    fn adjust_setpoint_without_subjects_changes_zones_not_following_a_parent() {
+        let (mut zones, mut devices) = bathroom(18.0);
+        zones.add(uuid(3), Zone::new("Kitchen".to_string()));
+        let mut larder = Zone::new("Larder".to_string());
+        larder.set_parent_uuid(Some(uuid(3)));
+        zones.add(uuid(4), larder);
+        let mut pantry = Zone::new("Pantry".to_string());
+        pantry.set_parent_uuid(Some(uuid(3)));
+        pantry.set_setpoint(Temperature::from_celsius(20.0));
+        zones.add(uuid(5), pantry);
+        let mut scenes = SceneCollection::new();
+        let policy = HealthPolicy::default();
+        let mut engine = engine_with(Rule {
+            name: "Frost".to_string(),
+            enabled: true,
+            trigger: Trigger::Event {
+                name: "frost".to_string(),
+            },
+            conditions: Vec::new(),
+            actions: vec![Action::AdjustSetpoint {
+                subject_uuids: Vec::new(),
+                delta: 1.0,
+                minutes: None,
+            }],
+        });
+        let mut home = Home {
+            zones: &mut zones,
+            devices: &mut devices,
+            scenes: &mut scenes,
+            policy: &policy,
+        };
+
+        assert_eq!(vec![uuid(9)], engine.fire_event("frost", 0, &mut home));
+        assert!(engine.fire_event("thaw", 0, &mut home).is_empty());
+
+        let mut kitchen = Zone::new("Kitchen".to_string());
+        kitchen.set_setpoint(Temperature::from_celsius(17.0));
+        assert_eq!(Some(&zone_at(17.0)), zones.get(&uuid(1)));
+        assert_eq!(Some(&kitchen), zones.get(&uuid(3)));
+        let larder = zones.get(&uuid(4)).unwrap();
+        assert_eq!(Temperature::from_celsius(17.0), larder.setpoint());
+        assert!(!larder.overrides_setpoint());
+        assert_eq!(Temperature::from_celsius(21.0), zones.get(&uuid(5)).unwrap().setpoint());
+    }
+
+    #[test]
+    fn dry_run_reports_changes_without_making_them() {
+        let (mut zones, mut devices) = bathroom(18.0);
+        let mut scenes = SceneCollection::new();
+        let policy = HealthPolicy::default();
+        let rule = boost_rule(Trigger::Event {
+            name: "shower".to_string(),
+        });
+        let home = Home {
+            zones: &mut zones,
+            devices: &mut devices,
+            scenes: &mut scenes,
+            policy: &policy,
+        };
+
+        let dry_run = rule.dry_run(Some("shower"), 0, &home);
+
+        assert!(dry_run.trigger_holds);
+        assert!(dry_run.conditions_hold);
+        assert_eq!(1, dry_run.changes.len());
+        assert_eq!(uuid(1), dry_run.changes[0].subject_uuid);
+        assert_eq!(Some(&Zone::new("Bathroom".to_string())), zones.get(&uuid(1)));
+    }
+
+    #[test]
+    fn rule_without_actions_is_rejected() {
+        let mut rule = boost_rule(Trigger::Time {
+            at: "06:00".parse().unwrap(),
+        });
+        rule.actions.clear();
+
+        assert!(RuleEngine::new().add(uuid(9), rule).is_err());
+    }
+}
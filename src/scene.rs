@@ -0,0 +1,131 @@
+use clock::{self, Timestamp};
+use device::{Device, DeviceCollection};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use temperature::Temperature;
+use uuid::Uuid;
+use zone::ZoneCollection;
+
+pub type SceneCollectionState = Arc<Mutex<SceneCollection>>;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Target {
+    setpoint: Temperature,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Scene {
+    name: String,
+    #[serde(default)]
+    zones: BTreeMap<Uuid, Target>,
+    #[serde(default)]
+    devices: BTreeMap<Uuid, Target>,
+}
+
+impl Scene {
+    pub fn check_targets(&self, zones: &ZoneCollection, devices: &DeviceCollection) -> Result<(), String> {
+        self.check_targets_with(|uuid| zones.get(uuid).is_some(), |uuid| devices.get(uuid))
+    }
+
+    pub fn check_targets_with<'d, Z, D>(&self, zone_exists: Z, device: D) -> Result<(), String>
+    where
+        Z: Fn(&Uuid) -> bool,
+        D: Fn(&Uuid) -> Option<&'d Device>,
+    {
+        if let Some(uuid) = self.zones.keys().find(|uuid| !zone_exists(uuid)) {
+            return Err(format!("zone {} does not exist", uuid));
+        }
+        for uuid in self.devices.keys() {
+            let device = device(uuid).ok_or_else(|| format!("device {} does not exist", uuid))?;
+            if !device.capabilities().has_setpoint {
+                return Err(format!("devices of type {} have no setpoint", device.device_type().as_str()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn targets_zone(&self, uuid: &Uuid) -> bool {
+        self.zones.contains_key(uuid)
+    }
+
+    pub fn targets_device(&self, uuid: &Uuid) -> bool {
+        self.devices.contains_key(uuid)
+    }
+
+    pub fn apply(&self, zones: &mut ZoneCollection, devices: &mut DeviceCollection) -> Result<(), String> {
+        self.check_targets(zones, devices)?;
+
+        for (uuid, target) in &self.zones {
+            if let Some(mut zone) = zones.get(uuid).cloned() {
+                zone.set_setpoint(target.setpoint);
+                zones.add(*uuid, zone);
+            }
+        }
+        for (uuid, target) in &self.devices {
+            if let Some(device) = devices.get_mut(uuid) {
+                device.set_setpoint(target.setpoint);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct ActiveScene {
+    scene_uuid: Uuid,
+    activated_at: Timestamp,
+}
+
+#[derive(Clone)]
+pub struct SceneCollection {
+    scenes: HashMap<Uuid, Scene>,
+    active: Option<ActiveScene>,
+}
+
+impl SceneCollection {
+    pub fn new() -> SceneCollection {
+        SceneCollection {
+            scenes: HashMap::new(),
+            active: None,
+        }
+    }
+
+    pub fn add(&mut self, uuid: Uuid, scene: Scene) {
+        self.scenes.insert(uuid, scene);
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> Option<&Scene> {
+        self.scenes.get(uuid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Uuid, &Scene)> {
+        self.scenes.iter()
+    }
+
+    pub fn remove(&mut self, uuid: &Uuid) -> Option<Scene> {
+        if self.active.map(|active| active.scene_uuid) == Some(*uuid) {
+            self.active = None;
+        }
+        self.scenes.remove(uuid)
+    }
+
+    pub fn active(&self) -> Option<ActiveScene> {
+        self.active
+    }
+
+    pub fn activate(
+        &mut self,
+        uuid: &Uuid,
+        zones: &mut ZoneCollection,
+        devices: &mut DeviceCollection,
+    ) -> Option<Result<(), String>> {
+        let result = self.scenes.get(uuid)?.apply(zones, devices);
+        if result.is_ok() {
+            self.active = Some(ActiveScene {
+                scene_uuid: *uuid,
+                activated_at: clock::now(),
+            });
+        }
+        Some(result)
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+pub type UnitPreferencesState = Arc<Mutex<UnitPreferences>>;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, PartialOrd, Debug)]
+#[serde(transparent)]
+pub struct Temperature(f64);
+
+pub const DEFAULT_SETPOINT: Temperature = Temperature(16.0);
+
+impl Temperature {
+    pub fn from_celsius(degrees: f64) -> Temperature {
+        Temperature(degrees)
+    }
+
+    pub fn celsius(self) -> f64 {
+        self.0
+    }
+
+    pub fn from_unit(value: f64, unit: TemperatureUnit) -> Temperature {
+        Temperature(unit.to_celsius(value))
+    }
+
+    pub fn in_unit(self, unit: TemperatureUnit) -> f64 {
+        unit.convert(self.0)
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "celsius",
+            TemperatureUnit::Fahrenheit => "fahrenheit",
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+        }
+    }
+
+    pub fn convert(self, celsius: f64) -> f64 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    pub fn to_celsius(self, value: f64) -> f64 {
+        match self {
+            TemperatureUnit::Celsius => value,
+            TemperatureUnit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+        }
+    }
+
+    pub fn convert_difference(self, celsius: f64) -> f64 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0,
+        }
+    }
+
+    pub fn difference_to_celsius(self, difference: f64) -> f64 {
+        match self {
+            TemperatureUnit::Celsius => difference,
+            TemperatureUnit::Fahrenheit => difference * 5.0 / 9.0,
+        }
+    }
+}
+
+impl Default for TemperatureUnit {
+    fn default() -> TemperatureUnit {
+        TemperatureUnit::Celsius
+    }
+}
+
+impl FromStr for TemperatureUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TemperatureUnit, String> {
+        match s.to_lowercase().as_str() {
+            "celsius" | "c" => Ok(TemperatureUnit::Celsius),
+            "fahrenheit" | "f" => Ok(TemperatureUnit::Fahrenheit),
+            _ => Err(format!("unknown temperature unit {}", s)),
+        }
+    }
+}
+
+pub struct UnitPreferences {
+    units: HashMap<String, TemperatureUnit>,
+}
+
+impl UnitPreferences {
+    pub fn new() -> UnitPreferences {
+        UnitPreferences { units: HashMap::new() }
+    }
+
+    pub fn unit(&self, actor: &str) -> TemperatureUnit {
+        self.units.get(actor).cloned().unwrap_or_default()
+    }
+
+    pub fn set_unit(&mut self, actor: &str, unit: TemperatureUnit) {
+        self.units.insert(actor.to_string(), unit);
+    }
+
+    pub fn remove(&mut self, actor: &str) {
+        self.units.remove(actor);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &TemperatureUnit)> {
+        self.units.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn converts_between_celsius_and_fahrenheit() {
+        let setpoint = Temperature::from_unit(68.0, TemperatureUnit::Fahrenheit);
+
+        assert_close(20.0, setpoint.celsius());
+        assert_close(68.0, setpoint.in_unit(TemperatureUnit::Fahrenheit));
+        assert_close(-40.0, TemperatureUnit::Fahrenheit.convert(-40.0));
+        assert_close(0.9, TemperatureUnit::Fahrenheit.convert_difference(0.5));
+    }
+
+    #[test]
+    fn parses_unit_names_and_letters() {
+        assert_eq!(Ok(TemperatureUnit::Fahrenheit), "F".parse());
+        assert_eq!(Ok(TemperatureUnit::Celsius), "celsius".parse());
+        assert!("kelvin".parse::<TemperatureUnit>().is_err());
+    }
+
+    #[test]
+    fn stores_temperatures_in_celsius() {
+        let setpoint = Temperature::from_celsius(21.5);
+
+        assert_eq!("21.5", serde_json::to_string(&setpoint).unwrap());
+        assert_eq!(setpoint, serde_json::from_str::<Temperature>("21.5").unwrap());
+    }
+}
@@ -0,0 +1,361 @@
+use audit::AuditLogState;
+use clock::{self, Timestamp};
+use device::DeviceCollectionState;
+use rocket::config::Config;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use temperature::Temperature;
+use uuid::Uuid;
+use zone::{ZoneCollection, ZoneCollectionState};
+
+mod provider;
+
+pub use self::provider::{FileProvider, HttpProvider, OutdoorTemperature};
+
+pub type WeatherCompensationState = Arc<Mutex<WeatherCompensation>>;
+
+const AUDIT_ACTOR: &str = "weather";
+const OFFSET_STEP: f64 = 0.5;
+const DEFAULT_INTERVAL: u64 = 600;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub struct CompensationCurve {
+    pub mild_outdoor: Temperature,
+    pub design_outdoor: Temperature,
+    pub min_flow: Temperature,
+    pub max_flow: Temperature,
+    #[serde(default)]
+    pub max_setpoint_offset: f64,
+}
+
+impl CompensationCurve {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.design_outdoor >= self.mild_outdoor {
+            return Err("design_outdoor must be colder than mild_outdoor".to_string());
+        }
+        if self.min_flow > self.max_flow {
+            return Err("min_flow must not be above max_flow".to_string());
+        }
+        if self.max_setpoint_offset < 0.0 {
+            return Err("max_setpoint_offset must not be negative".to_string());
+        }
+        Ok(())
+    }
+
+    fn coldness(&self, outdoor: Temperature) -> f64 {
+        let (mild, design) = (self.mild_outdoor.celsius(), self.design_outdoor.celsius());
+        let coldness = (mild - outdoor.celsius()) / (mild - design);
+        coldness.max(0.0).min(1.0)
+    }
+
+    pub fn flow_temperature(&self, outdoor: Temperature) -> Temperature {
+        let (min, max) = (self.min_flow.celsius(), self.max_flow.celsius());
+        Temperature::from_celsius(min + self.coldness(outdoor) * (max - min))
+    }
+
+    pub fn setpoint_offset(&self, outdoor: Temperature) -> f64 {
+        (self.coldness(outdoor) * self.max_setpoint_offset / OFFSET_STEP).round() * OFFSET_STEP
+    }
+}
+
+impl Default for CompensationCurve {
+    fn default() -> CompensationCurve {
+        CompensationCurve {
+            mild_outdoor: Temperature::from_celsius(18.0),
+            design_outdoor: Temperature::from_celsius(-5.0),
+            min_flow: Temperature::from_celsius(30.0),
+            max_flow: Temperature::from_celsius(70.0),
+            max_setpoint_offset: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct OutdoorReading {
+    temperature: Temperature,
+    read_at: Timestamp,
+}
+
+#[derive(Clone, Serialize, PartialEq, Debug)]
+pub struct Compensation {
+    curve: CompensationCurve,
+    outdoor: Option<OutdoorReading>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    flow_temperature: Option<Temperature>,
+    setpoint_offset: f64,
+}
+
+pub struct WeatherCompensation {
+    curve: CompensationCurve,
+    outdoor: Option<OutdoorReading>,
+    error: Option<String>,
+    applied_offset: f64,
+    offset_setpoints: HashMap<Uuid, (f64, f64)>,
+    interval: Option<Duration>,
+    last_evaluated: Option<Timestamp>,
+}
+
+impl WeatherCompensation {
+    pub fn new(curve: CompensationCurve) -> WeatherCompensation {
+        WeatherCompensation {
+            curve,
+            outdoor: None,
+            error: None,
+            applied_offset: 0.0,
+            offset_setpoints: HashMap::new(),
+            interval: None,
+            last_evaluated: None,
+        }
+    }
+
+    pub fn has_evaluator(&self) -> bool {
+        self.interval.is_some()
+    }
+
+    pub fn last_evaluated(&self) -> Option<Timestamp> {
+        self.last_evaluated
+    }
+
+    pub fn is_evaluating(&self, now: Timestamp) -> bool {
+        let interval = self.interval.map_or(0, |interval| interval.as_secs());
+        clock::ran_recently(self.last_evaluated, interval, now)
+    }
+
+    pub fn compensation(&self) -> Compensation {
+        Compensation {
+            curve: self.curve,
+            outdoor: self.outdoor,
+            error: self.error.clone(),
+            flow_temperature: self
+                .outdoor
+                .map(|outdoor| self.curve.flow_temperature(outdoor.temperature)),
+            setpoint_offset: self.applied_offset,
+        }
+    }
+
+    pub fn curve(&self) -> CompensationCurve {
+        self.curve
+    }
+
+    pub fn set_curve(&mut self, curve: CompensationCurve) -> Result<(), String> {
+        curve.validate()?;
+        self.set_validated_curve(curve);
+        Ok(())
+    }
+
+    pub fn set_validated_curve(&mut self, curve: CompensationCurve) {
+        self.curve = curve;
+    }
+
+    pub fn record(&mut self, now: Timestamp, reading: Result<f64, String>, zones: &mut ZoneCollection) -> bool {
+        self.last_evaluated = Some(now);
+        match reading {
+            Ok(temperature) => {
+                self.outdoor = Some(OutdoorReading {
+                    temperature: Temperature::from_celsius(temperature),
+                    read_at: now,
+                });
+                self.error = None;
+            }
+            Err(error) => self.error = Some(error),
+        }
+
+        let offset = match self.outdoor {
+            Some(outdoor) => self.curve.setpoint_offset(outdoor.temperature),
+            None => return false,
+        };
+        let top_level: Vec<_> = zones
+            .iter()
+            .filter(|(_, zone)| zone.parent_uuid().is_none())
+            .map(|(uuid, zone)| (*uuid, zone.clone()))
+            .collect();
+        let mut offset_setpoints = HashMap::new();
+        let mut changed = false;
+        for (uuid, mut zone) in top_level {
+            let current = zone.setpoint().celsius();
+            let base = match self.offset_setpoints.get(&uuid) {
+                Some(&(given, given_offset)) if (current - given).abs() < std::f64::EPSILON => given - given_offset,
+                _ => current,
+            };
+            let setpoint = base + offset;
+            if (setpoint - current).abs() >= std::f64::EPSILON {
+                zone.set_setpoint(Temperature::from_celsius(setpoint));
+                zones.add(uuid, zone);
+                changed = true;
+            }
+            offset_setpoints.insert(uuid, (setpoint, offset));
+        }
+        self.offset_setpoints = offset_setpoints;
+        self.applied_offset = offset;
+        changed
+    }
+}
+
+pub struct WeatherConfig {
+    pub interval: Duration,
+    pub provider: Box<dyn OutdoorTemperature>,
+}
+
+impl WeatherConfig {
+    pub fn from_config(config: &Config) -> Option<WeatherConfig> {
+        let table = config.get_table("weather").ok()?;
+        let get_str = |key: &str| table.get(key).and_then(|value| value.as_str());
+
+        let provider: Box<dyn OutdoorTemperature> = match (get_str("url"), get_str("file")) {
+            (Some(url), _) => match HttpProvider::new(url) {
+                Some(provider) => Box::new(provider),
+                None => {
+                    warn!("Ignoring invalid weather URL: {}", url);
+                    return None;
+                }
+            },
+            (None, Some(path)) => Box::new(FileProvider::new(path)),
+            (None, None) => return None,
+        };
+        let interval = match table.get("interval").and_then(|value| value.as_integer()) {
+            Some(interval) if interval > 0 => interval as u64,
+            Some(interval) => {
+                warn!("Ignoring invalid weather interval: {}", interval);
+                DEFAULT_INTERVAL
+            }
+            None => DEFAULT_INTERVAL,
+        };
+
+        Some(WeatherConfig {
+            interval: Duration::from_secs(interval),
+            provider,
+        })
+    }
+}
+
+pub fn spawn_evaluator(
+    weather: WeatherCompensationState,
+    zones: ZoneCollectionState,
+    devices: DeviceCollectionState,
+    audit: AuditLogState,
+    config: WeatherConfig,
+) -> thread::JoinHandle<()> {
+    weather.lock().unwrap().interval = Some(config.interval);
+
+    thread::spawn(move || loop {
+        let reading = config.provider.read();
+        if let Err(ref error) = reading {
+            error!("Failed to read the outdoor temperature: {}", error);
+        }
+        {
+            let now = clock::now();
+            let mut zones = zones.lock().unwrap();
+            let devices = devices.lock().unwrap();
+            let zones_before = zones.clone();
+            if weather.lock().unwrap().record(now, reading, &mut zones) {
+                audit
+                    .lock()
+                    .unwrap()
+                    .record_changes(now, AUDIT_ACTOR, (&zones_before, &*devices), (&*zones, &*devices));
+            }
+        }
+
+        thread::sleep(config.interval);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixtures::{house, setpoint, uuid};
+
+    fn curve_with_offset(max_setpoint_offset: f64) -> CompensationCurve {
+        CompensationCurve {
+            max_setpoint_offset,
+            ..CompensationCurve::default()
+        }
+    }
+
+    #[test]
+    fn flow_temperature_follows_curve_between_limits() {
+        let curve = CompensationCurve::default();
+
+        assert_eq!(30.0, curve.flow_temperature(Temperature::from_celsius(25.0)).celsius());
+        assert_eq!(50.0, curve.flow_temperature(Temperature::from_celsius(6.5)).celsius());
+        assert_eq!(70.0, curve.flow_temperature(Temperature::from_celsius(-12.0)).celsius());
+    }
+
+    #[test]
+    fn setpoint_offset_is_rounded_to_half_degrees() {
+        let curve = curve_with_offset(2.0);
+
+        assert_eq!(0.0, curve.setpoint_offset(Temperature::from_celsius(18.0)));
+        assert_eq!(1.0, curve.setpoint_offset(Temperature::from_celsius(6.5)));
+        assert_eq!(1.5, curve.setpoint_offset(Temperature::from_celsius(0.0)));
+        assert_eq!(2.0, curve.setpoint_offset(Temperature::from_celsius(-20.0)));
+    }
+
+    #[test]
+    fn invalid_curve_is_rejected() {
+        let mut weather = WeatherCompensation::new(CompensationCurve::default());
+        let inverted = CompensationCurve {
+            design_outdoor: Temperature::from_celsius(20.0),
+            ..CompensationCurve::default()
+        };
+
+        assert!(weather.set_curve(inverted).is_err());
+        assert!(weather.set_curve(curve_with_offset(-1.0)).is_err());
+        assert_eq!(CompensationCurve::default(), weather.compensation().curve);
+    }
+
+    #[test]
+    fn reading_offsets_top_level_zones_which_cascade() {
+        let mut weather = WeatherCompensation::new(curve_with_offset(2.0));
+        let mut zones = house();
+
+        assert!(weather.record(1000, Ok(6.5), &mut zones));
+
+        assert_eq!(Some(21.0), setpoint(&zones, 1));
+        assert_eq!(Some(21.0), setpoint(&zones, 2));
+        assert_eq!(Some(Temperature::from_celsius(50.0)), weather.compensation().flow_temperature);
+    }
+
+    #[test]
+    fn only_change_in_offset_is_applied() {
+        let mut weather = WeatherCompensation::new(curve_with_offset(2.0));
+        let mut zones = house();
+        weather.record(1000, Ok(6.5), &mut zones);
+
+        assert!(!weather.record(1600, Ok(6.0), &mut zones));
+        assert!(weather.record(2200, Ok(18.0), &mut zones));
+
+        assert_eq!(Some(20.0), setpoint(&zones, 1));
+    }
+
+    #[test]
+    fn setpoint_changed_meanwhile_gets_the_offset_again() {
+        let mut weather = WeatherCompensation::new(curve_with_offset(2.0));
+        let mut zones = house();
+        weather.record(1000, Ok(6.5), &mut zones);
+        let mut floor = zones.get(&uuid(1)).unwrap().clone();
+        floor.set_setpoint(Temperature::from_celsius(22.0));
+        zones.add(uuid(1), floor);
+
+        assert!(weather.record(1600, Ok(6.5), &mut zones));
+        assert_eq!(Some(23.0), setpoint(&zones, 1));
+        assert!(weather.record(2200, Ok(18.0), &mut zones));
+        assert_eq!(Some(22.0), setpoint(&zones, 1));
+    }
+
+    #[test]
+    fn failed_reading_keeps_last_temperature() {
+        let mut weather = WeatherCompensation::new(CompensationCurve::default());
+        let mut zones = house();
+        weather.record(1000, Ok(6.5), &mut zones);
+
+        weather.record(1600, Err("timed out".to_string()), &mut zones);
+
+        let compensation = weather.compensation();
+        assert_eq!(Some(1000), compensation.outdoor.map(|outdoor| outdoor.read_at));
+        assert_eq!(Some("timed out".to_string()), compensation.error);
+        assert_eq!(Some(Temperature::from_celsius(50.0)), compensation.flow_temperature);
+    }
+}
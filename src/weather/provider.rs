@@ -0,0 +1,126 @@
+use http::{connect, HttpUrl};
+use std::fs;
+use std::io::{Read, Write};
+
+pub trait OutdoorTemperature: Send {
+    fn read(&self) -> Result<f64, String>;
+}
+
+fn parse_temperature(body: &str) -> Result<f64, String> {
+    let body = body.trim();
+    if let Ok(temperature) = body.parse() {
+        return Ok(temperature);
+    }
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| value["temperature"].as_f64())
+        .ok_or_else(|| format!("no temperature in {}", body))
+}
+
+pub struct FileProvider {
+    path: String,
+}
+
+impl FileProvider {
+    pub fn new(path: &str) -> FileProvider {
+        FileProvider { path: path.to_string() }
+    }
+}
+
+impl OutdoorTemperature for FileProvider {
+    fn read(&self) -> Result<f64, String> {
+        let contents = fs::read_to_string(&self.path).map_err(|e| format!("{}: {}", self.path, e))?;
+        parse_temperature(&contents)
+    }
+}
+
+pub struct HttpProvider {
+    url: HttpUrl,
+}
+
+impl HttpProvider {
+    pub fn new(url: &str) -> Option<HttpProvider> {
+        HttpUrl::parse(url).map(|url| HttpProvider { url })
+    }
+}
+
+impl OutdoorTemperature for HttpProvider {
+    fn read(&self) -> Result<f64, String> {
+        let mut stream = connect(&self.url.address)?;
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+            self.url.path, self.url.host
+        );
+        stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+        let status_line = response.lines().next().unwrap_or_default();
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => {}
+            _ => return Err(format!("weather endpoint responded with {}", status_line.trim())),
+        }
+        match response.find("\r\n\r\n") {
+            Some(index) => parse_temperature(&response[index + 4..]),
+            None => Err("weather endpoint sent no body".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn serve_once(response: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/outdoor", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 1024];
+            let read = stream.read(&mut buffer).unwrap();
+            assert!(String::from_utf8_lossy(&buffer[..read]).starts_with("GET /outdoor HTTP/1.1"));
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn parses_number_or_json() {
+        assert_eq!(Ok(-2.5), parse_temperature("-2.5\n"));
+        assert_eq!(Ok(7.0), parse_temperature(r#"{"temperature": 7.0, "humidity": 80}"#));
+        assert!(parse_temperature("cold").is_err());
+    }
+
+    #[test]
+    fn file_provider_reads_file() {
+        let path = env::temp_dir().join(format!("outdoor-{}", std::process::id()));
+        fs::write(&path, "3.5\n").unwrap();
+
+        let temperature = FileProvider::new(path.to_str().unwrap()).read();
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Ok(3.5), temperature);
+    }
+
+    #[test]
+    fn http_provider_reads_json_body() {
+        let url = serve_once("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{\"temperature\":-4.0}");
+
+        assert_eq!(Ok(-4.0), HttpProvider::new(&url).unwrap().read());
+    }
+
+    #[test]
+    fn http_provider_fails_on_error_status() {
+        let url = serve_once("HTTP/1.1 503 Service Unavailable\r\n\r\n");
+
+        assert!(HttpProvider::new(&url).unwrap().read().is_err());
+    }
+
+    #[test]
+    fn http_provider_rejects_non_http_urls() {
+        assert!(HttpProvider::new("https://weather.local/outdoor").is_none());
+    }
+}
@@ -1,49 +1,207 @@
-use std::collections::HashMap;
+use clock::{self, Timestamp};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use temperature::{Temperature, DEFAULT_SETPOINT};
 use uuid::Uuid;
 
+pub type ZoneCollectionState = Arc<Mutex<ZoneCollection>>;
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
+pub struct OptimumStart {
+    pub heat_up_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preheat_start: Option<Timestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_at: Option<Timestamp>,
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 pub struct Zone {
     name: String,
-    setpoint: f64,
+    setpoint: Temperature,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent_uuid: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    overrides_setpoint: bool,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    optimum_start: Option<OptimumStart>,
 }
 
 impl Zone {
     pub fn new(name: String) -> Zone {
-        Zone { name, setpoint: 16.0 }
+        Zone {
+            name,
+            setpoint: DEFAULT_SETPOINT,
+            parent_uuid: None,
+            overrides_setpoint: false,
+            optimum_start: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn setpoint(&self) -> Temperature {
+        self.setpoint
     }
 
     pub fn set_name(&mut self, name: String) {
         self.name = name;
     }
 
-    pub fn set_setpoint(&mut self, setpoint: f64) {
+    pub fn set_setpoint(&mut self, setpoint: Temperature) {
         self.setpoint = setpoint;
+        self.overrides_setpoint = self.parent_uuid.is_some();
+    }
+
+    pub fn overrides_setpoint(&self) -> bool {
+        self.overrides_setpoint
+    }
+
+    pub fn inherit_setpoint(&mut self) {
+        self.overrides_setpoint = false;
+    }
+
+    pub fn parent_uuid(&self) -> Option<Uuid> {
+        self.parent_uuid
+    }
+
+    pub fn set_parent_uuid(&mut self, parent_uuid: Option<Uuid>) {
+        self.parent_uuid = parent_uuid;
+        if parent_uuid.is_none() {
+            self.overrides_setpoint = false;
+        }
+    }
+}
+
+pub fn check_hierarchy(parents: &HashMap<Uuid, Option<Uuid>>) -> Result<(), String> {
+    for (uuid, parent_uuid) in parents {
+        let mut ancestors = HashSet::new();
+        let mut ancestor = *parent_uuid;
+        while let Some(ancestor_uuid) = ancestor {
+            if ancestor_uuid == *uuid {
+                return Err(format!("zone {} cannot be inside itself", uuid));
+            }
+            if !ancestors.insert(ancestor_uuid) {
+                break;
+            }
+            ancestor = *parents
+                .get(&ancestor_uuid)
+                .ok_or_else(|| format!("parent zone {} does not exist", ancestor_uuid))?;
+        }
     }
+    Ok(())
 }
 
 #[derive(Clone, Serialize)]
 pub struct ZoneCollection {
     zones: HashMap<Uuid, Zone>,
+    #[serde(skip)]
+    created_at: HashMap<Uuid, Timestamp>,
+    #[serde(skip)]
+    versions: HashMap<Uuid, u64>,
+    #[serde(skip)]
+    last_version: u64,
 }
 
 impl ZoneCollection {
     pub fn new() -> ZoneCollection {
-        ZoneCollection { zones: HashMap::new() }
+        ZoneCollection {
+            zones: HashMap::new(),
+            created_at: HashMap::new(),
+            versions: HashMap::new(),
+            last_version: 0,
+        }
+    }
+
+    fn bump_version(&mut self, uuid: Uuid) {
+        self.last_version += 1;
+        self.versions.insert(uuid, self.last_version);
     }
 
-    pub fn add(&mut self, uuid: Uuid, zone: Zone) {
+    pub fn add(&mut self, uuid: Uuid, mut zone: Zone) {
+        self.created_at.entry(uuid).or_insert_with(clock::now);
+        self.bump_version(uuid);
+        if zone.optimum_start.is_none() {
+            zone.optimum_start = self.zones.get(&uuid).and_then(|previous| previous.optimum_start);
+        }
         self.zones.insert(uuid, zone);
+        self.inherit_setpoints(uuid);
+    }
+
+    pub fn check_parent(&self, uuid: &Uuid, zone: &Zone) -> Result<(), String> {
+        let mut parents: HashMap<Uuid, Option<Uuid>> =
+            self.zones.iter().map(|(uuid, zone)| (*uuid, zone.parent_uuid)).collect();
+        parents.insert(*uuid, zone.parent_uuid);
+        check_hierarchy(&parents)
+    }
+
+    fn children(&self, uuid: &Uuid) -> Vec<Uuid> {
+        self.zones
+            .iter()
+            .filter(|(_, zone)| zone.parent_uuid == Some(*uuid))
+            .map(|(uuid, _)| *uuid)
+            .collect()
+    }
+
+    fn inherit_setpoints(&mut self, uuid: Uuid) {
+        let mut pending = vec![uuid];
+        let mut visited = HashSet::new();
+        while let Some(uuid) = pending.pop() {
+            if !visited.insert(uuid) {
+                continue;
+            }
+            let inheriting = self.zones.get(&uuid).and_then(|zone| match zone.parent_uuid {
+                Some(ref parent_uuid) if !zone.overrides_setpoint => self.zones.get(parent_uuid).map(|parent| Zone {
+                    setpoint: parent.setpoint,
+                    ..zone.clone()
+                }),
+                _ => None,
+            });
+            if let Some(zone) = inheriting {
+                if self.zones.get(&uuid) != Some(&zone) {
+                    self.bump_version(uuid);
+                    self.zones.insert(uuid, zone);
+                }
+            }
+            pending.extend(self.children(&uuid));
+        }
     }
 
     pub fn get(&self, uuid: &Uuid) -> Option<&Zone> {
         self.zones.get(uuid)
     }
 
-    pub fn get_mut(&mut self, uuid: &Uuid) -> Option<&mut Zone> {
-        self.zones.get_mut(uuid)
+    pub fn set_optimum_start(&mut self, uuid: &Uuid, optimum_start: Option<OptimumStart>) {
+        if let Some(zone) = self.zones.get_mut(uuid) {
+            zone.optimum_start = optimum_start;
+        }
+    }
+
+    pub fn version(&self, uuid: &Uuid) -> Option<u64> {
+        self.versions.get(uuid).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Uuid, &Zone)> {
+        self.zones.iter()
+    }
+
+    pub fn created_at(&self, uuid: &Uuid) -> Timestamp {
+        self.created_at.get(uuid).cloned().unwrap_or(0)
     }
 
     pub fn remove(&mut self, uuid: &Uuid) {
-        self.zones.remove(uuid);
+        if let Some(removed) = self.zones.remove(uuid) {
+            for child_uuid in self.children(uuid) {
+                self.bump_version(child_uuid);
+                if let Some(child) = self.zones.get_mut(&child_uuid) {
+                    child.set_parent_uuid(removed.parent_uuid);
+                }
+                self.inherit_setpoints(child_uuid);
+            }
+        }
+        self.created_at.remove(uuid);
+        self.versions.remove(uuid);
     }
 }